@@ -19,10 +19,11 @@ use std::{
     marker::PhantomData,
     mem,
     ops::{Deref, DerefMut, Range},
-    ptr, slice,
+    ptr::{self, NonNull},
+    slice,
     sync::{
         atomic::{AtomicU64, AtomicUsize, Ordering},
-        Arc, Weak,
+        Arc, Mutex, Weak,
     },
     time::Instant,
 };
@@ -53,6 +54,39 @@ impl<T> Buffer<T> {
     }
 }
 
+impl<T> Buffer<T> {
+    fn empty() -> Self {
+        Self {
+            size: 0,
+            buffer: NonNull::dangling().as_ptr(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// SAFETY:
+// - `Buffer<T>` owns the memory it points to
+unsafe impl<T: Send> Send for Buffer<T> {}
+
+// Idle buffers returned by dropped batches, reused by the allocator instead of allocating
+struct BufferPool<T> {
+    size: usize,
+    buffers: Mutex<Vec<Buffer<T>>>,
+}
+
+impl<T> BufferPool<T> {
+    fn get(&self) -> Option<Buffer<T>> {
+        self.buffers.lock().unwrap().pop()
+    }
+
+    fn put(&self, buffer: Buffer<T>) {
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.size {
+            buffers.push(buffer);
+        }
+    }
+}
+
 impl<T> Drop for Buffer<T> {
     fn drop(&mut self) {
         // SAFETY:
@@ -69,16 +103,18 @@ struct RefCounter<T> {
     buffer: Buffer<T>,
 
     stats: Arc<StatsCounter>,
+    pool: Option<Arc<BufferPool<T>>>,
     creation_time: Instant,
 }
 
 impl<T> RefCounter<T> {
-    fn new(capacity: usize, stats: Arc<StatsCounter>) -> Self {
+    fn new(buffer: Buffer<T>, stats: Arc<StatsCounter>, pool: Option<Arc<BufferPool<T>>>) -> Self {
         stats.concurrent.fetch_add(1, Ordering::Relaxed);
         Self {
             ref_count: AtomicUsize::new(1),
-            buffer: Buffer::new(capacity),
+            buffer,
             stats,
+            pool,
             creation_time: Instant::now(),
         }
     }
@@ -91,6 +127,10 @@ impl<T> Drop for RefCounter<T> {
             self.creation_time.elapsed().as_nanos() as u64,
             Ordering::Relaxed,
         );
+        // items are already released by the last referer
+        if let Some(pool) = self.pool.take() {
+            pool.put(mem::replace(&mut self.buffer, Buffer::empty()));
+        }
     }
 }
 
@@ -100,10 +140,10 @@ struct InnerAllocator<T> {
 }
 
 impl<T> InnerAllocator<T> {
-    fn new(capacity: usize, stats: Arc<StatsCounter>) -> Self {
+    fn new(buffer: Buffer<T>, stats: Arc<StatsCounter>, pool: Option<Arc<BufferPool<T>>>) -> Self {
         Self {
             allocated: 0,
-            counter: Box::into_raw(Box::new(RefCounter::new(capacity, stats))),
+            counter: Box::into_raw(Box::new(RefCounter::new(buffer, stats, pool))),
         }
     }
 
@@ -149,11 +189,15 @@ impl<T> Drop for InnerAllocator<T> {
 /// `Allocator<T>` and `BatchedBuffer<T>`s it allocated share a same `RefCounter<T>`.
 /// The memory is released when `ref_count == 0`, that is when `Allocator<T>` and
 /// all its `BatchedBuffer<T>`s are dropped.
+///
+/// With a pool, the memory is kept for reuse by the allocator instead of being released,
+/// up to the pool size.
 pub struct Allocator<T> {
     capacity: usize,
     inner: InnerAllocator<T>,
 
     stats: Arc<StatsCounter>,
+    pool: Option<Arc<BufferPool<T>>>,
 }
 
 #[derive(Default)]
@@ -164,6 +208,9 @@ pub struct StatsCounter {
     concurrent: AtomicU64,
 
     max_alive: AtomicU64,
+
+    pool_hit: AtomicU64,
+    pool_miss: AtomicU64,
 }
 
 impl RefCountable for StatsCounter {
@@ -194,20 +241,44 @@ impl RefCountable for StatsCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.max_alive.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "pool_hit",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.pool_hit.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "pool_miss",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.pool_miss.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
 
 impl<T> Allocator<T> {
     pub fn new(capacity: usize) -> Self {
+        Self::with_pool(capacity, 0)
+    }
+
+    // keeps at most `pool_size` idle buffers for reuse, 0 means no pooling
+    pub fn with_pool(capacity: usize, pool_size: usize) -> Self {
         let stats = Arc::new(StatsCounter {
             batch_size: capacity * mem::size_of::<T>(),
             ..Default::default()
         });
+        let pool = if pool_size > 0 {
+            Some(Arc::new(BufferPool {
+                size: pool_size,
+                buffers: Mutex::new(Vec::with_capacity(pool_size)),
+            }))
+        } else {
+            None
+        };
         Self {
             capacity,
-            inner: InnerAllocator::new(capacity, stats.clone()),
+            inner: InnerAllocator::new(Buffer::new(capacity), stats.clone(), pool.clone()),
             stats,
+            pool,
         }
     }
 
@@ -217,8 +288,21 @@ impl<T> Allocator<T> {
 
     fn ensure_capacity(&mut self, size: usize) {
         if self.inner.allocated + size > self.capacity {
+            let buffer = match self.pool.as_ref() {
+                Some(pool) => match pool.get() {
+                    Some(b) => {
+                        self.stats.pool_hit.fetch_add(1, Ordering::Relaxed);
+                        b
+                    }
+                    None => {
+                        self.stats.pool_miss.fetch_add(1, Ordering::Relaxed);
+                        Buffer::new(self.capacity)
+                    }
+                },
+                None => Buffer::new(self.capacity),
+            };
             // old `InnerAllocator` will drop
-            self.inner = InnerAllocator::new(self.capacity, self.stats.clone());
+            self.inner = InnerAllocator::new(buffer, self.stats.clone(), self.pool.clone());
             self.stats.new.fetch_add(1, Ordering::Relaxed);
         }
     }
@@ -585,6 +669,29 @@ mod tests {
         assert_ne!(allocator.inner.counter, old_location);
     }
 
+    #[test]
+    fn pooled_allocation() {
+        let mut allocator: Allocator<u64> = Allocator::with_pool(16, 1);
+        let first = allocator.allocate_one_with(42);
+        let old_location = allocator.inner.counter().buffer.buffer;
+        for i in 0..15 {
+            let _ = allocator.allocate_one_with(i);
+        }
+        // buffer exhausted, the first batch is still referenced
+        let second = allocator.allocate_one_with(0);
+        assert_eq!(allocator.stats.pool_miss.load(Ordering::Relaxed), 1);
+        mem::drop(first);
+        for i in 0..15 {
+            let _ = allocator.allocate_one_with(i);
+        }
+        // the first batch is returned to pool and reused
+        let third = allocator.allocate_one_with(233);
+        assert_eq!(allocator.stats.pool_hit.load(Ordering::Relaxed), 1);
+        assert_eq!(allocator.inner.counter().buffer.buffer, old_location);
+        assert_eq!(*second, 0);
+        assert_eq!(*third, 233);
+    }
+
     #[test]
     fn modification() {
         let mut allocator = Allocator::new(1024);
@@ -430,6 +430,8 @@ pub const L7_PROTOCOL_INFERENCE_TTL: usize = 60;
 
 // RawPcap
 pub const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+// records with nanosecond timestamps
+pub const PCAP_MAGIC_NANO: u32 = 0xa1b23c4d;
 pub const RECORD_HEADER_LEN: usize = 16;
 
 // GRPC
@@ -61,7 +61,7 @@ pub enum QueueMessage {
     Names(Option<Vec<(String, bool)>>),
     // 请求queue name, 发送queue item
     Send(String),
-    On((String, Duration)),
+    On((String, Duration, QueueFilter)),
     Off(String),
     Continue,
     Clear,
@@ -70,6 +70,22 @@ pub enum QueueMessage {
     Err(String),
 }
 
+// Selects items sent by the queue debugger, so that a slice of a high volume queue can be
+// inspected instead of all of it
+#[derive(PartialEq, Debug, Default, Clone, Encode, Decode)]
+pub struct QueueFilter {
+    // stop after sending this number of items, 0 for unlimited
+    pub max_items: u32,
+    // items must contain all of the keywords, e.g. an ip address, a port or "tap_type: Cloud"
+    pub keywords: Vec<String>,
+}
+
+impl QueueFilter {
+    pub fn matches(&self, item: &str) -> bool {
+        self.keywords.iter().all(|k| item.contains(k.as_str()))
+    }
+}
+
 #[derive(Clone)]
 struct QueueContext {
     receiver: Arc<Receiver<String>>,
@@ -147,6 +163,7 @@ impl QueueDebugger {
         conn: SocketAddr,
         serialize_conf: Configuration,
         dur: Duration,
+        filter: QueueFilter,
     ) {
         let name = name.into();
         let sock = match UdpSocket::bind((IpAddr::from(Ipv6Addr::UNSPECIFIED), 0)) {
@@ -185,7 +202,11 @@ impl QueueDebugger {
 
                 let mut idle_now = Instant::now();
                 let mut msg_counter = 0;
-                while ctx.enabled.load(Ordering::SeqCst) && now.elapsed() < dur {
+                let mut sent = 0;
+                while ctx.enabled.load(Ordering::SeqCst)
+                    && now.elapsed() < dur
+                    && (filter.max_items == 0 || sent < filter.max_items)
+                {
                     let s = match ctx.receiver.recv(Some(QUEUE_RECV_TIMEOUT)) {
                         Ok(s) => s,
                         Err(Error::Terminated(..)) => {
@@ -211,7 +232,11 @@ impl QueueDebugger {
                         }
                         Err(Error::BatchTooLarge(_)) => unreachable!(),
                     };
+                    if !filter.matches(&s) {
+                        continue;
+                    }
                     msg_counter += 1;
+                    sent += 1;
                     let msg = QueueMessage::Send(s);
                     if let Err(e) = send_to(&sock, conn, msg, serialize_conf) {
                         warn!("send queue item error: {}", e);
@@ -121,7 +121,7 @@ impl PartialEq<IpProtocol> for u8 {
     }
 }
 
-#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
 #[repr(u8)]
 pub enum L4Protocol {
     Unknown = 0,
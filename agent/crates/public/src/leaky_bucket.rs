@@ -23,6 +23,8 @@ use std::{
     time::Duration,
 };
 
+use crate::counter::{Counter, CounterType, CounterValue, RefCountable};
+
 const TICK_INTERVAL: Duration = Duration::from_millis(100);
 const TICK_PER_SECOND: u64 =
     (Duration::from_secs(1).as_millis() / TICK_INTERVAL.as_millis()) as u64;
@@ -33,6 +35,10 @@ pub struct LeakyBucket {
     token: Arc<AtomicU64>,
     running: Arc<AtomicBool>,
 
+    // only counted when rate limited
+    admitted: AtomicU64,
+    rejected: AtomicU64,
+
     handle: JoinHandle<()>,
 }
 
@@ -82,6 +88,8 @@ impl LeakyBucket {
             rate,
             token,
             running,
+            admitted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
             handle,
         }
     }
@@ -91,12 +99,20 @@ impl LeakyBucket {
         self.handle.thread().unpark();
     }
 
+    pub fn rate(&self) -> Option<u64> {
+        match self.rate.load(Ordering::Relaxed) {
+            0 => None,
+            rate => Some(rate),
+        }
+    }
+
     pub fn acquire(&self, size: u64) -> bool {
         if self.rate.load(Ordering::Relaxed) == 0 {
             return true;
         }
 
-        self.token
+        let ok = self
+            .token
             .fetch_update(Ordering::Release, Ordering::Relaxed, |t| {
                 if t < size {
                     None
@@ -104,7 +120,35 @@ impl LeakyBucket {
                     Some(t - size)
                 }
             })
-            .is_ok()
+            .is_ok();
+        if ok {
+            self.admitted.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        ok
+    }
+}
+
+impl RefCountable for LeakyBucket {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "admitted",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.admitted.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "rejected",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.rejected.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "rate",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.rate.load(Ordering::Relaxed)),
+            ),
+        ]
     }
 }
 
@@ -165,4 +209,22 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn counters() {
+        let bucket = LeakyBucket::new(Some(1000));
+        thread::sleep(TICK_INTERVAL / 10);
+        while bucket.acquire(1) {}
+        let counters = bucket.get_counters();
+        assert_eq!(
+            counters[0].2,
+            CounterValue::Unsigned(BURST_MULTIPLE * 1000 / TICK_PER_SECOND)
+        );
+        assert_eq!(counters[1].2, CounterValue::Unsigned(1));
+        assert_eq!(
+            bucket.get_counters()[1].2,
+            CounterValue::Unsigned(0),
+            "counters not reset"
+        );
+    }
 }
@@ -24,7 +24,7 @@ use std::{
 
 use log::debug;
 
-use super::{bounded, Error, Receiver, Sender, StatsHandle};
+use super::{bounded, bounded_with_policy, Error, OverflowPolicy, Receiver, Sender, StatsHandle};
 
 use crate::debug::{QueueDebugger, QUEUE_LEN};
 
@@ -76,7 +76,16 @@ pub fn bounded_with_debug<T>(
     name: &'static str,
     debugger: &QueueDebugger,
 ) -> (DebugSender<T>, Receiver<T>, StatsHandle<T>) {
-    let (sender, receiver, handle) = bounded(size);
+    bounded_with_debug_and_policy(size, name, debugger, OverflowPolicy::Drop)
+}
+
+pub fn bounded_with_debug_and_policy<T>(
+    size: usize,
+    name: &'static str,
+    debugger: &QueueDebugger,
+    policy: OverflowPolicy,
+) -> (DebugSender<T>, Receiver<T>, StatsHandle<T>) {
+    let (sender, receiver, handle) = bounded_with_policy(size, policy);
 
     let (debug_sender, debug_receiver, _) = bounded(QUEUE_LEN);
     let enabled = Arc::new(AtomicBool::new(false));
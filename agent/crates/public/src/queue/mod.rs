@@ -17,10 +17,24 @@
 mod debug;
 mod overwrite_queue;
 
-pub use debug::{bounded_with_debug, DebugSender};
-pub use overwrite_queue::{bounded, Counter, Receiver, Sender, StatsHandle};
+use std::time::Duration;
+
+pub use debug::{bounded_with_debug, bounded_with_debug_and_policy, DebugSender};
+pub use overwrite_queue::{
+    bounded, bounded_with_policy, set_dwell_time_enabled, Counter, Receiver, Sender, StatsHandle,
+};
 use thiserror::Error;
 
+// What the sender does when the queue is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // Overwrite the oldest messages in the queue
+    #[default]
+    Drop,
+    // Wait for the receiver to free up space, overwrite the oldest messages on timeout
+    BlockWithTimeout(Duration),
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum Error<T> {
     #[error("the queue sending operation has timed out")]
@@ -24,11 +24,25 @@ use std::sync::{
 };
 use std::time::{Duration, Instant};
 
-use super::Error;
+use super::{Error, OverflowPolicy};
 use crate::counter as stats;
 
+// queues created afterwards stamp items with enqueue time for dwell time stats
+static DWELL_TIME_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dwell_time_enabled(enabled: bool) {
+    DWELL_TIME_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
 pub fn bounded<T>(size: usize) -> (Sender<T>, Receiver<T>, StatsHandle<T>) {
-    RefCounter::new(OverwriteQueue::with_capacity(size))
+    bounded_with_policy(size, OverflowPolicy::Drop)
+}
+
+pub fn bounded_with_policy<T>(
+    size: usize,
+    policy: OverflowPolicy,
+) -> (Sender<T>, Receiver<T>, StatsHandle<T>) {
+    RefCounter::new(OverwriteQueue::with_capacity(size, policy))
 }
 
 #[derive(Debug, Default)]
@@ -36,6 +50,11 @@ pub struct Counter {
     pub input: AtomicU64,
     pub output: AtomicU64,
     pub overwritten: AtomicU64,
+    pub blocked: AtomicU64,
+    // in nanoseconds, only updated if dwell time is enabled
+    pub dwell_time_sum: AtomicU64,
+    pub dwell_time_count: AtomicU64,
+    pub dwell_time_max: AtomicU64,
 }
 
 // fixed size MPSC overwrite queue implemented with ring buffer
@@ -50,16 +69,25 @@ struct OverwriteQueue<T: Sized> {
     reader_lock: Mutex<()>,
     writer_lock: Mutex<()>,
     notify: Condvar,
+    // notified by receiver when space is freed, only used with OverflowPolicy::BlockWithTimeout
+    not_full: Condvar,
+    policy: OverflowPolicy,
 
     terminated: AtomicBool,
 
     counter: Counter,
 
+    // enqueue time of items in nanoseconds since epoch, indexed as buffer
+    stamps: Option<Box<[AtomicU64]>>,
+    epoch: Instant,
+
     _marker: PhantomData<T>,
 }
 
 impl<T> OverwriteQueue<T> {
-    pub fn with_capacity(size: usize) -> Self {
+    const BLOCK_WAIT_SLICE: Duration = Duration::from_millis(10);
+
+    pub fn with_capacity(size: usize, policy: OverflowPolicy) -> Self {
         let size = size.next_power_of_two();
         let buffer = {
             let mut v = Vec::with_capacity(size);
@@ -76,8 +104,14 @@ impl<T> OverwriteQueue<T> {
             reader_lock: Mutex::new(()),
             writer_lock: Mutex::new(()),
             notify: Condvar::new(),
+            not_full: Condvar::new(),
+            policy,
             terminated: AtomicBool::new(false),
             counter: Counter::default(),
+            stamps: DWELL_TIME_ENABLED
+                .load(Ordering::Relaxed)
+                .then(|| (0..size).map(|_| AtomicU64::new(0)).collect()),
+            epoch: Instant::now(),
             _marker: PhantomData,
         }
     }
@@ -86,6 +120,15 @@ impl<T> OverwriteQueue<T> {
         self.terminated.load(Ordering::Relaxed)
     }
 
+    fn len(&self) -> usize {
+        let start = self.start.load(Ordering::Acquire);
+        let mut end = self.end.load(Ordering::Acquire);
+        if end < start {
+            end += 2 * self.size;
+        }
+        end - start
+    }
+
     unsafe fn raw_send(&self, msgs: *const T, count: usize) -> Result<(), Error<T>> {
         if self.terminated.load(Ordering::Acquire) {
             return Err(Error::Terminated(None, None));
@@ -93,7 +136,30 @@ impl<T> OverwriteQueue<T> {
         if count > self.size {
             return Err(Error::BatchTooLarge(None));
         }
-        let _lock = self.writer_lock.lock().unwrap();
+        let mut guard = self.writer_lock.lock().unwrap();
+        if let OverflowPolicy::BlockWithTimeout(timeout) = self.policy {
+            if self.len() + count > self.size {
+                self.counter.blocked.fetch_add(1, Ordering::Relaxed);
+                let now = Instant::now();
+                // overwrite as usual if receiver can not catch up in time
+                while self.len() + count > self.size && !self.terminated.load(Ordering::Acquire) {
+                    let elapsed = now.elapsed();
+                    if elapsed >= timeout {
+                        break;
+                    }
+                    // receiver notifies without writer_lock and the notification may be missed,
+                    // so wait in short slices
+                    guard = self
+                        .not_full
+                        .wait_timeout(guard, (timeout - elapsed).min(Self::BLOCK_WAIT_SLICE))
+                        .unwrap()
+                        .0;
+                }
+                if self.terminated.load(Ordering::Acquire) {
+                    return Err(Error::Terminated(None, None));
+                }
+            }
+        }
         let start = self.start.load(Ordering::Acquire);
         let raw_end = self.end.load(Ordering::Acquire);
         // the value of end will be less than start if it was wrapped
@@ -144,6 +210,12 @@ impl<T> OverwriteQueue<T> {
             self.buffer
                 .copy_from_nonoverlapping(msgs.add(free_after_end), count - free_after_end);
         }
+        if let Some(stamps) = self.stamps.as_ref() {
+            let now = self.epoch.elapsed().as_nanos() as u64;
+            for i in 0..count {
+                stamps[(raw_end + i) & (self.size - 1)].store(now, Ordering::Relaxed);
+            }
+        }
         self.end
             .store((raw_end + count) & (2 * self.size - 1), Ordering::Release);
         self.counter
@@ -209,6 +281,25 @@ impl<T> OverwriteQueue<T> {
             self.buffer
                 .copy_to_nonoverlapping(buffer.add(count_to_end), recv_count - count_to_end);
         }
+        if let Some(stamps) = self.stamps.as_ref() {
+            let now = self.epoch.elapsed().as_nanos() as u64;
+            let (mut sum, mut max) = (0, 0);
+            for i in 0..recv_count {
+                let dwell = now
+                    .saturating_sub(stamps[(start + i) & (self.size - 1)].load(Ordering::Relaxed));
+                sum += dwell;
+                max = max.max(dwell);
+            }
+            self.counter
+                .dwell_time_sum
+                .fetch_add(sum, Ordering::Relaxed);
+            self.counter
+                .dwell_time_count
+                .fetch_add(recv_count as u64, Ordering::Relaxed);
+            self.counter
+                .dwell_time_max
+                .fetch_max(max, Ordering::Relaxed);
+        }
         self.start.store(
             (start + recv_count) & (2 * self.size - 1),
             Ordering::Release,
@@ -216,6 +307,9 @@ impl<T> OverwriteQueue<T> {
         self.counter
             .output
             .fetch_add(recv_count as u64, Ordering::Relaxed);
+        if let OverflowPolicy::BlockWithTimeout(_) = self.policy {
+            self.not_full.notify_all();
+        }
         Ok(recv_count)
     }
 
@@ -223,6 +317,7 @@ impl<T> OverwriteQueue<T> {
         let _lock = self.reader_lock.lock().unwrap();
         self.terminated.swap(true, Ordering::Release);
         self.notify.notify_one();
+        self.not_full.notify_all();
     }
 }
 
@@ -480,7 +575,7 @@ impl<T: Send> stats::OwnedCountable for StatsHandle<T> {
         if end < start {
             end += 2 * queue.size;
         }
-        vec![
+        let mut counters = vec![
             (
                 "in",
                 stats::CounterType::Counted,
@@ -496,12 +591,34 @@ impl<T: Send> stats::OwnedCountable for StatsHandle<T> {
                 stats::CounterType::Counted,
                 stats::CounterValue::Unsigned(queue.counter.overwritten.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "blocked",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(queue.counter.blocked.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "pending",
                 stats::CounterType::Gauged,
                 stats::CounterValue::Unsigned((end - start) as u64),
             ),
-        ]
+        ];
+        // dwell times are reported in microseconds
+        if queue.stamps.is_some() {
+            let sum = queue.counter.dwell_time_sum.swap(0, Ordering::Relaxed);
+            let count = queue.counter.dwell_time_count.swap(0, Ordering::Relaxed);
+            let max = queue.counter.dwell_time_max.swap(0, Ordering::Relaxed);
+            counters.push((
+                "dwell_time_avg",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(if count > 0 { sum / count / 1000 } else { 0 }),
+            ));
+            counters.push((
+                "dwell_time_max",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(max / 1000),
+            ));
+        }
+        counters
     }
 
     fn closed(&self) -> bool {
@@ -625,6 +742,61 @@ mod tests {
         assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
     }
 
+    #[test]
+    fn block_with_timeout() {
+        let c = Arc::new(AtomicUsize::new(0));
+
+        {
+            let (s, r, _) =
+                bounded_with_policy(2, OverflowPolicy::BlockWithTimeout(Duration::from_secs(10)));
+
+            s.send(CountedU64::new(42, c.clone())).unwrap();
+            s.send(CountedU64::new(43, c.clone())).unwrap();
+            let h = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                let co = r.recv(None).unwrap();
+                assert_eq!(co, 42, "expected: 42, result: {}", co);
+                r
+            });
+            // blocks until 42 is received instead of overwriting it
+            s.send(CountedU64::new(44, c.clone())).unwrap();
+            let r = h.join().unwrap();
+
+            let co = r.recv(None).unwrap();
+            assert_eq!(co, 43, "expected: 43, result: {}", co);
+            let co = r.recv(None).unwrap();
+            assert_eq!(co, 44, "expected: 44, result: {}", co);
+        }
+
+        let c = c.load(Ordering::Acquire);
+        assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
+    }
+
+    #[test]
+    fn block_with_timeout_overwrite() {
+        let c = Arc::new(AtomicUsize::new(0));
+
+        {
+            let (s, r, _) = bounded_with_policy(
+                2,
+                OverflowPolicy::BlockWithTimeout(Duration::from_millis(10)),
+            );
+
+            s.send(CountedU64::new(42, c.clone())).unwrap();
+            s.send(CountedU64::new(43, c.clone())).unwrap();
+            // nobody receives, overwrite on timeout
+            s.send(CountedU64::new(44, c.clone())).unwrap();
+
+            let co = r.recv(None).unwrap();
+            assert_eq!(co, 43, "expected: 43, result: {}", co);
+            let co = r.recv(None).unwrap();
+            assert_eq!(co, 44, "expected: 44, result: {}", co);
+        }
+
+        let c = c.load(Ordering::Acquire);
+        assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
+    }
+
     #[test]
     fn queue_size_calculation() {
         let c = Arc::new(AtomicUsize::new(0));
@@ -739,4 +911,35 @@ mod tests {
         let c = c.load(Ordering::Acquire);
         assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
     }
+
+    #[test]
+    fn dwell_time() {
+        use stats::OwnedCountable;
+
+        set_dwell_time_enabled(true);
+        let (s, r, handle) = bounded(4);
+        set_dwell_time_enabled(false);
+
+        s.send_all(&mut vec![1, 2]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let mut co = Vec::with_capacity(4);
+        r.recv_all(&mut co, None).unwrap();
+        assert_eq!(co, vec![1, 2]);
+
+        let counters = handle.get_counters();
+        let dwell_max = counters
+            .iter()
+            .find(|c| c.0 == "dwell_time_max")
+            .map(|c| c.2);
+        assert!(
+            matches!(dwell_max, Some(stats::CounterValue::Unsigned(v)) if v >= 20000),
+            "unexpected dwell time {:?}",
+            dwell_max
+        );
+        assert!(!bounded::<u64>(4)
+            .2
+            .get_counters()
+            .iter()
+            .any(|c| c.0 == "dwell_time_max"));
+    }
 }
@@ -18,6 +18,7 @@ use std::fmt;
 use std::fmt::Debug;
 
 use num_enum::IntoPrimitive;
+use ring::hmac;
 
 /// A abstraction for sending data and serialize data
 pub trait Sendable: Debug + Send + 'static {
@@ -31,6 +32,105 @@ pub trait Sendable: Debug + Send + 'static {
     }
     // Serialize data to key-value and append to a string
     fn to_kv_string(&self, _: &mut String) {}
+    // Encode data without the fields excluded by filter and with the fields to hash hashed,
+    // data without optional fields ignores the filter
+    fn encode_filtered(
+        self,
+        buf: &mut Vec<u8>,
+        _: &FieldFilter,
+    ) -> Result<usize, prost::EncodeError>
+    where
+        Self: Sized,
+    {
+        self.encode(buf)
+    }
+    // Key of the flow the data belongs to, data with the same key is sent to the same
+    // ingester when balancing by hash
+    fn balance_key(&self) -> Option<u64> {
+        None
+    }
+}
+
+// Selects the optional fields of exported data by name, and the fields to be hashed with
+// salt before export
+#[derive(Clone)]
+pub struct FieldFilter {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+    hashed: Vec<String>,
+    salt: String,
+    // built from salt once instead of for every hashed field
+    hash_key: hmac::Key,
+}
+
+impl Default for FieldFilter {
+    fn default() -> Self {
+        Self {
+            allowed: vec![],
+            denied: vec![],
+            hashed: vec![],
+            salt: String::new(),
+            hash_key: hmac::Key::new(hmac::HMAC_SHA256, b""),
+        }
+    }
+}
+
+// hash_key is derived from salt
+impl PartialEq for FieldFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.allowed == other.allowed
+            && self.denied == other.denied
+            && self.hashed == other.hashed
+            && self.salt == other.salt
+    }
+}
+
+impl Eq for FieldFilter {}
+
+impl FieldFilter {
+    pub fn new(allowed: Vec<String>, denied: Vec<String>) -> Self {
+        Self {
+            allowed,
+            denied,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_hashed(mut self, hashed: Vec<String>, salt: String) -> Self {
+        self.hashed = hashed;
+        self.hash_key = hmac::Key::new(hmac::HMAC_SHA256, salt.as_bytes());
+        self.salt = salt;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allowed.is_empty() && self.denied.is_empty() && self.hashed.is_empty()
+    }
+
+    pub fn hashed(&self, field: &str) -> bool {
+        self.hashed.iter().any(|f| f == field)
+    }
+
+    pub fn hash_key(&self) -> &hmac::Key {
+        &self.hash_key
+    }
+
+    // A field is excluded if it is denied, or if it is not allowed when allowed is not empty
+    pub fn excluded(&self, field: &str) -> bool {
+        self.denied.iter().any(|f| f == field)
+            || (!self.allowed.is_empty() && !self.allowed.iter().any(|f| f == field))
+    }
+}
+
+// salt is not printed
+impl Debug for FieldFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FieldFilter")
+            .field("allowed", &self.allowed)
+            .field("denied", &self.denied)
+            .field("hashed", &self.hashed)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, Clone, Copy, IntoPrimitive)]
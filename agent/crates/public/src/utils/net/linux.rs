@@ -685,6 +685,17 @@ pub fn rule_list() -> Result<Vec<Rule>> {
     Ok(rules)
 }
 
+// Returns None if the link is not found in /sys/class/net of the current mount namespace
+pub fn link_mtu<S: AsRef<str>>(name: S) -> Option<u32> {
+    let mut path = PathBuf::from("/sys/class/net");
+    path.push(name.as_ref());
+    path.push("mtu");
+    match fs::read_to_string(&path) {
+        Ok(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
 fn read_u8_from_file<P: AsRef<Path>>(path: P) -> Option<u8> {
     match fs::read_to_string(path.as_ref()) {
         Ok(value) => value.trim().parse().ok(),
@@ -33,7 +33,11 @@ fn main() {
         .unwrap();
     let exc = ExceptionHandler::default();
 
-    let stats_collector = Arc::new(stats::Collector::new("localhost", Default::default()));
+    let stats_collector = Arc::new(stats::Collector::new(
+        "localhost",
+        vec![],
+        Default::default(),
+    ));
     let session = Arc::new(Session::new(
         20033,
         0,
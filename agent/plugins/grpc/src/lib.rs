@@ -15,13 +15,269 @@
  */
 
 use std::io;
-use std::net::ToSocketAddrs;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
 
-use tonic::transport::{Channel, Endpoint};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
 
-use public::consts::{GRPC_DEFAULT_TIMEOUT, GRPC_SESSION_TIMEOUT};
+use public::consts::GRPC_DEFAULT_TIMEOUT;
 
-pub async fn dial(remote: &str, remote_port: u16, _: String) -> Result<Channel, String> {
+const HTTP_PROXY_DEFAULT_PORT: u16 = 80;
+const SOCKS5_PROXY_DEFAULT_PORT: u16 = 1080;
+const HTTP_PROXY_MAX_RESPONSE_HEADER: usize = 8192;
+
+// How the controller certificate is verified when tls is enabled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TlsVerify {
+    // verify certificate chain and controller hostname
+    #[default]
+    Full,
+    // verify certificate chain only, allows certificates not issued for the controller address
+    CaOnly,
+    // do not verify the certificate at all, for labs with self-signed certificates only
+    InsecureSkipVerify,
+}
+
+impl TlsVerify {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "full" => Ok(Self::Full),
+            "ca-only" => Ok(Self::CaOnly),
+            "insecure-skip-verify" => Ok(Self::InsecureSkipVerify),
+            _ => Err(format!(
+                "invalid tls verify mode {}, choose from full|ca-only|insecure-skip-verify",
+                s
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::CaOnly => "ca-only",
+            Self::InsecureSkipVerify => "insecure-skip-verify",
+        }
+    }
+
+    // Connections are not wrapped with tls by this plugin, relaxed verification can not be
+    // applied and is refused instead of being silently ignored
+    pub fn supported(&self) -> bool {
+        *self == Self::Full
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ProxyScheme {
+    Http,
+    Socks5,
+}
+
+// Proxy used to tunnel the grpc connection, only proxies without authentication are supported
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proxy {
+    scheme: ProxyScheme,
+    host: String,
+    port: u16,
+    // hosts connected directly, in lower case without brackets
+    no_proxy: Vec<String>,
+}
+
+impl Proxy {
+    // Parses `http://host:port` or `socks5://host:port`, scheme defaults to http if omitted
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim().trim_end_matches('/');
+        let (scheme, address) = match s.split_once("://") {
+            Some(("http", address)) => (ProxyScheme::Http, address),
+            Some(("socks5" | "socks5h", address)) => (ProxyScheme::Socks5, address),
+            Some((scheme, _)) => return Err(format!("unsupported proxy scheme {}", scheme)),
+            None => (ProxyScheme::Http, s),
+        };
+        let default_port = match scheme {
+            ProxyScheme::Http => HTTP_PROXY_DEFAULT_PORT,
+            ProxyScheme::Socks5 => SOCKS5_PROXY_DEFAULT_PORT,
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            // colons in ipv6 address without port
+            Some((host, _)) if host.starts_with('[') && !host.ends_with(']') => {
+                (address, default_port)
+            }
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .map_err(|_| format!("invalid proxy port in {}", s))?,
+            ),
+            None => (address, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("invalid proxy address {}", s));
+        }
+        Ok(Self {
+            scheme,
+            host: host.to_owned(),
+            port,
+            no_proxy: vec![],
+        })
+    }
+
+    // Sets hosts to bypass the proxy in NO_PROXY format, a comma separated list of `*`,
+    // addresses, or domain names matching themselves and their subdomains
+    pub fn with_no_proxy(mut self, no_proxy: &str) -> Self {
+        self.no_proxy = no_proxy
+            .split(',')
+            .map(|e| {
+                e.trim()
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .to_ascii_lowercase()
+            })
+            .filter(|e| !e.is_empty())
+            .collect();
+        self
+    }
+
+    pub fn bypass(&self, host: &str) -> bool {
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+        let host_ip = host.parse::<IpAddr>().ok();
+        self.no_proxy.iter().any(|e| {
+            if e == "*" {
+                return true;
+            }
+            if let Ok(ip) = e.parse::<IpAddr>() {
+                return host_ip == Some(ip);
+            }
+            let domain = e.trim_start_matches('*').trim_start_matches('.');
+            host_ip.is_none()
+                && (host == domain
+                    || host
+                        .strip_suffix(domain)
+                        .map_or(false, |h| h.ends_with('.')))
+        })
+    }
+
+    async fn connect(&self, target: SocketAddr) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        match self.scheme {
+            ProxyScheme::Http => Self::http_connect(&mut stream, target).await?,
+            ProxyScheme::Socks5 => Self::socks5_connect(&mut stream, target).await?,
+        }
+        Ok(stream)
+    }
+
+    async fn http_connect(stream: &mut TcpStream, target: SocketAddr) -> io::Result<()> {
+        let request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target);
+        stream.write_all(request.as_bytes()).await?;
+
+        // read byte by byte to avoid consuming data after the response header
+        let mut response = Vec::with_capacity(128);
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= HTTP_PROXY_MAX_RESPONSE_HEADER {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "http proxy response header too large",
+                ));
+            }
+            if stream.read(&mut byte).await? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            response.push(byte[0]);
+        }
+        // status line is in the format of `HTTP/1.1 200 Connection established`
+        let status_line = String::from_utf8_lossy(&response);
+        let status_line = status_line.lines().next().unwrap_or_default();
+        if status_line.split_whitespace().nth(1) != Some("200") {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("http proxy responded with {}", status_line),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn socks5_connect(stream: &mut TcpStream, target: SocketAddr) -> io::Result<()> {
+        // version 5, 1 method, no authentication
+        stream.write_all(&[5, 1, 0]).await?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply != [5, 0] {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "socks5 proxy requires unsupported authentication",
+            ));
+        }
+
+        // version 5, command connect, reserved
+        let mut request = vec![5, 1, 0];
+        match target {
+            SocketAddr::V4(addr) => {
+                request.push(1);
+                request.extend_from_slice(&addr.ip().octets());
+            }
+            SocketAddr::V6(addr) => {
+                request.push(4);
+                request.extend_from_slice(&addr.ip().octets());
+            }
+        }
+        request.extend_from_slice(&target.port().to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("socks5 proxy connect failed with reply {}", reply[1]),
+            ));
+        }
+        // skip bound address and port
+        let addr_len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len).await?;
+                len[0] as usize
+            }
+            t => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("socks5 proxy replied invalid address type {}", t),
+                ))
+            }
+        };
+        let mut bound = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut bound).await?;
+        Ok(())
+    }
+}
+
+pub async fn dial(
+    remote: &str,
+    remote_port: u16,
+    cert_file_prefix: String,
+    tls_verify: TlsVerify,
+    proxy: Option<&Proxy>,
+    request_timeout: Duration,
+) -> Result<Channel, String> {
+    if !cert_file_prefix.is_empty() && !tls_verify.supported() {
+        return Err(format!(
+            "Dial server({} {}) failed: tls verify mode {} is not supported",
+            remote,
+            remote_port,
+            tls_verify.as_str()
+        ));
+    }
     let socket_address = match (remote, remote_port)
         .to_socket_addrs()
         .and_then(|mut iter| {
@@ -46,19 +302,114 @@ pub async fn dial(remote: &str, remote_port: u16, _: String) -> Result<Channel,
             ));
         }
     };
-
-    match endpoint
+    let endpoint = endpoint
         .connect_timeout(GRPC_DEFAULT_TIMEOUT)
-        .timeout(GRPC_SESSION_TIMEOUT)
-        .connect()
+        .timeout(request_timeout);
+
+    let Some(proxy) = proxy.filter(|p| !p.bypass(remote)).cloned() else {
+        return endpoint
+            .connect()
+            .await
+            .map_err(|e| format!("Dial server({} {}) failed: {}", remote, remote_port, e));
+    };
+    let proxy_address = format!("{}:{}", proxy.host, proxy.port);
+    // connect_timeout does not apply to custom connectors
+    let connector = service_fn(move |_: Uri| {
+        let proxy = proxy.clone();
+        async move {
+            match time::timeout(GRPC_DEFAULT_TIMEOUT, proxy.connect(socket_address)).await {
+                Ok(r) => r,
+                Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut)),
+            }
+        }
+    });
+    endpoint
+        .connect_with_connector(connector)
         .await
-    {
-        Ok(channel) => return Ok(channel),
-        Err(e) => {
-            return Err(format!(
-                "Dial server({} {}) failed: {}",
-                remote, remote_port, e
-            ));
+        .map_err(|e| {
+            format!(
+                "Dial server({} {}) through proxy {} failed: {}",
+                remote, remote_port, proxy_address, e
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proxy() {
+        let cases = [
+            ("http://10.1.1.1:3128", ProxyScheme::Http, "10.1.1.1", 3128),
+            ("proxy.local:8080/", ProxyScheme::Http, "proxy.local", 8080),
+            ("http://proxy.local", ProxyScheme::Http, "proxy.local", 80),
+            ("socks5://[::1]:1081", ProxyScheme::Socks5, "::1", 1081),
+            ("socks5h://[fe80::1]", ProxyScheme::Socks5, "fe80::1", 1080),
+        ];
+        for (input, scheme, host, port) in cases {
+            assert_eq!(
+                Proxy::parse(input),
+                Ok(Proxy {
+                    scheme,
+                    host: host.to_owned(),
+                    port,
+                    no_proxy: vec![],
+                }),
+                "parse {}",
+                input
+            );
         }
+        assert!(Proxy::parse("ftp://proxy.local").is_err());
+        assert!(Proxy::parse("http://proxy.local:port").is_err());
+        assert!(Proxy::parse("http://:3128").is_err());
+    }
+
+    #[test]
+    fn tls_verify_mode() {
+        for mode in [
+            TlsVerify::Full,
+            TlsVerify::CaOnly,
+            TlsVerify::InsecureSkipVerify,
+        ] {
+            assert_eq!(TlsVerify::parse(mode.as_str()), Ok(mode));
+        }
+        assert!(TlsVerify::parse("none").is_err());
+        assert!(TlsVerify::default().supported());
+        assert!(!TlsVerify::CaOnly.supported());
+        assert!(!TlsVerify::InsecureSkipVerify.supported());
+    }
+
+    #[test]
+    fn bypass_proxy() {
+        let proxy = Proxy::parse("http://10.1.1.1:3128").unwrap().with_no_proxy(
+            " Controller.local, .svc.cluster.local,10.2.2.2,[fd00::1],,*.example.com",
+        );
+        for host in [
+            "controller.local",
+            "CONTROLLER.local.",
+            "deepflow.svc.cluster.local",
+            "10.2.2.2",
+            "fd00::1",
+            "[fd00::1]",
+            "example.com",
+            "a.b.example.com",
+        ] {
+            assert!(proxy.bypass(host), "bypass {}", host);
+        }
+        for host in [
+            "controller.local.net",
+            "mycontroller.local",
+            "cluster.local",
+            "10.2.2.20",
+            "fd00::2",
+            "notexample.com",
+        ] {
+            assert!(!proxy.bypass(host), "proxy {}", host);
+        }
+
+        let proxy = Proxy::parse("http://10.1.1.1:3128").unwrap();
+        assert!(!proxy.bypass("controller.local"));
+        assert!(proxy.with_no_proxy("*").bypass("controller.local"));
     }
 }
@@ -24,7 +24,19 @@ impl PacketDedupMap {
         PacketDedupMap
     }
 
+    // Packets within `window` are compared for duplication, and at most `table_size` packets
+    // are kept in the dedup table. Zero values use the built-in defaults.
+    pub fn with_options(_window: Duration, _table_size: usize) -> Self {
+        PacketDedupMap
+    }
+
     pub fn duplicate(&mut self, _: &mut [u8], _: Duration) -> bool {
         false
     }
+
+    // Returns the number of packets evicted from dedup table before their window expires
+    // since last call
+    pub fn take_evictions(&mut self) -> u64 {
+        0
+    }
 }
@@ -45,6 +45,11 @@ impl PacketSequenceBlock {
     pub fn reverse_needed_for_new_packet(&mut self) {
         unimplemented!();
     }
+
+    // encoded size in bytes
+    pub fn size(&self) -> usize {
+        unimplemented!();
+    }
 }
 
 #[derive(Debug)]
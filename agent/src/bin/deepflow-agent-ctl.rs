@@ -29,12 +29,15 @@ use clap::{ArgEnum, Parser, Subcommand};
 use flate2::write::ZlibDecoder;
 
 use deepflow_agent::debug::{
-    Beacon, Client, Message, Module, PolicyMessage, RpcMessage, DEBUG_QUEUE_IDLE_TIMEOUT,
-    DEEPFLOW_AGENT_BEACON,
+    Beacon, Client, DispatcherMessage, Message, Module, PolicyMessage, RpcMessage, SenderMessage,
+    DEBUG_QUEUE_IDLE_TIMEOUT, DEEPFLOW_AGENT_BEACON,
 };
 #[cfg(target_os = "linux")]
 use deepflow_agent::debug::{EbpfMessage, PlatformMessage};
-use public::{consts::DEFAULT_CONTROLLER_PORT, debug::QueueMessage};
+use public::{
+    consts::DEFAULT_CONTROLLER_PORT,
+    debug::{QueueFilter, QueueMessage},
+};
 
 const ERR_PORT_MSG: &str = "error: The following required arguments were not provided:
     \t--port <PORT> required arguments were not provided";
@@ -52,6 +55,9 @@ struct Cmd {
     /// ipv6 format is 'fe80::5054:ff:fe95:c839', ipv4 format is '127.0.0.1'
     #[clap(short, long, parse(try_from_str), default_value_t=IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))]
     address: IpAddr,
+    /// token for remote deepflow-agent in token debug access mode
+    #[clap(long)]
+    token: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -68,6 +74,10 @@ enum ControllerCmd {
     #[cfg(target_os = "linux")]
     /// get information about the ebpf
     Ebpf(EbpfCmd),
+    /// get information about the dispatchers
+    Dispatcher(DispatcherCmd),
+    /// operate the senders
+    Sender(SenderCmd),
     /// get information about the deepflow-agent
     List,
 }
@@ -84,6 +94,18 @@ struct QueueCmd {
     /// monitoring duration in seconds
     #[clap(long, group = "monitor")]
     duration: Option<u64>,
+    /// stop monitoring after receiving this number of items
+    ///
+    /// eg: deepflow-agent-ctl queue --on 1-tagged-flow-to-quadruple-generator --duration 60 --limit 100
+    #[clap(long, requires = "on")]
+    limit: Option<u32>,
+    /// only receive items containing the keyword, can be repeated to match all of them
+    ///
+    /// eg: monitor flows of 10.1.1.1 port 80
+    ///
+    /// deepflow-agent-ctl queue --on 1-tagged-flow-to-quadruple-generator --duration 60 --filter 10.1.1.1 --filter "port_dst: 80"
+    #[clap(long, requires = "on")]
+    filter: Vec<String>,
     /// turn off monitor
     ///
     /// eg: turn off 1-tagged-flow-to-quadruple-generator queue monitor
@@ -140,6 +162,34 @@ struct AnalyzingArgs {
     id: Option<u32>,
 }
 
+#[derive(Debug, Parser)]
+struct DispatcherCmd {
+    #[clap(subcommand)]
+    subcmd: DispatcherSubCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum DispatcherSubCmd {
+    /// show the packet timestamp source in use by each dispatcher
+    ///
+    /// eg: deepflow-agent-ctl dispatcher timestamp
+    Timestamp,
+}
+
+#[derive(Debug, Parser)]
+struct SenderCmd {
+    #[clap(subcommand)]
+    subcmd: SenderSubCmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum SenderSubCmd {
+    /// flush data cached by all senders to the ingester immediately, e.g. before a planned restart
+    ///
+    /// eg: deepflow-agent-ctl sender flush
+    Flush,
+}
+
 #[cfg(target_os = "linux")]
 #[derive(Debug, Parser)]
 struct EbpfCmd {
@@ -276,6 +326,7 @@ struct Controller {
     cmd: Option<Cmd>,
     addr: IpAddr,
     port: Option<u16>,
+    token: Option<String>,
 }
 
 impl Controller {
@@ -284,6 +335,7 @@ impl Controller {
         Self {
             addr: cmd.address,
             port: cmd.port,
+            token: cmd.token.clone(),
             cmd: Some(cmd),
         }
     }
@@ -298,6 +350,8 @@ impl Controller {
             ControllerCmd::Policy(c) => self.policy(c),
             #[cfg(target_os = "linux")]
             ControllerCmd::Ebpf(c) => self.ebpf(c),
+            ControllerCmd::Dispatcher(c) => self.dispatcher(c),
+            ControllerCmd::Sender(c) => self.sender(c),
         }
     }
 
@@ -313,13 +367,16 @@ impl Controller {
             }
         };
 
-        let client = Client::new(
+        let mut client = Client::new(
             (
                 addr,
                 self.port.expect("need input a port to connect debugger"),
             )
                 .into(),
         )?;
+        if let Some(token) = self.token.as_ref() {
+            client.auth(token.clone())?;
+        }
         Ok(client)
     }
 
@@ -526,7 +583,14 @@ impl Controller {
 
             let msg = Message {
                 module: Module::Queue,
-                msg: QueueMessage::On((s, dur)),
+                msg: QueueMessage::On((
+                    s,
+                    dur,
+                    QueueFilter {
+                        max_items: c.limit.unwrap_or_default(),
+                        keywords: c.filter,
+                    },
+                )),
             };
             client.send_to(msg)?;
 
@@ -781,6 +845,68 @@ impl Controller {
             }
         }
     }
+
+    fn dispatcher(&self, c: DispatcherCmd) -> Result<()> {
+        if self.port.is_none() {
+            return Err(anyhow!(ERR_PORT_MSG));
+        }
+
+        let mut client = self.new_client()?;
+        match c.subcmd {
+            DispatcherSubCmd::Timestamp => {
+                client.send_to(Message {
+                    module: Module::Dispatcher,
+                    msg: DispatcherMessage::TimestampSource,
+                })?;
+            }
+        }
+
+        loop {
+            let Ok(res) = client.recv::<DispatcherMessage>() else {
+                continue;
+            };
+            match res {
+                DispatcherMessage::Context(c) => println!("{}", c),
+                DispatcherMessage::Done => return Ok(()),
+                DispatcherMessage::Err(e) => {
+                    println!("{}", e);
+                    return Ok(());
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn sender(&self, c: SenderCmd) -> Result<()> {
+        if self.port.is_none() {
+            return Err(anyhow!(ERR_PORT_MSG));
+        }
+
+        let mut client = self.new_client()?;
+        match c.subcmd {
+            SenderSubCmd::Flush => {
+                client.send_to(Message {
+                    module: Module::Sender,
+                    msg: SenderMessage::Flush,
+                })?;
+            }
+        }
+
+        loop {
+            let Ok(res) = client.recv::<SenderMessage>() else {
+                continue;
+            };
+            match res {
+                SenderMessage::Context(c) => println!("{}", c),
+                SenderMessage::Done => return Ok(()),
+                SenderMessage::Err(e) => {
+                    println!("{}", e);
+                    return Ok(());
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
 }
 
 fn main() {
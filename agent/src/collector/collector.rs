@@ -22,7 +22,6 @@ use std::{
         atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
         Arc, Mutex, Weak,
     },
-    thread,
     thread::JoinHandle,
     time::Duration,
 };
@@ -48,8 +47,9 @@ use crate::{
     },
     rpc::get_timestamp,
     trident::RunningMode,
-    utils::stats::{
-        self, Countable, Counter, CounterType, CounterValue, RefCountable, StatsOption,
+    utils::{
+        stats::{self, Countable, Counter, CounterType, CounterValue, RefCountable, StatsOption},
+        thread_builder,
     },
 };
 use public::{
@@ -66,10 +66,12 @@ pub struct CollectorCounter {
     out: AtomicU64,
     drop_before_window: AtomicU64,
     drop_inactive: AtomicU64,
+    drop_excluded_tap_type: AtomicU64,
     no_endpoint: AtomicU64,
     stash_len: AtomicU64,
     stash_capacity: AtomicU64,
     stash_shrinks: AtomicU64,
+    stash_evictions: AtomicU64,
     running: Arc<AtomicBool>,
 }
 
@@ -101,6 +103,11 @@ impl RefCountable for CollectorCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.drop_inactive.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "drop-excluded-tap-type",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.drop_excluded_tap_type.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "no-endpoint",
                 CounterType::Counted,
@@ -121,11 +128,16 @@ impl RefCountable for CollectorCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.stash_shrinks.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "stash-evictions",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.stash_evictions.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
 
-#[derive(Hash, PartialEq, Eq)]
+#[derive(Clone, Hash, PartialEq, Eq)]
 struct StashKey {
     fast_id: u128,
     src_ip: IpAddr,
@@ -331,6 +343,8 @@ struct Stash {
     inner: HashMap<StashKey, Document>,
     history_length: VecDeque<usize>,
     stash_init_capacity: usize,
+    // 0 means unlimited
+    stash_max_len: usize,
     global_thread_id: u8,
     doc_flag: DocumentFlag,
     context: Context,
@@ -339,7 +353,6 @@ struct Stash {
 impl Stash {
     // record stash size in last N flushes to determine shrinking size
     const HISTORY_RECORD_COUNT: usize = 10;
-    const MIN_STASH_CAPACITY: usize = 1024;
 
     fn new(
         ctx: Context,
@@ -355,8 +368,9 @@ impl Stash {
             get_timestamp(ctx.ntp_diff.load(Ordering::Relaxed)).as_secs() / MINUTE * MINUTE
                 - 2 * MINUTE,
         );
-        let inner = HashMap::with_capacity(Self::MIN_STASH_CAPACITY);
+        let inner = HashMap::with_capacity(ctx.stash_capacity);
         let stash_init_capacity = inner.capacity();
+        let stash_max_len = ctx.stash_max_len;
         Self {
             sender,
             closed_docs: Vec::with_capacity(QUEUE_BATCH_SIZE),
@@ -367,6 +381,7 @@ impl Stash {
             inner,
             history_length: [0; Self::HISTORY_RECORD_COUNT].into(),
             stash_init_capacity,
+            stash_max_len,
             doc_flag,
             context: ctx,
         }
@@ -431,6 +446,9 @@ impl Stash {
             None => return,
         };
         let flow = &acc_flow.flow;
+        if self.tap_type_excluded(flow, config) {
+            return;
+        }
 
         // PCAP and Distribution Policy Statistics
         if self.context.metric_type == MetricsType::MINUTE
@@ -614,6 +632,9 @@ impl Stash {
             if m.flow.close_type != CloseType::Unknown
                 && m.flow.close_type != CloseType::ForcedReport
             {
+                if self.tap_type_excluded(&m.flow, config) {
+                    return;
+                }
                 if !m.is_active_host0 && !m.is_active_host1 && !config.inactive_ip_enabled {
                     self.counter.drop_inactive.fetch_add(1, Ordering::Relaxed);
                     return;
@@ -668,6 +689,9 @@ impl Stash {
             Some(m) => m,
             None => return,
         };
+        if self.tap_type_excluded(&meter.flow, config) {
+            return;
+        }
 
         if !meter.is_active_host0 && !meter.is_active_host1 && !config.inactive_ip_enabled {
             self.counter.drop_inactive.fetch_add(1, Ordering::Relaxed);
@@ -809,6 +833,16 @@ impl Stash {
         }
     }
 
+    fn tap_type_excluded(&self, flow: &MiniFlow, config: &CollectorConfig) -> bool {
+        if config.metrics_excluded_tap_types[u16::from(flow.flow_key.tap_type) as usize] {
+            self.counter
+                .drop_excluded_tap_type
+                .fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
     fn push_closed_doc(&mut self, closed_doc: BoxedDocument) {
         self.closed_docs.push(closed_doc);
         if self.closed_docs.len() >= QUEUE_BATCH_SIZE {
@@ -838,20 +872,42 @@ impl Stash {
                 }
             }
         } else {
+            let full = self.stash_max_len > 0 && self.inner.len() >= self.stash_max_len;
             match self.inner.entry(key) {
                 Entry::Occupied(mut o) => {
                     let doc = o.get_mut();
                     doc.meter.sequential_merge(&meter);
                 }
-                Entry::Vacant(o) => {
+                Entry::Vacant(o) if !full => {
                     let mut doc = Document::new(meter);
                     doc.tagger = tagger;
                     o.insert(doc);
                 }
+                Entry::Vacant(o) => {
+                    let key = o.into_key();
+                    self.evict_one();
+                    let mut doc = Document::new(meter);
+                    doc.tagger = tagger;
+                    self.inner.insert(key, doc);
+                }
             }
         }
     }
 
+    // send an arbitrary stashed document before its slot ends to make room for a new key,
+    // the rest of its meter in this slot will be sent as another document with the same timestamp
+    fn evict_one(&mut self) {
+        let Some(key) = self.inner.keys().next().cloned() else {
+            return;
+        };
+        if let Some(mut doc) = self.inner.remove(&key) {
+            self.counter.stash_evictions.fetch_add(1, Ordering::Relaxed);
+            doc.timestamp = self.start_time.as_secs() as u32;
+            doc.flags |= self.doc_flag;
+            self.push_closed_doc(BoxedDocument(Box::new(doc)));
+        }
+    }
+
     fn flush_stats(&mut self) {
         self.history_length.rotate_right(1);
         self.history_length[0] = self.inner.len();
@@ -1160,6 +1216,18 @@ struct Context {
     metric_type: MetricsType,
     ntp_diff: Arc<AtomicI64>,
     agent_mode: RunningMode,
+    stash_capacity: usize,
+    stash_max_len: usize,
+}
+
+impl Context {
+    // thread names are truncated to 15 bytes on linux, keep them short
+    fn thread_name(&self, prefix: &str) -> String {
+        match self.metric_type {
+            MetricsType::MINUTE => format!("{}-1m-{}", prefix, self.id),
+            _ => format!("{}-1s-{}", prefix, self.id),
+        }
+    }
 }
 
 pub struct Collector {
@@ -1183,6 +1251,8 @@ impl Collector {
         config: CollectorAccess,
         ntp_diff: Arc<AtomicI64>,
         agent_mode: RunningMode,
+        stash_capacity: usize,
+        stash_max_len: usize,
     ) -> Self {
         let (kind, name) = match metric_type {
             MetricsType::MINUTE => {
@@ -1223,6 +1293,8 @@ impl Collector {
                 metric_type,
                 ntp_diff,
                 agent_mode,
+                stash_capacity,
+                stash_max_len,
             },
         }
     }
@@ -1238,8 +1310,8 @@ impl Collector {
         let sender = self.sender.clone();
         let ctx = self.context.clone();
         let config = self.config.clone();
-        let thread = thread::Builder::new()
-            .name("collector".to_owned())
+        let name = ctx.thread_name("collector");
+        let thread = thread_builder(name, config.load().thread_stack_size)
             .spawn(move || {
                 let mut stash = Stash::new(ctx, sender, counter);
                 let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
@@ -1324,6 +1396,8 @@ impl L7Collector {
         config: CollectorAccess,
         ntp_diff: Arc<AtomicI64>,
         agent_mode: RunningMode,
+        stash_capacity: usize,
+        stash_max_len: usize,
     ) -> Self {
         let (kind, name) = match metric_type {
             MetricsType::MINUTE => {
@@ -1364,6 +1438,8 @@ impl L7Collector {
                 metric_type,
                 ntp_diff,
                 agent_mode,
+                stash_capacity,
+                stash_max_len,
             },
         }
     }
@@ -1379,8 +1455,8 @@ impl L7Collector {
         let sender = self.sender.clone();
         let ctx = self.context.clone();
         let config = self.config.clone();
-        let thread = thread::Builder::new()
-            .name("l7_collector".to_owned())
+        let name = ctx.thread_name("l7-collect");
+        let thread = thread_builder(name, config.load().thread_stack_size)
             .spawn(move || {
                 let mut stash = Stash::new(ctx, sender, counter);
                 let mut l7_batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
@@ -15,17 +15,20 @@
  */
 
 use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
     Arc,
 };
-use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
-use thread::JoinHandle;
 
 use arc_swap::access::Access;
 use log::{debug, info, warn};
 use npb_pcap_policy::NpbTunnelType;
+use prost::Message;
 use rand::prelude::{Rng, SeedableRng, SmallRng};
 
 use super::consts::*;
@@ -34,14 +37,18 @@ use crate::collector::types::U16Set;
 use crate::common::Timestamp;
 use crate::common::{
     enums::TapType,
-    flow::CloseType,
+    flow::{CloseType, Flow},
     tagged_flow::{BoxedTaggedFlow, TaggedFlow},
 };
 use crate::config::handler::CollectorAccess;
 use crate::rpc::get_timestamp;
-use crate::utils::stats::{Counter, CounterType, CounterValue, RefCountable};
+use crate::utils::{
+    stats::{Counter, CounterType, CounterValue, RefCountable},
+    thread_builder,
+};
 use public::{
     buffer::BatchedBox,
+    proto::flow_log,
     queue::{DebugSender, Error, Receiver},
 };
 
@@ -49,9 +56,16 @@ const TIMESTAMP_SLOT_COUNT: usize = SECONDS_IN_MINUTE as usize;
 const QUEUE_READ_TIMEOUT: Duration = Duration::from_secs(1); // Must be less than or equal to FLUSH_TIMEOUT
 const FLUSH_TIMEOUT: Duration = Duration::from_secs(60);
 
+const CHECKPOINT_MAGIC: &[u8; 4] = b"DFAC";
+const CHECKPOINT_VERSION: u32 = 1;
+
 #[derive(Debug, Default)]
 pub struct FlowAggrCounter {
     drop_before_window: AtomicU64,
+    aggregated: AtomicU64,
+    window_flushed: AtomicU64,
+    window_flushed_ahead: AtomicU64, // flushed before timeout by flows beyond the window
+    window_max_flows: AtomicU64,
     out: AtomicU64,
     drop_in_throttle: AtomicU64,
     stash_total_len: AtomicU64,
@@ -108,6 +122,7 @@ impl FlowAggrThread {
         }
 
         let mut flow_aggr = FlowAggr::new(
+            self.id,
             self.input.clone(),
             self.output.clone(),
             self.running.clone(),
@@ -117,10 +132,12 @@ impl FlowAggrThread {
             self.metrics.clone(),
         );
         self.thread_handle = Some(
-            thread::Builder::new()
-                .name("flow-aggr".to_owned())
-                .spawn(move || flow_aggr.run())
-                .unwrap(),
+            thread_builder(
+                format!("flow-aggr-{}", self.id),
+                self.config.load().thread_stack_size,
+            )
+            .spawn(move || flow_aggr.run())
+            .unwrap(),
         );
         info!("l4 flow aggr id: {} started", self.id);
     }
@@ -146,6 +163,7 @@ impl FlowAggrThread {
 }
 
 pub struct FlowAggr {
+    id: usize,
     input: Arc<Receiver<Arc<BatchedBox<TaggedFlow>>>>,
     output: ThrottlingQueue,
     slot_start_time: Duration,
@@ -155,6 +173,7 @@ pub struct FlowAggr {
 
     flush_timeout: Duration,
     last_flush_time: Duration,
+    last_checkpoint_time: Duration,
     config: CollectorAccess,
 
     running: Arc<AtomicBool>,
@@ -169,6 +188,7 @@ impl FlowAggr {
     const MIN_STASH_CAPACITY_SECOND: usize = 1024;
 
     pub fn new(
+        id: usize,
         input: Arc<Receiver<Arc<BatchedBox<TaggedFlow>>>>,
         output: DebugSender<BoxedTaggedFlow>,
         running: Arc<AtomicBool>,
@@ -183,6 +203,7 @@ impl FlowAggr {
             flow_stashs.push_back(HashMap::with_capacity(Self::MIN_STASH_CAPACITY_SECOND));
         }
         Self {
+            id,
             input,
             output: ThrottlingQueue::new(output, config.clone()),
             flow_stashs,
@@ -190,6 +211,7 @@ impl FlowAggr {
             slot_start_time: Duration::ZERO,
             flush_timeout: Duration::from_secs(slot_count as u64),
             last_flush_time: Duration::ZERO,
+            last_checkpoint_time: Duration::ZERO,
             config,
             running,
             metrics,
@@ -198,8 +220,7 @@ impl FlowAggr {
         }
     }
 
-    fn minute_merge(&mut self, f: Arc<BatchedBox<TaggedFlow>>) {
-        let f = f.as_ref();
+    fn minute_merge(&mut self, f: &TaggedFlow) {
         let flow_time = Timestamp::from_secs(f.flow.start_time_in_minute());
         if flow_time < self.slot_start_time {
             debug!("flow drop before slot start time. flow stat time: {:?}, slot start time is {:?}, delay is {:?}", flow_time, self.slot_start_time, self.slot_start_time - flow_time);
@@ -212,6 +233,9 @@ impl FlowAggr {
         let mut time_slot = (flow_time - self.slot_start_time).as_secs() as usize;
         if time_slot >= self.slot_count {
             let flush_count = time_slot - self.slot_count + 1;
+            self.metrics
+                .window_flushed_ahead
+                .fetch_add(flush_count.min(self.slot_count) as u64, Ordering::Relaxed);
             self.flush_slots(flush_count);
             time_slot = self.slot_count - 1;
         }
@@ -225,7 +249,8 @@ impl FlowAggr {
                     stats.reverse();
                 }
             }
-            flow.sequential_merge(&f);
+            flow.sequential_merge(f);
+            self.metrics.aggregated.fetch_add(1, Ordering::Relaxed);
             if flow.flow.close_type != CloseType::ForcedReport {
                 if let Some(closed_flow) = flow_stash.remove(&flow_id) {
                     self.send_flow(closed_flow);
@@ -233,34 +258,18 @@ impl FlowAggr {
             }
         } else {
             if f.flow.close_type != CloseType::ForcedReport {
-                self.send_flow(Box::new(f.as_ref().clone()));
+                self.send_flow(Box::new(f.clone()));
             } else {
-                flow_stash.insert(f.flow.flow_id, Box::new(f.as_ref().clone()));
+                flow_stash.insert(f.flow.flow_id, Box::new(f.clone()));
             }
         }
     }
 
     fn send_flow(&mut self, mut f: Box<TaggedFlow>) {
-        // We use acl_gid to mark which flows are configured with PCAP storage policies.
-        // Since acl_gid is used for both PCAP and NPB functions, only the acl_gid used by PCAP is sent here.
-        let mut acl_gids = U16Set::new();
-        for policy_data in f.tag.policy_data.iter() {
-            let Some(policy_data) = policy_data else {
-                continue;
-            };
-            if !policy_data.contain_pcap() {
-                continue;
-            }
-            for action in policy_data.npb_actions.iter() {
-                if action.tunnel_type() != NpbTunnelType::Pcap {
-                    continue;
-                }
-                for gid in action.acl_gids().iter() {
-                    acl_gids.add(*gid);
-                }
-            }
+        // flows reloaded from checkpoint have no tag, and keep the acl_gids computed on checkpoint
+        if f.flow.acl_gids.is_empty() {
+            f.flow.acl_gids = pcap_acl_gids(&f);
         }
-        f.flow.acl_gids = Vec::from(acl_gids.list());
 
         if !f.flow.is_new_flow {
             f.flow.start_time = Timestamp::from_secs(f.flow.start_time_in_minute());
@@ -290,6 +299,10 @@ impl FlowAggr {
     fn flush_front_slot_and_rotate(&mut self) {
         let mut flow_stash = self.flow_stashs.pop_front().unwrap();
 
+        self.metrics.window_flushed.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .window_max_flows
+            .fetch_max(flow_stash.len() as u64, Ordering::Relaxed);
         for (_, flow) in flow_stash.drain() {
             self.send_flow(flow);
         }
@@ -334,6 +347,7 @@ impl FlowAggr {
     }
 
     fn run(&mut self) {
+        self.load_checkpoint();
         let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
         while self.running.load(Ordering::Relaxed) {
             match self.input.recv_all(&mut batch, Some(QUEUE_READ_TIMEOUT)) {
@@ -347,7 +361,7 @@ impl FlowAggr {
                             || config.l4_log_store_tap_types
                                 [u16::from(tagged_flow.flow.flow_key.tap_type) as usize]
                         {
-                            self.minute_merge(tagged_flow);
+                            self.minute_merge(&tagged_flow);
                         }
                     }
                     self.calc_stash_counters();
@@ -365,8 +379,219 @@ impl FlowAggr {
                 }
                 Err(Error::BatchTooLarge(_)) => unreachable!(),
             }
+            self.checkpoint_periodically();
+        }
+        // keep stashed flows in checkpoint for the next start if enabled, otherwise send
+        // them out instead of dropping them
+        let stashed = self.flow_stashs.iter().map(|s| s.len()).sum::<usize>();
+        if self.config.load().l4_flow_checkpoint.enabled && self.checkpoint().is_ok() {
+            self.output.flush_all();
+            info!("l4 flow aggr saved {} stashed flows on stop", stashed);
+            return;
+        }
+        self.flush_slots(self.slot_count);
+        self.output.flush_all();
+        info!("l4 flow aggr flushed {} stashed flows on stop", stashed);
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        Path::new(&self.config.load().l4_flow_checkpoint.dir)
+            .join(format!("flow-aggr-{}.checkpoint", self.id))
+    }
+
+    fn checkpoint(&mut self) -> io::Result<()> {
+        let now = get_timestamp(self.ntp_diff.load(Ordering::Relaxed));
+        self.last_checkpoint_time = now;
+        let path = self.checkpoint_path();
+        let flows = self
+            .flow_stashs
+            .iter()
+            .flat_map(|s| s.values().map(|f| f.as_ref()));
+        match write_checkpoint(&path, now, flows) {
+            Ok(n) => {
+                debug!("l4 flow aggr saved {} flows to {}", n, path.display());
+                Ok(())
+            }
+            Err(e) => {
+                warn!(
+                    "l4 flow aggr save checkpoint {} failed: {}",
+                    path.display(),
+                    e
+                );
+                Err(e)
+            }
         }
     }
+
+    fn checkpoint_periodically(&mut self) {
+        let config = self.config.load();
+        if !config.l4_flow_checkpoint.enabled {
+            return;
+        }
+        let now = get_timestamp(self.ntp_diff.load(Ordering::Relaxed));
+        if now < self.last_checkpoint_time + config.l4_flow_checkpoint.interval {
+            return;
+        }
+        let _ = self.checkpoint();
+    }
+
+    // Reloads flows stashed by the last run, the checkpoint is removed once read so that
+    // flows are not reloaded twice
+    fn load_checkpoint(&mut self) {
+        let config = self.config.load();
+        if !config.l4_flow_checkpoint.enabled {
+            return;
+        }
+        let path = self.checkpoint_path();
+        if !path.exists() {
+            return;
+        }
+        let now = get_timestamp(self.ntp_diff.load(Ordering::Relaxed));
+        match read_checkpoint(&path, now, config.l4_flow_checkpoint.max_age) {
+            Ok(Some(flows)) => {
+                info!(
+                    "l4 flow aggr reloaded {} flows from {}",
+                    flows.len(),
+                    path.display()
+                );
+                for f in flows.iter() {
+                    self.minute_merge(f);
+                }
+            }
+            Ok(None) => info!(
+                "l4 flow aggr discarded checkpoint {} older than {:?}",
+                path.display(),
+                config.l4_flow_checkpoint.max_age
+            ),
+            Err(e) => warn!(
+                "l4 flow aggr load checkpoint {} failed: {}",
+                path.display(),
+                e
+            ),
+        }
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("remove checkpoint {} failed: {}", path.display(), e);
+        }
+        self.last_checkpoint_time = now;
+    }
+}
+
+// We use acl_gid to mark which flows are configured with PCAP storage policies.
+// Since acl_gid is used for both PCAP and NPB functions, only the acl_gid used by PCAP is sent here.
+fn pcap_acl_gids(f: &TaggedFlow) -> Vec<u16> {
+    let mut acl_gids = U16Set::new();
+    for policy_data in f.tag.policy_data.iter() {
+        let Some(policy_data) = policy_data else {
+            continue;
+        };
+        if !policy_data.contain_pcap() {
+            continue;
+        }
+        for action in policy_data.npb_actions.iter() {
+            if action.tunnel_type() != NpbTunnelType::Pcap {
+                continue;
+            }
+            for gid in action.acl_gids().iter() {
+                acl_gids.add(*gid);
+            }
+        }
+    }
+    Vec::from(acl_gids.list())
+}
+
+// Checkpoint file layout, integers in little endian:
+//   magic (4 bytes) | version (u32) | checkpoint time in nanoseconds (u64)
+// followed by records of stashed flows:
+//   flow_stat_time in nanoseconds (u64) | pod_id (u32) | length (u32) | flow_log::Flow
+// Flows are stored in their l4_flow_log encoding, which keeps everything sent out.
+fn write_checkpoint<'a>(
+    path: &Path,
+    now: Duration,
+    flows: impl Iterator<Item = &'a TaggedFlow>,
+) -> io::Result<usize> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    writer.write_all(CHECKPOINT_MAGIC)?;
+    writer.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+    writer.write_all(&(now.as_nanos() as u64).to_le_bytes())?;
+    let mut count = 0;
+    let mut buf = vec![];
+    for f in flows {
+        let mut flow = f.flow.clone();
+        if flow.acl_gids.is_empty() {
+            flow.acl_gids = pcap_acl_gids(f);
+        }
+        let (flow_stat_time, pod_id) = (flow.flow_stat_time, flow.pod_id);
+        buf.clear();
+        flow_log::Flow::from(flow)
+            .encode(&mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.write_all(&flow_stat_time.as_nanos().to_le_bytes())?;
+        writer.write_all(&pod_id.to_le_bytes())?;
+        writer.write_all(&(buf.len() as u32).to_le_bytes())?;
+        writer.write_all(&buf)?;
+        count += 1;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(count)
+}
+
+// Returns None if the checkpoint is older than max_age
+fn read_checkpoint(
+    path: &Path,
+    now: Duration,
+    max_age: Duration,
+) -> io::Result<Option<Vec<TaggedFlow>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != CHECKPOINT_MAGIC
+        || u32::from_le_bytes(header[4..8].try_into().unwrap()) != CHECKPOINT_VERSION
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unknown checkpoint format",
+        ));
+    }
+    let time = Duration::from_nanos(u64::from_le_bytes(header[8..].try_into().unwrap()));
+    if now.saturating_sub(time) > max_age {
+        return Ok(None);
+    }
+
+    let mut flows = vec![];
+    let mut record = [0u8; 16];
+    let mut buf = vec![];
+    loop {
+        match reader.read_exact(&mut record) {
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let flow_stat_time = u64::from_le_bytes(record[..8].try_into().unwrap());
+        let pod_id = u32::from_le_bytes(record[8..12].try_into().unwrap());
+        buf.resize(
+            u32::from_le_bytes(record[12..].try_into().unwrap()) as usize,
+            0,
+        );
+        reader.read_exact(&mut buf)?;
+        let pb_flow = flow_log::Flow::decode(buf.as_slice())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut flow = Flow::from(pb_flow);
+        flow.flow_stat_time = Timestamp::from_nanos(flow_stat_time);
+        flow.pod_id = pod_id;
+        flows.push(TaggedFlow {
+            flow,
+            ..Default::default()
+        });
+    }
+    Ok(Some(flows))
 }
 
 impl RefCountable for FlowAggrCounter {
@@ -377,6 +602,26 @@ impl RefCountable for FlowAggrCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.drop_before_window.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "aggregated",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.aggregated.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "window-flushed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.window_flushed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "window-flushed-ahead",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.window_flushed_ahead.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "window-max-flows",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.window_max_flows.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "out",
                 CounterType::Counted,
@@ -500,6 +745,21 @@ impl ThrottlingQueue {
         self.cache_without_throttling.push(BoxedTaggedFlow(f));
     }
 
+    fn flush_all(&mut self) {
+        for cache in [
+            &mut self.cache_with_throttling,
+            &mut self.cache_without_throttling,
+        ] {
+            if let Err(e) = self.output.send_all(cache) {
+                debug!(
+                    "l4 flow push aggred flow to sender queue failed, because {:?}",
+                    e
+                );
+                cache.clear();
+            }
+        }
+    }
+
     pub fn update_throttle(&mut self) {
         let new = self.config.load().l4_log_collect_nps_threshold;
         if new < Self::MIN_L4_LOG_COLLECT_NPS_THRESHOLD
@@ -526,3 +786,113 @@ impl ThrottlingQueue {
         self.cache_with_throttling.truncate(self.throttle as usize);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use crate::common::{flow::FlowPerfStats, TapPort};
+
+    fn stashed_flow(flow_id: u64, ip_src: IpAddr, ip_dst: IpAddr) -> TaggedFlow {
+        let mut f = TaggedFlow::default();
+        f.flow.flow_id = flow_id;
+        f.flow.flow_key.ip_src = ip_src;
+        f.flow.flow_key.ip_dst = ip_dst;
+        f.flow.flow_key.port_src = 12345;
+        f.flow.flow_key.port_dst = 80;
+        f.flow.flow_key.tap_port = TapPort(0x1234);
+        f.flow.flow_metrics_peers[0].nat_real_ip = ip_src;
+        f.flow.flow_metrics_peers[0].nat_real_port = 12345;
+        f.flow.flow_metrics_peers[1].nat_real_ip = ip_dst;
+        f.flow.flow_metrics_peers[1].nat_real_port = 80;
+        f.flow.flow_metrics_peers[0].byte_count = 100;
+        f.flow.flow_metrics_peers[1].packet_count = 2;
+        f.flow.start_time = Timestamp::from_secs(1000);
+        f.flow.flow_stat_time = Timestamp::from_secs(1020);
+        f.flow.close_type = CloseType::ForcedReport;
+        f.flow.flow_perf_stats = Some(FlowPerfStats::default());
+        f.flow.flow_perf_stats.as_mut().unwrap().tcp.srt_max = 10;
+        f.flow.acl_gids = vec![3];
+        f.flow.pod_id = 7;
+        f.flow.request_domain = "example.com".to_owned();
+        f
+    }
+
+    fn encoded(f: &Flow) -> flow_log::Flow {
+        f.clone().into()
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flow-aggr-0.checkpoint");
+        let mut nat_flow = stashed_flow(
+            1,
+            Ipv4Addr::new(10, 0, 0, 1).into(),
+            Ipv4Addr::new(10, 0, 0, 2).into(),
+        );
+        nat_flow.flow.flow_metrics_peers[0].nat_real_ip = Ipv4Addr::new(192, 168, 0, 1).into();
+        nat_flow.flow.flow_metrics_peers[0].nat_real_port = 8080;
+        let flows = vec![
+            nat_flow,
+            stashed_flow(
+                2,
+                Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1).into(),
+                Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2).into(),
+            ),
+        ];
+
+        let now = Duration::from_secs(1030);
+        assert_eq!(write_checkpoint(&path, now, flows.iter()).unwrap(), 2);
+        let loaded = read_checkpoint(&path, now + Duration::from_secs(10), FLUSH_TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.len(), 2);
+        for (f, l) in flows.iter().zip(loaded.iter()) {
+            // reloaded flows are sent out the same as the stashed ones
+            assert_eq!(encoded(&f.flow), encoded(&l.flow));
+            assert_eq!(f.flow.flow_key, l.flow.flow_key);
+            assert_eq!(f.flow.start_time_in_minute(), l.flow.start_time_in_minute());
+            assert_eq!(l.flow.pod_id, 7);
+        }
+    }
+
+    #[test]
+    fn checkpoint_max_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flow-aggr-0.checkpoint");
+        let flows = vec![stashed_flow(
+            1,
+            Ipv4Addr::new(10, 0, 0, 1).into(),
+            Ipv4Addr::new(10, 0, 0, 2).into(),
+        )];
+        let now = Duration::from_secs(1030);
+        write_checkpoint(&path, now, flows.iter()).unwrap();
+
+        assert!(read_checkpoint(&path, now + FLUSH_TIMEOUT, FLUSH_TIMEOUT)
+            .unwrap()
+            .is_some());
+        assert!(read_checkpoint(
+            &path,
+            now + FLUSH_TIMEOUT + Duration::from_secs(1),
+            FLUSH_TIMEOUT
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn checkpoint_invalid_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flow-aggr-0.checkpoint");
+        fs::write(&path, b"not a checkpoint file").unwrap();
+        assert_eq!(
+            read_checkpoint(&path, Duration::ZERO, FLUSH_TIMEOUT)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -19,12 +19,11 @@ use std::sync::{
     atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
     Arc, Weak,
 };
-use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
 
 use arc_swap::access::Access;
 use log::{debug, info, warn};
-use thread::JoinHandle;
 
 use super::{
     check_active,
@@ -41,6 +40,7 @@ use crate::rpc::get_timestamp;
 use crate::utils::{
     possible_host::PossibleHost,
     stats::{Collector, Countable, Counter, CounterType, CounterValue, RefCountable},
+    thread_builder,
 };
 use public::{
     buffer::BatchedBox,
@@ -375,8 +375,8 @@ impl SubQuadGen {
 pub struct L7QuadrupleGeneratorThread {
     id: usize,
     l7_stats_input: Arc<Receiver<BatchedBox<L7Stats>>>,
-    l7_second_output: DebugSender<Box<AppMeterWithFlow>>,
-    l7_minute_output: DebugSender<Box<AppMeterWithFlow>>,
+    l7_second_output: Option<DebugSender<Box<AppMeterWithFlow>>>,
+    l7_minute_output: Option<DebugSender<Box<AppMeterWithFlow>>>,
     metrics_type: MetricsType,
     second_delay_seconds: u64,
     minute_delay_seconds: u64,
@@ -398,8 +398,8 @@ impl L7QuadrupleGeneratorThread {
     pub fn new(
         id: usize,
         l7_stats_input: Receiver<BatchedBox<L7Stats>>,
-        l7_second_output: DebugSender<Box<AppMeterWithFlow>>,
-        l7_minute_output: DebugSender<Box<AppMeterWithFlow>>,
+        l7_second_output: Option<DebugSender<Box<AppMeterWithFlow>>>,
+        l7_minute_output: Option<DebugSender<Box<AppMeterWithFlow>>>,
         metrics_type: MetricsType,
         second_delay_seconds: u64,
         minute_delay_seconds: u64,
@@ -454,10 +454,12 @@ impl L7QuadrupleGeneratorThread {
             self.stats.clone(),
         );
         self.thread_handle = Some(
-            thread::Builder::new()
-                .name("l7-quadruple-generator".to_owned())
-                .spawn(move || l7_quadruple_generator.handler_routine())
-                .unwrap(),
+            thread_builder(
+                format!("l7-quad-gen-{}", self.id),
+                self.config.load().thread_stack_size,
+            )
+            .spawn(move || l7_quadruple_generator.handler_routine())
+            .unwrap(),
         );
         info!("l7 quadruple generator id: {} started", self.id);
     }
@@ -505,8 +507,8 @@ impl L7QuadrupleGenerator {
     pub fn new(
         id: usize,
         l7_stats_input: Arc<Receiver<BatchedBox<L7Stats>>>,
-        l7_second_output: DebugSender<Box<AppMeterWithFlow>>,
-        l7_minute_output: DebugSender<Box<AppMeterWithFlow>>,
+        l7_second_output: Option<DebugSender<Box<AppMeterWithFlow>>>,
+        l7_minute_output: Option<DebugSender<Box<AppMeterWithFlow>>>,
         metrics_type: MetricsType,
         second_delay_seconds: u64,
         minute_delay_seconds: u64,
@@ -530,7 +532,9 @@ impl L7QuadrupleGenerator {
         let window_start = round_to_minute(get_timestamp(ntp_diff.load(Ordering::Relaxed)))
             - Duration::from_secs(2 * SECONDS_IN_MINUTE);
 
-        if metrics_type.contains(MetricsType::SECOND) {
+        if let Some(l7_second_output) =
+            l7_second_output.filter(|_| metrics_type.contains(MetricsType::SECOND))
+        {
             let mut quad_gen = SubQuadGen {
                 id,
                 l7_output: l7_second_output,
@@ -560,7 +564,9 @@ impl L7QuadrupleGenerator {
             second_quad_gen = Some(quad_gen);
         }
 
-        if metrics_type.contains(MetricsType::MINUTE) {
+        if let Some(l7_minute_output) =
+            l7_minute_output.filter(|_| metrics_type.contains(MetricsType::MINUTE))
+        {
             let mut quad_gen = SubQuadGen {
                 id,
                 l7_output: l7_minute_output,
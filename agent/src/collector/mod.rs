@@ -31,6 +31,7 @@ use bitflags::bitflags;
 
 use crate::{
     common::endpoint::EPC_INTERNET,
+    config::CollectorMetricsType,
     utils::{possible_host::PossibleHost, stats},
 };
 
@@ -47,6 +48,16 @@ bitflags! {
    }
 }
 
+impl From<CollectorMetricsType> for MetricsType {
+    fn from(t: CollectorMetricsType) -> Self {
+        match t {
+            CollectorMetricsType::Second => MetricsType::SECOND,
+            CollectorMetricsType::Minute => MetricsType::MINUTE,
+            CollectorMetricsType::Both => MetricsType::SECOND | MetricsType::MINUTE,
+        }
+    }
+}
+
 pub fn round_to_minute(t: Duration) -> Duration {
     Duration::from_secs(t.as_secs() / SECONDS_IN_MINUTE * SECONDS_IN_MINUTE)
 }
@@ -20,12 +20,11 @@ use std::sync::{
     atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
     Arc, Weak,
 };
-use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
 
 use arc_swap::access::Access;
 use log::{debug, error, info, warn};
-use thread::JoinHandle;
 
 use super::{
     check_active_host,
@@ -49,6 +48,7 @@ use crate::utils::{
     lru::Lru,
     possible_host::PossibleHost,
     stats::{Collector, Countable, Counter, CounterType, CounterValue, RefCountable},
+    thread_builder,
 };
 use public::{
     buffer::BatchedBox,
@@ -569,8 +569,8 @@ impl SubQuadGen {
 pub struct QuadrupleGeneratorThread {
     id: usize,
     input: Arc<Receiver<Arc<BatchedBox<TaggedFlow>>>>,
-    second_output: DebugSender<Box<FlowMeterWithFlow>>,
-    minute_output: DebugSender<Box<FlowMeterWithFlow>>,
+    second_output: Option<DebugSender<Box<FlowMeterWithFlow>>>,
+    minute_output: Option<DebugSender<Box<FlowMeterWithFlow>>>,
     toa_info_output: DebugSender<Box<(SocketAddr, SocketAddr)>>,
     flow_output: Option<DebugSender<Arc<BatchedBox<TaggedFlow>>>>, // Send TaggedFlows to FlowAggr, equal to None when processing eBPF data.
     connection_lru_capacity: usize,
@@ -592,8 +592,8 @@ impl QuadrupleGeneratorThread {
     pub fn new(
         id: usize,
         input: Receiver<Arc<BatchedBox<TaggedFlow>>>,
-        second_output: DebugSender<Box<FlowMeterWithFlow>>,
-        minute_output: DebugSender<Box<FlowMeterWithFlow>>,
+        second_output: Option<DebugSender<Box<FlowMeterWithFlow>>>,
+        minute_output: Option<DebugSender<Box<FlowMeterWithFlow>>>,
         toa_info_output: DebugSender<Box<(SocketAddr, SocketAddr)>>,
         flow_output: Option<DebugSender<Arc<BatchedBox<TaggedFlow>>>>,
         connection_lru_capacity: usize,
@@ -654,10 +654,12 @@ impl QuadrupleGeneratorThread {
             self.stats.clone(),
         );
         self.thread_handle = Some(
-            thread::Builder::new()
-                .name("quadruple-generator".to_owned())
-                .spawn(move || quadruple_generator.handler_routine())
-                .unwrap(),
+            thread_builder(
+                format!("quad-gen-{}", self.id),
+                self.config.load().thread_stack_size,
+            )
+            .spawn(move || quadruple_generator.handler_routine())
+            .unwrap(),
         );
         info!("quadruple generator id: {} started", self.id);
     }
@@ -718,8 +720,8 @@ impl QuadrupleGenerator {
     pub fn new(
         id: usize,
         input: Arc<Receiver<Arc<BatchedBox<TaggedFlow>>>>,
-        second_output: DebugSender<Box<FlowMeterWithFlow>>,
-        minute_output: DebugSender<Box<FlowMeterWithFlow>>,
+        second_output: Option<DebugSender<Box<FlowMeterWithFlow>>>,
+        minute_output: Option<DebugSender<Box<FlowMeterWithFlow>>>,
         toa_info_output: DebugSender<Box<(SocketAddr, SocketAddr)>>,
         proc_sync_enable: bool,
         flow_output: Option<DebugSender<Arc<BatchedBox<TaggedFlow>>>>,
@@ -748,7 +750,9 @@ impl QuadrupleGenerator {
         let window_start = round_to_minute(get_timestamp(ntp_diff.load(Ordering::Relaxed)))
             - Duration::from_secs(2 * SECONDS_IN_MINUTE);
 
-        if metrics_type.contains(MetricsType::SECOND) {
+        if let Some(second_output) =
+            second_output.filter(|_| metrics_type.contains(MetricsType::SECOND))
+        {
             second_quad_gen = Some(SubQuadGen {
                 id,
                 output: second_output,
@@ -784,7 +788,9 @@ impl QuadrupleGenerator {
             );
         }
 
-        if metrics_type.contains(MetricsType::MINUTE) {
+        if let Some(minute_output) =
+            minute_output.filter(|_| metrics_type.contains(MetricsType::MINUTE))
+        {
             minute_quad_gen = Some(SubQuadGen {
                 id,
                 output: minute_output,
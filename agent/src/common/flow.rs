@@ -25,6 +25,7 @@ use std::{
 };
 
 use log::{error, warn};
+use num_enum::TryFromPrimitive;
 use serde::{Serialize, Serializer};
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -58,7 +59,7 @@ pub use public::l7_protocol::*;
 
 const COUNTER_FLOW_ID_MASK: u64 = 0x00FFFFFF;
 
-#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[derive(Serialize, Debug, PartialEq, Clone, Copy, TryFromPrimitive)]
 #[repr(u8)]
 pub enum CloseType {
     Unknown = 0,
@@ -199,7 +200,35 @@ impl From<FlowKey> for flow_log::FlowKey {
     }
 }
 
-#[derive(Serialize, Debug, Clone, Copy, PartialOrd, PartialEq, Eq, Ord)]
+impl From<flow_log::FlowKey> for FlowKey {
+    fn from(f: flow_log::FlowKey) -> Self {
+        // both address families are encoded, ipv6 ones are all zeros in ipv4 flows
+        let ip6 = |b: &[u8]| <[u8; 16]>::try_from(b).map(Ipv6Addr::from).ok();
+        let (ip_src, ip_dst) = match (ip6(&f.ip6_src), ip6(&f.ip6_dst)) {
+            (Some(src), Some(dst)) if !src.is_unspecified() || !dst.is_unspecified() => {
+                (src.into(), dst.into())
+            }
+            _ => (
+                Ipv4Addr::from(f.ip_src).into(),
+                Ipv4Addr::from(f.ip_dst).into(),
+            ),
+        };
+        FlowKey {
+            vtap_id: f.vtap_id as u16,
+            tap_type: TapType::try_from(f.tap_type as u16).unwrap_or_default(),
+            tap_port: TapPort(f.tap_port),
+            mac_src: MacAddr::try_from(f.mac_src).unwrap_or_default(),
+            mac_dst: MacAddr::try_from(f.mac_dst).unwrap_or_default(),
+            ip_src,
+            ip_dst,
+            port_src: f.port_src as u16,
+            port_dst: f.port_dst as u16,
+            proto: IpProtocol::from(f.proto as u8),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialOrd, PartialEq, Eq, Ord, TryFromPrimitive)]
 #[repr(u8)]
 pub enum SignalSource {
     Packet = 0, // Packet data from AF_PACKET/Winpcap
@@ -319,6 +348,26 @@ impl From<TunnelField> for flow_log::TunnelField {
     }
 }
 
+impl From<flow_log::TunnelField> for TunnelField {
+    fn from(f: flow_log::TunnelField) -> Self {
+        TunnelField {
+            tx_ip0: f.tx_ip0.into(),
+            tx_ip1: f.tx_ip1.into(),
+            rx_ip0: f.rx_ip0.into(),
+            rx_ip1: f.rx_ip1.into(),
+            tx_mac0: f.tx_mac0,
+            tx_mac1: f.tx_mac1,
+            rx_mac0: f.rx_mac0,
+            rx_mac1: f.rx_mac1,
+            tx_id: f.tx_id,
+            rx_id: f.rx_id,
+            tunnel_type: TunnelType::try_from(f.tunnel_type as u8).unwrap_or_default(),
+            tier: f.tier as u8,
+            is_ipv6: false,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct TcpPerfCountsPeer {
     pub retrans_count: u32,
@@ -341,6 +390,15 @@ impl From<TcpPerfCountsPeer> for flow_log::TcpPerfCountsPeer {
     }
 }
 
+impl From<flow_log::TcpPerfCountsPeer> for TcpPerfCountsPeer {
+    fn from(p: flow_log::TcpPerfCountsPeer) -> Self {
+        TcpPerfCountsPeer {
+            retrans_count: p.retrans_count,
+            zero_win_count: p.zero_win_count,
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Default, Clone)]
 // UDPPerfStats仅有2个字段，复用art_max, art_sum, art_count
 pub struct TcpPerfStats {
@@ -474,6 +532,34 @@ impl From<TcpPerfStats> for flow_log::TcpPerfStats {
     }
 }
 
+impl From<flow_log::TcpPerfStats> for TcpPerfStats {
+    // rtt client and server sums and counts are not encoded and left zero
+    fn from(p: flow_log::TcpPerfStats) -> Self {
+        TcpPerfStats {
+            rtt_client_max: p.rtt_client_max,
+            rtt_server_max: p.rtt_server_max,
+            srt_max: p.srt_max,
+            art_max: p.art_max,
+            rtt: p.rtt,
+            srt_sum: p.srt_sum,
+            art_sum: p.art_sum,
+            srt_count: p.srt_count,
+            art_count: p.art_count,
+            counts_peers: [
+                p.counts_peer_tx.map(|c| c.into()).unwrap_or_default(),
+                p.counts_peer_rx.map(|c| c.into()).unwrap_or_default(),
+            ],
+            total_retrans_count: p.total_retrans_count,
+            cit_count: p.cit_count,
+            cit_sum: p.cit_sum,
+            cit_max: p.cit_max,
+            syn_count: p.syn_count,
+            synack_count: p.synack_count,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct FlowPerfStats {
     #[serde(flatten)]
@@ -536,6 +622,18 @@ impl From<FlowPerfStats> for flow_log::FlowPerfStats {
     }
 }
 
+impl From<flow_log::FlowPerfStats> for FlowPerfStats {
+    fn from(p: flow_log::FlowPerfStats) -> Self {
+        FlowPerfStats {
+            tcp: p.tcp.map(|t| t.into()).unwrap_or_default(),
+            l7: p.l7.map(|l| l.into()).unwrap_or_default(),
+            l4_protocol: L4Protocol::try_from(p.l4_protocol as u8).unwrap_or_default(),
+            l7_protocol: L7Protocol::from(p.l7_protocol as u8),
+            l7_failed_count: p.l7_failed_count,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct L7Stats {
     pub stats: L7PerfStats,
@@ -646,6 +744,22 @@ impl From<L7PerfStats> for flow_log::L7PerfStats {
     }
 }
 
+impl From<flow_log::L7PerfStats> for L7PerfStats {
+    fn from(p: flow_log::L7PerfStats) -> Self {
+        L7PerfStats {
+            request_count: p.request_count,
+            response_count: p.response_count,
+            err_client_count: p.err_client_count,
+            err_server_count: p.err_server_count,
+            err_timeout: p.err_timeout,
+            rrt_count: p.rrt_count,
+            rrt_sum: p.rrt_sum,
+            rrt_max: p.rrt_max,
+            tls_rtt: p.tls_rtt,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct FlowMetricsPeer {
     pub byte_count: u64,         // 每个流统计周期（目前是自然秒）清零
@@ -859,6 +973,34 @@ impl From<FlowMetricsPeer> for flow_log::FlowMetricsPeer {
     }
 }
 
+impl From<flow_log::FlowMetricsPeer> for FlowMetricsPeer {
+    // nat_real_ip is restored with the flow key in From<flow_log::Flow> for Flow
+    fn from(m: flow_log::FlowMetricsPeer) -> Self {
+        FlowMetricsPeer {
+            byte_count: m.byte_count,
+            l3_byte_count: m.l3_byte_count,
+            l4_byte_count: m.l4_byte_count,
+            packet_count: m.packet_count,
+            total_byte_count: m.total_byte_count,
+            total_packet_count: m.total_packet_count,
+            first: Timestamp::from_nanos(m.first),
+            last: Timestamp::from_nanos(m.last),
+
+            l3_epc_id: m.l3_epc_id,
+            is_l2_end: m.is_l2_end != 0,
+            is_l3_end: m.is_l3_end != 0,
+            is_active_host: m.is_active_host != 0,
+            is_device: m.is_device != 0,
+            tcp_flags: TcpFlags::from_bits_truncate(m.tcp_flags as u8),
+            is_vip_interface: m.is_vip_interface != 0,
+            is_vip: m.is_vip != 0,
+            nat_real_port: m.real_port as u16,
+            gpid: m.gpid,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum PacketDirection {
@@ -1221,6 +1363,69 @@ impl From<Flow> for flow_log::Flow {
     }
 }
 
+impl From<flow_log::Flow> for Flow {
+    // Decodes a flow from its l4_flow_log encoding, which encodes it back to the same message.
+    // Fields not in the encoding, such as flow_stat_time and directions, are left default.
+    fn from(f: flow_log::Flow) -> Self {
+        let real_ips = [
+            f.metrics_peer_src
+                .as_ref()
+                .map(|m| m.real_ip)
+                .unwrap_or_default(),
+            f.metrics_peer_dst
+                .as_ref()
+                .map(|m| m.real_ip)
+                .unwrap_or_default(),
+        ];
+        let mut flow = Flow {
+            flow_key: f.flow_key.map(|k| k.into()).unwrap_or_default(),
+            flow_metrics_peers: [
+                f.metrics_peer_src.map(|m| m.into()).unwrap_or_default(),
+                f.metrics_peer_dst.map(|m| m.into()).unwrap_or_default(),
+            ],
+            tunnel: f.tunnel.map(|t| t.into()).unwrap_or_default(),
+            flow_id: f.flow_id,
+            start_time: Timestamp::from_nanos(f.start_time),
+            end_time: Timestamp::from_nanos(f.end_time),
+            duration: Timestamp::from_nanos(f.duration),
+            eth_type: EthernetType::from(f.eth_type as u16),
+            vlan: f.vlan as u16,
+            flow_perf_stats: if f.has_perf_stats != 0 {
+                f.perf_stats.map(|p| p.into())
+            } else {
+                None
+            },
+            close_type: CloseType::try_from(f.close_type as u8).unwrap_or_default(),
+            signal_source: SignalSource::try_from(f.signal_source as u8).unwrap_or_default(),
+            is_active_service: f.is_active_service != 0,
+            queue_hash: f.queue_hash as u8,
+            is_new_flow: f.is_new_flow != 0,
+            tap_side: TapSide::try_from(f.tap_side as u8).unwrap_or_default(),
+            syn_seq: f.syn_seq,
+            synack_seq: f.synack_seq,
+            last_keepalive_seq: f.last_keepalive_seq,
+            last_keepalive_ack: f.last_keepalive_ack,
+            acl_gids: f.acl_gids.into_iter().map(|g| g as u16).collect(),
+            direction_score: f.direction_score as u8,
+            request_domain: f.request_domain,
+            ..Default::default()
+        };
+        // The encoded flow key holds the real ip and port before and after nat, swap them back.
+        // Only ipv4 real ips are encoded, ipv6 flows are restored as if there is no nat.
+        let key = &mut flow.flow_key;
+        let [src, dst] = &mut flow.flow_metrics_peers;
+        swap(&mut key.port_src, &mut src.nat_real_port);
+        swap(&mut key.port_dst, &mut dst.nat_real_port);
+        src.nat_real_ip = key.ip_src;
+        dst.nat_real_ip = key.ip_dst;
+        if key.ip_src.is_ipv4() {
+            key.ip_src = Ipv4Addr::from(real_ips[0]).into();
+            key.ip_dst = Ipv4Addr::from(real_ips[1]).into();
+        }
+        flow
+    }
+}
+
 pub fn get_direction(
     flow: &Flow,
     trident_type: TridentType,
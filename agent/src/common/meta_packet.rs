@@ -910,6 +910,14 @@ impl<'a> MetaPacket<'a> {
         self.l4_payload_len as usize
     }
 
+    // Whether l4 payload is cut by capture length, always false for eBPF
+    pub fn is_l4_payload_truncated(&self) -> bool {
+        if self.tap_port.is_from(TapPort::FROM_EBPF) {
+            return false;
+        }
+        self.get_l4_payload().map(|p| p.len()).unwrap_or_default() < self.l4_payload_len()
+    }
+
     // The socket_id obtained by ebpf from upprobe and kprobe on the same flow,
     // but the application protocols are inconsistent.
     pub fn generate_ebpf_flow_id(&self) -> u64 {
@@ -18,13 +18,15 @@ use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 
 use prost::Message;
-use public::sender::{SendMessageType, Sendable};
+use public::sender::{FieldFilter, SendMessageType, Sendable};
 use serde::Serialize;
 
 use super::flow::Flow;
 use super::tag::Tag;
 use super::TapPort;
 
+use crate::sender::{flow_balance_key, hash_field_bytes, hash_field_u32};
+
 use public::proto::flow_log;
 
 #[derive(Serialize, Default, Clone, Debug)]
@@ -78,6 +80,11 @@ impl fmt::Display for TaggedFlow {
     }
 }
 
+// Optional l4_flow_log fields which can be excluded from export
+pub const L4_EXPORT_FIELDS: &[&str] = &["mac_src", "mac_dst", "tunnel"];
+// l4_flow_log fields which can be hashed before export
+pub const L4_HASHABLE_FIELDS: &[&str] = &["ip_src", "ip_dst"];
+
 #[derive(Debug)]
 pub struct BoxedTaggedFlow(pub Box<TaggedFlow>);
 
@@ -91,6 +98,48 @@ impl Sendable for BoxedTaggedFlow {
             .map(|_| pb_tagged_flow.encoded_len())
     }
 
+    fn encode_filtered(
+        self,
+        buf: &mut Vec<u8>,
+        filter: &FieldFilter,
+    ) -> Result<usize, prost::EncodeError> {
+        let mut flow: flow_log::Flow = self.0.flow.into();
+        if let Some(flow_key) = flow.flow_key.as_mut() {
+            if filter.excluded("mac_src") {
+                flow_key.mac_src = 0;
+            }
+            if filter.excluded("mac_dst") {
+                flow_key.mac_dst = 0;
+            }
+            if filter.hashed("ip_src") {
+                hash_field_u32(filter.hash_key(), &mut flow_key.ip_src);
+                hash_field_bytes(filter.hash_key(), &mut flow_key.ip6_src);
+            }
+            if filter.hashed("ip_dst") {
+                hash_field_u32(filter.hash_key(), &mut flow_key.ip_dst);
+                hash_field_bytes(filter.hash_key(), &mut flow_key.ip6_dst);
+            }
+        }
+        if filter.excluded("tunnel") {
+            flow.tunnel = None;
+        }
+        let pb_tagged_flow = flow_log::TaggedFlow { flow: Some(flow) };
+        pb_tagged_flow
+            .encode(buf)
+            .map(|_| pb_tagged_flow.encoded_len())
+    }
+
+    fn balance_key(&self) -> Option<u64> {
+        let key = &self.0.flow.flow_key;
+        Some(flow_balance_key(
+            key.ip_src,
+            key.ip_dst,
+            key.port_src,
+            key.port_dst,
+            u8::from(key.proto),
+        ))
+    }
+
     fn to_kv_string(&self, dst: &mut String) {
         let json = serde_json::to_string(&(*self.0)).unwrap();
         dst.push_str(&json);
@@ -185,4 +234,26 @@ mod tests {
         assert_eq!(pb_flow.is_active_service, 1);
         assert_eq!(pb_flow.perf_stats.unwrap().tcp.unwrap().rtt, 10);
     }
+
+    #[test]
+    fn encode_filtered() {
+        let mut tflow = TaggedFlow::default();
+        tflow.flow.flow_key.mac_src = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66].into();
+        tflow.flow.flow_key.mac_dst = [0x66, 0x55, 0x44, 0x33, 0x22, 0x11].into();
+        tflow.flow.tunnel.tunnel_type = TunnelType::Vxlan;
+
+        let mut buf: Vec<u8> = vec![];
+        let filter = FieldFilter::new(vec!["mac_dst".to_owned()], vec!["tunnel".to_owned()]);
+        let encoded_len = BoxedTaggedFlow(Box::new(tflow))
+            .encode_filtered(&mut buf, &filter)
+            .unwrap();
+        let rlt: Result<flow_log::TaggedFlow, prost::DecodeError> =
+            Message::decode(buf.as_slice().get(..encoded_len).unwrap());
+
+        let pb_flow = rlt.unwrap().flow.unwrap();
+        assert!(pb_flow.tunnel.is_none());
+        let flow_key = pb_flow.flow_key.unwrap();
+        assert_eq!(flow_key.mac_src, 0);
+        assert_eq!(flow_key.mac_dst, 0x665544332211);
+    }
 }
@@ -16,8 +16,9 @@
 
 use std::{
     collections::{HashMap, HashSet},
-    fmt,
+    fmt, fs, io,
     net::Ipv4Addr,
+    path::Path,
     str::FromStr,
     sync::{
         atomic::{AtomicU16, Ordering},
@@ -26,6 +27,7 @@ use std::{
 };
 
 use log::warn;
+use serde::Deserialize;
 
 use public::proto::trident;
 
@@ -141,6 +143,91 @@ impl TapTyper {
     }
 }
 
+// An entry in local tap types file, used when there is no server to push tap types
+//
+// packet:
+//   - tap-type: 3
+//     vlan: 100
+// sflow/netflow/netstream:
+//   - tap-type: 3
+//     packet-type: sflow
+//     source-ip: 10.1.2.3
+//     tap-port: 20
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+struct TapTypeEntry {
+    tap_type: u32,
+    packet_type: String,
+    vlan: u32,
+    source_ip: String,
+    tap_port: u32,
+}
+
+impl Default for TapTypeEntry {
+    fn default() -> Self {
+        Self {
+            tap_type: 0,
+            packet_type: "packet".to_owned(),
+            vlan: 0,
+            source_ip: "".to_owned(),
+            tap_port: 0,
+        }
+    }
+}
+
+impl TryFrom<TapTypeEntry> for trident::TapType {
+    type Error = io::Error;
+
+    fn try_from(e: TapTypeEntry) -> Result<Self, Self::Error> {
+        let packet_type = match e.packet_type.to_lowercase().as_str() {
+            "packet" => trident::PacketType::Packet,
+            "sflow" => trident::PacketType::Sflow,
+            "netflow-v5" => trident::PacketType::NetflowV5,
+            "netstream-v5" => trident::PacketType::NetstreamV5,
+            "netflow-v9" => trident::PacketType::NetflowV9,
+            "netstream-v9" => trident::PacketType::NetstreamV9,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid packet-type {}", other),
+                ))
+            }
+        };
+        Ok(match packet_type {
+            trident::PacketType::Packet => trident::TapType {
+                tap_type: Some(e.tap_type),
+                packet_type: Some(packet_type as i32),
+                vlan: Some(e.vlan),
+                source_ip: None,
+                tap_port: None,
+            },
+            _ => trident::TapType {
+                tap_type: Some(e.tap_type),
+                packet_type: Some(packet_type as i32),
+                vlan: None,
+                source_ip: Some(e.source_ip),
+                tap_port: Some(e.tap_port),
+            },
+        })
+    }
+}
+
+// Parses tap types from yaml or json contents, validation is left to TapTyper::on_tap_types_change
+pub fn parse_tap_types<C: AsRef<str>>(contents: C) -> io::Result<Vec<trident::TapType>> {
+    let contents = contents.as_ref();
+    if contents.trim().is_empty() {
+        // parsing empty string leads to EOF error
+        return Ok(vec![]);
+    }
+    let entries: Vec<TapTypeEntry> = serde_yaml::from_str(contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    entries.into_iter().map(|e| e.try_into()).collect()
+}
+
+pub fn load_tap_types_from_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<trident::TapType>> {
+    parse_tap_types(fs::read_to_string(path)?)
+}
+
 impl Default for TapTyper {
     fn default() -> Self {
         Self::new()
@@ -229,6 +316,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_local_tap_types() {
+        let yaml = r#"
+- tap-type: 3
+  vlan: 100
+- tap-type: 4
+  packet-type: sflow
+  source-ip: 1.2.3.4
+  tap-port: 20
+"#;
+        let json = r#"[{"tap-type": 3, "vlan": 100}, {"tap-type": 4, "packet-type": "sflow", "source-ip": "1.2.3.4", "tap-port": 20}]"#;
+        for contents in [yaml, json] {
+            let tap_types = parse_tap_types(contents).unwrap();
+            assert_eq!(tap_types.len(), 2);
+            assert_eq!(tap_types[0].packet_type(), trident::PacketType::Packet);
+            assert_eq!(tap_types[0].vlan(), 100);
+            assert_eq!(tap_types[1].packet_type(), trident::PacketType::Sflow);
+            assert_eq!(tap_types[1].source_ip(), "1.2.3.4");
+
+            let mut tap_typer = TapTyper::new();
+            tap_typer.on_tap_types_change(tap_types);
+            verify_vlan(&tap_typer, 100, 3);
+            verify_xflow(&mut tap_typer, "1.2.3.4", 20, 4);
+        }
+
+        assert!(parse_tap_types("").unwrap().is_empty());
+        assert!(parse_tap_types("- tap-type: 3\n  packet-type: unknown\n").is_err());
+    }
+
     #[test]
     fn assert_tap_typer_update() {
         let mut tap_typer = TapTyper::new();
@@ -16,9 +16,11 @@
 
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::net::{IpAddr, ToSocketAddrs};
+use std::mem;
+use std::net::IpAddr;
 use std::path::Path;
 use std::time::Duration;
 
@@ -27,12 +29,14 @@ use md5::{Digest, Md5};
 use regex::Regex;
 use serde::{
     de::{self, Unexpected},
-    Deserialize, Deserializer,
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 use thiserror::Error;
 use tokio::runtime::Runtime;
 
 use crate::common::l7_protocol_log::L7ProtocolParser;
+use crate::common::tagged_flow::{L4_EXPORT_FIELDS, L4_HASHABLE_FIELDS};
+use crate::flow_generator::protocol_logs::{L7_EXPORT_FIELDS, L7_HASHABLE_FIELDS};
 use crate::flow_generator::{DnsLog, OracleLog, TlsLog};
 use crate::{
     common::{
@@ -41,31 +45,41 @@ use crate::{
         l7_protocol_log::{get_all_protocol, L7ProtocolParserInterface},
         DEFAULT_LOG_FILE, L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT, L7_PROTOCOL_INFERENCE_TTL,
     },
+    exception::ExceptionHandler,
     flow_generator::protocol_logs::SLOT_WIDTH,
     metric::document::TapSide,
     rpc::Session,
     trident::RunningMode,
+    utils::{
+        capture_schedule::CaptureWindow, dns, environment::get_k8s_node_name, sensitive::Sensitive,
+    },
 };
 use public::{
     bitmap::Bitmap,
-    consts::NPB_DEFAULT_PORT,
+    consts::{GRPC_SESSION_TIMEOUT, NPB_DEFAULT_PORT},
     proto::{
         common,
-        trident::{self, KubernetesClusterIdRequest, TapMode},
+        trident::{self, Exception, KubernetesClusterIdRequest, TapMode},
     },
+    queue::OverflowPolicy,
     utils::bitmap::parse_u16_range_list_to_bitmap,
 };
 
 pub const K8S_CA_CRT_PATH: &str = "/run/secrets/kubernetes.io/serviceaccount/ca.crt";
 const MINUTE: Duration = Duration::from_secs(60);
+const K8S_CLUSTER_ID_MIN_BACKOFF: Duration = Duration::from_secs(5);
+const K8S_CLUSTER_ID_MAX_BACKOFF: Duration = MINUTE;
+const K8S_CLUSTER_ID_EXCEPTION_ATTEMPTS: u32 = 5;
 const DEFAULT_STANDALONE_CONFIG: &str = "/etc/deepflow-agent-standalone.yaml";
+const HTTPS_PROXY_ENVS: [&str; 2] = ["HTTPS_PROXY", "https_proxy"];
+const NO_PROXY_ENVS: [&str; 2] = ["NO_PROXY", "no_proxy"];
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error("controller-ips is empty")]
     ControllerIpsEmpty,
-    #[error("controller-ips invalid")]
-    ControllerIpsInvalid,
+    #[error("controller-ips invalid: {}", .0.join(", "))]
+    ControllerIpsInvalid(Vec<String>),
     #[error("runtime config invalid: {0}")]
     RuntimeConfigInvalid(String),
     #[error("yaml config invalid: {0}")]
@@ -95,6 +109,18 @@ impl<'de> Deserialize<'de> for AgentIdType {
     }
 }
 
+impl Serialize for AgentIdType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::IpMac => serializer.serialize_str("ip-and-mac"),
+            Self::Ip => serializer.serialize_str("ip"),
+        }
+    }
+}
+
 impl From<AgentIdType> for trident::AgentIdentifier {
     fn from(t: AgentIdType) -> Self {
         match t {
@@ -104,14 +130,111 @@ impl From<AgentIdType> for trident::AgentIdentifier {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum HostnameSource {
+    #[default]
+    Os,
+    Env(String),
+    Static(String),
+    K8sNode,
+}
+
+impl HostnameSource {
+    // Os is not resolved here, it is refreshed from the OS by whoever uses it
+    pub fn resolve(&self) -> io::Result<Option<String>> {
+        let not_found = |msg: String| io::Error::new(io::ErrorKind::NotFound, msg);
+        match self {
+            Self::Os => Ok(None),
+            Self::Env(name) => match env::var(name) {
+                Ok(v) if !v.is_empty() => Ok(Some(v)),
+                _ => Err(not_found(format!("environment variable {} not set", name))),
+            },
+            Self::Static(name) => Ok(Some(name.clone())),
+            Self::K8sNode => match get_k8s_node_name() {
+                Some(v) => Ok(Some(v)),
+                None => Err(not_found("kubernetes node name not found".to_owned())),
+            },
+        }
+    }
+}
+
+impl fmt::Display for HostnameSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Os => write!(f, "os"),
+            Self::Env(name) => write!(f, "env:{}", name),
+            Self::Static(name) => write!(f, "static:{}", name),
+            Self::K8sNode => write!(f, "k8s_node"),
+        }
+    }
+}
+
+impl Serialize for HostnameSource {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HostnameSource {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.split_once(':') {
+            None if s == "os" => Ok(Self::Os),
+            None if s == "k8s_node" || s == "k8s-node" => Ok(Self::K8sNode),
+            Some(("env", name)) if !name.is_empty() => Ok(Self::Env(name.to_owned())),
+            Some(("static", name)) if !name.is_empty() => Ok(Self::Static(name.to_owned())),
+            _ => Err(de::Error::invalid_value(
+                Unexpected::Str(&s),
+                &"os|env:<VAR>|static:<value>|k8s_node",
+            )),
+        }
+    }
+}
+
+// Where agent logs are written, remote logging is not affected
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogTarget {
+    #[default]
+    File,
+    // plain lines without rotation, for log collectors reading stdout of containers
+    Stdout,
+    Both,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct Config {
     pub controller_ips: Vec<String>,
+    // resolve controller domain names with SRV records before A records
+    pub controller_srv_lookup: bool,
+    // nameservers resolving controller domain names instead of the system resolver
+    pub controller_resolver_nameservers: Vec<IpAddr>,
+    // look up /etc/hosts before querying nameservers
+    pub controller_resolver_hosts_file: bool,
+    // controller-ips as configured, before domain names are resolved
+    #[serde(skip)]
+    pub controller_addrs: Vec<String>,
+    // controller ports from SRV records
+    #[serde(skip)]
+    pub controller_ports: HashMap<String, u16>,
     pub controller_port: u16,
     pub controller_tls_port: u16,
     pub controller_cert_file_prefix: String,
+    pub controller_tls_verify: String,
+    pub controller_proxy: Sensitive<String>,
     pub log_file: String,
+    pub log_to: LogTarget,
+    // symlink to the current log file, log_file if empty
+    pub log_file_symlink: String,
+    // log to stderr and remote only if log file directory is not writable
+    pub log_file_fallback: bool,
     pub kubernetes_cluster_id: String,
     pub kubernetes_cluster_name: Option<String>,
     pub vtap_group_id_request: String,
@@ -119,20 +242,72 @@ pub struct Config {
     #[serde(skip)]
     pub agent_mode: RunningMode,
     pub override_os_hostname: Option<String>,
+    pub hostname_source: HostnameSource,
+    pub prefer_route_ctrl_ip: bool,
+    pub tap_types_file: String,
     pub async_worker_thread_number: u16,
     pub agent_unique_identifier: AgentIdType,
     #[cfg(target_os = "linux")]
     pub pid_file: String,
     pub team_id: String,
+    pub sync_interval_jitter: u8,
+    pub dump_effective_config: bool,
+    pub controller_timeouts: ControllerTimeouts,
+    pub stats_disabled: bool,
+    pub queue_dwell_time_enabled: bool,
+    // salt of export-fields-hashed, generated and kept next to log file if empty
+    pub export_hash_salt: Sensitive<String>,
 }
 
 impl Config {
     pub fn load_from_file<T: AsRef<Path>>(path: T) -> Result<Self, ConfigError> {
-        let contents =
-            fs::read_to_string(path).map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?;
+        let path = path.as_ref();
+        let contents = if path.is_dir() {
+            Self::merge_fragments(path)?
+        } else {
+            fs::read_to_string(path).map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?
+        };
         Self::load(&contents)
     }
 
+    // Merges all *.yaml files in dir in lexical order of file names, later ones win on conflicts
+    fn merge_fragments(dir: &Path) -> Result<String, ConfigError> {
+        let mut paths = fs::read_dir(dir)
+            .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().map(|e| e == "yaml").unwrap_or_default())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut merged = serde_yaml::Value::Mapping(Default::default());
+        for path in paths {
+            let contents = fs::read_to_string(&path).map_err(|e| {
+                ConfigError::YamlConfigInvalid(format!("{}: {}", path.display(), e))
+            })?;
+            // parsing empty or comment only file leads to EOF error
+            if contents
+                .lines()
+                .all(|l| l.trim().is_empty() || l.trim_start().starts_with('#'))
+            {
+                continue;
+            }
+            let value = serde_yaml::from_str(&contents).map_err(|e| {
+                ConfigError::YamlConfigInvalid(format!("{}: {}", path.display(), e))
+            })?;
+            debug!("merging config fragment {}", path.display());
+            merge_yaml_value(&mut merged, value, "");
+        }
+        serde_yaml::to_string(&merged).map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))
+    }
+
+    pub fn controller_resolver(&self) -> dns::Resolver {
+        dns::Resolver::new(
+            self.controller_resolver_nameservers.clone(),
+            self.controller_resolver_hosts_file,
+        )
+    }
+
     pub fn load<C: AsRef<str>>(contents: C) -> Result<Self, ConfigError> {
         let contents = contents.as_ref();
         if contents.len() == 0 {
@@ -142,19 +317,38 @@ impl Config {
             let mut cfg: Self = serde_yaml::from_str(contents)
                 .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?;
 
-            for i in 0..cfg.controller_ips.len() {
-                if cfg.controller_ips[i].parse::<IpAddr>().is_err() {
-                    let ip = resolve_domain(&cfg.controller_ips[i]);
-                    if ip.is_none() {
-                        return Err(ConfigError::ControllerIpsInvalid);
-                    }
-
-                    cfg.controller_domain_name
-                        .push(cfg.controller_ips[i].clone());
-                    cfg.controller_ips[i] = ip.unwrap();
+            cfg.controller_addrs = mem::take(&mut cfg.controller_ips);
+            let resolved = resolve_controller_addrs(
+                &cfg.controller_addrs,
+                cfg.controller_srv_lookup,
+                &cfg.controller_resolver(),
+            )
+            .map_err(ConfigError::ControllerIpsInvalid)?;
+            cfg.controller_ips = resolved.ips;
+            cfg.controller_ports = resolved.ports;
+            cfg.controller_domain_name.extend(resolved.domain_names);
+
+            match grpc::TlsVerify::parse(&cfg.controller_tls_verify) {
+                Ok(v) if !cfg.controller_cert_file_prefix.is_empty() && !v.supported() => {
+                    return Err(ConfigError::YamlConfigInvalid(format!(
+                        "controller-tls-verify {} is not supported by this build",
+                        cfg.controller_tls_verify
+                    )));
                 }
+                Ok(_) => (),
+                Err(e) => return Err(ConfigError::YamlConfigInvalid(e)),
+            }
+
+            if cfg.sync_interval_jitter > 50 {
+                warn!(
+                    "sync-interval-jitter {} out of range [0, 50], set to 50",
+                    cfg.sync_interval_jitter
+                );
+                cfg.sync_interval_jitter = 50;
             }
 
+            cfg.controller_timeouts.check();
+
             // convert relative path to absolute
             if Path::new(&cfg.log_file).is_relative() {
                 let Ok(mut pb) = env::current_dir() else {
@@ -176,7 +370,47 @@ impl Config {
         }
     }
 
-    pub async fn async_get_k8s_cluster_id(session: &Session, config: &Config) -> Option<String> {
+    // Static config in canonical yaml as the agent runs with, environment overrides applied
+    pub fn to_effective_yaml(&self) -> Result<String, serde_yaml::Error> {
+        let mut c = self.clone();
+        if let Some(proxy) = self.get_controller_proxy() {
+            c.controller_proxy = proxy.into();
+        }
+        serde_yaml::to_string(&c)
+    }
+
+    // controller-tls-verify is validated on load
+    pub fn get_controller_tls_verify(&self) -> grpc::TlsVerify {
+        grpc::TlsVerify::parse(&self.controller_tls_verify).unwrap_or_default()
+    }
+
+    // controller-proxy takes precedence over HTTPS_PROXY environment variable
+    pub fn get_controller_proxy(&self) -> Option<String> {
+        if !self.controller_proxy.is_empty() {
+            return Some(self.controller_proxy.expose().clone());
+        }
+        HTTPS_PROXY_ENVS
+            .into_iter()
+            .filter_map(|k| env::var(k).ok())
+            .find(|v| !v.is_empty())
+    }
+
+    // NO_PROXY environment variable only applies to proxy from HTTPS_PROXY
+    pub fn get_controller_no_proxy(&self) -> Option<String> {
+        if !self.controller_proxy.is_empty() {
+            return None;
+        }
+        NO_PROXY_ENVS
+            .into_iter()
+            .filter_map(|k| env::var(k).ok())
+            .find(|v| !v.is_empty())
+    }
+
+    pub async fn async_get_k8s_cluster_id(
+        session: &Session,
+        config: &Config,
+        exception_handler: &ExceptionHandler,
+    ) -> Option<String> {
         let ca_md5 = match fs::read_to_string(K8S_CA_CRT_PATH) {
             Ok(c) => Some(
                 Md5::digest(c.as_bytes())
@@ -192,7 +426,11 @@ impl Config {
             }
         };
 
+        let mut attempt = 0;
+        let mut backoff = K8S_CLUSTER_ID_MIN_BACKOFF;
         loop {
+            attempt += 1;
+            info!("get kubernetes_cluster_id attempt {}", attempt);
             let request = KubernetesClusterIdRequest {
                 ca_md5: ca_md5.clone(),
                 kubernetes_cluster_name: config.kubernetes_cluster_name.clone(),
@@ -210,48 +448,97 @@ impl Config {
                             "get_kubernetes_cluster_id grpc call from server error: {}",
                             cluster_id_response.error_msg()
                         );
-                        tokio::time::sleep(MINUTE).await;
-                        continue;
-                    }
-                    match cluster_id_response.cluster_id {
-                        Some(id) => {
-                            if id.is_empty() {
-                                error!("call get_kubernetes_cluster_id return cluster_id is empty string");
-                                tokio::time::sleep(MINUTE).await;
-                                continue;
+                    } else {
+                        match cluster_id_response.cluster_id {
+                            Some(id) if !id.is_empty() => {
+                                info!(
+                                    "set kubernetes_cluster_id to {} after {} attempts",
+                                    id, attempt
+                                );
+                                exception_handler.clear(Exception::ControllerSocketError);
+                                // FIXME: The channel in the session will become invalid after success here, so reset the session.
+                                // ==============================================================================================
+                                // FIXME: 这里获取成功后 Session 中的 Channel 会失效，所以在这里重置 Session
+                                session.reset();
+                                return Some(id);
+                            }
+                            Some(_) => error!(
+                                "call get_kubernetes_cluster_id return cluster_id is empty string"
+                            ),
+                            None => {
+                                error!("call get_kubernetes_cluster_id return response is none")
                             }
-                            info!("set kubernetes_cluster_id to {}", id);
-                            // FIXME: The channel in the session will become invalid after success here, so reset the session.
-                            // ==============================================================================================
-                            // FIXME: 这里获取成功后 Session 中的 Channel 会失效，所以在这里重置 Session
-                            session.reset();
-                            return Some(id);
-                        }
-                        None => {
-                            error!("call get_kubernetes_cluster_id return response is none")
                         }
                     }
                 }
                 Err(e) => error!("get_kubernetes_cluster_id grpc call error: {}", e),
             }
-            tokio::time::sleep(MINUTE).await;
+            if attempt == K8S_CLUSTER_ID_EXCEPTION_ATTEMPTS {
+                exception_handler.set(Exception::ControllerSocketError);
+            }
+            if attempt >= K8S_CLUSTER_ID_EXCEPTION_ATTEMPTS {
+                error!(
+                    "get kubernetes_cluster_id failed {} times, agent keeps waiting for it before starting, retry in {:?}",
+                    attempt, backoff
+                );
+            } else {
+                warn!(
+                    "get kubernetes_cluster_id attempt {} failed, retry in {:?}",
+                    attempt, backoff
+                );
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(K8S_CLUSTER_ID_MAX_BACKOFF);
         }
     }
 
     // 目的是为了k8s采集器configmap中不配置k8s-cluster-id也能实现注册。
     // 如果agent在容器中运行且ConfigMap中kubernetes-cluster-id为空,
-    // 调用GetKubernetesClusterID RPC，获取cluster-id, 如果RPC调用失败，sleep 1分钟后再次调用，直到成功
+    // 调用GetKubernetesClusterID RPC，获取cluster-id, 如果RPC调用失败，退避重试（最长间隔1分钟），直到成功
     // ======================================================================================================
     // The purpose is to enable registration without configuring k8s-cluster-id in the k8s collector configmap.
     // If agent is running in container and the kubernetes-cluster-id in the
-    // ConfigMap is empty, Call GetKubernetesClusterID RPC to get the cluster-id, if the RPC call fails, call it again
-    // after 1 minute of sleep until it succeeds
+    // ConfigMap is empty, Call GetKubernetesClusterID RPC to get the cluster-id, if the RPC call fails, retry
+    // with exponential backoff (at most 1 minute) until it succeeds. Exception ControllerSocketError is set
+    // after K8S_CLUSTER_ID_EXCEPTION_ATTEMPTS failures and cleared on success
     pub fn get_k8s_cluster_id(
         runtime: &Runtime,
         session: &Session,
         config: &Config,
+        exception_handler: &ExceptionHandler,
     ) -> Option<String> {
-        runtime.block_on(Self::async_get_k8s_cluster_id(session, config))
+        runtime.block_on(Self::async_get_k8s_cluster_id(
+            session,
+            config,
+            exception_handler,
+        ))
+    }
+}
+
+// Mappings are merged recursively, anything else in `other` replaces the one in `base`
+fn merge_yaml_value(base: &mut serde_yaml::Value, other: serde_yaml::Value, path: &str) {
+    match (base, other) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(other)) => {
+            for (k, v) in other {
+                let key_path = match k.as_str() {
+                    Some(key) if path.is_empty() => key.to_owned(),
+                    Some(key) => format!("{}.{}", path, key),
+                    None => format!("{}.{:?}", path, k),
+                };
+                match base.get_mut(&k) {
+                    Some(b) => merge_yaml_value(b, v, &key_path),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, other) => {
+            if *base != other {
+                debug!("config {} overridden from {:?} to {:?}", path, base, other);
+                *base = other;
+            }
+        }
     }
 }
 
@@ -259,21 +546,95 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             controller_ips: vec![],
+            controller_srv_lookup: false,
+            controller_resolver_nameservers: vec![],
+            controller_resolver_hosts_file: true,
+            controller_addrs: vec![],
+            controller_ports: HashMap::new(),
             controller_port: 30035,
             controller_tls_port: 30135,
             controller_cert_file_prefix: "".into(),
+            controller_tls_verify: "full".into(),
+            controller_proxy: Default::default(),
             log_file: DEFAULT_LOG_FILE.into(),
+            log_to: LogTarget::File,
+            log_file_symlink: "".into(),
+            log_file_fallback: false,
             kubernetes_cluster_id: "".into(),
             kubernetes_cluster_name: Default::default(),
             vtap_group_id_request: "".into(),
             controller_domain_name: vec![],
             agent_mode: Default::default(),
             override_os_hostname: None,
+            hostname_source: Default::default(),
+            prefer_route_ctrl_ip: false,
+            tap_types_file: "".into(),
             async_worker_thread_number: 16,
             agent_unique_identifier: Default::default(),
             #[cfg(target_os = "linux")]
             pid_file: Default::default(),
             team_id: "".into(),
+            sync_interval_jitter: 10,
+            dump_effective_config: false,
+            controller_timeouts: Default::default(),
+            stats_disabled: false,
+            queue_dwell_time_enabled: false,
+            export_hash_salt: Default::default(),
+        }
+    }
+}
+
+// Timeouts of controller rpcs by class, flow acls are delivered in sync and push responses
+// and follow the timeouts of these rpcs
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ControllerTimeouts {
+    // sync and ntp, heartbeats to controller
+    #[serde(with = "humantime_serde")]
+    pub sync: Duration,
+    // config push, upgrade and plugin
+    #[serde(with = "humantime_serde")]
+    pub config_fetch: Duration,
+    // genesis, kubernetes, gpid and prometheus syncs
+    #[serde(with = "humantime_serde")]
+    pub resource_sync: Duration,
+}
+
+impl ControllerTimeouts {
+    const MIN: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(600);
+
+    fn check(&mut self) {
+        for (name, t) in [
+            ("sync", &mut self.sync),
+            ("config-fetch", &mut self.config_fetch),
+            ("resource-sync", &mut self.resource_sync),
+        ] {
+            if *t < Self::MIN || *t > Self::MAX {
+                warn!(
+                    "controller-timeouts.{} {:?} out of range [{:?}, {:?}], set to {:?}",
+                    name,
+                    t,
+                    Self::MIN,
+                    Self::MAX,
+                    GRPC_SESSION_TIMEOUT
+                );
+                *t = GRPC_SESSION_TIMEOUT;
+            }
+        }
+    }
+
+    pub fn max(&self) -> Duration {
+        self.sync.max(self.config_fetch).max(self.resource_sync)
+    }
+}
+
+impl Default for ControllerTimeouts {
+    fn default() -> Self {
+        Self {
+            sync: GRPC_SESSION_TIMEOUT,
+            config_fetch: GRPC_SESSION_TIMEOUT,
+            resource_sync: GRPC_SESSION_TIMEOUT,
         }
     }
 }
@@ -373,6 +734,7 @@ pub struct EbpfYamlConfig {
     pub thread_num: usize,
     pub perf_pages_count: usize,
     pub ring_size: usize,
+    pub poll_batch_size: usize,
     pub max_socket_entries: usize,
     pub max_trace_entries: usize,
     pub socket_map_max_reclaim: usize,
@@ -388,6 +750,30 @@ pub struct EbpfYamlConfig {
     pub syscall_out_of_order_cache_size: usize,
     pub syscall_out_of_order_reassembly: Vec<String>,
     pub syscall_segmentation_reassembly: Vec<String>,
+    pub probes: Vec<String>,
+}
+
+impl EbpfYamlConfig {
+    pub const PROBE_SYSCALL: &'static str = "syscall";
+    pub const PROBE_GOLANG_UPROBE: &'static str = "golang-uprobe";
+    pub const PROBE_GOLANG_SYMBOL_UPROBE: &'static str = "golang-symbol-uprobe";
+    pub const PROBE_OPENSSL_UPROBE: &'static str = "openssl-uprobe";
+    pub const PROBE_ON_CPU_PROFILE: &'static str = "on-cpu-profile";
+    pub const PROBE_OFF_CPU_PROFILE: &'static str = "off-cpu-profile";
+
+    pub const PROBES: [&'static str; 6] = [
+        Self::PROBE_SYSCALL,
+        Self::PROBE_GOLANG_UPROBE,
+        Self::PROBE_GOLANG_SYMBOL_UPROBE,
+        Self::PROBE_OPENSSL_UPROBE,
+        Self::PROBE_ON_CPU_PROFILE,
+        Self::PROBE_OFF_CPU_PROFILE,
+    ];
+
+    // an empty allow-list attaches all probes
+    pub fn probe_allowed(&self, probe: &str) -> bool {
+        self.probes.is_empty() || self.probes.iter().any(|p| p == probe)
+    }
 }
 
 impl Default for EbpfYamlConfig {
@@ -399,6 +785,7 @@ impl Default for EbpfYamlConfig {
             thread_num: 1,
             perf_pages_count: 128,
             ring_size: 65536,
+            poll_batch_size: 1024,
             max_socket_entries: 524288,
             max_trace_entries: 524288,
             socket_map_max_reclaim: 520000,
@@ -415,6 +802,7 @@ impl Default for EbpfYamlConfig {
             syscall_out_of_order_reassembly: vec![],
             syscall_segmentation_reassembly: vec![],
             syscall_out_of_order_cache_size: 16,
+            probes: vec![],
         }
     }
 }
@@ -507,6 +895,26 @@ pub struct L7LogBlacklist {
     pub value: String,
 }
 
+#[derive(Clone, Copy, Default, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NpbEncapsulationType {
+    #[default]
+    Vxlan,
+    Erspan,
+    Vlan,
+}
+
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NpbEncapsulationConfig {
+    pub destination: String,
+    #[serde(rename = "type")]
+    pub encapsulation_type: NpbEncapsulationType,
+    pub vni: Option<u32>,
+    pub vlan_id: Option<u16>,
+    pub erspan_session_id: Option<u16>,
+}
+
 #[derive(Clone, Copy, Default, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct OracleParseConfig {
@@ -521,6 +929,29 @@ pub struct BondGroup {
     pub tap_interfaces: Vec<String>,
 }
 
+// Interfaces captured together by a single dispatcher, entries are globs supporting
+// `*` and `?`, or regexes if prefixed with `regex:` like src-interfaces
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct InterfaceGroup {
+    pub interfaces: Vec<String>,
+}
+
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CapturePacketSizeOverride {
+    pub protocol: String, // tcp or udp
+    pub ports: String,    // e.g. "80,8080-8090"
+    pub capture_packet_size: u32,
+}
+
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CaptureBpfOverride {
+    pub dispatcher_index: usize,
+    pub capture_bpf: String,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct YamlConfig {
@@ -531,30 +962,61 @@ pub struct YamlConfig {
     pub af_packet_blocks_enabled: bool,
     #[serde(alias = "afpacket-blocks")]
     pub af_packet_blocks: usize,
+    pub af_packet_fanout: AfPacketFanoutConfig,
+    pub capture_timestamp_source: CaptureTimestampSource,
     pub enable_debug_stats: bool,
     pub analyzer_dedup_disabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub analyzer_dedup_window: Duration,
+    pub analyzer_dedup_table_size: usize,
+    pub analyzer_pps_threshold: u64,
     pub default_tap_type: u32,
+    // fall back to Cloud Network on invalid default_tap_type instead of failing to load
+    pub default_tap_type_lenient: bool,
+    pub debug_access: DebugAccessConfig,
     pub debug_listen_port: u16,
     pub enable_qos_bypass: bool,
+    pub export_fields_allowed: Vec<String>,
+    pub export_fields_denied: Vec<String>,
+    pub export_fields_hashed: Vec<String>,
     pub fast_path_map_size: usize,
     pub first_path_level: u32,
     pub local_dispatcher_count: usize,
     pub src_interfaces: Vec<String>,
     pub tap_interface_bond_groups: Vec<BondGroup>,
+    pub tap_interface_groups: Vec<InterfaceGroup>,
+    pub capture_packet_size_overrides: Vec<CapturePacketSizeOverride>,
+    // capture packet size follows the mtu of tap interfaces, ignoring capture_packet_size
+    pub capture_packet_size_auto: bool,
+    pub capture_bpf_overrides: Vec<CaptureBpfOverride>,
     pub mirror_traffic_pcp: u16,
+    pub mirror_traffic_pcp_tap_types: HashMap<u16, u32>,
     pub vtap_group_id_request: String,
     pub pcap: PcapConfig,
+    pub l4_flow_checkpoint: FlowCheckpointConfig,
     pub flow: FlowGeneratorConfig,
     pub flow_queue_size: usize,
     pub quadruple_queue_size: usize,
     pub analyzer_queue_size: usize,
     pub analyzer_raw_packet_block_size: usize,
     pub batched_buffer_size_limit: usize,
+    // idle batched buffers kept for reuse by each allocator, 0 means disabled
+    pub batched_buffer_pool_size: usize,
     pub dpdk_enabled: bool,
+    pub af_xdp_enabled: bool,
     pub dispatcher_queue: bool,
     pub libpcap_enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub pcap_interface_refresh_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub capture_warm_up: Duration,
+    pub capture_schedule: Vec<String>,
+    #[serde(with = "humantime_serde")]
+    pub disable_grace_period: Duration,
+    pub thread_stack_size: usize,
     pub xflow_collector: XflowGeneratorConfig,
     pub vxlan_flags: u8,
+    pub vxlan_ports: Vec<u16>,
     pub ignore_overlay_vlan: bool,
     pub collector_sender_queue_size: usize,
     pub collector_sender_queue_count: usize,
@@ -566,14 +1028,41 @@ pub struct YamlConfig {
     pub second_flow_extra_delay: Duration,
     #[serde(with = "humantime_serde")]
     pub packet_delay: Duration,
+    // Potential delay from other processing steps in flow_map
+    #[serde(with = "humantime_serde")]
+    pub common_delay: Duration,
+    // Potential delay from processing steps in quadruple_generator
+    #[serde(with = "humantime_serde")]
+    pub quadruple_process_delay: Duration,
+    // Extra tolerance for minute quadruple_generator and collector window
+    #[serde(with = "humantime_serde")]
+    pub minute_flow_extra_delay: Duration,
     pub triple: TripleMapConfig,
     pub kubernetes_poller_type: KubernetesPollerType,
+    pub collector_metrics_type: CollectorMetricsType,
+    pub metrics_excluded_tap_types: Vec<u16>,
+    pub collector_connection_lru_capacity: usize,
+    pub collector_stash_capacity: usize,
+    pub collector_stash_max_len: usize,
+    pub queue_overflow_policies: HashMap<String, QueueOverflowConfig>,
     pub trim_tunnel_types: Vec<String>,
     pub analyzer_ip: String,
+    pub analyzer_fallback_ips: Vec<String>,
+    #[serde(with = "humantime_serde")]
+    pub analyzer_ip_refresh_interval: Duration,
+    pub sender_balance: SenderBalance,
+    pub sender_batches: HashMap<String, SenderBatchConfig>,
+    pub observe_mode: bool,
+    #[serde(with = "humantime_serde")]
+    pub remote_log_reconnect_backoff_initial: Duration,
+    #[serde(with = "humantime_serde")]
+    pub remote_log_reconnect_backoff_max: Duration,
+    pub non_essential_component_failure: ComponentFailureAction,
     pub grpc_buffer_size: usize,
     #[serde(with = "humantime_serde")]
     pub l7_log_session_aggr_timeout: Duration,
     pub l7_log_session_slot_capacity: usize,
+    pub l7_log_sampling_rate: u32,
     pub tap_mac_script: String,
     pub cloud_gateway_traffic: bool,
     pub kubernetes_namespace: String,
@@ -585,14 +1074,24 @@ pub struct YamlConfig {
     pub ebpf_collector_queue_size: usize,
     pub l7_protocol_inference_max_fail_count: usize,
     pub l7_protocol_inference_ttl: usize,
+    // Enterprise Edition Feature: packet-sequence
+    // use packet_sequence_enabled() instead, None follows packet_sequence_flag
+    pub packet_sequence_enabled: Option<bool>,
     pub packet_sequence_block_size: usize, // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_queue_size: usize, // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_queue_count: usize, // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_flag: u8,          // Enterprise Edition Feature: packet-sequence
+    #[serde(with = "humantime_serde")]
+    pub packet_sequence_flush_interval: Duration, // Enterprise Edition Feature: packet-sequence
     pub feature_flags: Vec<String>,
     pub l7_protocol_enabled: Vec<String>,
     pub ebpf: EbpfYamlConfig,
     pub external_agent_http_proxy_compressed: bool,
+    // None means listening on all interfaces
+    pub external_agent_http_proxy_bind_address: Option<IpAddr>,
+    pub external_agent_http_proxy_max_body_size: usize,
+    pub external_agent_http_proxy_max_decompressed_body_size: usize,
+    pub external_agent_http_proxy_max_decompression_ratio: usize,
     pub standalone_data_file_size: u32,
     pub standalone_data_file_dir: String,
     pub log_file: String,
@@ -601,6 +1100,7 @@ pub struct YamlConfig {
     pub l7_protocol_ports: HashMap<String, String>,
     pub l7_log_blacklist: HashMap<String, Vec<L7LogBlacklist>>,
     pub npb_port: u16,
+    pub npb_encapsulations: Vec<NpbEncapsulationConfig>,
     // process and socket scan config
     pub os_proc_root: String,
     pub os_proc_socket_sync_interval: u32, // for sec
@@ -615,6 +1115,7 @@ pub struct YamlConfig {
     pub os_proc_sync_tagged_only: bool,
     #[serde(with = "humantime_serde")]
     pub guard_interval: Duration,
+    pub monitor: MonitorConfig,
     pub check_core_file_disabled: bool,
     pub memory_trim_disabled: bool,
     pub forward_capacity: usize,
@@ -627,6 +1128,7 @@ pub struct YamlConfig {
     pub prometheus_extra_config: PrometheusExtraConfig,
     pub process_scheduling_priority: i8,
     pub cpu_affinity: String,
+    pub control_plane_cpu_affinity: String,
     pub external_profile_integration_disabled: bool,
     pub external_trace_integration_disabled: bool,
     pub external_metric_integration_disabled: bool,
@@ -672,6 +1174,100 @@ impl YamlConfig {
             c.pcap.flush_interval = MINUTE;
         }
 
+        c.capture_packet_size_overrides.retain(|o| {
+            let valid = (o.protocol.eq_ignore_ascii_case("tcp")
+                || o.protocol.eq_ignore_ascii_case("udp"))
+                && parse_u16_range_list_to_bitmap(&o.ports, true).is_some()
+                && o.capture_packet_size >= 128
+                && o.capture_packet_size <= 65535;
+            if !valid {
+                warn!(
+                    "invalid capture-packet-size-overrides entry {:?}, ignored",
+                    o
+                );
+            }
+            valid
+        });
+        for field in c
+            .export_fields_allowed
+            .iter()
+            .chain(c.export_fields_denied.iter())
+        {
+            if !L4_EXPORT_FIELDS.contains(&field.as_str())
+                && !L7_EXPORT_FIELDS.contains(&field.as_str())
+            {
+                warn!("unknown export field {}, ignored", field);
+            }
+        }
+        for field in c.export_fields_hashed.iter() {
+            if !L4_HASHABLE_FIELDS.contains(&field.as_str())
+                && !L7_HASHABLE_FIELDS.contains(&field.as_str())
+            {
+                warn!("export field {} can not be hashed, ignored", field);
+            }
+        }
+        if c.external_agent_http_proxy_max_body_size < 1 << 20 {
+            warn!(
+                "external-agent-http-proxy-max-body-size {} less than 1048576, set to 1048576",
+                c.external_agent_http_proxy_max_body_size
+            );
+            c.external_agent_http_proxy_max_body_size = 1 << 20;
+        }
+        if c.external_agent_http_proxy_max_decompressed_body_size
+            < c.external_agent_http_proxy_max_body_size
+        {
+            warn!(
+                "external-agent-http-proxy-max-decompressed-body-size {} less than max-body-size, set to {}",
+                c.external_agent_http_proxy_max_decompressed_body_size,
+                c.external_agent_http_proxy_max_body_size
+            );
+            c.external_agent_http_proxy_max_decompressed_body_size =
+                c.external_agent_http_proxy_max_body_size;
+        }
+        c.analyzer_fallback_ips.retain(|ip| {
+            if ip.trim().is_empty() {
+                warn!("empty analyzer-fallback-ips entry, ignored");
+                return false;
+            }
+            true
+        });
+        c.capture_schedule
+            .retain(|s| match s.parse::<CaptureWindow>() {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!("invalid capture-schedule entry {}: {}, ignored", s, e);
+                    false
+                }
+            });
+        c.capture_bpf_overrides.retain(|o| {
+            if o.capture_bpf.trim().is_empty() {
+                warn!("invalid capture-bpf-overrides entry {:?}, ignored", o);
+                return false;
+            }
+            true
+        });
+        for (name, c) in c.queue_overflow_policies.iter_mut() {
+            if c.block_timeout < Duration::from_millis(1)
+                || c.block_timeout > Duration::from_secs(1)
+            {
+                warn!(
+                    "queue-overflow-policies {} block-timeout {:?} out of range [1ms, 1s], set to 100ms",
+                    name, c.block_timeout
+                );
+                c.block_timeout = Duration::from_millis(100);
+            }
+        }
+        c.mirror_traffic_pcp_tap_types.retain(|pcp, tap_type| {
+            if *pcp > 7 || *tap_type == 0 || *tap_type > 255 {
+                warn!(
+                    "invalid mirror-traffic-pcp-tap-types entry {}: {}, ignored",
+                    pcp, tap_type
+                );
+                return false;
+            }
+            true
+        });
+
         if c.flow.flush_interval < Duration::from_secs(1)
             || c.flow.flush_interval > Duration::from_secs(10)
         {
@@ -683,6 +1279,19 @@ impl YamlConfig {
         if c.quadruple_queue_size == 0 {
             c.quadruple_queue_size = 1 << 18;
         }
+        if c.collector_connection_lru_capacity == 0 {
+            c.collector_connection_lru_capacity = (c.flow.hash_slots << 3) as usize;
+        }
+        if c.collector_stash_capacity < 1024 {
+            c.collector_stash_capacity = 1024;
+        }
+        if c.collector_stash_max_len > 0 && c.collector_stash_max_len < c.collector_stash_capacity {
+            warn!(
+                "collector-stash-max-len {} is less than collector-stash-capacity {}, set to {}",
+                c.collector_stash_max_len, c.collector_stash_capacity, c.collector_stash_capacity
+            );
+            c.collector_stash_max_len = c.collector_stash_capacity;
+        }
         if c.analyzer_queue_size == 0 {
             c.analyzer_queue_size = 1 << 17;
         }
@@ -692,6 +1301,15 @@ impl YamlConfig {
         if c.batched_buffer_size_limit < 1024 {
             c.batched_buffer_size_limit = 1024;
         }
+        if c.batched_buffer_pool_size > MAX_BATCHED_BUFFER_POOL_SIZE {
+            warn!(
+                "batched-buffer-pool-size {} exceeds {}, use {} instead",
+                c.batched_buffer_pool_size,
+                MAX_BATCHED_BUFFER_POOL_SIZE,
+                MAX_BATCHED_BUFFER_POOL_SIZE
+            );
+            c.batched_buffer_pool_size = MAX_BATCHED_BUFFER_POOL_SIZE;
+        }
         if c.collector_sender_queue_size == 0 {
             c.collector_sender_queue_size = if tap_mode == trident::TapMode::Analyzer {
                 8 << 20
@@ -722,6 +1340,10 @@ impl YamlConfig {
             c.l7_log_session_slot_capacity = 1024;
         }
 
+        if c.l7_log_sampling_rate == 0 {
+            c.l7_log_sampling_rate = 1;
+        }
+
         if c.external_metrics_sender_queue_size == 0 {
             c.external_metrics_sender_queue_size = 1 << 12;
         }
@@ -743,6 +1365,11 @@ impl YamlConfig {
             c.packet_sequence_block_size = 256;
         }
 
+        // Enterprise Edition Feature: packet-sequence
+        if c.packet_sequence_flush_interval > Duration::from_secs(60) {
+            c.packet_sequence_flush_interval = Duration::from_secs(60);
+        }
+
         // Enterprise Edition Feature: packet-sequence
         if c.packet_sequence_queue_size == 0 {
             if tap_mode == trident::TapMode::Analyzer {
@@ -761,6 +1388,7 @@ impl YamlConfig {
             c.vxlan_flags = 0xff;
         }
         c.vxlan_flags |= 0x08;
+        c.vxlan_ports.retain(|port| *port != 0);
 
         if c.standalone_data_file_size == 0 {
             c.standalone_data_file_size = 200;
@@ -774,6 +1402,36 @@ impl YamlConfig {
                 .unwrap()
                 .to_string();
         }
+        if c.pcap.file_dir.len() == 0 {
+            c.pcap.file_dir = Path::new(&c.standalone_data_file_dir)
+                .join("pcap")
+                .to_str()
+                .unwrap()
+                .to_string();
+        }
+        if c.pcap.file_size == 0 {
+            c.pcap.file_size = 100;
+        }
+        if c.pcap.file_rotate_interval < MINUTE {
+            c.pcap.file_rotate_interval = MINUTE;
+        }
+        if c.pcap.file_max_count == 0 {
+            c.pcap.file_max_count = 1;
+        }
+        if c.l4_flow_checkpoint.dir.len() == 0 {
+            c.l4_flow_checkpoint.dir = Path::new(&c.standalone_data_file_dir)
+                .join("checkpoint")
+                .to_str()
+                .unwrap()
+                .to_string();
+        }
+        c.l4_flow_checkpoint.interval = c
+            .l4_flow_checkpoint
+            .interval
+            .clamp(Duration::from_secs(1), MINUTE);
+        if c.l4_flow_checkpoint.max_age < c.l4_flow_checkpoint.interval {
+            c.l4_flow_checkpoint.max_age = c.l4_flow_checkpoint.interval;
+        }
         if c.npb_port == 0 {
             c.npb_port = NPB_DEFAULT_PORT;
         }
@@ -786,6 +1444,66 @@ impl YamlConfig {
         if c.ebpf.ring_size < 8192 || c.ebpf.ring_size > 131072 {
             c.ebpf.ring_size = 65536;
         }
+        if c.ebpf.poll_batch_size < 64 || c.ebpf.poll_batch_size > 65536 {
+            c.ebpf.poll_batch_size = 1024;
+        }
+        if !c.pcap_interface_refresh_interval.is_zero()
+            && (c.pcap_interface_refresh_interval < Duration::from_secs(1)
+                || c.pcap_interface_refresh_interval > Duration::from_secs(3600))
+        {
+            c.pcap_interface_refresh_interval = Duration::from_secs(10);
+        }
+        if c.capture_warm_up > Duration::from_secs(300) {
+            c.capture_warm_up = Duration::from_secs(300);
+        }
+        if c.disable_grace_period > Duration::from_secs(300) {
+            c.disable_grace_period = Duration::from_secs(300);
+        }
+        if c.analyzer_dedup_window > Duration::from_secs(1) {
+            c.analyzer_dedup_window = Duration::from_secs(1);
+        }
+        if c.analyzer_dedup_table_size > 1 << 24 {
+            c.analyzer_dedup_table_size = 1 << 24;
+        }
+        if !c.analyzer_ip_refresh_interval.is_zero()
+            && (c.analyzer_ip_refresh_interval < Duration::from_secs(5)
+                || c.analyzer_ip_refresh_interval > Duration::from_secs(3600))
+        {
+            c.analyzer_ip_refresh_interval = Duration::from_secs(60);
+        }
+        for (name, b) in c.sender_batches.iter_mut() {
+            if !b.max_delay.is_zero()
+                && (b.max_delay < Duration::from_millis(10)
+                    || b.max_delay > Duration::from_secs(60))
+            {
+                warn!(
+                    "sender-batches {} max-delay {:?} out of range [10ms, 60s], batch delay is not limited",
+                    name, b.max_delay
+                );
+                b.max_delay = Duration::ZERO;
+            }
+        }
+        if c.remote_log_reconnect_backoff_initial < Duration::from_secs(1)
+            || c.remote_log_reconnect_backoff_initial > Duration::from_secs(300)
+        {
+            c.remote_log_reconnect_backoff_initial = Duration::from_secs(10);
+        }
+        if c.remote_log_reconnect_backoff_max < c.remote_log_reconnect_backoff_initial {
+            warn!(
+                "remote-log-reconnect-backoff-max {:?} less than remote-log-reconnect-backoff-initial {:?}, use {:?} instead",
+                c.remote_log_reconnect_backoff_max,
+                c.remote_log_reconnect_backoff_initial,
+                c.remote_log_reconnect_backoff_initial
+            );
+            c.remote_log_reconnect_backoff_max = c.remote_log_reconnect_backoff_initial;
+        } else if c.remote_log_reconnect_backoff_max > Duration::from_secs(3600) {
+            c.remote_log_reconnect_backoff_max = Duration::from_secs(3600);
+        }
+        if c.thread_stack_size != 0
+            && (c.thread_stack_size < 1 << 16 || c.thread_stack_size > 1 << 28)
+        {
+            c.thread_stack_size = 0;
+        }
         if c.ebpf.max_socket_entries < 100000 || c.ebpf.max_socket_entries > 2000000 {
             c.ebpf.max_socket_entries = 524288;
         }
@@ -813,11 +1531,53 @@ impl YamlConfig {
         if !(8..=1024).contains(&c.ebpf.syscall_out_of_order_cache_size) {
             c.ebpf.syscall_out_of_order_cache_size = 16;
         }
+        if !c.ebpf.probes.is_empty() {
+            c.ebpf.probes.retain(|p| {
+                let valid = EbpfYamlConfig::PROBES.contains(&p.as_str());
+                if !valid {
+                    warn!("invalid ebpf probe {}, ignored", p);
+                }
+                valid
+            });
+            if c.ebpf.probes.is_empty() {
+                warn!("no valid ebpf probe configured, all probes will be attached");
+            }
+        }
 
         if c.guard_interval < Duration::from_secs(1) || c.guard_interval > Duration::from_secs(3600)
         {
             c.guard_interval = Duration::from_secs(10);
         }
+        if c.debug_access.mode == DebugAccessMode::Token && c.debug_access.token.is_empty() {
+            warn!("debug-access mode is token but no token configured, only local access allowed");
+        }
+        if c.af_packet_fanout.dispatchers > MAX_AF_PACKET_FANOUT_DISPATCHERS {
+            warn!(
+                "af-packet-fanout dispatchers {} exceeds {}, use {} instead",
+                c.af_packet_fanout.dispatchers,
+                MAX_AF_PACKET_FANOUT_DISPATCHERS,
+                MAX_AF_PACKET_FANOUT_DISPATCHERS
+            );
+            c.af_packet_fanout.dispatchers = MAX_AF_PACKET_FANOUT_DISPATCHERS;
+        }
+        if c.monitor.interval < Duration::from_secs(1)
+            || c.monitor.interval > Duration::from_secs(3600)
+        {
+            warn!(
+                "monitor interval {:?} out of range [1s, 1h], use 10s instead",
+                c.monitor.interval
+            );
+            c.monitor.interval = Duration::from_secs(10);
+        }
+        if c.monitor.cpu_threshold > 100 {
+            c.monitor.cpu_threshold = 100;
+        }
+        if c.monitor.memory_threshold > 100 {
+            c.monitor.memory_threshold = 100;
+        }
+        if c.monitor.fd_limit_ratio > 100 {
+            c.monitor.fd_limit_ratio = 100;
+        }
 
         if c.kubernetes_api_list_limit < 10 {
             c.kubernetes_api_list_limit = 10;
@@ -831,6 +1591,14 @@ impl YamlConfig {
             c.forward_capacity = 1 << 14;
         }
 
+        if c.default_tap_type_lenient && !(1..=255).contains(&c.default_tap_type) {
+            warn!(
+                "default-tap-type {} not in [1, 255], set to 3 (Cloud Network)",
+                c.default_tap_type
+            );
+            c.default_tap_type = 3;
+        }
+
         if let Err(e) = c.validate() {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()));
         }
@@ -878,9 +1646,87 @@ impl YamlConfig {
     }
 
     fn validate(&self) -> Result<(), ConfigError> {
+        if !(1..=255).contains(&self.default_tap_type) {
+            return Err(ConfigError::YamlConfigInvalid(format!(
+                "default-tap-type {} not in [1, 255], set default-tap-type-lenient to fall back to 3",
+                self.default_tap_type
+            )));
+        }
         Ok(())
     }
 
+    pub fn get_queue_overflow_policy(&self, name: &str) -> OverflowPolicy {
+        self.queue_overflow_policies
+            .get(name)
+            .map(|c| OverflowPolicy::from(*c))
+            .unwrap_or_default()
+    }
+
+    // Fields feeding EbpfCollector can not be applied without restart, because EbpfCollector
+    // can not be recreated in the same process
+    pub fn restart_required_fields(&self, other: &Self) -> Vec<&'static str> {
+        let mut fields = vec![];
+        macro_rules! diff {
+            ($($field:ident => $name:literal),* $(,)?) => {
+                $(
+                    if self.$field != other.$field {
+                        fields.push($name);
+                    }
+                )*
+            };
+        }
+        macro_rules! diff_ebpf {
+            ($($field:ident => $name:literal),* $(,)?) => {
+                $(
+                    if self.ebpf.$field != other.ebpf.$field {
+                        fields.push(concat!("ebpf.", $name));
+                    }
+                )*
+            };
+        }
+        // Only fields used to initialize EbpfCollector, which is kept by components rebuild and
+        // can not be recreated in a process. Other ebpf fields are read when its threads start.
+        diff_ebpf!(
+            uprobe_proc_regexp => "uprobe-process-name-regexs",
+            probes => "probes",
+            kprobe_whitelist => "kprobe-whitelist",
+            kprobe_blacklist => "kprobe-blacklist",
+            syscall_segmentation_reassembly => "syscall-segmentation-reassembly",
+            go_tracing_timeout => "go-tracing-timeout",
+            io_event_collect_mode => "io-event-collect-mode",
+            io_event_minimal_duration => "io-event-minimal-duration",
+            thread_num => "thread-num",
+            perf_pages_count => "perf-pages-count",
+            ring_size => "ring-size",
+            max_socket_entries => "max-socket-entries",
+            max_trace_entries => "max-trace-entries",
+            socket_map_max_reclaim => "socket-map-max-reclaim",
+            java_symbol_file_max_space_limit => "java-symbol-file-max-space-limit",
+            java_symbol_file_refresh_defer_interval => "java-symbol-file-refresh-defer-interval",
+            on_cpu_profile => "on-cpu-profile",
+            off_cpu_profile => "off-cpu-profile",
+        );
+        diff!(
+            ebpf_collector_queue_size => "ebpf-collector-queue-size",
+            l7_protocol_enabled => "l7-protocol-enabled",
+            l7_protocol_ports => "l7-protocol-ports",
+            // flow senders of EbpfCollector are bound to the collectors of this type
+            collector_metrics_type => "collector-metrics-type",
+        );
+        fields
+    }
+
+    // Enterprise Edition Feature: packet-sequence
+    // enabled when packet-sequence-flag is set, unless packet-sequence-enabled is false
+    pub fn packet_sequence_enabled(&self) -> bool {
+        self.packet_sequence_enabled
+            .unwrap_or(self.packet_sequence_flag > 0)
+    }
+
+    pub fn restart_required(&self, other: &Self) -> bool {
+        !self.restart_required_fields(other).is_empty()
+    }
+
     pub fn get_protocol_port(&self) -> HashMap<String, String> {
         let mut new = self.l7_protocol_ports.clone();
 
@@ -935,32 +1781,56 @@ impl Default for YamlConfig {
             profiler: false,
             af_packet_blocks_enabled: false,
             af_packet_blocks: 128,
+            af_packet_fanout: AfPacketFanoutConfig::default(),
+            capture_timestamp_source: CaptureTimestampSource::Software,
             enable_debug_stats: false,
             analyzer_dedup_disabled: false,
+            analyzer_dedup_window: Duration::ZERO,
+            analyzer_dedup_table_size: 0,
+            analyzer_pps_threshold: 0,
             default_tap_type: 3,
+            default_tap_type_lenient: false,
+            debug_access: DebugAccessConfig::default(),
             debug_listen_port: 0,
             enable_qos_bypass: false,
+            export_fields_allowed: vec![],
+            export_fields_denied: vec![],
+            export_fields_hashed: vec![],
             fast_path_map_size: 1 << 14,
             first_path_level: 0,
             src_interfaces: vec![],
             tap_interface_bond_groups: vec![],
+            tap_interface_groups: vec![],
+            capture_packet_size_overrides: vec![],
+            capture_packet_size_auto: false,
+            capture_bpf_overrides: vec![],
             mirror_traffic_pcp: 0,
+            mirror_traffic_pcp_tap_types: HashMap::new(),
             vtap_group_id_request: "".into(),
             pcap: Default::default(),
+            l4_flow_checkpoint: Default::default(),
             flow: Default::default(),
             flow_queue_size: 65536,
             quadruple_queue_size: 262144,
             analyzer_queue_size: 131072,
             analyzer_raw_packet_block_size: 65536,
             batched_buffer_size_limit: 131072,
+            batched_buffer_pool_size: 0,
             dpdk_enabled: false,
+            af_xdp_enabled: false,
             dispatcher_queue: false,
             #[cfg(any(target_os = "linux", target_os = "android"))]
             libpcap_enabled: false,
             #[cfg(target_os = "windows")]
             libpcap_enabled: true,
+            pcap_interface_refresh_interval: Duration::from_secs(10),
+            capture_warm_up: Duration::ZERO,
+            capture_schedule: vec![],
+            disable_grace_period: Duration::ZERO,
+            thread_stack_size: 0,
             xflow_collector: Default::default(),
             vxlan_flags: 0xff,
+            vxlan_ports: vec![],
             ignore_overlay_vlan: false,
             // default size changes according to tap_mode
             collector_sender_queue_size: 1 << 16,
@@ -972,13 +1842,31 @@ impl Default for YamlConfig {
             flow_sender_queue_count: 1,
             second_flow_extra_delay: Duration::from_secs(0),
             packet_delay: Duration::from_secs(1),
+            common_delay: Duration::from_secs(5),
+            quadruple_process_delay: Duration::from_secs(5),
+            minute_flow_extra_delay: Duration::from_secs(0),
             triple: Default::default(),
             kubernetes_poller_type: KubernetesPollerType::Adaptive,
+            collector_metrics_type: CollectorMetricsType::Both,
+            metrics_excluded_tap_types: vec![],
+            collector_connection_lru_capacity: 0,
+            collector_stash_capacity: 1024,
+            collector_stash_max_len: 0,
+            queue_overflow_policies: HashMap::new(),
             trim_tunnel_types: vec![],
             analyzer_ip: "".into(),
+            analyzer_fallback_ips: vec![],
+            analyzer_ip_refresh_interval: Duration::from_secs(60),
+            sender_balance: SenderBalance::Failover,
+            sender_batches: HashMap::new(),
+            observe_mode: false,
+            remote_log_reconnect_backoff_initial: Duration::from_secs(10),
+            remote_log_reconnect_backoff_max: Duration::from_secs(300),
+            non_essential_component_failure: ComponentFailureAction::Warn,
             grpc_buffer_size: 5,
             l7_log_session_aggr_timeout: Duration::from_secs(120),
             l7_log_session_slot_capacity: 1024,
+            l7_log_sampling_rate: 1,
             tap_mac_script: "".into(),
             cloud_gateway_traffic: false,
             kubernetes_namespace: "".into(),
@@ -988,10 +1876,12 @@ impl Default for YamlConfig {
             external_metrics_sender_queue_size: 1 << 12,
             l7_protocol_inference_max_fail_count: L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT,
             l7_protocol_inference_ttl: L7_PROTOCOL_INFERENCE_TTL,
+            packet_sequence_enabled: None, // Enterprise Edition Feature: packet-sequence
             packet_sequence_block_size: 256, // Enterprise Edition Feature: packet-sequence
             packet_sequence_queue_size: 1 << 16, // Enterprise Edition Feature: packet-sequence
-            packet_sequence_queue_count: 1,  // Enterprise Edition Feature: packet-sequence
-            packet_sequence_flag: 0,         // Enterprise Edition Feature: packet-sequence
+            packet_sequence_queue_count: 1, // Enterprise Edition Feature: packet-sequence
+            packet_sequence_flag: 0,       // Enterprise Edition Feature: packet-sequence
+            packet_sequence_flush_interval: Duration::ZERO, // Enterprise Edition Feature: packet-sequence
             feature_flags: vec![],
             l7_protocol_enabled: {
                 let mut protos = vec![];
@@ -1003,6 +1893,10 @@ impl Default for YamlConfig {
                 protos
             },
             external_agent_http_proxy_compressed: false,
+            external_agent_http_proxy_bind_address: None,
+            external_agent_http_proxy_max_body_size: 32 << 20,
+            external_agent_http_proxy_max_decompressed_body_size: 128 << 20,
+            external_agent_http_proxy_max_decompression_ratio: 100,
             standalone_data_file_size: 200,
             standalone_data_file_dir: Path::new(DEFAULT_LOG_FILE)
                 .parent()
@@ -1019,6 +1913,7 @@ impl Default for YamlConfig {
             l7_log_blacklist: HashMap::new(),
             ebpf: EbpfYamlConfig::default(),
             npb_port: NPB_DEFAULT_PORT,
+            npb_encapsulations: vec![],
             os_proc_root: "/proc".into(),
             os_proc_socket_sync_interval: 10,
             os_proc_socket_min_lifetime: 3,
@@ -1033,6 +1928,7 @@ impl Default for YamlConfig {
             os_proc_sync_enabled: false,
             os_proc_sync_tagged_only: false,
             guard_interval: Duration::from_secs(10),
+            monitor: MonitorConfig::default(),
             check_core_file_disabled: false,
             memory_trim_disabled: false,
             fast_path_disabled: false,
@@ -1042,6 +1938,7 @@ impl Default for YamlConfig {
             prometheus_extra_config: PrometheusExtraConfig::default(),
             process_scheduling_priority: 0,
             cpu_affinity: "".to_string(),
+            control_plane_cpu_affinity: "".to_string(),
             external_profile_integration_disabled: false,
             external_trace_integration_disabled: false,
             external_metric_integration_disabled: false,
@@ -1109,6 +2006,36 @@ pub struct PcapConfig {
     pub flush_interval: Duration,
     pub buffer_size: u64,
     pub flow_buffer_size: u32,
+    // local pcap files, only used in standalone mode
+    pub file_dir: String,
+    pub file_size: u32, // unit: MB
+    #[serde(with = "humantime_serde")]
+    pub file_rotate_interval: Duration,
+    pub file_max_count: u32,
+}
+
+// Checkpoints of flows aggregated by l4 flow aggr, reloaded on the next start
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct FlowCheckpointConfig {
+    pub enabled: bool,
+    pub dir: String,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    // checkpoints older than this are discarded on start
+    #[serde(with = "humantime_serde")]
+    pub max_age: Duration,
+}
+
+impl Default for FlowCheckpointConfig {
+    fn default() -> Self {
+        FlowCheckpointConfig {
+            enabled: false,
+            dir: "".to_string(),
+            interval: Duration::from_secs(10),
+            max_age: Duration::from_secs(120),
+        }
+    }
 }
 
 impl Default for PcapConfig {
@@ -1118,6 +2045,10 @@ impl Default for PcapConfig {
             flush_interval: Duration::from_secs(60),
             buffer_size: 96 << 10,      // 96K
             flow_buffer_size: 64 << 10, // 64K
+            file_dir: "".to_string(),
+            file_size: 100,
+            file_rotate_interval: Duration::from_secs(600),
+            file_max_count: 10,
         }
     }
 }
@@ -1212,6 +2143,186 @@ pub enum KubernetesPollerType {
     Passive,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollectorMetricsType {
+    Second,
+    Minute,
+    Both,
+}
+
+// Action on construction failures of components not required for capturing and sending flows
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComponentFailureAction {
+    #[default]
+    Warn,
+    Abort,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SenderBalance {
+    #[default]
+    Failover,
+    RoundRobin,
+    Hash,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureTimestampSource {
+    #[default]
+    Software,
+    Kernel,
+    Hardware,
+}
+
+impl fmt::Display for CaptureTimestampSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Software => write!(f, "software"),
+            Self::Kernel => write!(f, "kernel"),
+            Self::Hardware => write!(f, "hardware"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum DebugAccessMode {
+    // loopback and controller ips only
+    #[default]
+    Local,
+    // local access, and clients authenticated with the token
+    Token,
+    // any source address
+    Any,
+}
+
+// Access control for requests received by the debugger (deepflow-agent-ctl)
+#[derive(Default, Deserialize, PartialEq, Eq, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DebugAccessConfig {
+    pub mode: DebugAccessMode,
+    pub token: String,
+}
+
+impl fmt::Debug for DebugAccessConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DebugAccessConfig")
+            .field("mode", &self.mode)
+            .field("token", &if self.token.is_empty() { "" } else { "******" })
+            .finish()
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum AfPacketFanoutMode {
+    // by flow hash, fragments are defragmented before hashing
+    #[default]
+    Hash,
+    // by the cpu the packet is received on
+    Cpu,
+}
+
+const MAX_AF_PACKET_FANOUT_DISPATCHERS: usize = 64;
+const MAX_BATCHED_BUFFER_POOL_SIZE: usize = 1024;
+
+// Mirror and analyzer mode only, each tap interface is captured by `dispatchers` dispatchers
+// in a fanout group with id `group_id` plus the interface index
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AfPacketFanoutConfig {
+    // 0 or 1 means disabled
+    pub dispatchers: usize,
+    pub mode: AfPacketFanoutMode,
+    pub group_id: u16,
+}
+
+impl AfPacketFanoutConfig {
+    pub fn enabled(&self) -> bool {
+        self.dispatchers > 1
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueOverflowPolicy {
+    #[default]
+    Drop,
+    BlockWithTimeout,
+}
+
+// A batch is sent when any of the limits is reached, besides the encoded size limit
+// and the queue read timeout
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SenderBatchConfig {
+    // 0 means unlimited
+    pub max_items: usize,
+    // 0 means unlimited
+    #[serde(with = "humantime_serde")]
+    pub max_delay: Duration,
+}
+
+// Sampled by monitor, exceptions are raised when any threshold is exceeded
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MonitorConfig {
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    // percentage of max-millicpus, 0 means disabled
+    pub cpu_threshold: u32,
+    // percentage of max-memory, 0 means disabled
+    pub memory_threshold: u32,
+    // number of open file descriptors, 0 means disabled
+    pub fd_threshold: u32,
+    // percentage of the soft fd limit (RLIMIT_NOFILE), 0 means disabled
+    pub fd_limit_ratio: u32,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            cpu_threshold: 0,
+            memory_threshold: 0,
+            fd_threshold: 0,
+            fd_limit_ratio: 90,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct QueueOverflowConfig {
+    pub policy: QueueOverflowPolicy,
+    #[serde(with = "humantime_serde")]
+    pub block_timeout: Duration,
+}
+
+impl Default for QueueOverflowConfig {
+    fn default() -> Self {
+        Self {
+            policy: QueueOverflowPolicy::Drop,
+            block_timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+impl From<QueueOverflowConfig> for OverflowPolicy {
+    fn from(c: QueueOverflowConfig) -> Self {
+        match c.policy {
+            QueueOverflowPolicy::Drop => OverflowPolicy::Drop,
+            QueueOverflowPolicy::BlockWithTimeout => {
+                OverflowPolicy::BlockWithTimeout(c.block_timeout)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(default = "RuntimeConfig::standalone_default")]
 pub struct RuntimeConfig {
@@ -1520,10 +2631,20 @@ impl RuntimeConfig {
         }
 
         if self.proxy_controller_port == 0 {
-            return Err(ConfigError::RuntimeConfigInvalid(format!(
-                "proxy-controller-port({}) invalid",
-                self.proxy_controller_port
-            )));
+            // the port is used for both direct and proxied controller connections after the first sync
+            return Err(ConfigError::RuntimeConfigInvalid(
+                if self.proxy_controller_ip.is_empty() {
+                    format!(
+                        "proxy-controller-port({}) invalid",
+                        self.proxy_controller_port
+                    )
+                } else {
+                    format!(
+                        "proxy-controller-port({}) invalid, required by proxy-controller-ip({})",
+                        self.proxy_controller_port, self.proxy_controller_ip
+                    )
+                },
+            ));
         }
 
         if self.capture_packet_size > 65535 || self.capture_packet_size < 128 {
@@ -1846,23 +2967,99 @@ where
 }
 
 // resolve domain name (without port) to ip address
-fn resolve_domain(addr: &str) -> Option<String> {
-    match format!("{}:1", addr).to_socket_addrs() {
-        Ok(mut addr) => match addr.next() {
-            Some(addr) => Some(addr.ip().to_string()),
-            None => None,
-        },
-        Err(e) => {
-            eprintln!("{:?}", e);
-            None
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ResolvedControllers {
+    pub ips: Vec<String>,
+    pub ports: HashMap<String, u16>,
+    pub domain_names: Vec<String>,
+}
+
+// RFC 1123 host name, labels of letters, digits and hyphens not starting or ending with hyphen.
+// Labels may start with an underscore for SRV names like `_deepflow._tcp.example.com`
+fn is_valid_hostname(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            let label = label.strip_prefix('_').unwrap_or(label);
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+// Resolves domain names in controller-ips, with srv_lookup SRV records are tried before
+// A records and a domain name may resolve to several controllers with their own ports.
+// Returns all invalid entries with reasons if any entry is malformed or fails to resolve.
+pub fn resolve_controller_addrs(
+    addrs: &[String],
+    srv_lookup: bool,
+    resolver: &dns::Resolver,
+) -> Result<ResolvedControllers, Vec<String>> {
+    let mut resolved = ResolvedControllers::default();
+    let mut invalid = vec![];
+    for addr in addrs {
+        if addr.parse::<IpAddr>().is_ok() {
+            resolved.ips.push(addr.clone());
+            continue;
+        }
+        if !is_valid_hostname(addr) {
+            invalid.push(format!("{:?} is neither an ip nor a host name", addr));
+            continue;
+        }
+        resolved.domain_names.push(addr.clone());
+        if srv_lookup {
+            let targets = resolver.resolve_controller_srv(addr);
+            if !targets.is_empty() {
+                debug!("controller {} resolved by srv to {:?}", addr, targets);
+                for (ip, port) in targets {
+                    let ip = ip.to_string();
+                    if !resolved.ips.contains(&ip) {
+                        resolved.ports.insert(ip.clone(), port);
+                        resolved.ips.push(ip);
+                    }
+                }
+                continue;
+            }
+        }
+        match resolver.lookup_host(addr) {
+            Ok(ips) if !ips.is_empty() => resolved.ips.push(ips[0].to_string()),
+            Ok(_) => invalid.push(format!("{} resolved to no address", addr)),
+            Err(e) => {
+                eprintln!("resolve {} with {} failed: {:?}", addr, resolver, e);
+                invalid.push(format!("{} resolve failed: {}", addr, e));
+            }
         }
     }
+    if invalid.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(invalid)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn controller_no_proxy() {
+        env::set_var("NO_PROXY", "controller.local,10.1.1.1");
+        let mut config = Config::default();
+        assert_eq!(
+            config.get_controller_no_proxy().as_deref(),
+            Some("controller.local,10.1.1.1")
+        );
+        // configured controller-proxy is used for all controllers
+        config.controller_proxy = "http://10.2.2.2:3128".to_owned().into();
+        assert_eq!(config.get_controller_no_proxy(), None);
+        env::remove_var("NO_PROXY");
+    }
+
     #[test]
     fn read_yaml_file() {
         // TODO: improve test cases
@@ -1871,4 +3068,116 @@ mod tests {
         assert_eq!(c.controller_ips.len(), 1);
         assert_eq!(&c.controller_ips[0], "127.0.0.1");
     }
+
+    #[test]
+    fn load_config_fragments() {
+        let dir = tempfile::tempdir().unwrap();
+        let fragments = [
+            (
+                "00-base.yaml",
+                "controller-ips:\n  - 127.0.0.1\ncontroller-port: 30035\nteam-id: a\n",
+            ),
+            ("10-override.yaml", "controller-port: 30036\n"),
+            ("20-empty.yaml", "# nothing here\n"),
+            ("30-ignored.yml", "team-id: b\n"),
+        ];
+        for (name, contents) in fragments {
+            fs::write(dir.path().join(name), contents).unwrap();
+        }
+        let c = Config::load_from_file(dir.path()).expect("failed loading config fragments");
+        assert_eq!(c.controller_ips, vec!["127.0.0.1".to_owned()]);
+        assert_eq!(c.controller_port, 30036);
+        assert_eq!(c.team_id, "a");
+    }
+
+    #[test]
+    fn load_controller_timeouts() {
+        let c = Config::load("controller-timeouts:\n  sync: 3s\n  resource-sync: 1h\n")
+            .expect("failed loading controller timeouts");
+        assert_eq!(c.controller_timeouts.sync, Duration::from_secs(3));
+        assert_eq!(c.controller_timeouts.config_fetch, GRPC_SESSION_TIMEOUT);
+        // out of range
+        assert_eq!(c.controller_timeouts.resource_sync, GRPC_SESSION_TIMEOUT);
+        assert_eq!(c.controller_timeouts.max(), GRPC_SESSION_TIMEOUT);
+    }
+
+    #[test]
+    fn validate_default_tap_type() {
+        assert!(YamlConfig::load("default-tap-type: 256\n", TapMode::Mirror).is_err());
+        assert!(YamlConfig::load("default-tap-type: 0\n", TapMode::Mirror).is_err());
+        let c = YamlConfig::load(
+            "default-tap-type: 256\ndefault-tap-type-lenient: true\n",
+            TapMode::Mirror,
+        )
+        .unwrap();
+        assert_eq!(c.default_tap_type, 3);
+        let c = YamlConfig::load("default-tap-type: 4\n", TapMode::Mirror).unwrap();
+        assert_eq!(c.default_tap_type, 4);
+    }
+
+    #[test]
+    fn packet_sequence_enabled_by_flag() {
+        let c = YamlConfig::load("packet-sequence-flag: 1", TapMode::Local).unwrap();
+        assert!(c.packet_sequence_enabled());
+        let c = YamlConfig::load(
+            "packet-sequence-flag: 1\npacket-sequence-enabled: false",
+            TapMode::Local,
+        )
+        .unwrap();
+        assert!(!c.packet_sequence_enabled());
+        let c = YamlConfig::load("packet-sequence-enabled: true", TapMode::Local).unwrap();
+        assert!(c.packet_sequence_enabled());
+        assert!(!YamlConfig::default().packet_sequence_enabled());
+    }
+
+    #[test]
+    fn yaml_config_restart_required() {
+        let old = YamlConfig::default();
+        let mut new = old.clone();
+        new.flow_queue_size += 1;
+        assert!(!old.restart_required(&new));
+
+        new.ebpf.disabled = !old.ebpf.disabled;
+        new.ebpf.poll_batch_size += 1;
+        new.ebpf.global_ebpf_pps_threshold += 1;
+        new.l7_log_session_aggr_timeout += Duration::from_secs(1);
+        new.l7_protocol_inference_ttl += 1;
+        assert!(!old.restart_required(&new));
+
+        new.ebpf.ring_size += 1;
+        new.l7_protocol_enabled.push("HTTP".to_owned());
+        assert_eq!(
+            old.restart_required_fields(&new),
+            vec!["ebpf.ring-size", "l7-protocol-enabled"]
+        );
+    }
+
+    #[test]
+    fn resolve_malformed_controller_addrs() {
+        let addrs = ["127.0.0.1", "a b", "-bad.example", "::1"].map(|s| s.to_owned());
+        let resolver = dns::Resolver::new(vec![], false);
+        let invalid = resolve_controller_addrs(&addrs, false, &resolver).unwrap_err();
+        assert_eq!(invalid.len(), 2);
+        assert!(invalid[0].contains("a b"));
+        assert!(invalid[1].contains("-bad.example"));
+        assert!(is_valid_hostname("deepflow-server.deepflow.svc."));
+        assert!(is_valid_hostname("_deepflow._tcp.example.com"));
+        assert!(!is_valid_hostname("_.example.com"));
+        assert!(!is_valid_hostname("__a.example.com"));
+    }
+
+    #[test]
+    fn parse_hostname_source() {
+        for (s, expected) in [
+            ("os", Some(HostnameSource::Os)),
+            ("k8s_node", Some(HostnameSource::K8sNode)),
+            ("env:NODE", Some(HostnameSource::Env("NODE".to_owned()))),
+            ("static:a:b", Some(HostnameSource::Static("a:b".to_owned()))),
+            ("static:", None),
+            ("pod", None),
+        ] {
+            let parsed = serde_yaml::from_str::<HostnameSource>(s).ok();
+            assert_eq!(parsed, expected, "parsing {}", s);
+        }
+    }
 }
@@ -32,7 +32,7 @@ use flexi_logger::{
     Naming,
 };
 use http2::get_expected_headers;
-use log::{info, warn, Level};
+use log::{debug, info, warn, Level};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use nix::{
     sched::{sched_setaffinity, CpuSet},
@@ -43,7 +43,7 @@ use sysinfo::SystemExt;
 use sysinfo::{CpuRefreshKind, RefreshKind, System};
 use tokio::runtime::Runtime;
 
-use super::config::{ExtraLogFields, L7LogBlacklist, OracleParseConfig};
+use super::config::{ExtraLogFields, L7LogBlacklist, NpbEncapsulationConfig, OracleParseConfig};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use super::{
     config::EbpfYamlConfig, OsProcRegexp, OS_PROC_REGEXP_MATCH_ACTION_ACCEPT,
@@ -51,10 +51,11 @@ use super::{
 };
 use super::{
     config::{
-        Config, HttpEndpointExtraction, KubernetesResourceConfig, MatchRule, PcapConfig,
-        PortConfig, YamlConfig,
+        Config, HostnameSource, HttpEndpointExtraction, KubernetesResourceConfig, MatchRule,
+        PcapConfig, PortConfig, YamlConfig,
     },
-    ConfigError, KubernetesPollerType, RuntimeConfig,
+    ConfigError, DebugAccessConfig, FlowCheckpointConfig, KubernetesPollerType, MonitorConfig,
+    RuntimeConfig, SenderBalance, SenderBatchConfig,
 };
 use crate::flow_generator::protocol_logs::decode_new_rpc_trace_context_with_type;
 use crate::rpc::Session;
@@ -74,8 +75,8 @@ use crate::{
     ebpf::CAP_LEN_MAX,
     platform::ProcRegRewrite,
     utils::environment::{
-        get_container_resource_limits, get_ctrl_ip_and_mac, is_tt_workload,
-        set_container_resource_limit,
+        bind_control_plane_threads, get_container_resource_limits, get_ctrl_ip_and_mac,
+        is_tt_workload, set_container_resource_limit,
     },
 };
 #[cfg(target_os = "linux")]
@@ -90,6 +91,7 @@ use public::proto::{
     common::TridentType,
     trident::{self, CaptureSocketType, Exception, IfMacSource, SocketType, TapMode},
 };
+use public::sender::FieldFilter;
 
 use crate::{trident::AgentId, utils::cgroups::is_kernel_available_for_cgroups};
 use public::utils::net::MacAddr;
@@ -116,6 +118,8 @@ pub type DiagnoseAccess = Access<DiagnoseConfig>;
 
 pub type LogAccess = Access<LogConfig>;
 
+pub type ReconnectBackoffAccess = Access<ReconnectBackoff>;
+
 pub type FlowAccess = Access<FlowConfig>;
 
 pub type LogParserAccess = Access<LogParserConfig>;
@@ -142,11 +146,14 @@ pub struct CollectorConfig {
     pub l4_log_collect_nps_threshold: u64,
     pub l4_log_store_tap_types: [bool; 256],
     pub l4_log_ignore_tap_sides: [bool; TapSide::MAX as usize + 1],
+    pub metrics_excluded_tap_types: [bool; 256],
     pub l7_metrics_enabled: bool,
     pub trident_type: TridentType,
     pub vtap_id: u16,
     pub cloud_gateway_traffic: bool,
     pub packet_delay: Duration,
+    pub thread_stack_size: usize,
+    pub l4_flow_checkpoint: FlowCheckpointConfig,
 }
 
 impl fmt::Debug for CollectorConfig {
@@ -183,6 +190,16 @@ impl fmt::Debug for CollectorConfig {
                     })
                     .collect::<Vec<_>>(),
             )
+            .field(
+                "metrics_excluded_tap_types",
+                &self
+                    .metrics_excluded_tap_types
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, b)| *b)
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>(),
+            )
             .field(
                 "l4_log_collect_nps_threshold",
                 &self.l4_log_collect_nps_threshold,
@@ -192,6 +209,8 @@ impl fmt::Debug for CollectorConfig {
             .field("vtap_id", &self.vtap_id)
             .field("cloud_gateway_traffic", &self.cloud_gateway_traffic)
             .field("packet_delay", &self.packet_delay)
+            .field("thread_stack_size", &self.thread_stack_size)
+            .field("l4_flow_checkpoint", &self.l4_flow_checkpoint)
             .finish()
     }
 }
@@ -208,12 +227,17 @@ pub struct EnvironmentConfig {
     pub system_load_circuit_breaker_threshold: f32,
     pub system_load_circuit_breaker_recover: f32,
     pub system_load_circuit_breaker_metric: trident::SystemLoadMetric,
+    pub monitor: MonitorConfig,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct SenderConfig {
     pub mtu: u32,
     pub dest_ip: String,
+    pub dest_fallback_ips: Vec<String>,
+    pub balance: SenderBalance,
+    // keyed by sender name
+    pub batches: HashMap<String, SenderBatchConfig>,
     pub vtap_id: u16,
     pub team_id: u32,
     pub organize_id: u32,
@@ -232,6 +256,9 @@ pub struct SenderConfig {
     pub server_tx_bandwidth_threshold: u64,
     pub bandwidth_probe_interval: Duration,
     pub enabled: bool,
+    // count and drop data instead of sending
+    pub observe_mode: bool,
+    pub export_field_filter: FieldFilter,
 }
 
 impl Default for SenderConfig {
@@ -254,6 +281,7 @@ pub struct NpbConfig {
     pub socket_type: trident::SocketType,
     pub ignore_overlay_vlan: bool,
     pub queue_size: usize,
+    pub encapsulations: Vec<NpbEncapsulationConfig>,
 }
 
 impl Default for NpbConfig {
@@ -315,6 +343,7 @@ pub struct HandlerConfig {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct DispatcherConfig {
     pub global_pps_threshold: u64,
+    pub analyzer_pps_threshold: u64,
     pub capture_packet_size: u32,
     pub l7_log_packet_size: u32,
     pub tunnel_type_bitmap: TunnelTypeBitmap,
@@ -345,6 +374,21 @@ pub struct DispatcherConfig {
     pub bond_group: Vec<String>,
 }
 
+impl DispatcherConfig {
+    // threshold of rx_leaky_bucket, None means unlimited
+    pub fn rx_pps_threshold(&self) -> Option<u64> {
+        let threshold = match self.tap_mode {
+            TapMode::Analyzer => self.analyzer_pps_threshold,
+            _ => self.global_pps_threshold,
+        };
+        if threshold == 0 {
+            None
+        } else {
+            Some(threshold)
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct LogConfig {
     pub log_level: Level,
@@ -352,6 +396,15 @@ pub struct LogConfig {
     pub log_retention: u32,
     pub rsyslog_enabled: bool,
     pub host: String,
+    pub reconnect_backoff: ReconnectBackoff,
+}
+
+// Interval between reconnections to the ingester, doubled on each consecutive failure
+// from initial up to max
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ReconnectBackoff {
+    pub initial: Duration,
+    pub max: Duration,
 }
 
 #[derive(Clone)]
@@ -463,6 +516,7 @@ pub struct FlowConfig {
     pub rrt_udp_timeout: usize, //micro sec
 
     pub batched_buffer_size_limit: usize,
+    pub batched_buffer_pool_size: usize,
 
     pub oracle_parse_conf: OracleParseConfig,
 
@@ -561,6 +615,7 @@ impl From<&RuntimeConfig> for FlowConfig {
             rrt_tcp_timeout: conf.yaml_config.rrt_tcp_timeout.as_micros() as usize,
             rrt_udp_timeout: conf.yaml_config.rrt_udp_timeout.as_micros() as usize,
             batched_buffer_size_limit: conf.yaml_config.batched_buffer_size_limit,
+            batched_buffer_pool_size: conf.yaml_config.batched_buffer_pool_size,
             oracle_parse_conf: conf.yaml_config.oracle_parse_config,
             obfuscate_enabled_protocols: L7ProtocolBitmap::from(
                 &conf
@@ -862,15 +917,19 @@ pub struct LogParserConfig {
     pub l7_log_collect_nps_threshold: u64,
     pub l7_log_session_aggr_timeout: Duration,
     pub l7_log_session_slot_capacity: usize,
+    pub l7_log_sampling_rate: u32,
     pub l7_log_dynamic: L7LogDynamicConfig,
     pub l7_log_ignore_tap_sides: [bool; TapSide::MAX as usize + 1],
     pub http_endpoint_disabled: bool,
     pub http_endpoint_trie: HttpEndpointTrie,
     pub obfuscate_enabled_protocols: L7ProtocolBitmap,
+    // protocols identified without inference (eBPF or app table) are checked against this
+    pub l7_protocol_enabled_bitmap: L7ProtocolBitmap,
     pub l7_log_blacklist: HashMap<String, Vec<L7LogBlacklist>>,
     pub l7_log_blacklist_trie: HashMap<L7Protocol, BlacklistTrie>,
     pub unconcerned_dns_nxdomain_response_suffixes: Vec<String>,
     pub unconcerned_dns_nxdomain_trie: DnsNxdomainTrie,
+    pub thread_stack_size: usize,
 }
 
 impl Default for LogParserConfig {
@@ -879,15 +938,18 @@ impl Default for LogParserConfig {
             l7_log_collect_nps_threshold: 0,
             l7_log_session_aggr_timeout: Duration::ZERO,
             l7_log_session_slot_capacity: 1024,
+            l7_log_sampling_rate: 1,
             l7_log_dynamic: L7LogDynamicConfig::default(),
             l7_log_ignore_tap_sides: [false; TapSide::MAX as usize + 1],
             http_endpoint_disabled: false,
             http_endpoint_trie: HttpEndpointTrie::new(),
             obfuscate_enabled_protocols: L7ProtocolBitmap::default(),
+            l7_protocol_enabled_bitmap: L7ProtocolBitmap::default(),
             l7_log_blacklist: HashMap::new(),
             l7_log_blacklist_trie: HashMap::new(),
             unconcerned_dns_nxdomain_response_suffixes: vec![],
             unconcerned_dns_nxdomain_trie: DnsNxdomainTrie::default(),
+            thread_stack_size: 0,
         }
     }
 }
@@ -907,6 +969,7 @@ impl fmt::Debug for LogParserConfig {
                 "l7_log_session_slot_capacity",
                 &self.l7_log_session_slot_capacity,
             )
+            .field("l7_log_sampling_rate", &self.l7_log_sampling_rate)
             .field("l7_log_dynamic", &self.l7_log_dynamic)
             .field(
                 "l7_log_ignore_tap_sides",
@@ -923,11 +986,16 @@ impl fmt::Debug for LogParserConfig {
                     })
                     .collect::<Vec<_>>(),
             )
+            .field(
+                "l7_protocol_enabled_bitmap",
+                &self.l7_protocol_enabled_bitmap,
+            )
             .field("l7_log_blacklist_trie", &self.l7_log_blacklist)
             .field(
                 "unconcerned_dns_nxdomain_trie",
                 &self.unconcerned_dns_nxdomain_response_suffixes,
             )
+            .field("thread_stack_size", &self.thread_stack_size)
             .finish()
     }
 }
@@ -940,6 +1008,7 @@ pub struct DebugConfig {
     pub controller_port: u16,
     pub listen_port: u16,
     pub agent_mode: RunningMode,
+    pub access: DebugAccessConfig,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -968,6 +1037,7 @@ pub struct SynchronizerConfig {
 #[derive(Clone, PartialEq, Eq)]
 pub struct EbpfConfig {
     // 动态配置
+    pub enabled: bool,
     pub collector_enabled: bool,
     pub l7_metrics_enabled: bool,
     pub vtap_id: u16,
@@ -990,6 +1060,7 @@ pub struct EbpfConfig {
 impl fmt::Debug for EbpfConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("EbpfConfig")
+            .field("enabled", &self.enabled)
             .field("collector_enabled", &self.collector_enabled)
             .field("l7_metrics_enabled", &self.l7_metrics_enabled)
             .field("vtap_id", &self.vtap_id)
@@ -1369,6 +1440,7 @@ impl L7LogDynamicConfig {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct MetricServerConfig {
     pub enabled: bool,
+    pub bind_address: Option<IpAddr>,
     pub port: u16,
     pub compressed: bool,
 }
@@ -1427,6 +1499,14 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.to_string(),
             }
         };
+        // hostname from hostname-source takes precedence over the one configured on controller
+        let host = match static_config.hostname_source {
+            HostnameSource::Os => conf.host.clone(),
+            _ => static_config
+                .override_os_hostname
+                .clone()
+                .unwrap_or_else(|| conf.host.clone()),
+        };
         let proxy_controller_ip = if conf.proxy_controller_ip.len() > 0 {
             conf.proxy_controller_ip.clone()
         } else {
@@ -1452,6 +1532,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 system_load_circuit_breaker_threshold: conf.system_load_circuit_breaker_threshold,
                 system_load_circuit_breaker_recover: conf.system_load_circuit_breaker_recover,
                 system_load_circuit_breaker_metric: conf.system_load_circuit_breaker_metric,
+                monitor: conf.yaml_config.monitor,
             },
             synchronizer: SynchronizerConfig {
                 sync_interval: Duration::from_secs(conf.sync_interval),
@@ -1461,12 +1542,13 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
             },
             stats: StatsConfig {
                 interval: Duration::from_secs(conf.stats_interval),
-                host: conf.host.clone(),
+                host: host.clone(),
                 analyzer_ip: dest_ip.clone(),
                 analyzer_port: conf.analyzer_port,
             },
             dispatcher: DispatcherConfig {
                 global_pps_threshold: conf.global_pps_threshold,
+                analyzer_pps_threshold: conf.yaml_config.analyzer_pps_threshold,
                 capture_packet_size: conf.capture_packet_size,
                 dpdk_enabled: conf.yaml_config.dpdk_enabled,
                 dispatcher_queue: conf.yaml_config.dispatcher_queue,
@@ -1509,6 +1591,15 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
             sender: SenderConfig {
                 mtu: conf.mtu,
                 dest_ip: dest_ip.clone(),
+                dest_fallback_ips: conf
+                    .yaml_config
+                    .analyzer_fallback_ips
+                    .iter()
+                    .filter(|ip| **ip != dest_ip)
+                    .cloned()
+                    .collect(),
+                balance: conf.yaml_config.sender_balance,
+                batches: conf.yaml_config.sender_batches.clone(),
                 vtap_id: conf.vtap_id as u16,
                 team_id: conf.team_id,
                 organize_id: conf.organize_id,
@@ -1527,6 +1618,15 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 standalone_data_file_size: conf.yaml_config.standalone_data_file_size,
                 standalone_data_file_dir: conf.yaml_config.standalone_data_file_dir.clone(),
                 enabled: conf.collector_enabled,
+                observe_mode: conf.yaml_config.observe_mode,
+                export_field_filter: FieldFilter::new(
+                    conf.yaml_config.export_fields_allowed.clone(),
+                    conf.yaml_config.export_fields_denied.clone(),
+                )
+                .with_hashed(
+                    conf.yaml_config.export_fields_hashed.clone(),
+                    static_config.export_hash_salt.expose().clone(),
+                ),
             },
             npb: NpbConfig {
                 mtu: conf.mtu,
@@ -1540,6 +1640,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 dedup_enabled: conf.npb_dedup_enabled,
                 socket_type: conf.npb_socket_type,
                 queue_size: conf.yaml_config.collector_sender_queue_size,
+                encapsulations: conf.yaml_config.npb_encapsulations.clone(),
             },
             collector: CollectorConfig {
                 enabled: conf.collector_enabled,
@@ -1569,8 +1670,21 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                     }
                     tap_sides
                 },
+                metrics_excluded_tap_types: {
+                    let mut tap_types = [false; 256];
+                    for &t in conf.yaml_config.metrics_excluded_tap_types.iter() {
+                        if t >= u16::from(TapType::Max) {
+                            warn!("invalid tap type: {}", t);
+                        } else {
+                            tap_types[t as usize] = true;
+                        }
+                    }
+                    tap_types
+                },
                 cloud_gateway_traffic: conf.yaml_config.cloud_gateway_traffic,
                 packet_delay: conf.yaml_config.packet_delay,
+                thread_stack_size: conf.yaml_config.thread_stack_size,
+                l4_flow_checkpoint: conf.yaml_config.l4_flow_checkpoint.clone(),
             },
             handler: HandlerConfig {
                 npb_dedup_enabled: conf.npb_dedup_enabled,
@@ -1639,6 +1753,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 l7_log_collect_nps_threshold: conf.l7_log_collect_nps_threshold,
                 l7_log_session_aggr_timeout: conf.yaml_config.l7_log_session_aggr_timeout,
                 l7_log_session_slot_capacity: conf.yaml_config.l7_log_session_slot_capacity,
+                l7_log_sampling_rate: conf.yaml_config.l7_log_sampling_rate,
                 l7_log_dynamic: L7LogDynamicConfig::new(
                     conf.http_log_proxy_client.to_string().to_ascii_lowercase(),
                     conf.http_log_x_request_id
@@ -1683,6 +1798,9 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                         .l7_protocol_advanced_features
                         .obfuscate_enabled_protocols,
                 ),
+                l7_protocol_enabled_bitmap: L7ProtocolBitmap::from(
+                    &conf.yaml_config.l7_protocol_enabled,
+                ),
                 l7_log_blacklist: conf.yaml_config.l7_log_blacklist.clone(),
                 l7_log_blacklist_trie: {
                     let mut blacklist_trie = HashMap::new();
@@ -1707,6 +1825,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                         .l7_protocol_advanced_features
                         .unconcerned_dns_nxdomain_response_suffixes,
                 ),
+                thread_stack_size: conf.yaml_config.thread_stack_size,
             },
             debug: DebugConfig {
                 vtap_id: conf.vtap_id as u16,
@@ -1719,6 +1838,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 listen_port: conf.yaml_config.debug_listen_port,
                 controller_port: static_config.controller_port,
                 agent_mode: static_config.agent_mode,
+                access: conf.yaml_config.debug_access.clone(),
             },
             log: LogConfig {
                 log_level: conf.log_level,
@@ -1734,10 +1854,15 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                         conf.rsyslog_enabled
                     }
                 },
-                host: conf.host.clone(),
+                host: host.clone(),
+                reconnect_backoff: ReconnectBackoff {
+                    initial: conf.yaml_config.remote_log_reconnect_backoff_initial,
+                    max: conf.yaml_config.remote_log_reconnect_backoff_max,
+                },
             },
             #[cfg(any(target_os = "linux", target_os = "android"))]
             ebpf: EbpfConfig {
+                enabled: !conf.yaml_config.ebpf.disabled,
                 collector_enabled: conf.collector_enabled,
                 l7_metrics_enabled: conf.l7_metrics_enabled,
                 vtap_id: conf.vtap_id as u16,
@@ -1806,6 +1931,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
             },
             metric_server: MetricServerConfig {
                 enabled: conf.external_agent_http_proxy_enabled,
+                bind_address: conf.yaml_config.external_agent_http_proxy_bind_address,
                 port: conf.external_agent_http_proxy_port as u16,
                 compressed: conf.yaml_config.external_agent_http_proxy_compressed,
             },
@@ -1829,9 +1955,62 @@ pub struct ConfigHandler {
     pub static_config: Config,
     pub candidate_config: ModuleConfig,
     pub current_config: Arc<ArcSwap<ModuleConfig>>,
+    // set by on_config if running components can not apply the new config,
+    // cleared by the caller after rebuilding components
+    pub rebuild_required: bool,
 }
 
 impl ConfigHandler {
+    // Parses comma separated cpu ids, empty means all cpus, returns None if any id is invalid
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn parse_cpu_set(cpus: &str) -> Option<CpuSet> {
+        let system =
+            System::new_with_specifics(RefreshKind::new().with_cpu(CpuRefreshKind::everything()));
+        let cpu_count = system.cpus().len() as usize;
+        let mut cpu_set = CpuSet::new();
+        if cpus.is_empty() {
+            for i in 0..cpu_count {
+                let _ = cpu_set.set(i);
+            }
+            return Some(cpu_set);
+        }
+        for id in cpus.split(',') {
+            match id.trim().parse::<usize>() {
+                Ok(id) if id < cpu_count => {
+                    if let Err(e) = cpu_set.set(id) {
+                        warn!("Invalid CPU Affinity config {}, error: {:?}", cpus, e);
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(cpu_set)
+    }
+
+    // Binds control plane threads to control_plane_cpu_affinity and the others to
+    // cpu_affinity. Called again after components are started because threads inherit
+    // the affinity of the thread spawning them, which is a control plane thread.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_control_plane_threads(&self) {
+        let yaml_config = &self.candidate_config.yaml_config;
+        let Some(control_plane) = Self::parse_cpu_set(&yaml_config.control_plane_cpu_affinity)
+        else {
+            warn!(
+                "Invalid control plane CPU Affinity config {}.",
+                yaml_config.control_plane_cpu_affinity
+            );
+            return;
+        };
+        let Some(data_plane) = Self::parse_cpu_set(&yaml_config.cpu_affinity) else {
+            return;
+        };
+        match bind_control_plane_threads(&control_plane, &data_plane) {
+            Ok(n) => debug!("{} control plane threads bound to {:?}", n, control_plane),
+            Err(e) => warn!("Control plane CPU Affinity bind error: {}.", e),
+        }
+    }
+
     pub fn new(config: Config, ctrl_ip: IpAddr, ctrl_mac: MacAddr) -> Self {
         let candidate_config =
             ModuleConfig::try_from((config.clone(), RuntimeConfig::default())).unwrap();
@@ -1851,6 +2030,7 @@ impl ConfigHandler {
             candidate_config,
             current_config,
             logger_handle: None,
+            rebuild_required: false,
         }
     }
 
@@ -1913,6 +2093,12 @@ impl ConfigHandler {
         })
     }
 
+    pub fn remote_log_reconnect_backoff(&self) -> ReconnectBackoffAccess {
+        Map::new(self.current_config.clone(), |config| -> &ReconnectBackoff {
+            &config.log.reconnect_backoff
+        })
+    }
+
     pub fn flow(&self) -> FlowAccess {
         Map::new(self.current_config.clone(), |config| -> &FlowConfig {
             &config.flow
@@ -2017,6 +2203,17 @@ impl ConfigHandler {
             );
         }
 
+        if yaml_config.mirror_traffic_pcp_tap_types
+            != new_config.yaml_config.mirror_traffic_pcp_tap_types
+        {
+            yaml_config.mirror_traffic_pcp_tap_types =
+                new_config.yaml_config.mirror_traffic_pcp_tap_types.clone();
+            info!(
+                "mirror_traffic_pcp_tap_types set to {:?}",
+                yaml_config.mirror_traffic_pcp_tap_types
+            );
+        }
+
         if yaml_config.prometheus_extra_config != new_config.yaml_config.prometheus_extra_config {
             info!(
                 "prometheus_extra_config set to {:?}",
@@ -2054,50 +2251,36 @@ impl ConfigHandler {
                 "CPU Affinity set to {}.",
                 new_config.yaml_config.cpu_affinity
             );
-            let mut cpu_set = CpuSet::new();
-            let splits = new_config.yaml_config.cpu_affinity.split(',');
-            let mut invalid_config = false;
-            let system = System::new_with_specifics(
-                RefreshKind::new().with_cpu(CpuRefreshKind::everything()),
-            );
-            let cpu_count = system.cpus().len() as usize;
-            if new_config.yaml_config.cpu_affinity.len() > 0 {
-                for id in splits.into_iter() {
-                    match id.parse::<usize>() {
-                        Ok(id) if id < cpu_count => {
-                            if let Err(e) = cpu_set.set(id) {
-                                warn!(
-                                    "Invalid CPU Affinity config {}, error: {:?}",
-                                    new_config.yaml_config.cpu_affinity, e
-                                );
-                                invalid_config = true;
-                            }
-                        }
-                        _ => {
-                            invalid_config = true;
-                            break;
-                        }
-                    };
-                }
-            } else {
-                for i in 0..cpu_count {
-                    let _ = cpu_set.set(i);
+            match Self::parse_cpu_set(&new_config.yaml_config.cpu_affinity) {
+                Some(cpu_set) => {
+                    let pid = std::process::id() as i32;
+                    if let Err(e) = sched_setaffinity(Pid::from_raw(pid), &cpu_set) {
+                        warn!("CPU Affinity({:?}) bind error: {:?}.", &cpu_set, e);
+                    }
                 }
-            }
-
-            if invalid_config {
-                warn!(
+                None => warn!(
                     "Invalid CPU Affinity config {}.",
                     new_config.yaml_config.cpu_affinity
-                );
-            } else {
-                let pid = std::process::id() as i32;
-                if let Err(e) = sched_setaffinity(Pid::from_raw(pid), &cpu_set) {
-                    warn!("CPU Affinity({:?}) bind error: {:?}.", &cpu_set, e);
-                }
+                ),
             }
         }
 
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if yaml_config.control_plane_cpu_affinity
+            != new_config.yaml_config.control_plane_cpu_affinity
+            || (!new_config.yaml_config.control_plane_cpu_affinity.is_empty()
+                && yaml_config.cpu_affinity != new_config.yaml_config.cpu_affinity)
+        {
+            info!(
+                "Control plane CPU Affinity set to {}.",
+                new_config.yaml_config.control_plane_cpu_affinity
+            );
+            fn control_plane_callback(handler: &ConfigHandler, _: &mut AgentComponents) {
+                handler.bind_control_plane_threads();
+            }
+            callbacks.push(control_plane_callback);
+        }
+
         if yaml_config.external_profile_integration_disabled
             != new_config.yaml_config.external_profile_integration_disabled
         {
@@ -2173,9 +2356,8 @@ impl ConfigHandler {
                         .as_ref()
                         .map(|re| public::netns::find_ns_files_by_regex(&re));
                     if old_netns != new_netns {
-                        info!("query net namespaces changed from {:?} to {:?}, restart agent to create dispatcher for extra namespaces, deepflow-agent restart...", old_netns, new_netns);
-                        crate::utils::notify_exit(public::consts::NORMAL_EXIT_WITH_RESTART);
-                        return vec![];
+                        info!("query net namespaces changed from {:?} to {:?}, rebuild components to create dispatcher for extra namespaces", old_netns, new_netns);
+                        self.rebuild_required = true;
                     }
 
                     c.kubernetes_poller.set_netns_regex(regex);
@@ -2214,21 +2396,51 @@ impl ConfigHandler {
                 callbacks.push(switch_recv_engine);
             }
 
+            if candidate_config.dispatcher.proxy_controller_ip
+                != new_config.dispatcher.proxy_controller_ip
+                || candidate_config.dispatcher.proxy_controller_port
+                    != new_config.dispatcher.proxy_controller_port
+            {
+                let d = &new_config.dispatcher;
+                // proxy_controller_ip falls back to the first controller ip when not assigned
+                let mode = if static_config
+                    .controller_ips
+                    .contains(&d.proxy_controller_ip)
+                {
+                    "direct"
+                } else {
+                    "proxied"
+                };
+                info!(
+                    "controller endpoint set to {}:{} ({}), initial controller {}:{}",
+                    d.proxy_controller_ip,
+                    d.proxy_controller_port,
+                    mode,
+                    static_config.controller_ips[0],
+                    static_config.controller_port
+                );
+            }
+
             if candidate_config.dispatcher.capture_packet_size
                 != new_config.dispatcher.capture_packet_size
             {
                 candidate_config.dispatcher.capture_packet_size =
                     new_config.dispatcher.capture_packet_size;
                 if !components.is_none() {
-                    info!("Capture packet size update, deepflow-agent restart...");
-                    crate::utils::notify_exit(1);
-                    return vec![];
+                    info!("Capture packet size update, rebuild components");
+                    self.rebuild_required = true;
                 }
             }
 
             if candidate_config.dispatcher.capture_socket_type
                 != new_config.dispatcher.capture_socket_type
             {
+                // AF_PACKET version is set when the socket is created
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                if !components.is_none() {
+                    info!("Afpacket version update, rebuild components");
+                    self.rebuild_required = true;
+                }
                 restart_dispatcher = !cfg!(target_os = "windows");
             }
 
@@ -2305,20 +2517,11 @@ impl ConfigHandler {
                     handler: &ConfigHandler,
                     components: &mut AgentComponents,
                 ) {
-                    match handler.candidate_config.tap_mode {
-                        TapMode::Analyzer => {
-                            components.rx_leaky_bucket.set_rate(None);
-                            info!("dispatcher.global pps set ulimit when tap_mode=analyzer");
-                        }
-                        _ => {
-                            components.rx_leaky_bucket.set_rate(Some(
-                                handler.candidate_config.dispatcher.global_pps_threshold,
-                            ));
-                            info!(
-                                "dispatcher.global pps threshold change to {}",
-                                handler.candidate_config.dispatcher.global_pps_threshold
-                            );
-                        }
+                    let threshold = handler.candidate_config.dispatcher.rx_pps_threshold();
+                    components.rx_leaky_bucket.set_rate(threshold);
+                    match threshold {
+                        Some(t) => info!("dispatcher.global pps threshold change to {}", t),
+                        None => info!("dispatcher.global pps set unlimited"),
                     }
                 }
                 callbacks.push(leaky_bucket_callback);
@@ -2368,6 +2571,12 @@ impl ConfigHandler {
                     candidate_config.log.log_threshold, new_config.log.log_threshold
                 )
             }
+            if candidate_config.log.reconnect_backoff != new_config.log.reconnect_backoff {
+                info!(
+                    "remote log reconnect backoff {:?} -> {:?}",
+                    candidate_config.log.reconnect_backoff, new_config.log.reconnect_backoff
+                )
+            }
             if candidate_config.log.log_retention != new_config.log.log_retention {
                 match self.logger_handle.as_mut() {
                     Some(h) => match h.flw_config() {
@@ -2583,6 +2792,14 @@ impl ConfigHandler {
                 new_config.environment.system_load_circuit_breaker_threshold;
         }
 
+        if candidate_config.environment.monitor != new_config.environment.monitor {
+            info!(
+                "monitor config change from {:?} to {:?}",
+                candidate_config.environment.monitor, new_config.environment.monitor
+            );
+            candidate_config.environment.monitor = new_config.environment.monitor;
+        }
+
         if candidate_config.flow != new_config.flow {
             if candidate_config.flow.collector_enabled != new_config.flow.collector_enabled {
                 restart_dispatcher = true;
@@ -2952,6 +3169,14 @@ impl ConfigHandler {
                         .set_port(new_config.metric_server.port);
                 }
             }
+            if candidate_config.metric_server.bind_address != new_config.metric_server.bind_address
+            {
+                if let Some(c) = components.as_mut() {
+                    c.metrics_server_component
+                        .external_metrics_server
+                        .set_bind_address(new_config.metric_server.bind_address);
+                }
+            }
             if candidate_config.metric_server.compressed != new_config.metric_server.compressed {
                 fn metric_server_callback(
                     handler: &ConfigHandler,
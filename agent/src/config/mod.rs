@@ -18,8 +18,12 @@ mod config;
 pub mod handler;
 
 pub use config::{
-    AgentIdType, Config, ConfigError, KubernetesPollerType, OracleParseConfig, PcapConfig,
-    PrometheusExtraConfig, RuntimeConfig, YamlConfig, K8S_CA_CRT_PATH,
+    resolve_controller_addrs, AfPacketFanoutConfig, AfPacketFanoutMode, AgentIdType,
+    CaptureTimestampSource, CollectorMetricsType, ComponentFailureAction, Config, ConfigError,
+    ControllerTimeouts, DebugAccessConfig, DebugAccessMode, EbpfYamlConfig, FlowCheckpointConfig,
+    InterfaceGroup, KubernetesPollerType, LogTarget, MonitorConfig, NpbEncapsulationConfig,
+    NpbEncapsulationType, OracleParseConfig, PcapConfig, PrometheusExtraConfig, RuntimeConfig,
+    SenderBalance, SenderBatchConfig, YamlConfig, K8S_CA_CRT_PATH,
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use config::{
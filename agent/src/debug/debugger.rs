@@ -15,6 +15,7 @@
  */
 
 use std::{
+    collections::HashMap,
     io::{self, ErrorKind},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
     sync::{
@@ -22,7 +23,7 @@ use std::{
         Arc, Mutex,
     },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use arc_swap::access::Access;
@@ -30,24 +31,30 @@ use bincode::{
     config::{self, Configuration},
     decode_from_std_read, encode_to_vec, Decode, Encode,
 };
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use parking_lot::RwLock;
 use tokio::runtime::Runtime;
 
+use super::{
+    dispatcher::{DispatcherDebugger, DispatcherMessage},
+    policy::{PolicyDebugger, PolicyMessage},
+    rpc::{RpcDebugger, RpcMessage},
+    sender::{SenderDebugger, SenderMessage},
+    AuthMessage, Beacon, Message, Module, BEACON_INTERVAL, BEACON_INTERVAL_MIN,
+    DEEPFLOW_AGENT_BEACON,
+};
 #[cfg(target_os = "linux")]
 use super::{
     ebpf::{EbpfDebugger, EbpfMessage},
     platform::{PlatformDebugger, PlatformMessage},
 };
-use super::{
-    policy::{PolicyDebugger, PolicyMessage},
-    rpc::{RpcDebugger, RpcMessage},
-    Beacon, Message, Module, BEACON_INTERVAL, BEACON_INTERVAL_MIN, DEEPFLOW_AGENT_BEACON,
-};
 #[cfg(target_os = "linux")]
 use crate::platform::{ApiWatcher, GenericPoller};
 use crate::{
-    config::handler::DebugAccess,
+    config::{
+        handler::{DebugAccess, DebugConfig},
+        DebugAccessMode,
+    },
     policy::PolicySetter,
     rpc::{Session, StaticConfig, Status},
     trident::AgentId,
@@ -58,6 +65,9 @@ use public::{
     debug::{send_to, Error, QueueDebugger, QueueMessage, Result, MAX_BUF_SIZE},
 };
 
+// Clients authenticated with token are allowed until the session expires
+const AUTH_SESSION_TIMEOUT: Duration = Duration::from_secs(600);
+
 struct ModuleDebuggers {
     #[cfg(target_os = "linux")]
     pub platform: PlatformDebugger,
@@ -66,6 +76,8 @@ struct ModuleDebuggers {
     pub policy: PolicyDebugger,
     #[cfg(target_os = "linux")]
     pub ebpf: EbpfDebugger,
+    pub dispatcher: Arc<DispatcherDebugger>,
+    pub sender: Arc<SenderDebugger>,
 }
 
 pub struct Debugger {
@@ -135,6 +147,7 @@ impl Debugger {
                 }
                 let sock_clone = sock.clone();
                 let running_clone = running.clone();
+                let access_conf = conf.clone();
                 let serialize_conf = config::standard();
                 #[cfg(target_os = "linux")]
                 let agent_mode = conf.load().agent_mode;
@@ -191,6 +204,7 @@ impl Debugger {
                     })
                     .unwrap();
 
+                let mut sessions = HashMap::new();
                 while running.load(Ordering::Relaxed) {
                     let mut buf = [0u8; MAX_BUF_SIZE];
                     let mut addr = None;
@@ -202,6 +216,20 @@ impl Debugger {
                             if addr.is_none() {
                                 addr.replace(a);
                             }
+                            match Self::check_access(
+                                (&sock, addr.unwrap()),
+                                &buf,
+                                &access_conf.load(),
+                                &mut sessions,
+                                serialize_conf,
+                            ) {
+                                Ok(true) => (),
+                                Ok(false) => continue,
+                                Err(e) => {
+                                    warn!("handle client auth error: {}", e);
+                                    continue;
+                                }
+                            }
                             Self::dispatch(
                                 (&sock, addr.unwrap()),
                                 &buf,
@@ -292,6 +320,7 @@ impl Debugger {
                 let sock_v4_clone = sock_v4.clone();
                 let sock_v6_clone = sock_v6.clone();
                 let running_clone = running.clone();
+                let access_conf = conf.clone();
                 let serialize_conf = config::standard();
                 let beacon_port = conf.load().controller_port;
                 let beacon_thread = thread::Builder::new()
@@ -360,6 +389,7 @@ impl Debugger {
                     })
                     .unwrap();
 
+                let mut sessions = HashMap::new();
                 while running.load(Ordering::Relaxed) {
                     if has_ipv4 {
                         let mut buf_v4 = [0u8; MAX_BUF_SIZE];
@@ -372,6 +402,20 @@ impl Debugger {
                                 if addr_v4.is_none() {
                                     addr_v4.replace(a);
                                 }
+                                match Self::check_access(
+                                    (&sock_v4, addr_v4.unwrap()),
+                                    &buf_v4,
+                                    &access_conf.load(),
+                                    &mut sessions,
+                                    serialize_conf,
+                                ) {
+                                    Ok(true) => (),
+                                    Ok(false) => continue,
+                                    Err(e) => {
+                                        warn!("handle client auth error: {}", e);
+                                        continue;
+                                    }
+                                }
                                 Self::dispatch(
                                     (&sock_v4, addr_v4.unwrap()),
                                     &buf_v4,
@@ -408,6 +452,20 @@ impl Debugger {
                                 if addr_v6.is_none() {
                                     addr_v6.replace(a);
                                 }
+                                match Self::check_access(
+                                    (&sock_v6, addr_v6.unwrap()),
+                                    &buf_v6,
+                                    &access_conf.load(),
+                                    &mut sessions,
+                                    serialize_conf,
+                                ) {
+                                    Ok(true) => (),
+                                    Ok(false) => continue,
+                                    Err(e) => {
+                                        warn!("handle client auth error: {}", e);
+                                        continue;
+                                    }
+                                }
                                 Self::dispatch(
                                     (&sock_v6, addr_v6.unwrap()),
                                     &buf_v6,
@@ -441,6 +499,90 @@ impl Debugger {
         info!("debugger started");
     }
 
+    fn is_local(ip: IpAddr, controller_ips: &[IpAddr]) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            _ => ip,
+        };
+        ip.is_loopback() || controller_ips.contains(&ip)
+    }
+
+    // Compares in time independent of where the tokens differ
+    fn token_eq(a: &str, b: &str) -> bool {
+        a.len() == b.len()
+            && a.bytes()
+                .zip(b.bytes())
+                .fold(0, |acc, (x, y)| acc | (x ^ y))
+                == 0
+    }
+
+    // Whether requests from the peer are allowed, expired sessions are removed
+    fn allowed(
+        peer: SocketAddr,
+        conf: &DebugConfig,
+        sessions: &mut HashMap<SocketAddr, Instant>,
+    ) -> bool {
+        let access = &conf.access;
+        let token_enabled = access.mode == DebugAccessMode::Token && !access.token.is_empty();
+        sessions.retain(|_, t| t.elapsed() < AUTH_SESSION_TIMEOUT);
+        match access.mode {
+            DebugAccessMode::Any => true,
+            _ => {
+                Self::is_local(peer.ip(), &conf.controller_ips)
+                    || (token_enabled && sessions.contains_key(&peer))
+            }
+        }
+    }
+
+    // Answers an auth request, a session is started for the peer if the token matches
+    fn auth(
+        peer: SocketAddr,
+        req: AuthMessage,
+        allowed: bool,
+        conf: &DebugConfig,
+        sessions: &mut HashMap<SocketAddr, Instant>,
+    ) -> AuthMessage {
+        let access = &conf.access;
+        let token_enabled = access.mode == DebugAccessMode::Token && !access.token.is_empty();
+        match req {
+            AuthMessage::Token(token) if token_enabled && Self::token_eq(&token, &access.token) => {
+                sessions.insert(peer, Instant::now());
+                AuthMessage::Ok
+            }
+            _ if allowed => AuthMessage::Ok,
+            _ => {
+                warn!("debugger auth from {} denied", peer);
+                AuthMessage::Denied
+            }
+        }
+    }
+
+    // Returns whether the request can be dispatched, auth requests are answered here
+    fn check_access(
+        conn: (&Arc<UdpSocket>, SocketAddr),
+        mut payload: &[u8],
+        conf: &DebugConfig,
+        sessions: &mut HashMap<SocketAddr, Instant>,
+        serialize_conf: Configuration,
+    ) -> Result<bool> {
+        let allowed = Self::allowed(conn.1, conf, sessions);
+
+        if payload.first().copied() != Some(Module::Auth.into()) {
+            if !allowed {
+                debug!(
+                    "debugger request from {} denied in {:?} access mode",
+                    conn.1, conf.access.mode
+                );
+            }
+            return Ok(allowed);
+        }
+
+        let req: Message<AuthMessage> = decode_from_std_read(&mut payload, serialize_conf)?;
+        let resp = Self::auth(conn.1, req.into_inner(), allowed, conf, sessions);
+        send_to(conn.0, conn.1, resp, serialize_conf)?;
+        Ok(false)
+    }
+
     fn dispatch(
         conn: (&Arc<UdpSocket>, SocketAddr),
         mut payload: &[u8],
@@ -508,10 +650,10 @@ impl Debugger {
                         let msgs = debugger.queue_names();
                         iter_send_to(conn.0, conn.1, msgs.iter(), serialize_conf)?;
                     }
-                    QueueMessage::On((name, duration)) => {
+                    QueueMessage::On((name, duration, filter)) => {
                         let msg = debugger.turn_on_queue(name.as_str());
                         send_to(conn.0, conn.1, msg, serialize_conf)?;
-                        debugger.send(name, conn.1, serialize_conf, duration);
+                        debugger.send(name, conn.1, serialize_conf, duration, filter);
                     }
                     _ => unreachable!(),
                 }
@@ -549,6 +691,26 @@ impl Debugger {
                     _ => unreachable!(),
                 }
             }
+            Module::Dispatcher => {
+                let req: Message<DispatcherMessage> =
+                    decode_from_std_read(&mut payload, serialize_conf)?;
+                let debugger = &debuggers.dispatcher;
+                let resp = match req.into_inner() {
+                    DispatcherMessage::TimestampSource => debugger.timestamp_sources(),
+                    _ => unreachable!(),
+                };
+                iter_send_to(conn.0, conn.1, resp.iter(), serialize_conf)?;
+            }
+            Module::Sender => {
+                let req: Message<SenderMessage> =
+                    decode_from_std_read(&mut payload, serialize_conf)?;
+                let debugger = &debuggers.sender;
+                let resp = match req.into_inner() {
+                    SenderMessage::Flush => debugger.flush(),
+                    _ => unreachable!(),
+                };
+                iter_send_to(conn.0, conn.1, resp.iter(), serialize_conf)?;
+            }
             _ => warn!("invalid module or invalid request, skip it"),
         }
 
@@ -574,6 +736,8 @@ impl Debugger {
             policy: PolicyDebugger::new(context.policy_setter),
             #[cfg(target_os = "linux")]
             ebpf: EbpfDebugger::new(),
+            dispatcher: Default::default(),
+            sender: Default::default(),
         };
 
         Self {
@@ -589,6 +753,14 @@ impl Debugger {
         self.debuggers.queue.clone()
     }
 
+    pub fn clone_dispatcher(&self) -> Arc<DispatcherDebugger> {
+        self.debuggers.dispatcher.clone()
+    }
+
+    pub fn clone_sender(&self) -> Arc<SenderDebugger> {
+        self.debuggers.sender.clone()
+    }
+
     pub fn notify_stop(&self) -> Option<JoinHandle<()>> {
         if !self.running.swap(false, Ordering::Relaxed) {
             return None;
@@ -639,6 +811,18 @@ impl Client {
         Ok(())
     }
 
+    // Required before other requests if the agent is in token access mode
+    pub fn auth(&mut self, token: String) -> Result<()> {
+        self.send_to(Message::new(Module::Auth, AuthMessage::Token(token)))?;
+        match self.recv()? {
+            AuthMessage::Ok => Ok(()),
+            _ => Err(Error::IoError(io::Error::new(
+                ErrorKind::PermissionDenied,
+                "debugger access denied",
+            ))),
+        }
+    }
+
     pub fn recv<D: Decode>(&mut self) -> Result<D> {
         let mut buf = [0u8; MAX_BUF_SIZE];
         match self.sock.recv(&mut buf) {
@@ -671,3 +855,131 @@ where
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{config::DebugAccessConfig, trident::RunningMode};
+
+    fn debug_config(mode: DebugAccessMode, token: &str) -> DebugConfig {
+        DebugConfig {
+            vtap_id: 0,
+            enabled: true,
+            controller_ips: vec!["10.1.1.1".parse().unwrap()],
+            controller_port: DEFAULT_CONTROLLER_PORT,
+            listen_port: 0,
+            agent_mode: RunningMode::Managed,
+            access: DebugAccessConfig {
+                mode,
+                token: token.to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn is_local() {
+        let controller_ips = vec!["10.1.1.1".parse().unwrap(), "fd00::1".parse().unwrap()];
+        for ip in [
+            "127.0.0.1",
+            "::1",
+            "::ffff:127.0.0.1",
+            "10.1.1.1",
+            "::ffff:10.1.1.1",
+            "fd00::1",
+        ] {
+            assert!(
+                Debugger::is_local(ip.parse().unwrap(), &controller_ips),
+                "{}",
+                ip
+            );
+        }
+        for ip in ["10.1.1.2", "::ffff:10.1.1.2", "fd00::2", "0.0.0.0"] {
+            assert!(
+                !Debugger::is_local(ip.parse().unwrap(), &controller_ips),
+                "{}",
+                ip
+            );
+        }
+    }
+
+    #[test]
+    fn access_decision() {
+        let local: SocketAddr = "127.0.0.1:30035".parse().unwrap();
+        let remote: SocketAddr = "10.2.2.2:30035".parse().unwrap();
+        let mut sessions = HashMap::new();
+
+        let conf = debug_config(DebugAccessMode::Local, "secret");
+        assert!(Debugger::allowed(local, &conf, &mut sessions));
+        assert!(!Debugger::allowed(remote, &conf, &mut sessions));
+        // token is ignored in local mode
+        let req = AuthMessage::Token("secret".to_owned());
+        assert_eq!(
+            Debugger::auth(remote, req, false, &conf, &mut sessions),
+            AuthMessage::Denied
+        );
+        assert!(sessions.is_empty());
+
+        let conf = debug_config(DebugAccessMode::Any, "");
+        assert!(Debugger::allowed(local, &conf, &mut sessions));
+        assert!(Debugger::allowed(remote, &conf, &mut sessions));
+
+        let conf = debug_config(DebugAccessMode::Token, "secret");
+        assert!(Debugger::allowed(local, &conf, &mut sessions));
+        assert!(!Debugger::allowed(remote, &conf, &mut sessions));
+        for token in ["secreT", "secret ", "", "secre"] {
+            let req = AuthMessage::Token(token.to_owned());
+            assert_eq!(
+                Debugger::auth(remote, req, false, &conf, &mut sessions),
+                AuthMessage::Denied
+            );
+        }
+        assert!(!Debugger::allowed(remote, &conf, &mut sessions));
+        let req = AuthMessage::Token("secret".to_owned());
+        assert_eq!(
+            Debugger::auth(remote, req, false, &conf, &mut sessions),
+            AuthMessage::Ok
+        );
+        assert!(Debugger::allowed(remote, &conf, &mut sessions));
+        // sessions are bound to the peer address
+        assert!(!Debugger::allowed(
+            "10.2.2.2:30036".parse().unwrap(),
+            &conf,
+            &mut sessions
+        ));
+
+        // token mode without token allows local access only
+        let conf = debug_config(DebugAccessMode::Token, "");
+        let req = AuthMessage::Token("".to_owned());
+        assert_eq!(
+            Debugger::auth(remote, req, false, &conf, &mut sessions),
+            AuthMessage::Denied
+        );
+        assert!(!Debugger::allowed(remote, &conf, &mut sessions));
+    }
+
+    #[test]
+    fn session_expiry() {
+        let remote: SocketAddr = "10.2.2.2:30035".parse().unwrap();
+        let conf = debug_config(DebugAccessMode::Token, "secret");
+        let mut sessions = HashMap::new();
+        let Some(expired) = Instant::now().checked_sub(AUTH_SESSION_TIMEOUT) else {
+            return;
+        };
+        sessions.insert(remote, expired);
+        assert!(!Debugger::allowed(remote, &conf, &mut sessions));
+        assert!(sessions.is_empty());
+
+        sessions.insert(remote, expired + Duration::from_secs(1));
+        assert!(Debugger::allowed(remote, &conf, &mut sessions));
+    }
+
+    #[test]
+    fn token_eq() {
+        assert!(Debugger::token_eq("secret", "secret"));
+        assert!(Debugger::token_eq("", ""));
+        assert!(!Debugger::token_eq("secret", "secreT"));
+        assert!(!Debugger::token_eq("secret", "secret1"));
+        assert!(!Debugger::token_eq("", "secret"));
+    }
+}
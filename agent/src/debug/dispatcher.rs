@@ -0,0 +1,72 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+
+use bincode::{Decode, Encode};
+use parking_lot::RwLock;
+
+use crate::config::CaptureTimestampSource;
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum DispatcherMessage {
+    Unknown,
+    TimestampSource,
+    Context(String),
+    Done,
+    Err(String),
+}
+
+#[derive(Default)]
+pub struct DispatcherDebugger {
+    // dispatcher id -> (src interface, timestamp source in use)
+    timestamp_sources: RwLock<BTreeMap<usize, (String, CaptureTimestampSource)>>,
+}
+
+impl DispatcherDebugger {
+    pub fn set_timestamp_source(
+        &self,
+        id: usize,
+        src_interface: &str,
+        source: CaptureTimestampSource,
+    ) {
+        self.timestamp_sources
+            .write()
+            .insert(id, (src_interface.to_owned(), source));
+    }
+
+    pub fn remove_timestamp_source(&self, id: usize) {
+        self.timestamp_sources.write().remove(&id);
+    }
+
+    pub(super) fn timestamp_sources(&self) -> Vec<DispatcherMessage> {
+        let sources = self.timestamp_sources.read();
+        if sources.is_empty() {
+            return vec![DispatcherMessage::Err("no dispatcher found".to_string())];
+        }
+        let mut msgs = sources
+            .iter()
+            .map(|(id, (src_interface, source))| {
+                DispatcherMessage::Context(format!(
+                    "dispatcher {} src_interface {:?} timestamp source {}",
+                    id, src_interface, source
+                ))
+            })
+            .collect::<Vec<_>>();
+        msgs.push(DispatcherMessage::Done);
+        msgs
+    }
+}
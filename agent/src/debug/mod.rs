@@ -15,21 +15,25 @@
  */
 
 mod debugger;
+mod dispatcher;
 #[cfg(target_os = "linux")]
 mod ebpf;
 #[cfg(target_os = "linux")]
 mod platform;
 mod policy;
 mod rpc;
+mod sender;
 
 use bincode::{Decode, Encode};
 pub use debugger::{Client, ConstructDebugCtx, Debugger};
+pub use dispatcher::{DispatcherDebugger, DispatcherMessage};
 #[cfg(target_os = "linux")]
 pub use ebpf::EbpfMessage;
 #[cfg(target_os = "linux")]
 pub use platform::PlatformMessage;
 pub use policy::PolicyMessage;
 pub use rpc::{ConfigResp, RpcMessage};
+pub use sender::{SenderDebugger, SenderMessage};
 
 use std::str;
 use std::time::Duration;
@@ -54,6 +58,9 @@ pub enum Module {
     Policy,
     #[cfg(target_os = "linux")]
     Ebpf,
+    Dispatcher,
+    Sender,
+    Auth,
 }
 
 impl Default for Module {
@@ -68,6 +75,13 @@ pub struct Beacon {
     pub hostname: String,
 }
 
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum AuthMessage {
+    Token(String),
+    Ok,
+    Denied,
+}
+
 #[derive(Encode, Decode, PartialEq, Debug)]
 pub struct Message<T> {
     pub module: Module,
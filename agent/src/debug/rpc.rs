@@ -281,8 +281,12 @@ impl RpcDebugger {
     pub(super) fn current_version(&self) -> Result<Vec<RpcMessage>> {
         let status = self.status.read();
         let version = format!(
-            "platformData version: {}\n groups version: {}\nflowAcls version: {}",
-            status.version_platform_data, status.version_groups, status.version_acls
+            "platformData version: {}\n groups version: {}\nflowAcls version: {}\nvtap group id: {} (requested {:?})",
+            status.version_platform_data,
+            status.version_groups,
+            status.version_acls,
+            status.vtap_group_id,
+            self.config.vtap_group_id_request.read(),
         );
 
         Ok(vec![RpcMessage::Version(Some(version)), RpcMessage::Fin])
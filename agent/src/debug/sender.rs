@@ -0,0 +1,56 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use bincode::{Decode, Encode};
+use parking_lot::RwLock;
+
+use crate::sender::uniform_sender::SenderFlusher;
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum SenderMessage {
+    Unknown,
+    Flush,
+    Context(String),
+    Done,
+    Err(String),
+}
+
+#[derive(Default)]
+pub struct SenderDebugger {
+    flushers: RwLock<Vec<SenderFlusher>>,
+}
+
+impl SenderDebugger {
+    pub fn set_flushers(&self, flushers: Vec<SenderFlusher>) {
+        *self.flushers.write() = flushers;
+    }
+
+    pub(super) fn flush(&self) -> Vec<SenderMessage> {
+        let flushers = self.flushers.read();
+        if flushers.is_empty() {
+            return vec![SenderMessage::Err("no sender found".to_string())];
+        }
+        let mut msgs = flushers
+            .iter()
+            .map(|f| {
+                f.flush();
+                SenderMessage::Context(format!("{} sender id {} flush requested", f.name, f.id))
+            })
+            .collect::<Vec<_>>();
+        msgs.push(SenderMessage::Done);
+        msgs
+    }
+}
@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::IpAddr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use arc_swap::access::Access;
+use log::{info, warn};
+
+use super::{resolve_analyzer_ip, DispatcherListener};
+use crate::config::handler::DispatcherAccess;
+
+// Re-resolves analyzer_ip periodically if it is a domain name, and capture bpf of
+// dispatchers is rebuilt with the source ip to the new address when it changes
+pub struct AnalyzerIpWatcher {
+    config: DispatcherAccess,
+    interval: Duration,
+    prefer_ipv6: bool,
+    listeners: Arc<Mutex<Vec<DispatcherListener>>>,
+
+    running: Arc<(Mutex<bool>, Condvar)>,
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl AnalyzerIpWatcher {
+    pub fn new(
+        config: DispatcherAccess,
+        interval: Duration,
+        prefer_ipv6: bool,
+        listeners: Vec<DispatcherListener>,
+    ) -> Self {
+        Self {
+            config,
+            interval,
+            prefer_ipv6,
+            listeners: Arc::new(Mutex::new(listeners)),
+            running: Default::default(),
+            thread_handle: None,
+        }
+    }
+
+    // dispatchers are rebuilt on interface changes in mirror and analyzer mode
+    pub fn set_listeners(&self, listeners: Vec<DispatcherListener>) {
+        *self.listeners.lock().unwrap() = listeners;
+    }
+
+    pub fn start(&mut self) {
+        {
+            let mut running = self.running.0.lock().unwrap();
+            if *running {
+                return;
+            }
+            *running = true;
+        }
+
+        let config = self.config.clone();
+        let interval = self.interval;
+        let prefer_ipv6 = self.prefer_ipv6;
+        let listeners = self.listeners.clone();
+        let running = self.running.clone();
+        self.thread_handle = Some(
+            thread::Builder::new()
+                .name("analyzer-ip-watcher".to_owned())
+                .spawn(move || Self::run(config, interval, prefer_ipv6, listeners, running))
+                .unwrap(),
+        );
+        info!("analyzer ip watcher started with interval {:?}", interval);
+    }
+
+    pub fn notify_stop(&mut self) -> Option<JoinHandle<()>> {
+        {
+            let mut running = self.running.0.lock().unwrap();
+            if !*running {
+                return None;
+            }
+            *running = false;
+        }
+        self.running.1.notify_one();
+        info!("notified stopping analyzer ip watcher");
+        self.thread_handle.take()
+    }
+
+    fn run(
+        config: DispatcherAccess,
+        interval: Duration,
+        prefer_ipv6: bool,
+        listeners: Arc<Mutex<Vec<DispatcherListener>>>,
+        running: Arc<(Mutex<bool>, Condvar)>,
+    ) {
+        let mut last_ip: Option<IpAddr> = None;
+        let (running, timer) = &*running;
+        loop {
+            let guard = timer
+                .wait_timeout_while(running.lock().unwrap(), interval, |r| *r)
+                .unwrap()
+                .0;
+            if !*guard {
+                break;
+            }
+            drop(guard);
+
+            let conf = config.load();
+            if conf.analyzer_ip.parse::<IpAddr>().is_ok() {
+                last_ip = None;
+                continue;
+            }
+            let ip = match resolve_analyzer_ip(&conf.analyzer_ip, prefer_ipv6) {
+                Ok(ip) => ip,
+                Err(e) => {
+                    warn!("Dns lookup {} error: {:?}", conf.analyzer_ip, e);
+                    continue;
+                }
+            };
+            if last_ip.is_some() && last_ip != Some(ip) {
+                info!(
+                    "analyzer {} ip {} change to {}",
+                    conf.analyzer_ip,
+                    last_ip.unwrap(),
+                    ip
+                );
+            }
+            last_ip = Some(ip);
+            // listeners skip rebuilding if bpf is built with the same address
+            for listener in listeners.lock().unwrap().iter() {
+                listener.on_analyzer_ip_resolved(&conf, ip);
+            }
+        }
+    }
+}
@@ -17,9 +17,10 @@
 use std::{
     collections::HashMap,
     mem::drop,
+    net::IpAddr,
     ops::Add,
     sync::{atomic::Ordering, Arc, RwLock},
-    thread::{self, JoinHandle},
+    thread::JoinHandle,
     time::Duration,
 };
 
@@ -39,12 +40,16 @@ use crate::{
         base_dispatcher::{BaseDispatcherListener, TapTypeHandler},
         error::Result,
     },
-    flow_generator::{flow_map::Config, FlowMap},
+    flow_generator::{
+        flow_map::{AllocatorStats, Config},
+        FlowMap,
+    },
     handler::{MiniPacket, PacketHandler},
     rpc::get_timestamp,
     utils::{
         bytes::read_u32_be,
         stats::{self, Countable, QueueStats},
+        thread_builder,
     },
 };
 use public::{
@@ -124,10 +129,18 @@ impl AnalyzerModeDispatcherListener {
         self.base.on_config_change(config)
     }
 
+    pub(super) fn on_analyzer_ip_resolved(&self, config: &DispatcherConfig, analyzer_ip: IpAddr) {
+        self.base.on_analyzer_ip_resolved(config, analyzer_ip)
+    }
+
     pub fn id(&self) -> usize {
         return self.base.id;
     }
 
+    pub fn rebuild_required(&self) -> bool {
+        self.base.rebuild_required.load(Ordering::Relaxed)
+    }
+
     pub fn flow_acl_change(&self) {
         // Start capturing traffic after resource information is distributed
         self.base.pause.store(false, Ordering::Relaxed);
@@ -159,6 +172,7 @@ pub(super) struct AnalyzerModeDispatcher {
     pub(super) stats_collector: Arc<stats::Collector>,
     pub(super) inner_queue_size: usize,
     pub(super) raw_packet_block_size: usize,
+    pub(super) raw_packet_pool_size: usize,
 }
 
 impl AnalyzerModeDispatcher {
@@ -257,7 +271,13 @@ impl AnalyzerModeDispatcher {
         let counter = base.counter.clone();
         let analyzer_dedup_disabled = base.analyzer_dedup_disabled;
         let vm_mac_addrs = self.vm_mac_addrs.clone();
-        let mut dedup = PacketDedupMap::new();
+        let mut dedup = {
+            let options = base.options.lock().unwrap();
+            PacketDedupMap::with_options(
+                options.analyzer_dedup_window,
+                options.analyzer_dedup_table_size,
+            )
+        };
         let id = base.id;
         let pool_raw_size = self.pool_raw_size;
 
@@ -272,10 +292,10 @@ impl AnalyzerModeDispatcher {
         let collector_config = base.collector_config.clone();
         let packet_sequence_output_queue = base.packet_sequence_output_queue.clone(); // Enterprise Edition Feature: packet-sequence
         let stats = base.stats.clone();
+        let thread_stack_size = base.thread_stack_size;
 
         self.flow_generator_thread_handler.replace(
-            thread::Builder::new()
-                .name("dispatcher-packet-to-flow-generator".to_owned())
+            thread_builder(format!("dp-flow-{}", id), thread_stack_size)
                 .spawn(move || {
                     let mut timestamp_map: HashMap<TapType, Duration> = HashMap::new();
                     let mut batch = Vec::with_capacity(HANDLER_BATCH_SIZE);
@@ -288,7 +308,7 @@ impl AnalyzerModeDispatcher {
                         log_output_queue,
                         ntp_diff,
                         &flow_map_config.load(),
-                        Some(packet_sequence_output_queue), // Enterprise Edition Feature: packet-sequence
+                        packet_sequence_output_queue, // Enterprise Edition Feature: packet-sequence
                         stats,
                         false, // !from_ebpf
                     );
@@ -358,12 +378,16 @@ impl AnalyzerModeDispatcher {
                             let mut overlay_packet = packet.raw;
                             overlay_packet.truncate(decap_length..raw_length);
                             // Only cloud traffic goes to de-duplication
-                            if tap_type == TapType::Cloud
-                                && !analyzer_dedup_disabled
-                                && dedup.duplicate(overlay_packet.as_mut(), timestamp)
-                            {
-                                debug!("packet is duplicate");
-                                continue;
+                            if tap_type == TapType::Cloud && !analyzer_dedup_disabled {
+                                let duplicate = dedup.duplicate(overlay_packet.as_mut(), timestamp);
+                                counter
+                                    .dedup_evicted
+                                    .fetch_add(dedup.take_evictions(), Ordering::Relaxed);
+                                if duplicate {
+                                    counter.deduped.fetch_add(1, Ordering::Relaxed);
+                                    debug!("packet is duplicate");
+                                    continue;
+                                }
                             }
 
                             let (tap_port, src_local, dst_local) = Self::lookup_l2end(
@@ -443,10 +467,10 @@ impl AnalyzerModeDispatcher {
         let terminated = base.terminated.clone();
         let handler_builder = self.base.handler_builder.clone();
         let id = base.id;
+        let thread_stack_size = base.thread_stack_size;
 
         self.pipeline_thread_handler.replace(
-            thread::Builder::new()
-                .name("dispatcher-additional-packet-pipeline".to_owned())
+            thread_builder(format!("dp-pipeline-{}", base.id), thread_stack_size)
                 .spawn(move || {
                     let mut tap_pipelines: HashMap<TapType, AnalyzerPipeline> = HashMap::new();
                     let mut batch = Vec::with_capacity(HANDLER_BATCH_SIZE);
@@ -526,7 +550,15 @@ impl AnalyzerModeDispatcher {
         let mut prev_timestamp = get_timestamp(time_diff);
         let id = base.id;
         let mut batch = Vec::with_capacity(HANDLER_BATCH_SIZE);
-        let mut allocator = Allocator::new(self.raw_packet_block_size);
+        let mut allocator =
+            Allocator::with_pool(self.raw_packet_block_size, self.raw_packet_pool_size);
+        self.stats_collector.register_countable(
+            &AllocatorStats {
+                id: id as u32,
+                obj_type: "RawPacket",
+            },
+            Countable::Ref(allocator.counter()),
+        );
 
         while !base.terminated.load(Ordering::Relaxed) {
             if base.reset_whitelist.swap(false, Ordering::Relaxed) {
@@ -557,7 +589,7 @@ impl AnalyzerModeDispatcher {
                 base.check_and_update_bpf();
                 continue;
             }
-            if base.pause.load(Ordering::Relaxed) {
+            if base.pause.load(Ordering::Relaxed) || base.capture_paused.load(Ordering::Relaxed) {
                 continue;
             }
 
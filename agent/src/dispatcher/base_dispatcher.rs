@@ -16,6 +16,7 @@
 
 use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
+use std::io;
 use std::mem;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::{
@@ -26,7 +27,7 @@ use std::thread;
 use std::time::Duration;
 
 use dns_lookup::lookup_host;
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 
 use super::{
     error::{Error, Result},
@@ -61,7 +62,7 @@ use public::{
     packet::Packet,
     proto::trident::{Exception, IfMacSource, TapMode},
     queue::DebugSender,
-    utils::net::{self, get_route_src_ip, Link, MacAddr},
+    utils::net::{self, addr_list, get_route_src_ip, link_by_name, Link, MacAddr},
     LeakyBucket,
 };
 
@@ -76,6 +77,7 @@ pub(super) struct BaseDispatcher {
 
     pub(super) options: Arc<Mutex<Options>>,
     pub(super) bpf_options: Arc<Mutex<BpfOptions>>,
+    pub(super) capture_bpf_override: Option<String>,
 
     pub(super) leaky_bucket: Arc<LeakyBucket>,
     pub(super) handler_builder: Arc<Mutex<Vec<PacketHandlerBuilder>>>,
@@ -92,6 +94,9 @@ pub(super) struct BaseDispatcher {
     pub(super) tap_type_handler: TapTypeHandler,
 
     pub(super) need_update_bpf: Arc<AtomicBool>,
+    // pcap interfaces set by the listener, libpcap engine is reopened with them on next bpf update
+    #[cfg(target_os = "windows")]
+    pub(super) pending_pcap_interfaces: Arc<Mutex<Option<Vec<Link>>>>,
     // 该表中的tap接口采集包长不截断
     pub(super) reset_whitelist: Arc<AtomicBool>,
     pub(super) tap_interface_whitelist: TapInterfaceWhitelist,
@@ -114,17 +119,21 @@ pub(super) struct BaseDispatcher {
 
     pub(super) npb_dedup_enabled: Arc<AtomicBool>,
     pub(super) pause: Arc<AtomicBool>,
+    // set by capture schedule, independent of pause which is cleared on flow acl change
+    pub(super) capture_paused: Arc<AtomicBool>,
     pub(super) queue_debugger: Arc<QueueDebugger>,
 
     // Enterprise Edition Feature: packet-sequence
     pub(super) packet_sequence_output_queue:
-        DebugSender<Box<packet_sequence_block::PacketSequenceBlock>>,
+        Option<DebugSender<Box<packet_sequence_block::PacketSequenceBlock>>>,
 
     #[cfg(target_os = "linux")]
     pub(super) netns: public::netns::NsFile,
 
     pub(super) bond_group_map: HashMap<u32, MacAddr>,
 
+    pub(super) thread_stack_size: usize,
+
     // dispatcher id for easy debugging
     pub log_id: String,
 }
@@ -158,9 +167,12 @@ impl BaseDispatcher {
             src_interface_index: self.src_interface_index as usize,
             options: self.options.clone(),
             bpf_options: self.bpf_options.clone(),
+            capture_bpf_override: self.capture_bpf_override.clone(),
             pipelines: self.pipelines.clone(),
             tap_interfaces: self.tap_interfaces.clone(),
             need_update_bpf: self.need_update_bpf.clone(),
+            #[cfg(target_os = "windows")]
+            pending_pcap_interfaces: self.pending_pcap_interfaces.clone(),
             #[cfg(target_os = "linux")]
             platform_poller: self.platform_poller.clone(),
             capture_bpf: "".into(),
@@ -168,6 +180,7 @@ impl BaseDispatcher {
             proxy_controller_port: DEFAULT_CONTROLLER_PORT,
             analyzer_ip: default_address.to_string(),
             analyzer_port: DEFAULT_INGESTER_PORT,
+            resolved_analyzer_ip: Default::default(),
             tunnel_type_bitmap: self.tunnel_type_bitmap.clone(),
             tunnel_type_trim_bitmap: self.tunnel_type_trim_bitmap.clone(),
             handler_builders: self.handler_builder.clone(),
@@ -179,6 +192,8 @@ impl BaseDispatcher {
             pause: self.pause.clone(),
             local_dispatcher_count: self.local_dispatcher_count,
             bond_group_map: self.bond_group_map.clone(),
+            engine_snap_len: self.options.lock().unwrap().capture_snap_len(),
+            rebuild_required: Default::default(),
         }
     }
 
@@ -206,6 +221,10 @@ impl BaseDispatcher {
             }
             Ok(links) => links,
         };
+        self.open_libpcap(&pcap_interfaces)
+    }
+
+    fn open_libpcap(&mut self, pcap_interfaces: &[Link]) -> Result<()> {
         let options = self.options.lock().unwrap();
         self.engine = if options.tap_mode == TapMode::Local && options.libpcap_enabled {
             if pcap_interfaces.is_empty() {
@@ -226,13 +245,15 @@ impl BaseDispatcher {
             let libpcap = Libpcap::new(
                 src_ifaces.clone(),
                 options.packet_blocks,
-                options.snap_len,
+                options.capture_snap_len(),
                 &self.queue_debugger,
             )
             .map_err(|e| Error::Libpcap(e.to_string()))?;
             info!(
                 "libpcap init with {:?} block {} snap {}",
-                src_ifaces, options.packet_blocks, options.snap_len
+                src_ifaces,
+                options.packet_blocks,
+                options.capture_snap_len()
             );
             self.need_update_bpf.store(true, Ordering::Relaxed);
             RecvEngine::Libpcap(Some(libpcap))
@@ -394,6 +415,18 @@ impl BaseDispatcher {
             return;
         }
 
+        let pending_pcap_interfaces = self.pending_pcap_interfaces.lock().unwrap().take();
+        if let Some(interfaces) = pending_pcap_interfaces {
+            if let Err(e) = self.open_libpcap(&interfaces) {
+                warn!(
+                    "Dispatcher{} reopen libpcap with interfaces {:?} failed: {}",
+                    self.log_id,
+                    interfaces.iter().map(|l| &l.name).collect::<Vec<_>>(),
+                    e
+                );
+            }
+        }
+
         let bpf_options = self.bpf_options.lock().unwrap();
         if let Err(e) = self
             .engine
@@ -526,7 +559,7 @@ impl BaseDispatcher {
             bpf_options.get_bpf_instructions(
                 &tap_interfaces,
                 &self.tap_interface_whitelist,
-                self.options.lock().unwrap().snap_len,
+                self.options.lock().unwrap().capture_snap_len(),
             ),
             &CString::new(bpf_options.get_bpf_syntax()).unwrap(),
         ) {
@@ -544,6 +577,8 @@ pub(super) struct TapTypeHandler {
     pub(super) tap_typer: Arc<TapTyper>,
     pub(super) default_tap_type: TapType,
     pub(super) mirror_traffic_pcp: u16,
+    // tap_type indexed by vlan pcp, only used in mirror mode
+    pub(super) pcp_tap_types: [Option<TapType>; 8],
     pub(super) tap_mode: TapMode,
 }
 
@@ -558,7 +593,12 @@ impl TapTypeHandler {
             eth_type = read_u16_be(&packet[FIELD_OFFSET_ETH_TYPE + VLAN_HEADER_SIZE..]);
             // tap_type从qinq外层的vlan获取
             let pcp = (vlan_tag >> 13) & 0x7;
-            if pcp == self.mirror_traffic_pcp && self.tap_mode == TapMode::Analyzer {
+            if self.tap_mode == TapMode::Mirror {
+                // unmapped pcp falls back to default_tap_type
+                if let Some(t) = self.pcp_tap_types[pcp as usize] {
+                    tap_type = t;
+                }
+            } else if pcp == self.mirror_traffic_pcp && self.tap_mode == TapMode::Analyzer {
                 let vid = vlan_tag & VLAN_ID_MASK;
                 if let Some(t) = self.tap_typer.get_tap_type_by_vlan(vid) {
                     if t != TapType::Unknown {
@@ -634,10 +674,13 @@ pub struct BaseDispatcherListener {
     pub src_interface_index: usize,
     pub options: Arc<Mutex<Options>>,
     pub bpf_options: Arc<Mutex<BpfOptions>>,
+    pub capture_bpf_override: Option<String>,
     pub handler_builders: Arc<Mutex<Vec<PacketHandlerBuilder>>>,
     pub pipelines: Arc<Mutex<HashMap<u32, Arc<Mutex<Pipeline>>>>>,
     pub tap_interfaces: Arc<Mutex<Vec<Link>>>,
     pub need_update_bpf: Arc<AtomicBool>,
+    #[cfg(target_os = "windows")]
+    pub pending_pcap_interfaces: Arc<Mutex<Option<Vec<Link>>>>,
     #[cfg(target_os = "linux")]
     pub platform_poller: Arc<crate::platform::GenericPoller>,
     pub tunnel_type_bitmap: Arc<Mutex<TunnelTypeBitmap>>,
@@ -646,11 +689,16 @@ pub struct BaseDispatcherListener {
     pub reset_whitelist: Arc<AtomicBool>,
     pub pause: Arc<AtomicBool>,
     pub bond_group_map: HashMap<u32, MacAddr>,
+    // frames of the recv engine are sized by the snap_len at creation
+    engine_snap_len: usize,
+    pub rebuild_required: Arc<AtomicBool>,
     capture_bpf: String,
     proxy_controller_ip: String,
     analyzer_ip: String,
     proxy_controller_port: u16,
     analyzer_port: u16,
+    // address of analyzer_ip used by the current bpf, shared by clones of the listener
+    resolved_analyzer_ip: Arc<Mutex<Option<IpAddr>>>,
     #[cfg(target_os = "linux")]
     pub netns: public::netns::NsFile,
 
@@ -669,12 +717,20 @@ impl BaseDispatcherListener {
     }
 
     fn on_bpf_change(&mut self, config: &DispatcherConfig) {
+        let snap_len = {
+            let options = self.options.lock().unwrap();
+            if options.snap_len_auto {
+                options.snap_len
+            } else {
+                config.capture_packet_size as usize
+            }
+        };
         if self.capture_bpf == config.capture_bpf
             && self.proxy_controller_ip == config.proxy_controller_ip
             && self.proxy_controller_port == config.proxy_controller_port
             && self.analyzer_ip == config.analyzer_ip
             && self.analyzer_port == config.analyzer_port
-            && self.options.lock().unwrap().snap_len == config.capture_packet_size as usize
+            && self.options.lock().unwrap().snap_len == snap_len
         {
             return;
         }
@@ -683,39 +739,71 @@ impl BaseDispatcherListener {
         self.proxy_controller_port = config.proxy_controller_port;
         self.analyzer_ip = config.analyzer_ip.clone();
         self.analyzer_port = config.analyzer_port;
-        self.options.lock().unwrap().snap_len = config.capture_packet_size as usize;
+        self.options.lock().unwrap().snap_len = snap_len;
 
-        let analyzer_ip = if self.analyzer_ip.parse::<IpAddr>().is_ok() {
-            self.analyzer_ip.parse::<IpAddr>().unwrap()
-        } else {
-            let ips = lookup_host(&self.analyzer_ip);
-            if ips.is_err() {
-                warn!("Dns lookup {} error: {:?}", self.analyzer_ip, ips);
+        let prefer_ipv6 = self.options.lock().unwrap().is_ipv6;
+        let analyzer_ip = match resolve_analyzer_ip(&self.analyzer_ip, prefer_ipv6) {
+            Ok(ip) => ip,
+            Err(e) => {
+                warn!("Dns lookup {} error: {:?}", self.analyzer_ip, e);
                 return;
             }
-            ips.unwrap()[0]
         };
+        self.update_bpf(config, analyzer_ip);
+    }
+
+    // Called periodically with the address analyzer_ip resolves to, bpf is rebuilt if it changes
+    pub(super) fn on_analyzer_ip_resolved(&self, config: &DispatcherConfig, analyzer_ip: IpAddr) {
+        if *self.resolved_analyzer_ip.lock().unwrap() == Some(analyzer_ip) {
+            return;
+        }
+        info!(
+            "{} analyzer {} resolved to {}, rebuild capture bpf",
+            self.log_id, config.analyzer_ip, analyzer_ip
+        );
+        self.update_bpf(config, analyzer_ip);
+    }
 
-        let source_ip = get_route_src_ip(&analyzer_ip);
+    fn update_bpf(&self, config: &DispatcherConfig, analyzer_ip: IpAddr) {
+        let options = self.options.lock().unwrap();
+        let src_interface = if options.tap_mode == TapMode::Analyzer {
+            self.src_interface.as_str()
+        } else {
+            ""
+        };
+        let source_ip = get_source_ip(src_interface, &analyzer_ip);
         if source_ip.is_err() {
             warn!("get route to {} failed", analyzer_ip);
             return;
         }
 
-        let options = self.options.lock().unwrap();
+        info!(
+            "{} capture bpf excludes controller ports {}, {} and proxy controller port {}",
+            self.log_id,
+            options.controller_port,
+            options.controller_tls_port,
+            config.proxy_controller_port
+        );
+        let source_ip = source_ip.unwrap();
         let bpf_builder = bpf::Builder {
-            is_ipv6: options.is_ipv6,
+            controller_is_ipv6: options.is_ipv6,
+            // the analyzer may be reached in a different address family from the controller
+            analyzer_is_ipv6: source_ip.is_ipv6(),
             vxlan_flags: options.vxlan_flags,
             npb_port: options.npb_port,
+            vxlan_ports: options.vxlan_ports.clone(),
             controller_port: options.controller_port,
             controller_tls_port: options.controller_tls_port,
-            proxy_controller_port: self.proxy_controller_port,
-            analyzer_source_ip: source_ip.unwrap(),
-            analyzer_port: self.analyzer_port,
+            proxy_controller_port: config.proxy_controller_port,
+            analyzer_source_ip: source_ip,
+            analyzer_port: config.analyzer_port,
         };
 
         let mut bpf_options = self.bpf_options.lock().unwrap();
-        bpf_options.capture_bpf = config.capture_bpf.clone();
+        bpf_options.capture_bpf = self
+            .capture_bpf_override
+            .clone()
+            .unwrap_or_else(|| config.capture_bpf.clone());
         #[cfg(any(target_os = "linux", target_os = "android"))]
         {
             bpf_options.bpf_syntax = bpf_builder.build_pcap_syntax();
@@ -725,6 +813,7 @@ impl BaseDispatcherListener {
             bpf_options.bpf_syntax_str = bpf_builder.build_pcap_syntax_to_str();
         }
         self.need_update_bpf.store(true, Ordering::Release);
+        *self.resolved_analyzer_ip.lock().unwrap() = Some(analyzer_ip);
 
         mem::drop(bpf_options);
     }
@@ -745,65 +834,76 @@ impl BaseDispatcherListener {
         self.on_npb_dedup_change(config);
     }
 
+    // Pipelines are updated add-before-remove: new pipelines are built without holding the
+    // pipelines lock, then inserted before the stale ones are removed, so that packets on
+    // unchanged interfaces are never blocked or dropped during the update
     pub(super) fn on_vm_change(&self, keys: &[u32], vm_macs: &[MacAddr]) {
         assert_eq!(keys.len(), vm_macs.len());
         // assert keys in assending order for bsearch
         assert!(keys.windows(2).all(|w| w[0] <= w[1]));
-        let mut pipelines = self.pipelines.lock().unwrap();
 
-        let mut deleted = Vec::new();
-        pipelines.retain(|k, v| {
-            match keys.binary_search(k) {
-                // 在POD和修改ifMacSource场景下，网卡对应的mac地址也会改变，这里需要比较mac
-                Ok(id) if vm_macs[id] == v.lock().unwrap().vm_mac => true,
-                _ => {
-                    deleted.push(v.lock().unwrap().vm_mac);
-                    false
-                }
-            }
-        });
-        if deleted.len() > 0 {
-            info!("Dispatcher{} Removing VMs: {:?}", self.log_id, deleted);
-        }
-        if pipelines.len() == keys.len() {
-            return;
-        }
+        let to_add = {
+            let pipelines = self.pipelines.lock().unwrap();
+            keys.iter()
+                .zip(vm_macs.iter())
+                .filter(|(key, vm_mac)| match pipelines.get(*key) {
+                    // 在POD和修改ifMacSource场景下，网卡对应的mac地址也会改变，这里需要比较mac
+                    Some(p) => p.lock().unwrap().vm_mac != **vm_mac,
+                    None => true,
+                })
+                .map(|(key, vm_mac)| (*key, *vm_mac))
+                .collect::<Vec<_>>()
+        };
 
-        let mut added = Vec::new();
-        for (i, key) in keys.iter().enumerate() {
-            if pipelines.contains_key(key)
-                && pipelines.get(key).unwrap().lock().unwrap().vm_mac == vm_macs[i]
-            {
-                // vm mac already checked
-                continue;
-            }
-            let vm_mac = vm_macs[i];
+        let mut added = Vec::with_capacity(to_add.len());
+        let mut new_pipelines = Vec::with_capacity(to_add.len());
+        for (key, vm_mac) in to_add {
             added.push(vm_mac);
             let handlers = self
                 .handler_builders
                 .lock()
                 .unwrap()
                 .iter()
-                .map(|b| b.build_with(self.id, *key, vm_mac))
+                .map(|b| b.build_with(self.id, key, vm_mac))
                 .collect();
             let bond_mac = self
                 .bond_group_map
-                .get(key)
+                .get(&key)
                 .unwrap_or_else(|| &vm_mac)
                 .clone();
-            pipelines.insert(
-                *key,
+            new_pipelines.push((
+                key,
                 Arc::new(Mutex::new(Pipeline {
                     vm_mac,
                     bond_mac,
                     handlers,
                     timestamp: Duration::ZERO,
                 })),
-            );
+            ));
+        }
+
+        let mut pipelines = self.pipelines.lock().unwrap();
+        let mut deleted = Vec::new();
+        for (key, pipeline) in new_pipelines {
+            if let Some(old) = pipelines.insert(key, pipeline) {
+                deleted.push(old.lock().unwrap().vm_mac);
+            }
         }
+        pipelines.retain(|k, v| {
+            if keys.binary_search(k).is_ok() {
+                return true;
+            }
+            deleted.push(v.lock().unwrap().vm_mac);
+            false
+        });
+        drop(pipelines);
+
         if added.len() > 0 {
             info!("Dispatcher{} Adding VMs: {:?}", self.log_id, added);
         }
+        if deleted.len() > 0 {
+            info!("Dispatcher{} Removing VMs: {:?}", self.log_id, deleted);
+        }
     }
 
     pub(super) fn on_tap_interface_change(&self, mut interfaces: Vec<Link>, _: IfMacSource) {
@@ -826,9 +926,44 @@ impl BaseDispatcherListener {
         if *tap_interfaces == interfaces {
             return;
         }
+        if let Some(mtu_snap_len) = super::mtu_snap_len(&interfaces) {
+            let mut options = self.options.lock().unwrap();
+            if options.snap_len_auto {
+                if mtu_snap_len > self.engine_snap_len {
+                    if !self.rebuild_required.swap(true, Ordering::Relaxed) {
+                        info!(
+                            "Dispatcher{} snap_len {} required by interface mtu exceeds frame size {}, rebuild required",
+                            self.log_id, mtu_snap_len, self.engine_snap_len
+                        );
+                    }
+                } else if options.snap_len != mtu_snap_len {
+                    info!(
+                        "Dispatcher{} snap_len set to {} by interface mtu",
+                        self.log_id, mtu_snap_len
+                    );
+                    options.snap_len = mtu_snap_len;
+                }
+            } else if options.capture_snap_len() < mtu_snap_len {
+                warn!(
+                    "Dispatcher{} capture packet size {} is less than {} required by interface mtu, large frames will be truncated",
+                    self.log_id,
+                    options.capture_snap_len(),
+                    mtu_snap_len
+                );
+            }
+        }
         *tap_interfaces = interfaces;
         self.need_update_bpf.store(true, Ordering::Release);
     }
+
+    #[cfg(target_os = "windows")]
+    pub(super) fn on_pcap_interfaces_change(&self, interfaces: &[Link]) {
+        self.pending_pcap_interfaces
+            .lock()
+            .unwrap()
+            .replace(interfaces.to_vec());
+        self.need_update_bpf.store(true, Ordering::Release);
+    }
 }
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -842,3 +977,44 @@ impl BaseDispatcherListener {
         }
     }
 }
+
+// Resolves the analyzer, preferring addresses of the same family as the controller ip
+// if the name has both ipv4 and ipv6 addresses
+pub fn resolve_analyzer_ip(analyzer_ip: &str, prefer_ipv6: bool) -> io::Result<IpAddr> {
+    if let Ok(ip) = analyzer_ip.parse::<IpAddr>() {
+        return Ok(ip);
+    }
+    let ips = lookup_host(analyzer_ip)?;
+    ips.iter()
+        .find(|ip| ip.is_ipv6() == prefer_ipv6)
+        .or(ips.first())
+        .copied()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no address found for {}", analyzer_ip),
+            )
+        })
+}
+
+// Source ip used by bpf to filter out the traffic sent to the analyzer. A non-empty
+// `src_interface` prefers the address on that interface, so that analyzer dispatchers on
+// multi-homed hosts reference their own local ip instead of the route source ip.
+pub fn get_source_ip(src_interface: &str, analyzer_ip: &IpAddr) -> net::Result<IpAddr> {
+    if !src_interface.is_empty() {
+        let addr = link_by_name(src_interface).and_then(|link| {
+            Ok(addr_list()?.into_iter().find(|addr| {
+                addr.if_index == link.if_index && addr.ip_addr.is_ipv6() == analyzer_ip.is_ipv6()
+            }))
+        });
+        match addr {
+            Ok(Some(addr)) => return Ok(addr.ip_addr),
+            Ok(None) => debug!(
+                "no address of the same family as {} on {}, use route source ip",
+                analyzer_ip, src_interface
+            ),
+            Err(e) => warn!("get address of {} failed: {:?}", src_interface, e),
+        }
+    }
+    get_route_src_ip(analyzer_ip)
+}
@@ -16,11 +16,14 @@
 
 use std::collections::{HashMap, HashSet};
 use std::mem::drop;
+use std::net::IpAddr;
 use std::process::Command;
 use std::str;
 use std::sync::atomic::Ordering;
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "windows"))]
 use std::sync::Arc;
+#[cfg(target_os = "windows")]
+use std::sync::Mutex;
 use std::time::Duration;
 
 use arc_swap::access::Access;
@@ -29,6 +32,7 @@ use regex::Regex;
 
 use super::base_dispatcher::{BaseDispatcher, BaseDispatcherListener};
 use super::error::Result;
+use super::Options;
 
 #[cfg(target_os = "linux")]
 use crate::platform::{GenericPoller, LibvirtXmlExtractor, Poller};
@@ -72,11 +76,13 @@ impl LocalModeDispatcher {
             base.log_output_queue.clone(),
             base.ntp_diff.clone(),
             &base.flow_map_config.load(),
-            Some(base.packet_sequence_output_queue.clone()), // Enterprise Edition Feature: packet-sequence
+            base.packet_sequence_output_queue.clone(), // Enterprise Edition Feature: packet-sequence
             base.stats.clone(),
             false, // !from_ebpf
         );
         let tunnel_type_trim_bitmap = base.tunnel_type_trim_bitmap.clone();
+        let snap_len_overrides = base.options.lock().unwrap().snap_len_overrides.clone();
+        let mut snap_len = base.options.lock().unwrap().snap_len;
 
         while !base.terminated.load(Ordering::Relaxed) {
             let config = Config {
@@ -102,6 +108,7 @@ impl LocalModeDispatcher {
                 )
             };
             if recved.is_none() {
+                snap_len = base.options.lock().unwrap().snap_len;
                 flow_map.inject_flush_ticker(&config, Duration::ZERO);
                 if base.tap_interface_whitelist.next_sync(Duration::ZERO) {
                     base.need_update_bpf.store(true, Ordering::Relaxed);
@@ -110,7 +117,7 @@ impl LocalModeDispatcher {
                 base.check_and_update_bpf();
                 continue;
             }
-            if base.pause.load(Ordering::Relaxed) {
+            if base.pause.load(Ordering::Relaxed) || base.capture_paused.load(Ordering::Relaxed) {
                 continue;
             }
             #[cfg(target_os = "windows")]
@@ -196,7 +203,7 @@ impl LocalModeDispatcher {
                     continue;
                 }
             };
-            let overlay_packet = &packet.data[decap_length..];
+            let mut overlay_packet = &packet.data[decap_length..];
             let mut meta_packet = MetaPacket::empty();
             let offset = Duration::ZERO;
             if let Err(e) = meta_packet.update(
@@ -210,6 +217,32 @@ impl LocalModeDispatcher {
                 warn!("meta_packet update failed: {:?}", e);
                 continue;
             }
+            if !snap_len_overrides.is_empty() {
+                // packets are captured with the largest snap length, slice it to the
+                // length of the matched protocol
+                let slice_len = Options::slice_len(
+                    snap_len,
+                    &snap_len_overrides,
+                    meta_packet.lookup_key.proto,
+                    meta_packet.lookup_key.src_port,
+                    meta_packet.lookup_key.dst_port,
+                );
+                if overlay_packet.len() > slice_len {
+                    overlay_packet = &overlay_packet[..slice_len];
+                    meta_packet = MetaPacket::empty();
+                    if let Err(e) = meta_packet.update(
+                        overlay_packet,
+                        src_local,
+                        dst_local,
+                        timestamp + offset,
+                        packet.data.len() - decap_length,
+                    ) {
+                        base.counter.invalid_packets.fetch_add(1, Ordering::Relaxed);
+                        warn!("meta_packet update failed: {:?}", e);
+                        continue;
+                    }
+                }
+            }
 
             base.counter.rx.fetch_add(1, Ordering::Relaxed);
             base.counter
@@ -298,6 +331,9 @@ pub struct LocalModeDispatcherListener {
     #[cfg(target_os = "linux")]
     extractor: Arc<LibvirtXmlExtractor>,
     rewriter: MacRewriter,
+    // last blacklist from controller, reapplied when pcap interfaces change
+    #[cfg(target_os = "windows")]
+    blacklist: Arc<Mutex<Vec<u64>>>,
 }
 
 impl LocalModeDispatcherListener {
@@ -310,6 +346,8 @@ impl LocalModeDispatcherListener {
             #[cfg(target_os = "linux")]
             extractor,
             rewriter: MacRewriter::new(),
+            #[cfg(target_os = "windows")]
+            blacklist: Default::default(),
         }
     }
 
@@ -322,12 +360,20 @@ impl LocalModeDispatcherListener {
         self.base.on_config_change(config)
     }
 
+    pub(super) fn on_analyzer_ip_resolved(&self, config: &DispatcherConfig, analyzer_ip: IpAddr) {
+        self.base.on_analyzer_ip_resolved(config, analyzer_ip)
+    }
+
     pub fn on_vm_change(&self, _: &[MacAddr]) {}
 
     pub fn id(&self) -> usize {
         return self.base.id;
     }
 
+    pub fn rebuild_required(&self) -> bool {
+        self.base.rebuild_required.load(Ordering::Relaxed)
+    }
+
     pub fn local_dispatcher_count(&self) -> usize {
         return self.base.local_dispatcher_count;
     }
@@ -345,6 +391,10 @@ impl LocalModeDispatcherListener {
         trident_type: TridentType,
         blacklist: &Vec<u64>,
     ) {
+        #[cfg(target_os = "windows")]
+        {
+            *self.blacklist.lock().unwrap() = blacklist.clone();
+        }
         let mut interfaces = interfaces.to_vec();
         if !blacklist.is_empty() {
             // 当虚拟机内的容器节点已部署采集器时，宿主机采集器需要排除容器节点的接口，避免采集双份重复流量
@@ -381,10 +431,25 @@ impl LocalModeDispatcherListener {
             #[cfg(target_os = "linux")]
             &self.base.options.lock().unwrap().tap_mac_script,
         );
+        // pipelines for added interfaces must be ready before bpf starts to accept them
         self.base.on_vm_change(&keys, &macs);
         self.base.on_tap_interface_change(interfaces, if_mac_source);
     }
 
+    #[cfg(target_os = "windows")]
+    pub fn on_pcap_interfaces_change(
+        &self,
+        pcap_interfaces: &[Link],
+        tap_interfaces: &[Link],
+        if_mac_source: IfMacSource,
+        trident_type: TridentType,
+    ) {
+        // libpcap captures on all matched interfaces while tap interfaces are shared among dispatchers
+        self.base.on_pcap_interfaces_change(pcap_interfaces);
+        let blacklist = self.blacklist.lock().unwrap().clone();
+        self.on_tap_interface_change(tap_interfaces, if_mac_source, trident_type, &blacklist);
+    }
+
     fn get_mapped_macs(
         &self,
         interfaces: &Vec<Link>,
@@ -18,12 +18,13 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::mem::drop;
+use std::net::IpAddr;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use std::process::Command;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use std::str;
 use std::sync::{atomic::Ordering, Arc};
-use std::thread::{self, JoinHandle};
+use std::thread::JoinHandle;
 use std::time::Duration;
 
 use arc_swap::access::Access;
@@ -42,12 +43,16 @@ use crate::{
         MetaPacket, TapPort, FIELD_OFFSET_ETH_TYPE, MAC_ADDR_LEN, VLAN_HEADER_SIZE,
     },
     config::DispatcherConfig,
-    flow_generator::{flow_map::Config, FlowMap},
+    flow_generator::{
+        flow_map::{AllocatorStats, Config},
+        FlowMap,
+    },
     handler::MiniPacket,
     rpc::get_timestamp,
     utils::{
         bytes::read_u16_be,
         stats::{self, Countable, QueueStats},
+        thread_builder,
     },
 };
 use public::{
@@ -79,6 +84,7 @@ pub(super) struct LocalPlusModeDispatcher {
     pub(super) pipeline_thread_handler: Option<JoinHandle<()>>,
     pub(super) inner_queue_size: usize,
     pub(super) raw_packet_block_size: usize,
+    pub(super) raw_packet_pool_size: usize,
     pub(super) pool_raw_size: usize,
 }
 
@@ -118,10 +124,10 @@ impl LocalPlusModeDispatcher {
         let ctrl_mac = base.ctrl_mac;
         let pool_raw_size = self.pool_raw_size;
         let tunnel_type_trim_bitmap = base.tunnel_type_trim_bitmap.clone();
+        let thread_stack_size = base.thread_stack_size;
 
         self.flow_generator_thread_handler.replace(
-            thread::Builder::new()
-                .name("dispatcher-packet-to-flow-generator".to_owned())
+            thread_builder(format!("dp-flow-{}", id), thread_stack_size)
                 .spawn(move || {
                     let mut batch = Vec::with_capacity(HANDLER_BATCH_SIZE);
                     let mut output_batch = Vec::with_capacity(HANDLER_BATCH_SIZE);
@@ -133,7 +139,7 @@ impl LocalPlusModeDispatcher {
                         log_output_queue,
                         ntp_diff,
                         &flow_map_config.load(),
-                        Some(packet_sequence_output_queue), // Enterprise Edition Feature: packet-sequence
+                        packet_sequence_output_queue, // Enterprise Edition Feature: packet-sequence
                         stats,
                         false, // !from_ebpf
                     );
@@ -302,10 +308,10 @@ impl LocalPlusModeDispatcher {
         let base = &self.base;
         let terminated = base.terminated.clone();
         let pipelines = base.pipelines.clone();
+        let thread_stack_size = base.thread_stack_size;
 
         self.pipeline_thread_handler.replace(
-            thread::Builder::new()
-                .name("dispatcher-additional-packet-pipeline".to_owned())
+            thread_builder(format!("dp-pipeline-{}", base.id), thread_stack_size)
                 .spawn(move || {
                     let mut batch = Vec::with_capacity(HANDLER_BATCH_SIZE);
                     while !terminated.load(Ordering::Relaxed) {
@@ -383,7 +389,15 @@ impl LocalPlusModeDispatcher {
         let mut prev_timestamp = get_timestamp(time_diff);
         let id = base.id;
         let mut batch = Vec::with_capacity(HANDLER_BATCH_SIZE);
-        let mut allocator = Allocator::new(self.raw_packet_block_size);
+        let mut allocator =
+            Allocator::with_pool(self.raw_packet_block_size, self.raw_packet_pool_size);
+        self.stats_collector.register_countable(
+            &AllocatorStats {
+                id: id as u32,
+                obj_type: "RawPacket",
+            },
+            Countable::Ref(allocator.counter()),
+        );
 
         while !base.terminated.load(Ordering::Relaxed) {
             if base.reset_whitelist.swap(false, Ordering::Relaxed) {
@@ -414,7 +428,7 @@ impl LocalPlusModeDispatcher {
                 base.check_and_update_bpf();
                 continue;
             }
-            if base.pause.load(Ordering::Relaxed) {
+            if base.pause.load(Ordering::Relaxed) || base.capture_paused.load(Ordering::Relaxed) {
                 continue;
             }
 
@@ -497,12 +511,20 @@ impl LocalPlusModeDispatcherListener {
         self.base.on_config_change(config)
     }
 
+    pub(super) fn on_analyzer_ip_resolved(&self, config: &DispatcherConfig, analyzer_ip: IpAddr) {
+        self.base.on_analyzer_ip_resolved(config, analyzer_ip)
+    }
+
     pub fn on_vm_change(&self, _: &[MacAddr]) {}
 
     pub fn id(&self) -> usize {
         return self.base.id;
     }
 
+    pub fn rebuild_required(&self) -> bool {
+        self.base.rebuild_required.load(Ordering::Relaxed)
+    }
+
     pub fn local_dispatcher_count(&self) -> usize {
         return self.base.local_dispatcher_count;
     }
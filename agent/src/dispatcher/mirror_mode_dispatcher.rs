@@ -17,6 +17,7 @@
 use std::{
     collections::HashMap,
     mem::drop,
+    net::IpAddr,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
@@ -153,10 +154,18 @@ impl MirrorModeDispatcherListener {
         self.base.on_config_change(config)
     }
 
+    pub(super) fn on_analyzer_ip_resolved(&self, config: &DispatcherConfig, analyzer_ip: IpAddr) {
+        self.base.on_analyzer_ip_resolved(config, analyzer_ip)
+    }
+
     pub fn id(&self) -> usize {
         return self.base.id;
     }
 
+    pub fn rebuild_required(&self) -> bool {
+        self.base.rebuild_required.load(Ordering::Relaxed)
+    }
+
     pub fn flow_acl_change(&self) {
         // Start capturing traffic after resource information is distributed
         self.base.pause.store(false, Ordering::Relaxed);
@@ -399,7 +408,7 @@ impl MirrorModeDispatcher {
             self.base.log_output_queue.clone(),
             self.base.ntp_diff.clone(),
             &self.base.flow_map_config.load(),
-            Some(self.base.packet_sequence_output_queue.clone()), // Enterprise Edition Feature: packet-sequence
+            self.base.packet_sequence_output_queue.clone(), // Enterprise Edition Feature: packet-sequence
             self.base.stats.clone(),
             false, // !from_ebpf
         );
@@ -435,7 +444,9 @@ impl MirrorModeDispatcher {
                 self.base.check_and_update_bpf();
                 continue;
             }
-            if self.base.pause.load(Ordering::Relaxed) {
+            if self.base.pause.load(Ordering::Relaxed)
+                || self.base.capture_paused.load(Ordering::Relaxed)
+            {
                 continue;
             }
             let (mut packet, mut timestamp) = recved.unwrap();
@@ -19,12 +19,16 @@ pub(crate) mod recv_engine;
 
 mod base_dispatcher;
 
+mod analyzer_ip_watcher;
 mod analyzer_mode_dispatcher;
 mod local_mode_dispatcher;
 mod local_plus_mode_dispatcher;
 mod mirror_mode_dispatcher;
+#[cfg(target_os = "windows")]
+mod pcap_interface_watcher;
 
-use std::thread::{self, JoinHandle};
+use std::net::IpAddr;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use std::{
     collections::HashMap,
@@ -43,16 +47,24 @@ use public::debug::QueueDebugger;
 use special_recv_engine::Dpdk;
 use special_recv_engine::Libpcap;
 
+pub use analyzer_ip_watcher::AnalyzerIpWatcher;
 use analyzer_mode_dispatcher::{AnalyzerModeDispatcher, AnalyzerModeDispatcherListener}; // Enterprise Edition Feature: analyzer_mode
+pub use base_dispatcher::{get_source_ip, resolve_analyzer_ip};
 use base_dispatcher::{BaseDispatcher, TapTypeHandler};
 use error::{Error, Result};
 use local_mode_dispatcher::{LocalModeDispatcher, LocalModeDispatcherListener};
 use local_plus_mode_dispatcher::{LocalPlusModeDispatcher, LocalPlusModeDispatcherListener};
 use mirror_mode_dispatcher::{MirrorModeDispatcher, MirrorModeDispatcherListener};
+#[cfg(target_os = "windows")]
+pub use pcap_interface_watcher::PcapInterfaceWatcher;
+#[cfg(target_os = "linux")]
+use recv_engine::af_xdp::XdpSocket;
 pub use recv_engine::RecvEngine;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use recv_engine::{
-    af_packet::{self, bpf::*, BpfSyntax, OptTpacketVersion, RawInstruction, Tpacket},
+    af_packet::{
+        self, bpf::*, BpfSyntax, OptTpacketVersion, RawInstruction, Tpacket, TpacketGroup,
+    },
     DEFAULT_BLOCK_SIZE, FRAME_SIZE_MAX, FRAME_SIZE_MIN, POLL_TIMEOUT,
 };
 
@@ -64,11 +76,13 @@ use crate::common::decapsulate::TunnelTypeBitmap;
 use crate::platform::LibvirtXmlExtractor;
 use crate::{
     common::{
-        enums::TapType, flow::L7Stats, FlowAclListener, FlowAclListenerId, TaggedFlow, TapTyper,
+        enums::{IpProtocol, TapType},
+        flow::L7Stats,
+        FlowAclListener, FlowAclListenerId, TaggedFlow, TapTyper,
     },
     config::{
         handler::{CollectorAccess, FlowAccess, LogParserAccess},
-        DispatcherConfig,
+        CaptureTimestampSource, DispatcherConfig,
     },
     exception::ExceptionHandler,
     flow_generator::AppProto,
@@ -77,12 +91,14 @@ use crate::{
     utils::{
         environment::get_mac_by_name,
         stats::{self, Collector},
+        thread_builder,
     },
 };
 
 #[cfg(target_os = "linux")]
 use public::netns::NsFile;
 use public::{
+    bitmap::Bitmap,
     buffer::BatchedBox,
     proto::{
         common::TridentType,
@@ -138,10 +154,14 @@ impl DispatcherFlavor {
 }
 
 pub struct Dispatcher {
+    id: usize,
+    // timestamp source of the recv engine created at build, may differ from the configured one
+    timestamp_source: CaptureTimestampSource,
     flavor: Mutex<Option<DispatcherFlavor>>,
     terminated: Arc<AtomicBool>,
     running: AtomicBool,
     handle: Mutex<Option<JoinHandle<DispatcherFlavor>>>,
+    thread_stack_size: usize,
 }
 
 impl Dispatcher {
@@ -161,8 +181,7 @@ impl Dispatcher {
         self.terminated.store(false, Ordering::Relaxed);
         let mut flavor = self.flavor.lock().unwrap().take().unwrap();
         self.handle.lock().unwrap().replace(
-            thread::Builder::new()
-                .name("dispatcher".to_owned())
+            thread_builder(format!("dispatcher-{}", self.id), self.thread_stack_size)
                 .spawn(move || {
                     flavor.run();
                     flavor
@@ -184,6 +203,10 @@ impl Dispatcher {
     pub fn is_running(&self) -> bool {
         self.running.load(Ordering::Relaxed)
     }
+
+    pub fn timestamp_source(&self) -> CaptureTimestampSource {
+        self.timestamp_source
+    }
 }
 
 impl Dispatcher {
@@ -259,6 +282,16 @@ impl DispatcherListener {
         }
     }
 
+    // Whether the dispatcher has to be rebuilt to capture full frames on its tap interfaces
+    pub fn rebuild_required(&self) -> bool {
+        match self {
+            Self::Local(a) => a.rebuild_required(),
+            Self::LocalPlus(a) => a.rebuild_required(),
+            Self::Mirror(a) => a.rebuild_required(),
+            Self::Analyzer(a) => a.rebuild_required(),
+        }
+    }
+
     fn local_dispatcher_count(&self) -> usize {
         match self {
             Self::Local(a) => a.local_dispatcher_count(),
@@ -276,6 +309,15 @@ impl DispatcherListener {
         }
     }
 
+    pub(super) fn on_analyzer_ip_resolved(&self, config: &DispatcherConfig, analyzer_ip: IpAddr) {
+        match self {
+            Self::Local(l) => l.on_analyzer_ip_resolved(config, analyzer_ip),
+            Self::LocalPlus(l) => l.on_analyzer_ip_resolved(config, analyzer_ip),
+            Self::Analyzer(l) => l.on_analyzer_ip_resolved(config, analyzer_ip), // Enterprise Edition Feature: analyzer_mode
+            Self::Mirror(l) => l.on_analyzer_ip_resolved(config, analyzer_ip),
+        }
+    }
+
     pub fn on_vm_change(&self, vm_mac_addrs: &[MacAddr], gateway_vmac_addrs: &[MacAddr]) {
         match self {
             // Enterprise Edition Feature: analyzer_mode
@@ -349,6 +391,25 @@ impl DispatcherListener {
             }
         }
     }
+
+    // Only local mode captures with libpcap on windows
+    #[cfg(target_os = "windows")]
+    pub fn on_pcap_interfaces_change(
+        &self,
+        interfaces: &[Link],
+        if_mac_source: IfMacSource,
+        trident_type: TridentType,
+    ) {
+        match self {
+            Self::Local(l) => l.on_pcap_interfaces_change(
+                interfaces,
+                self.get_interfaces(interfaces),
+                if_mac_source,
+                trident_type,
+            ),
+            _ => (),
+        }
+    }
 }
 
 pub struct BpfOptions {
@@ -484,6 +545,21 @@ impl BpfOptions {
         return Some(prog);
     }
 
+    // Returns whether capture_bpf can be compiled, empty capture_bpf is always valid
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn capture_bpf_valid(&self) -> bool {
+        if self.capture_bpf.is_empty() {
+            return true;
+        }
+        match self.to_pcap_bpf_prog() {
+            Some(mut prog) => {
+                unsafe { pcap_sys::pcap_freecode(&mut prog) };
+                true
+            }
+            None => false,
+        }
+    }
+
     #[cfg(any(target_os = "linux", target_os = "android"))]
     pub fn get_bpf_instructions(
         &self,
@@ -565,22 +641,97 @@ impl BpfOptions {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct SnapLenOverride {
+    pub protocol: IpProtocol,
+    pub ports: Bitmap,
+    pub snap_len: usize,
+}
+
+impl SnapLenOverride {
+    fn matches(&self, protocol: IpProtocol, src_port: u16, dst_port: u16) -> bool {
+        self.protocol == protocol
+            && (self.ports.get(src_port as usize).unwrap_or_default()
+                || self.ports.get(dst_port as usize).unwrap_or_default())
+    }
+}
+
+// ethernet header and a vlan tag, which are not included in interface mtu
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MTU_L2_OVERHEAD: usize = 18;
+
+// Returns the snap length required to capture full frames on all the links,
+// None if mtu of none of the links is known
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn mtu_snap_len(links: &[Link]) -> Option<usize> {
+    links
+        .iter()
+        .filter_map(|l| public::utils::net::link_mtu(&l.name))
+        .max()
+        .map(|mtu| (mtu as usize + MTU_L2_OVERHEAD).min(u16::MAX as usize))
+}
+
+#[cfg(target_os = "windows")]
+pub fn mtu_snap_len(_: &[Link]) -> Option<usize> {
+    None
+}
+
 #[derive(Default)]
 pub struct Options {
     pub packet_blocks: usize,
     #[cfg(any(target_os = "linux", target_os = "android"))]
     pub af_packet_version: OptTpacketVersion,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub af_packet_fanout: Option<af_packet::Fanout>,
     pub snap_len: usize,
+    // snap_len follows the mtu of tap interfaces instead of capture_packet_size
+    pub snap_len_auto: bool,
+    // Packets are captured with the largest length among `snap_len` and all the overrides,
+    // and then sliced in the dispatcher, so each override costs capture buffer memory for
+    // every packet, not only for the matched ones
+    pub snap_len_overrides: Arc<Vec<SnapLenOverride>>,
     pub tap_mode: TapMode,
     pub dpdk_enabled: bool,
+    // mirror and analyzer mode only, falls back to AF_PACKET if not supported
+    #[cfg(target_os = "linux")]
+    pub af_xdp_enabled: bool,
     pub libpcap_enabled: bool,
     pub dispatcher_queue: bool,
     pub tap_mac_script: String,
     pub is_ipv6: bool,
     pub vxlan_flags: u8,
     pub npb_port: u16,
+    pub vxlan_ports: Vec<u16>,
     pub controller_port: u16,
     pub controller_tls_port: u16,
+    // analyzer mode only, zero values use the defaults of PacketDedupMap
+    pub analyzer_dedup_window: Duration,
+    pub analyzer_dedup_table_size: usize,
+    pub capture_timestamp_source: CaptureTimestampSource,
+}
+
+impl Options {
+    // snap length used by recv engines and bpf
+    pub fn capture_snap_len(&self) -> usize {
+        self.snap_len_overrides
+            .iter()
+            .fold(self.snap_len, |len, o| len.max(o.snap_len))
+    }
+
+    // returns the length a packet should be sliced to
+    pub fn slice_len(
+        snap_len: usize,
+        overrides: &[SnapLenOverride],
+        protocol: IpProtocol,
+        src_port: u16,
+        dst_port: u16,
+    ) -> usize {
+        overrides
+            .iter()
+            .find(|o| o.matches(protocol, src_port, dst_port))
+            .map(|o| o.snap_len)
+            .unwrap_or(snap_len)
+    }
 }
 
 pub struct Pipeline {
@@ -603,6 +754,9 @@ struct PacketCounter {
     get_token_failed: AtomicU64,
 
     retired: AtomicU64,
+    // analyzer mode only
+    deduped: AtomicU64,
+    dedup_evicted: AtomicU64,
     kernel_counter: Arc<dyn stats::RefCountable>,
 }
 
@@ -621,6 +775,8 @@ impl PacketCounter {
             get_token_failed: AtomicU64::new(0),
 
             retired: AtomicU64::new(0),
+            deduped: AtomicU64::new(0),
+            dedup_evicted: AtomicU64::new(0),
             kernel_counter,
         }
     }
@@ -674,6 +830,16 @@ impl stats::RefCountable for PacketCounter {
                 stats::CounterType::Counted,
                 stats::CounterValue::Unsigned(self.retired.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "deduped",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.deduped.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "dedup_evicted",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.dedup_evicted.swap(0, Ordering::Relaxed)),
+            ),
         ]);
         counters
     }
@@ -684,14 +850,17 @@ pub struct DispatcherBuilder {
     id: Option<usize>,
     local_dispatcher_count: usize,
     pause: Option<bool>,
-    src_interface: Option<String>,
+    capture_paused: Option<Arc<AtomicBool>>,
+    src_interfaces: Vec<String>,
     ctrl_mac: Option<MacAddr>,
     leaky_bucket: Option<Arc<LeakyBucket>>,
     options: Option<Arc<Mutex<Options>>>,
     handler_builders: Arc<Mutex<Vec<PacketHandlerBuilder>>>,
     bpf_options: Option<Arc<Mutex<BpfOptions>>>,
+    capture_bpf_override: Option<String>,
     default_tap_type: Option<TapType>,
     mirror_traffic_pcp: Option<u16>,
+    pcp_tap_types: Option<HashMap<u16, TapType>>,
     tap_typer: Option<Arc<TapTyper>>,
     analyzer_dedup_disabled: Option<bool>,
     #[cfg(target_os = "linux")]
@@ -717,8 +886,10 @@ pub struct DispatcherBuilder {
     queue_debugger: Option<Arc<QueueDebugger>>,
     analyzer_queue_size: Option<usize>,
     analyzer_raw_packet_block_size: Option<usize>,
+    batched_buffer_pool_size: usize,
     tunnel_type_trim_bitmap: Option<TunnelTypeBitmap>,
     bond_group: Option<Vec<String>>,
+    thread_stack_size: usize,
 }
 
 impl DispatcherBuilder {
@@ -736,8 +907,20 @@ impl DispatcherBuilder {
         self
     }
 
+    pub fn capture_paused(mut self, v: Arc<AtomicBool>) -> Self {
+        self.capture_paused = Some(v);
+        self
+    }
+
     pub fn src_interface(mut self, v: String) -> Self {
-        self.src_interface = Some(v);
+        self.src_interfaces = vec![v];
+        self
+    }
+
+    // Packets from all interfaces are fanned in to the same dispatcher pipeline,
+    // the first one is used where a single interface is required
+    pub fn src_interfaces(mut self, v: Vec<String>) -> Self {
+        self.src_interfaces = v;
         self
     }
 
@@ -766,6 +949,12 @@ impl DispatcherBuilder {
         self
     }
 
+    // Replaces the capture bpf of the shared `bpf_options` for this dispatcher only
+    pub fn capture_bpf_override(mut self, v: String) -> Self {
+        self.capture_bpf_override = Some(v);
+        self
+    }
+
     pub fn default_tap_type(mut self, v: TapType) -> Self {
         self.default_tap_type = Some(v);
         self
@@ -776,6 +965,11 @@ impl DispatcherBuilder {
         self
     }
 
+    pub fn pcp_tap_types(mut self, v: HashMap<u16, TapType>) -> Self {
+        self.pcp_tap_types = Some(v);
+        self
+    }
+
     pub fn tap_typer(mut self, v: Arc<TapTyper>) -> Self {
         self.tap_typer = Some(v);
         self
@@ -882,11 +1076,21 @@ impl DispatcherBuilder {
         self
     }
 
+    pub fn batched_buffer_pool_size(mut self, v: usize) -> Self {
+        self.batched_buffer_pool_size = v;
+        self
+    }
+
     pub fn local_dispatcher_count(mut self, v: usize) -> Self {
         self.local_dispatcher_count = v;
         self
     }
 
+    pub fn thread_stack_size(mut self, v: usize) -> Self {
+        self.thread_stack_size = v;
+        self
+    }
+
     pub fn tunnel_type_trim_bitmap(mut self, v: TunnelTypeBitmap) -> Self {
         self.tunnel_type_trim_bitmap = Some(v);
         self
@@ -907,31 +1111,36 @@ impl DispatcherBuilder {
             .options
             .ok_or(Error::ConfigIncomplete("no options".into()))?;
         let tap_mode = options.lock().unwrap().tap_mode;
-        let snap_len = options.lock().unwrap().snap_len;
+        let snap_len = options.lock().unwrap().capture_snap_len();
         let queue_debugger = self
             .queue_debugger
             .ok_or(Error::ConfigIncomplete("no queue debugger".into()))?;
         let dispatcher_queue = options.lock().unwrap().dispatcher_queue;
         let engine = Self::get_engine(
             &self.pcap_interfaces,
-            &mut self.src_interface,
+            &self.src_interfaces,
             tap_mode,
             &options,
             &queue_debugger,
         )?;
 
         let kernel_counter = engine.get_counter_handle();
+        let timestamp_source = engine.timestamp_source();
         let id = self.id.ok_or(Error::ConfigIncomplete("no id".into()))?;
         let terminated = Arc::new(AtomicBool::new(false));
         let stat_counter = Arc::new(PacketCounter::new(terminated.clone(), kernel_counter));
         let collector = self
             .stats_collector
             .ok_or(Error::StatsCollector("no stats collector"))?;
-        let src_interface = if tap_mode == TapMode::Local {
-            "".to_string()
+        let src_interfaces = if tap_mode == TapMode::Local {
+            vec![]
         } else {
-            self.src_interface.unwrap_or("".to_string())
+            self.src_interfaces
+                .into_iter()
+                .filter(|i| !i.is_empty())
+                .collect::<Vec<_>>()
         };
+        let src_interface = src_interfaces.get(0).cloned().unwrap_or_default();
 
         #[cfg(target_os = "linux")]
         let local_tap_interfaces = public::netns::link_list_in_netns(&netns).unwrap_or_default();
@@ -961,12 +1170,26 @@ impl DispatcherBuilder {
             .take()
             .ok_or(Error::ConfigIncomplete("no platform poller".into()))?;
 
+        let bpf_options = self.bpf_options.unwrap_or_default();
+        let bpf_options = match self.capture_bpf_override.as_ref() {
+            // dispatcher with an override compiles its own filter, the default syntax is
+            // copied from the shared options and kept up to date by its listener
+            Some(capture_bpf) => {
+                let shared = bpf_options.lock().unwrap();
+                Arc::new(Mutex::new(BpfOptions {
+                    capture_bpf: capture_bpf.clone(),
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    bpf_syntax: shared.bpf_syntax.clone(),
+                    bpf_syntax_str: shared.bpf_syntax_str.clone(),
+                }))
+            }
+            None => bpf_options,
+        };
+
         let base = BaseDispatcher {
             log_id: {
                 let mut lid = vec![id.to_string()];
-                if &src_interface != "" {
-                    lid.push(src_interface.clone());
-                }
+                lid.extend(src_interfaces.iter().cloned());
                 #[cfg(target_os = "linux")]
                 if &src_interface == "" && netns != NsFile::Root {
                     lid.push(netns.to_string());
@@ -984,7 +1207,8 @@ impl DispatcherBuilder {
                 .ok_or(Error::ConfigIncomplete("no ctrl_mac".into()))?,
 
             options,
-            bpf_options: self.bpf_options.unwrap_or_default(),
+            bpf_options,
+            capture_bpf_override: self.capture_bpf_override,
 
             leaky_bucket: self
                 .leaky_bucket
@@ -1005,10 +1229,21 @@ impl DispatcherBuilder {
                 mirror_traffic_pcp: self
                     .mirror_traffic_pcp
                     .ok_or(Error::ConfigIncomplete("no mirror_traffic_pcp".into()))?,
+                pcp_tap_types: {
+                    let mut pcp_tap_types = [None; 8];
+                    for (pcp, tap_type) in self.pcp_tap_types.unwrap_or_default() {
+                        if let Some(t) = pcp_tap_types.get_mut(pcp as usize) {
+                            *t = Some(tap_type);
+                        }
+                    }
+                    pcp_tap_types
+                },
                 tap_mode,
             },
 
             need_update_bpf: Arc::new(AtomicBool::new(true)),
+            #[cfg(target_os = "windows")]
+            pending_pcap_interfaces: Default::default(),
             reset_whitelist: Default::default(),
             tap_interface_whitelist: Default::default(),
 
@@ -1058,20 +1293,19 @@ impl DispatcherBuilder {
                 .take()
                 .ok_or(Error::ConfigIncomplete("no ntp_diff".into()))?,
             // Enterprise Edition Feature: packet-sequence
-            packet_sequence_output_queue: self
-                .packet_sequence_output_queue
-                .take()
-                .ok_or(Error::ConfigIncomplete("no packet_sequence_block".into()))?,
+            packet_sequence_output_queue: self.packet_sequence_output_queue.take(),
             #[cfg(target_os = "linux")]
             netns,
             npb_dedup_enabled: Arc::new(AtomicBool::new(false)),
             pause: Arc::new(AtomicBool::new(self.pause.unwrap())),
+            capture_paused: self.capture_paused.take().unwrap_or_default(),
             queue_debugger: queue_debugger.clone(),
             tunnel_type_trim_bitmap: self
                 .tunnel_type_trim_bitmap
                 .take()
                 .ok_or(Error::ConfigIncomplete("no trim tunnel type".into()))?,
             bond_group_map,
+            thread_stack_size: self.thread_stack_size,
         };
         collector.register_countable(
             &stats::SingleTagModule("dispatcher", "id", base.id),
@@ -1100,6 +1334,7 @@ impl DispatcherBuilder {
                         raw_packet_block_size: self.analyzer_raw_packet_block_size.take().ok_or(
                             Error::ConfigIncomplete("no analyzer-raw-packet-block-size".into()),
                         )?,
+                        raw_packet_pool_size: self.batched_buffer_pool_size,
                     })
                 } else {
                     DispatcherFlavor::Local(LocalModeDispatcher {
@@ -1172,6 +1407,7 @@ impl DispatcherBuilder {
                     raw_packet_block_size: self.analyzer_raw_packet_block_size.take().ok_or(
                         Error::ConfigIncomplete("no analyzer-raw-packet-block-size".into()),
                     )?,
+                    raw_packet_pool_size: self.batched_buffer_pool_size,
                 })
             }
             _ => {
@@ -1185,17 +1421,20 @@ impl DispatcherBuilder {
         #[cfg(target_os = "linux")]
         let _ = public::netns::reset_netns()?;
         Ok(Dispatcher {
+            id,
+            timestamp_source,
             flavor: Mutex::new(Some(dispatcher)),
             terminated,
             running: AtomicBool::new(false),
             handle: Mutex::new(None),
+            thread_stack_size: self.thread_stack_size,
         })
     }
 
     #[allow(unused_variables)]
     fn get_engine(
         pcap_interfaces: &Option<Vec<Link>>,
-        src_interface: &mut Option<String>,
+        src_interfaces: &[String],
         tap_mode: TapMode,
         options: &Arc<Mutex<Options>>,
         queue_debugger: &Arc<QueueDebugger>,
@@ -1224,12 +1463,14 @@ impl DispatcherBuilder {
                     .collect();
                 info!(
                     "Libpcap init with: {:?} {} {}",
-                    &src_ifaces, options.packet_blocks, options.snap_len
+                    &src_ifaces,
+                    options.packet_blocks,
+                    options.capture_snap_len()
                 );
                 let libpcap = Libpcap::new(
                     src_ifaces,
                     options.packet_blocks,
-                    options.snap_len,
+                    options.capture_snap_len(),
                     queue_debugger,
                 )
                 .map_err(|e| error::Error::Libpcap(e.to_string()))?;
@@ -1243,11 +1484,33 @@ impl DispatcherBuilder {
                 ));
                 #[cfg(not(target_arch = "s390x"))]
                 {
-                    Ok(RecvEngine::Dpdk(Dpdk::new(None, None, options.snap_len)))
+                    Ok(RecvEngine::Dpdk(Dpdk::new(
+                        None,
+                        None,
+                        options.capture_snap_len(),
+                    )))
                 }
             }
             #[cfg(any(target_os = "linux", target_os = "android"))]
             TapMode::Local | TapMode::Mirror | TapMode::Analyzer => {
+                #[cfg(target_os = "linux")]
+                if options.af_xdp_enabled
+                    && options.tap_mode != TapMode::Local
+                    && src_interfaces.len() <= 1
+                {
+                    let iface = src_interfaces.get(0).map(|s| s.as_str()).unwrap_or("");
+                    match XdpSocket::new(
+                        iface,
+                        options.packet_blocks * DEFAULT_BLOCK_SIZE,
+                        POLL_TIMEOUT,
+                    ) {
+                        Ok(s) => return Ok(RecvEngine::AfXdp(s)),
+                        Err(e) => warn!(
+                            "AF_XDP init on {} failed: {}, fallback to AF_PACKET",
+                            iface, e
+                        ),
+                    }
+                }
                 let afp = af_packet::Options {
                     frame_size: if options.tap_mode == TapMode::Analyzer {
                         FRAME_SIZE_MIN as u32
@@ -1258,9 +1521,21 @@ impl DispatcherBuilder {
                     num_blocks: options.packet_blocks as u32,
                     poll_timeout: POLL_TIMEOUT.as_nanos() as isize,
                     version: options.af_packet_version,
-                    iface: src_interface.as_ref().unwrap_or(&"".to_string()).clone(),
+                    iface: src_interfaces.get(0).cloned().unwrap_or_default(),
+                    timestamp_source: options.capture_timestamp_source,
+                    fanout: options.af_packet_fanout,
                     ..Default::default()
                 };
+                if src_interfaces.len() > 1 && options.tap_mode != TapMode::Local {
+                    info!(
+                        "Afpacket init on interfaces {:?} with {:?}",
+                        src_interfaces, afp
+                    );
+                    return Ok(RecvEngine::AfPacketGroup(TpacketGroup::new(
+                        afp,
+                        src_interfaces,
+                    )?));
+                }
                 info!("Afpacket init with {:?}", afp);
                 Ok(RecvEngine::AfPacket(Tpacket::new(afp)?))
             }
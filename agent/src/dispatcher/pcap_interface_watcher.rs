@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use arc_swap::access::Access;
+use log::{debug, info, warn};
+
+use super::DispatcherListener;
+use crate::config::handler::DispatcherAccess;
+use public::{
+    proto::trident::TapMode,
+    utils::net::{links_by_name_regex, Link},
+};
+
+// Windows has no netlink to notify adapter changes, adapters are re-enumerated periodically
+// and libpcap of each dispatcher is reopened when they change
+pub struct PcapInterfaceWatcher {
+    config: DispatcherAccess,
+    interval: Duration,
+    listeners: Vec<DispatcherListener>,
+
+    running: Arc<(Mutex<bool>, Condvar)>,
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl PcapInterfaceWatcher {
+    pub fn new(
+        config: DispatcherAccess,
+        interval: Duration,
+        listeners: Vec<DispatcherListener>,
+    ) -> Self {
+        Self {
+            config,
+            interval,
+            listeners,
+            running: Default::default(),
+            thread_handle: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        {
+            let mut running = self.running.0.lock().unwrap();
+            if *running {
+                return;
+            }
+            *running = true;
+        }
+
+        let config = self.config.clone();
+        let interval = self.interval;
+        let listeners = self.listeners.clone();
+        let running = self.running.clone();
+        self.thread_handle = Some(
+            thread::Builder::new()
+                .name("pcap-interface-watcher".to_owned())
+                .spawn(move || Self::run(config, interval, listeners, running))
+                .unwrap(),
+        );
+        info!(
+            "pcap interface watcher started with interval {:?}",
+            interval
+        );
+    }
+
+    pub fn notify_stop(&mut self) -> Option<JoinHandle<()>> {
+        {
+            let mut running = self.running.0.lock().unwrap();
+            if !*running {
+                return None;
+            }
+            *running = false;
+        }
+        self.running.1.notify_one();
+        info!("notified stopping pcap interface watcher");
+        self.thread_handle.take()
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.notify_stop() {
+            let _ = handle.join();
+            info!("stopped pcap interface watcher");
+        }
+    }
+
+    fn get_links(tap_interface_regex: &str) -> Option<Vec<Link>> {
+        match links_by_name_regex(tap_interface_regex) {
+            Ok(mut links) => {
+                links.sort();
+                Some(links)
+            }
+            Err(e) => {
+                warn!("get interfaces by name regex failed: {}", e);
+                None
+            }
+        }
+    }
+
+    fn run(
+        config: DispatcherAccess,
+        interval: Duration,
+        listeners: Vec<DispatcherListener>,
+        running: Arc<(Mutex<bool>, Condvar)>,
+    ) {
+        let mut last_links =
+            Self::get_links(&config.load().tap_interface_regex).unwrap_or_default();
+        let (running, timer) = &*running;
+        loop {
+            let guard = timer
+                .wait_timeout_while(running.lock().unwrap(), interval, |r| *r)
+                .unwrap()
+                .0;
+            if !*guard {
+                break;
+            }
+            drop(guard);
+
+            let conf = config.load();
+            if conf.tap_mode != TapMode::Local {
+                continue;
+            }
+            let Some(links) = Self::get_links(&conf.tap_interface_regex) else {
+                continue;
+            };
+            if links == last_links {
+                continue;
+            }
+            let added = links
+                .iter()
+                .filter(|l| !last_links.contains(l))
+                .map(|l| &l.name)
+                .collect::<Vec<_>>();
+            let removed = last_links
+                .iter()
+                .filter(|l| !links.contains(l))
+                .map(|l| &l.name)
+                .collect::<Vec<_>>();
+            info!(
+                "pcap interfaces changed, added: {:?}, removed: {:?}",
+                added, removed
+            );
+            if links.is_empty() {
+                // libpcap needs at least one interface, keep capturing on the previous ones
+                warn!(
+                    "tap-interface-regex({}) do not match any interface, keep previous pcap interfaces",
+                    conf.tap_interface_regex
+                );
+            } else {
+                debug!("pcap interfaces: {:?}", links);
+                for listener in listeners.iter() {
+                    listener.on_pcap_interfaces_change(
+                        &links,
+                        conf.if_mac_source,
+                        conf.trident_type,
+                    );
+                }
+            }
+            last_links = links;
+        }
+    }
+}
@@ -22,9 +22,9 @@ pub mod tpacket;
 
 pub use bpf::*;
 #[cfg(any(target_os = "linux", target_os = "android"))]
-pub use options::{OptSocketType, OptTpacketVersion, Options};
+pub use options::{Fanout, OptSocketType, OptTpacketVersion, Options};
 #[cfg(any(target_os = "linux", target_os = "android"))]
-pub use tpacket::Tpacket;
+pub use tpacket::{Tpacket, TpacketGroup};
 
 /* example
 
@@ -20,6 +20,8 @@ pub use public::error::af_packet::{Error, Result};
 
 use public::proto::trident::CaptureSocketType;
 
+use crate::config::{AfPacketFanoutMode, CaptureTimestampSource};
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd)]
 pub enum OptTpacketVersion {
     TpacketVersionHighestavailablet = -1,
@@ -69,6 +71,13 @@ impl OptSocketType {
     }
 }
 
+// Sockets in the same fanout group share packets of the interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fanout {
+    pub group_id: u16,
+    pub mode: AfPacketFanoutMode,
+}
+
 #[derive(Clone, Debug)]
 pub struct Options {
     pub frame_size: u32,
@@ -80,6 +89,9 @@ pub struct Options {
     pub version: OptTpacketVersion,
     pub socket_type: OptSocketType,
     pub iface: String,
+    // requested source, Tpacket falls back to kernel and then software if not supported
+    pub timestamp_source: CaptureTimestampSource,
+    pub fanout: Option<Fanout>,
 }
 
 impl Default for Options {
@@ -94,6 +106,8 @@ impl Default for Options {
             version: OptTpacketVersion::TpacketVersionHighestavailablet,
             socket_type: OptSocketType::SocketTypeRaw,
             iface: "".to_string(),
+            timestamp_source: CaptureTimestampSource::Software,
+            fanout: None,
         }
     }
 }
@@ -15,30 +15,54 @@
  */
 
 use std::fmt::{Debug, Formatter, Result as DebugResult};
+use std::fs::File;
 use std::io;
 use std::mem;
 use std::net::Shutdown;
 use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+use std::time::Duration;
 
 use libc::{
-    c_int, c_uint, c_void, getsockopt, mmap, munmap, off_t, poll, pollfd, setsockopt, size_t,
-    sockaddr, sockaddr_ll, socket, socklen_t, write, AF_PACKET, ETH_P_ALL, MAP_LOCKED,
-    MAP_NORESERVE, MAP_SHARED, POLLERR, POLLIN, PROT_READ, PROT_WRITE, SOL_PACKET, SOL_SOCKET,
-    SO_ATTACH_FILTER,
+    c_char, c_int, c_uint, c_void, clock_gettime, clockid_t, getsockopt, ioctl, mmap, munmap,
+    off_t, poll, pollfd, setsockopt, size_t, sockaddr, sockaddr_ll, socket, socklen_t, timespec,
+    write, AF_PACKET, CLOCK_REALTIME, ETH_P_ALL, IFNAMSIZ, MAP_LOCKED, MAP_NORESERVE, MAP_SHARED,
+    POLLERR, POLLIN, PROT_READ, PROT_WRITE, SOF_TIMESTAMPING_RAW_HARDWARE,
+    SOF_TIMESTAMPING_RX_HARDWARE, SOF_TIMESTAMPING_RX_SOFTWARE, SOF_TIMESTAMPING_SOFTWARE,
+    SOL_PACKET, SOL_SOCKET, SO_ATTACH_FILTER, SO_TIMESTAMPING,
 };
-use log::warn;
+use log::{info, warn};
 use public::error::*;
 use public::packet::Packet;
 use socket2::Socket;
 
 use super::{bpf, header, options};
 
-use crate::utils::stats;
+use crate::{
+    config::{AfPacketFanoutMode, CaptureTimestampSource},
+    utils::stats,
+};
 use public::utils::net::{self, link_by_name};
 
 const PACKET_VERSION: c_int = 10;
 const PACKET_RX_RING: c_int = 5;
 const PACKET_STATISTICS: c_int = 6;
+const PACKET_TIMESTAMP: c_int = 17;
+const PACKET_FANOUT: c_int = 18;
+const PACKET_FANOUT_HASH: c_int = 0;
+const PACKET_FANOUT_CPU: c_int = 2;
+const PACKET_FANOUT_FLAG_DEFRAG: c_int = 0x8000;
+const SIOCETHTOOL: c_uint = 0x8946;
+const SIOCSHWTSTAMP: c_uint = 0x89b0;
+const SIOCGHWTSTAMP: c_uint = 0x89b1;
+const ETHTOOL_GET_TS_INFO: u32 = 0x41;
+const HWTSTAMP_TX_OFF: c_int = 0;
+const HWTSTAMP_FILTER_ALL: c_int = 1;
+// hardware timestamps are not used if the PTP hardware clock is further away from the system clock
+const MAX_PHC_OFFSET: Duration = Duration::from_millis(10);
 const MILLI_SECONDS: u32 = 1000000;
 
 // https://www.ietf.org/archive/id/draft-gharris-opsawg-pcap-01.html
@@ -58,6 +82,43 @@ pub struct TpacketStats {
     pub tp_drops: c_uint,
 }
 
+#[derive(Default)]
+#[repr(C)]
+struct HwtstampConfig {
+    flags: c_int,
+    tx_type: c_int,
+    rx_filter: c_int,
+}
+
+#[derive(Default)]
+#[repr(C)]
+struct EthtoolTsInfo {
+    cmd: u32,
+    so_timestamping: u32,
+    phc_index: i32,
+    tx_types: u32,
+    tx_reserved: [u32; 3],
+    rx_filters: u32,
+    rx_reserved: [u32; 3],
+}
+
+#[repr(C)]
+struct HwtstampIfreq {
+    ifr_name: [c_char; IFNAMSIZ],
+    ifr_data: *mut c_void,
+    _pad: [u8; 16],
+}
+
+// Hardware timestamp config of an interface before it is changed, restored when the last
+// Tpacket using hardware timestamps on the interface is dropped
+struct SavedHwtstamp {
+    iface: String,
+    config: HwtstampConfig,
+    users: usize,
+}
+
+static SAVED_HWTSTAMPS: Mutex<Vec<SavedHwtstamp>> = Mutex::new(Vec::new());
+
 #[derive(Clone, Debug)]
 #[repr(C)]
 pub struct TpacketStatsV3 {
@@ -80,6 +141,8 @@ pub struct Tpacket {
     header_next_needed: bool,
 
     tp_version: options::OptTpacketVersion,
+    timestamp_source: CaptureTimestampSource,
+    hardware_timestamp_enabled: bool,
 
     v3: Option<*mut header::V3Wrapper>,
 }
@@ -87,8 +150,8 @@ pub struct Tpacket {
 impl Debug for Tpacket {
     fn fmt(&self, f: &mut Formatter<'_>) -> DebugResult {
         f.write_fmt(format_args!(
-            "Tpacket {{ socket: {:?}, ring: {:?}, opts: {:?}, offset: {:?}, tp_version: {:?} }}",
-            self.raw_socket, self.ring, self.opts, self.offset, self.tp_version
+            "Tpacket {{ socket: {:?}, ring: {:?}, opts: {:?}, offset: {:?}, tp_version: {:?}, timestamp_source: {} }}",
+            self.raw_socket, self.ring, self.opts, self.offset, self.tp_version, self.timestamp_source
         ))
     }
 }
@@ -187,6 +250,184 @@ impl Tpacket {
         }
     }
 
+    fn iface_ioctl(&self, request: c_uint, data: *mut c_void) -> af_packet::Result<()> {
+        if self.opts.iface.is_empty() || self.opts.iface.len() >= IFNAMSIZ {
+            return Err(af_packet::Error::InvalidOption(
+                "hardware timestamp requires a valid interface name.",
+            ));
+        }
+        let mut req = HwtstampIfreq {
+            ifr_name: [0; IFNAMSIZ],
+            ifr_data: data,
+            _pad: [0; 16],
+        };
+        for (d, s) in req.ifr_name.iter_mut().zip(self.opts.iface.as_bytes()) {
+            *d = *s as c_char;
+        }
+        unsafe {
+            if ioctl(
+                self.raw_socket.as_raw_fd(),
+                request as _,
+                &mut req as *mut HwtstampIfreq,
+            ) == -1
+            {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        Ok(())
+    }
+
+    // Raw hardware timestamps are time of the PTP hardware clock (PHC) of the NIC instead of
+    // the system clock, they can only be used as packet time if the PHC is synchronized with
+    // the system clock, e.g. by phc2sys
+    fn check_phc_synchronized(&self) -> af_packet::Result<()> {
+        let mut info = EthtoolTsInfo {
+            cmd: ETHTOOL_GET_TS_INFO,
+            ..Default::default()
+        };
+        self.iface_ioctl(SIOCETHTOOL, &mut info as *mut EthtoolTsInfo as *mut c_void)?;
+        if info.phc_index < 0 {
+            return Err(af_packet::Error::InvalidOption(
+                "interface has no PTP hardware clock.",
+            ));
+        }
+        let phc = File::open(format!("/dev/ptp{}", info.phc_index))?;
+        // FD_TO_CLOCKID in linux/posix-timers.h
+        let phc_clock = (((!phc.as_raw_fd()) << 3) | 3) as clockid_t;
+        let (mut phc_time, mut system_time): (timespec, timespec) =
+            unsafe { (mem::zeroed(), mem::zeroed()) };
+        unsafe {
+            if clock_gettime(phc_clock, &mut phc_time) == -1
+                || clock_gettime(CLOCK_REALTIME, &mut system_time) == -1
+            {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        let nanos = |t: &timespec| t.tv_sec as i128 * 1_000_000_000 + t.tv_nsec as i128;
+        let offset = (nanos(&phc_time) - nanos(&system_time)).unsigned_abs();
+        if offset > MAX_PHC_OFFSET.as_nanos() {
+            warn!(
+                "Afpacket {} PTP hardware clock /dev/ptp{} is {:?} away from system clock",
+                self.opts.iface,
+                info.phc_index,
+                Duration::from_nanos(offset as u64)
+            );
+            return Err(af_packet::Error::InvalidOption(
+                "PTP hardware clock is not synchronized with system clock.",
+            ));
+        }
+        Ok(())
+    }
+
+    // enable hardware timestamping of received packets on the NIC, requires CAP_NET_ADMIN
+    fn enable_hardware_timestamp(&mut self) -> af_packet::Result<()> {
+        self.check_phc_synchronized()?;
+        {
+            let mut saved = SAVED_HWTSTAMPS.lock().unwrap();
+            match saved.iter_mut().find(|s| s.iface == self.opts.iface) {
+                Some(s) => s.users += 1,
+                None => {
+                    // the config can not be restored if it can not be read
+                    let mut old_config = HwtstampConfig::default();
+                    self.iface_ioctl(
+                        SIOCGHWTSTAMP,
+                        &mut old_config as *mut HwtstampConfig as *mut c_void,
+                    )?;
+                    let mut config = HwtstampConfig {
+                        tx_type: HWTSTAMP_TX_OFF,
+                        rx_filter: HWTSTAMP_FILTER_ALL,
+                        ..Default::default()
+                    };
+                    self.iface_ioctl(
+                        SIOCSHWTSTAMP,
+                        &mut config as *mut HwtstampConfig as *mut c_void,
+                    )?;
+                    saved.push(SavedHwtstamp {
+                        iface: self.opts.iface.clone(),
+                        config: old_config,
+                        users: 1,
+                    });
+                }
+            }
+        }
+        self.hardware_timestamp_enabled = true;
+        if let Err(e) = self.setsockopt(
+            SOL_PACKET,
+            PACKET_TIMESTAMP,
+            (SOF_TIMESTAMPING_RX_HARDWARE | SOF_TIMESTAMPING_RAW_HARDWARE) as c_int,
+        ) {
+            self.restore_hardware_timestamp();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn restore_hardware_timestamp(&mut self) {
+        if !self.hardware_timestamp_enabled {
+            return;
+        }
+        self.hardware_timestamp_enabled = false;
+        let mut saved = SAVED_HWTSTAMPS.lock().unwrap();
+        let Some(index) = saved.iter().position(|s| s.iface == self.opts.iface) else {
+            return;
+        };
+        saved[index].users -= 1;
+        if saved[index].users > 0 {
+            return;
+        }
+        let mut s = saved.remove(index);
+        match self.iface_ioctl(
+            SIOCSHWTSTAMP,
+            &mut s.config as *mut HwtstampConfig as *mut c_void,
+        ) {
+            Ok(_) => info!(
+                "Afpacket {} restored hardware timestamp config tx_type {} rx_filter {}",
+                s.iface, s.config.tx_type, s.config.rx_filter
+            ),
+            Err(e) => warn!(
+                "Afpacket {} restore hardware timestamp config failed: {}",
+                s.iface, e
+            ),
+        }
+    }
+
+    fn set_timestamp_source(&mut self) {
+        if self.opts.timestamp_source == CaptureTimestampSource::Hardware {
+            match self.enable_hardware_timestamp() {
+                Ok(_) => {
+                    self.timestamp_source = CaptureTimestampSource::Hardware;
+                    return;
+                }
+                Err(e) => warn!(
+                    "Afpacket {} hardware timestamp not supported: {}, fallback to kernel",
+                    self.opts.iface, e
+                ),
+            }
+        }
+        if self.opts.timestamp_source != CaptureTimestampSource::Software {
+            // timestamps skbs when received by the kernel instead of when copied to the ring
+            match self.setsockopt(
+                SOL_SOCKET,
+                SO_TIMESTAMPING,
+                (SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE) as c_int,
+            ) {
+                Ok(_) => {
+                    self.timestamp_source = CaptureTimestampSource::Kernel;
+                    return;
+                }
+                Err(e) => warn!(
+                    "Afpacket {} kernel timestamp not supported: {}, fallback to software",
+                    self.opts.iface, e
+                ),
+            }
+        }
+        self.timestamp_source = CaptureTimestampSource::Software;
+    }
+
+    pub fn timestamp_source(&self) -> CaptureTimestampSource {
+        self.timestamp_source
+    }
+
     fn set_ring(&self) -> af_packet::Result<()> {
         if self.tp_version == options::OptTpacketVersion::TpacketVersion2 {
             let mut req: header::TpacketReq = Default::default();
@@ -354,6 +595,9 @@ impl Tpacket {
         TpacketCounter {
             tp_version: self.tp_version,
             fd: self.raw_socket.as_raw_fd(),
+            iface: self.opts.iface.clone(),
+            num_blocks: self.opts.num_blocks,
+            dropping: AtomicBool::new(false),
         }
     }
 
@@ -384,22 +628,53 @@ impl Tpacket {
             should_release_packet: false,
             header_next_needed: false,
             tp_version: opts.version,
+            timestamp_source: CaptureTimestampSource::Software,
+            hardware_timestamp_enabled: false,
             v3: Option::None,
         };
         tpacket.bind()?;
         tpacket.set_version()?;
+        tpacket.set_timestamp_source();
+        if tpacket.timestamp_source != opts.timestamp_source {
+            info!(
+                "Afpacket {} uses {} timestamp instead of {}",
+                opts.iface, tpacket.timestamp_source, opts.timestamp_source
+            );
+        }
         tpacket.set_ring()?;
         tpacket.mmap_ring()?;
         tpacket.set_bpf(vec![bpf::BpfSyntax::RetConstant(bpf::RetConstant {
             val: 0,
         })
         .to_instruction()])?;
+        tpacket.set_fanout()?;
         Ok(tpacket)
     }
+
+    fn set_fanout(&self) -> af_packet::Result<()> {
+        let Some(fanout) = self.opts.fanout else {
+            return Ok(());
+        };
+        let mode = match fanout.mode {
+            AfPacketFanoutMode::Hash => PACKET_FANOUT_HASH | PACKET_FANOUT_FLAG_DEFRAG,
+            AfPacketFanoutMode::Cpu => PACKET_FANOUT_CPU,
+        };
+        self.setsockopt(
+            SOL_PACKET,
+            PACKET_FANOUT,
+            fanout.group_id as c_int | mode << 16,
+        )?;
+        info!(
+            "Afpacket {} joined fanout group {} with mode {:?}",
+            self.opts.iface, fanout.group_id, fanout.mode
+        );
+        Ok(())
+    }
 }
 
 impl Drop for Tpacket {
     fn drop(&mut self) {
+        self.restore_hardware_timestamp();
         if !self.ring.is_null() {
             unsafe {
                 munmap(
@@ -416,10 +691,48 @@ impl Drop for Tpacket {
 pub struct TpacketCounter {
     tp_version: options::OptTpacketVersion,
     fd: i32,
+    iface: String,
+    num_blocks: u32,
+    // logged only when kernel drops start or stop
+    dropping: AtomicBool,
 }
 
 impl stats::RefCountable for TpacketCounter {
     fn get_counters(&self) -> Vec<stats::Counter> {
+        let mut counters = self.kernel_counters();
+        let mut packets = 0;
+        let mut drops = 0;
+        for (name, _, value) in counters.iter() {
+            match (*name, value) {
+                ("kernel_packets", stats::CounterValue::Unsigned(v)) => packets = *v,
+                ("kernel_drops", stats::CounterValue::Unsigned(v)) => drops = *v,
+                _ => (),
+            }
+        }
+        let dropping = drops > 0;
+        if self.dropping.swap(dropping, Ordering::Relaxed) != dropping {
+            if dropping {
+                // tp_packets includes dropped packets
+                warn!(
+                    "Afpacket {} kernel dropped {} of {} packets with {} blocks, consider increasing afpacket-blocks",
+                    self.iface, drops, packets, self.num_blocks
+                );
+            } else {
+                info!("Afpacket {} kernel drops stopped", self.iface);
+            }
+        }
+        counters.push((
+            "af_packet_blocks",
+            stats::CounterType::Gauged,
+            stats::CounterValue::Unsigned(self.num_blocks as u64),
+        ));
+        counters
+    }
+}
+
+impl TpacketCounter {
+    // PACKET_STATISTICS are reset by the kernel on each read
+    fn kernel_counters(&self) -> Vec<stats::Counter> {
         if self.tp_version == options::OptTpacketVersion::TpacketVersion3 {
             let mut stats_v3 = TpacketStatsV3 {
                 tp_packets: 0,
@@ -494,3 +807,127 @@ impl stats::RefCountable for TpacketCounter {
         }
     }
 }
+
+// Fans in several AF_PACKET sockets so that a single dispatcher can capture from a set
+// of interfaces. Members are read without blocking in turn, the group blocks on all of
+// their fds with the poll timeout when none of them has packets.
+pub struct TpacketGroup {
+    members: Vec<Tpacket>,
+    poll_fds: Vec<pollfd>,
+    poll_timeout: isize,
+    next: usize,
+}
+
+// it's safe because every member is Send
+unsafe impl Send for TpacketGroup {}
+
+impl TpacketGroup {
+    pub fn new(opts: options::Options, ifaces: &[String]) -> Result<Self> {
+        let mut members = Vec::with_capacity(ifaces.len());
+        for iface in ifaces {
+            members.push(Tpacket::new(options::Options {
+                iface: iface.clone(),
+                poll_timeout: 0,
+                // fanout groups are bound to a single interface
+                fanout: None,
+                ..opts.clone()
+            })?);
+        }
+        let poll_fds = members
+            .iter()
+            .map(|m| pollfd {
+                fd: m.raw_socket.as_raw_fd(),
+                events: POLLIN | POLLERR,
+                revents: 0,
+            })
+            .collect();
+        Ok(Self {
+            members,
+            poll_fds,
+            poll_timeout: opts.poll_timeout,
+            next: 0,
+        })
+    }
+
+    // The returned packet is valid until the next read of the same member, which is no
+    // sooner than the next call to this function.
+    pub unsafe fn read(&mut self) -> Option<Packet> {
+        if self.members.is_empty() {
+            return None;
+        }
+        if let Some(p) = self.read_members() {
+            return Some(p);
+        }
+        let timeout = self.poll_timeout / MILLI_SECONDS as isize;
+        self.poll_fds.iter_mut().for_each(|p| p.revents = 0);
+        let n = poll(
+            self.poll_fds.as_mut_ptr(),
+            self.poll_fds.len() as _,
+            timeout as i32,
+        );
+        if n <= 0 {
+            return None;
+        }
+        self.read_members()
+    }
+
+    unsafe fn read_members(&mut self) -> Option<Packet> {
+        let count = self.members.len();
+        for i in 0..count {
+            let index = (self.next + i) % count;
+            // returning a borrow conditionally from the loop is rejected by the borrow
+            // checker, the packet only borrows the member it was read from
+            let member: *mut Tpacket = &mut self.members[index];
+            if let Some(p) = (*member).read() {
+                self.next = (index + 1) % count;
+                return Some(p);
+            }
+        }
+        None
+    }
+
+    pub fn set_bpf(&self, ins: Vec<bpf::RawInstruction>) -> af_packet::Result<()> {
+        for m in self.members.iter() {
+            m.set_bpf(ins.clone())?;
+        }
+        Ok(())
+    }
+
+    // members share the same options, so they end up with the same source
+    pub fn timestamp_source(&self) -> CaptureTimestampSource {
+        self.members
+            .get(0)
+            .map(|m| m.timestamp_source())
+            .unwrap_or(CaptureTimestampSource::Software)
+    }
+
+    pub fn get_counter_handle(&self) -> TpacketGroupCounter {
+        TpacketGroupCounter(
+            self.members
+                .iter()
+                .map(|m| m.get_counter_handle())
+                .collect(),
+        )
+    }
+}
+
+pub struct TpacketGroupCounter(Vec<TpacketCounter>);
+
+impl stats::RefCountable for TpacketGroupCounter {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        let mut counters: Vec<stats::Counter> = vec![];
+        for c in self.0.iter() {
+            for (name, counter_type, value) in c.get_counters() {
+                match (counters.iter_mut().find(|c| c.0 == name), value) {
+                    (
+                        Some((_, _, stats::CounterValue::Unsigned(sum))),
+                        stats::CounterValue::Unsigned(v),
+                    ) => *sum += v,
+                    (Some(_), _) => (),
+                    (None, _) => counters.push((name, counter_type, value)),
+                }
+            }
+        }
+        counters
+    }
+}
@@ -0,0 +1,696 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// AF_XDP recv engine
+//
+// One XDP socket is bound to each RX queue of the interface, and a minimal XDP program
+// redirects every packet of the queue to its socket with XDP_PASS as fallback. Packets
+// redirected to the sockets do not reach the kernel network stack, so this engine is
+// only used for mirror and analyzer interfaces.
+
+use std::fs;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libc::{
+    c_int, c_void, close, getsockopt, mmap, munmap, poll, pollfd, setsockopt, socket, socklen_t,
+    syscall, SYS_bpf, MAP_ANONYMOUS, MAP_FAILED, MAP_POPULATE, MAP_PRIVATE, MAP_SHARED, POLLIN,
+    PROT_READ, PROT_WRITE, SOCK_RAW,
+};
+use log::{info, warn};
+use public::packet::Packet;
+use public::utils::net::link_by_name;
+
+use crate::utils::stats;
+
+const AF_XDP: c_int = 44;
+const SOL_XDP: c_int = 283;
+
+const XDP_MMAP_OFFSETS: c_int = 1;
+const XDP_RX_RING: c_int = 2;
+const XDP_UMEM_REG: c_int = 4;
+const XDP_UMEM_FILL_RING: c_int = 5;
+const XDP_UMEM_COMPLETION_RING: c_int = 6;
+const XDP_STATISTICS: c_int = 7;
+
+const XDP_PGOFF_RX_RING: i64 = 0;
+const XDP_UMEM_PGOFF_FILL_RING: i64 = 0x100000000;
+
+const BPF_MAP_CREATE: c_int = 0;
+const BPF_MAP_UPDATE_ELEM: c_int = 2;
+const BPF_PROG_LOAD: c_int = 5;
+const BPF_LINK_CREATE: c_int = 28;
+
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+const BPF_PROG_TYPE_XDP: u32 = 6;
+const BPF_XDP: u32 = 37;
+const BPF_FUNC_REDIRECT_MAP: i32 = 51;
+const XDP_PASS: i32 = 2;
+
+pub const FRAME_SIZE: usize = 4096;
+const MIN_FRAMES: usize = 1024;
+const MAX_FRAMES: usize = 32768;
+
+#[repr(C)]
+struct XdpUmemReg {
+    addr: u64,
+    len: u64,
+    chunk_size: u32,
+    headroom: u32,
+    flags: u32,
+}
+
+#[derive(Default)]
+#[repr(C)]
+struct XdpRingOffset {
+    producer: u64,
+    consumer: u64,
+    desc: u64,
+    flags: u64,
+}
+
+#[derive(Default)]
+#[repr(C)]
+struct XdpMmapOffsets {
+    rx: XdpRingOffset,
+    tx: XdpRingOffset,
+    fr: XdpRingOffset,
+    cr: XdpRingOffset,
+}
+
+#[repr(C)]
+struct SockaddrXdp {
+    sxdp_family: u16,
+    sxdp_flags: u16,
+    sxdp_ifindex: u32,
+    sxdp_queue_id: u32,
+    sxdp_shared_umem_fd: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct XdpDesc {
+    addr: u64,
+    len: u32,
+    options: u32,
+}
+
+#[derive(Default)]
+#[repr(C)]
+struct XdpStatistics {
+    rx_dropped: u64,
+    rx_invalid_descs: u64,
+    tx_invalid_descs: u64,
+}
+
+#[repr(C)]
+struct BpfInsn {
+    code: u8,
+    regs: u8, // dst_reg: 4 bits, src_reg: 4 bits
+    off: i16,
+    imm: i32,
+}
+
+impl BpfInsn {
+    const fn new(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Self {
+        Self {
+            code,
+            regs: (src << 4) | (dst & 0xf),
+            off,
+            imm,
+        }
+    }
+}
+
+#[repr(C)]
+struct BpfMapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+}
+
+#[repr(C)]
+struct BpfMapUpdateAttr {
+    map_fd: u32,
+    _pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+struct BpfProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+}
+
+#[repr(C)]
+struct BpfLinkCreateAttr {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+fn bpf<T>(cmd: c_int, attr: &mut T) -> io::Result<c_int> {
+    let ret = unsafe {
+        syscall(
+            SYS_bpf,
+            cmd,
+            attr as *mut T as *mut c_void,
+            mem::size_of::<T>() as u32,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret as c_int)
+}
+
+// XSKMAP and the XDP program attached to the interface, detached when dropped
+struct XdpProgram {
+    map_fd: c_int,
+    prog_fd: c_int,
+    link_fd: c_int,
+}
+
+impl XdpProgram {
+    fn attach(if_index: u32, queue_count: usize) -> io::Result<Self> {
+        let mut map_attr = BpfMapCreateAttr {
+            map_type: BPF_MAP_TYPE_XSKMAP,
+            key_size: 4,
+            value_size: 4,
+            max_entries: queue_count as u32,
+            map_flags: 0,
+        };
+        let map_fd = bpf(BPF_MAP_CREATE, &mut map_attr)?;
+        let mut program = XdpProgram {
+            map_fd,
+            prog_fd: -1,
+            link_fd: -1,
+        };
+
+        // return bpf_redirect_map(&xsks_map, ctx->rx_queue_index, XDP_PASS);
+        let insns = [
+            // r2 = *(u32 *)(r1 + offsetof(struct xdp_md, rx_queue_index))
+            BpfInsn::new(0x61, 2, 1, 16, 0),
+            // r1 = xsks_map
+            BpfInsn::new(0x18, 1, 1, 0, map_fd),
+            BpfInsn::new(0, 0, 0, 0, 0),
+            // r3 = XDP_PASS
+            BpfInsn::new(0xb7, 3, 0, 0, XDP_PASS),
+            BpfInsn::new(0x85, 0, 0, 0, BPF_FUNC_REDIRECT_MAP),
+            BpfInsn::new(0x95, 0, 0, 0, 0),
+        ];
+        let license = b"GPL\0";
+        let mut prog_attr = BpfProgLoadAttr {
+            prog_type: BPF_PROG_TYPE_XDP,
+            insn_cnt: insns.len() as u32,
+            insns: insns.as_ptr() as u64,
+            license: license.as_ptr() as u64,
+            log_level: 0,
+            log_size: 0,
+            log_buf: 0,
+        };
+        program.prog_fd = bpf(BPF_PROG_LOAD, &mut prog_attr)?;
+
+        let mut link_attr = BpfLinkCreateAttr {
+            prog_fd: program.prog_fd as u32,
+            target_ifindex: if_index,
+            attach_type: BPF_XDP,
+            flags: 0,
+        };
+        program.link_fd = bpf(BPF_LINK_CREATE, &mut link_attr)?;
+        Ok(program)
+    }
+
+    fn register(&self, queue_id: u32, fd: c_int) -> io::Result<()> {
+        let mut attr = BpfMapUpdateAttr {
+            map_fd: self.map_fd as u32,
+            _pad: 0,
+            key: &queue_id as *const u32 as u64,
+            value: &fd as *const c_int as u64,
+            flags: 0,
+        };
+        bpf(BPF_MAP_UPDATE_ELEM, &mut attr).map(|_| ())
+    }
+}
+
+impl Drop for XdpProgram {
+    fn drop(&mut self) {
+        for fd in [self.link_fd, self.prog_fd, self.map_fd] {
+            if fd >= 0 {
+                unsafe {
+                    close(fd);
+                }
+            }
+        }
+    }
+}
+
+// single producer single consumer ring shared with the kernel
+struct Ring {
+    producer: *const AtomicU32,
+    consumer: *const AtomicU32,
+    descs: *mut u8,
+    mask: u32,
+    map: *mut c_void,
+    map_len: usize,
+}
+
+impl Ring {
+    fn map(
+        fd: c_int,
+        off: &XdpRingOffset,
+        size: usize,
+        desc_len: usize,
+        pgoff: i64,
+    ) -> io::Result<Self> {
+        let map_len = off.desc as usize + size * desc_len;
+        let map = unsafe {
+            mmap(
+                ptr::null_mut(),
+                map_len,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED | MAP_POPULATE,
+                fd,
+                pgoff,
+            )
+        };
+        if map == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let base = map as *mut u8;
+        unsafe {
+            Ok(Self {
+                producer: base.add(off.producer as usize) as *const AtomicU32,
+                consumer: base.add(off.consumer as usize) as *const AtomicU32,
+                descs: base.add(off.desc as usize),
+                mask: size as u32 - 1,
+                map,
+                map_len,
+            })
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.map, self.map_len);
+        }
+    }
+}
+
+// xdp socket and the umem registered to it
+struct Umem {
+    fd: c_int,
+    addr: *mut c_void,
+    len: usize,
+}
+
+impl Umem {
+    fn new(frames: usize) -> io::Result<Self> {
+        let fd = unsafe { socket(AF_XDP, SOCK_RAW, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = frames * FRAME_SIZE;
+        let addr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if addr == MAP_FAILED {
+            let e = io::Error::last_os_error();
+            unsafe {
+                close(fd);
+            }
+            return Err(e);
+        }
+        Ok(Self { fd, addr, len })
+    }
+
+    fn setsockopt<T>(&self, name: c_int, value: &T) -> io::Result<()> {
+        let ret = unsafe {
+            setsockopt(
+                self.fd,
+                SOL_XDP,
+                name,
+                value as *const T as *const c_void,
+                mem::size_of::<T>() as socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn new_rings(&self, frames: usize) -> io::Result<(Ring, Ring)> {
+        let reg = XdpUmemReg {
+            addr: self.addr as u64,
+            len: self.len as u64,
+            chunk_size: FRAME_SIZE as u32,
+            headroom: 0,
+            flags: 0,
+        };
+        self.setsockopt(XDP_UMEM_REG, &reg)?;
+        let size = frames as u32;
+        self.setsockopt(XDP_UMEM_FILL_RING, &size)?;
+        // completion ring is required by bind even though nothing is transmitted
+        self.setsockopt(XDP_UMEM_COMPLETION_RING, &size)?;
+        self.setsockopt(XDP_RX_RING, &size)?;
+
+        let mut off = XdpMmapOffsets::default();
+        let mut len = mem::size_of::<XdpMmapOffsets>() as socklen_t;
+        let ret = unsafe {
+            getsockopt(
+                self.fd,
+                SOL_XDP,
+                XDP_MMAP_OFFSETS,
+                &mut off as *mut XdpMmapOffsets as *mut c_void,
+                &mut len,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fill = Ring::map(
+            self.fd,
+            &off.fr,
+            frames,
+            mem::size_of::<u64>(),
+            XDP_UMEM_PGOFF_FILL_RING,
+        )?;
+        let rx = Ring::map(
+            self.fd,
+            &off.rx,
+            frames,
+            mem::size_of::<XdpDesc>(),
+            XDP_PGOFF_RX_RING,
+        )?;
+        Ok((fill, rx))
+    }
+}
+
+impl Drop for Umem {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+            munmap(self.addr, self.len);
+        }
+    }
+}
+
+// rings are declared before umem to be unmapped before the socket is closed
+struct XdpQueue {
+    fill: Ring,
+    rx: Ring,
+    umem: Umem,
+}
+
+impl XdpQueue {
+    fn new(if_index: u32, queue_id: u32, frames: usize) -> io::Result<Self> {
+        let umem = Umem::new(frames)?;
+        let (fill, rx) = umem.new_rings(frames)?;
+        let mut queue = XdpQueue { fill, rx, umem };
+
+        // hand all frames to the kernel
+        for i in 0..frames {
+            queue.refill((i * FRAME_SIZE) as u64);
+        }
+
+        let addr = SockaddrXdp {
+            sxdp_family: AF_XDP as u16,
+            sxdp_flags: 0,
+            sxdp_ifindex: if_index,
+            sxdp_queue_id: queue_id,
+            sxdp_shared_umem_fd: 0,
+        };
+        let ret = unsafe {
+            libc::bind(
+                queue.umem.fd,
+                &addr as *const SockaddrXdp as *const libc::sockaddr,
+                mem::size_of::<SockaddrXdp>() as socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(queue)
+    }
+
+    fn refill(&mut self, addr: u64) {
+        unsafe {
+            let producer = &*self.fill.producer;
+            let index = producer.load(Ordering::Relaxed);
+            let slot = self.fill.descs as *mut u64;
+            *slot.add((index & self.fill.mask) as usize) = addr & !(FRAME_SIZE as u64 - 1);
+            producer.store(index.wrapping_add(1), Ordering::Release);
+        }
+    }
+
+    fn next(&mut self) -> Option<XdpDesc> {
+        unsafe {
+            let consumer = &*self.rx.consumer;
+            let index = consumer.load(Ordering::Relaxed);
+            if (*self.rx.producer).load(Ordering::Acquire) == index {
+                return None;
+            }
+            let slot = self.rx.descs as *const XdpDesc;
+            let desc = *slot.add((index & self.rx.mask) as usize);
+            consumer.store(index.wrapping_add(1), Ordering::Release);
+            Some(desc)
+        }
+    }
+}
+
+pub struct XdpSocket {
+    iface: String,
+    if_index: u32,
+    queues: Vec<XdpQueue>,
+    next_queue: usize,
+    // frame of the last returned packet, given back to the kernel on next read
+    pending: Option<(usize, u64)>,
+    poll_timeout: Duration,
+    _program: XdpProgram,
+}
+
+// it's safe because rings and umem point to mmap'ed buffers owned by the socket
+unsafe impl Send for XdpSocket {}
+
+impl XdpSocket {
+    pub fn new(iface: &str, memory: usize, poll_timeout: Duration) -> io::Result<Self> {
+        let link = link_by_name(iface)
+            .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.to_string()))?;
+        let queue_count = Self::rx_queue_count(iface)?;
+        let frames = Self::frames_per_queue(memory, queue_count)?;
+
+        let program = XdpProgram::attach(link.if_index, queue_count)?;
+        let mut queues = Vec::with_capacity(queue_count);
+        for queue_id in 0..queue_count as u32 {
+            let queue = XdpQueue::new(link.if_index, queue_id, frames)?;
+            program.register(queue_id, queue.umem.fd)?;
+            queues.push(queue);
+        }
+        info!(
+            "AF_XDP {} init with {} queues {} frames per queue",
+            iface, queue_count, frames
+        );
+        Ok(Self {
+            iface: iface.to_owned(),
+            if_index: link.if_index,
+            queues,
+            next_queue: 0,
+            pending: None,
+            poll_timeout,
+            _program: program,
+        })
+    }
+
+    // Each queue takes the largest power of two frames within its share of memory, up to
+    // MAX_FRAMES, fails if the share can not fit MIN_FRAMES
+    fn frames_per_queue(memory: usize, queue_count: usize) -> io::Result<usize> {
+        let frames = memory / FRAME_SIZE / queue_count.max(1);
+        if frames < MIN_FRAMES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "memory {} can not fit {} frames of {} bytes for each of {} queues",
+                    memory, MIN_FRAMES, FRAME_SIZE, queue_count
+                ),
+            ));
+        }
+        Ok((1 << (usize::BITS - 1 - frames.leading_zeros())).min(MAX_FRAMES))
+    }
+
+    fn rx_queue_count(iface: &str) -> io::Result<usize> {
+        let count = fs::read_dir(format!("/sys/class/net/{}/queues", iface))?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with("rx-"))
+            .count();
+        Ok(count.max(1))
+    }
+
+    fn recv_any(&mut self) -> Option<(usize, XdpDesc)> {
+        let n = self.queues.len();
+        for i in 0..n {
+            let q = (self.next_queue + i) % n;
+            if let Some(desc) = self.queues[q].next() {
+                self.next_queue = (q + 1) % n;
+                return Some((q, desc));
+            }
+        }
+        None
+    }
+
+    // The data referenced in the packet points to umem. The life cycle
+    // of the packet cannot exceed the next call to the read function.
+    pub unsafe fn read(&mut self) -> Option<Packet> {
+        if let Some((q, addr)) = self.pending.take() {
+            self.queues[q].refill(addr);
+        }
+        let (q, desc) = match self.recv_any() {
+            Some(r) => r,
+            None => {
+                let mut fds: Vec<_> = self
+                    .queues
+                    .iter()
+                    .map(|q| pollfd {
+                        fd: q.umem.fd,
+                        events: POLLIN,
+                        revents: 0,
+                    })
+                    .collect();
+                let n = poll(
+                    fds.as_mut_ptr(),
+                    fds.len() as _,
+                    self.poll_timeout.as_millis() as c_int,
+                );
+                if n <= 0 {
+                    return None;
+                }
+                self.recv_any()?
+            }
+        };
+        self.pending = Some((q, desc.addr));
+        let data = std::slice::from_raw_parts_mut(
+            (self.queues[q].umem.addr as *mut u8).add(desc.addr as usize),
+            desc.len as usize,
+        );
+        Some(Packet {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            if_index: self.if_index as isize,
+            capture_length: desc.len as isize,
+            data,
+            ..Default::default()
+        })
+    }
+
+    pub fn get_counter_handle(&self) -> XdpCounter {
+        XdpCounter {
+            fds: self.queues.iter().map(|q| q.umem.fd).collect(),
+            last_drops: Default::default(),
+        }
+    }
+}
+
+impl Drop for XdpSocket {
+    fn drop(&mut self) {
+        info!("AF_XDP {} closed", self.iface);
+    }
+}
+
+pub struct XdpCounter {
+    fds: Vec<c_int>,
+    last_drops: AtomicU64,
+}
+
+impl stats::RefCountable for XdpCounter {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        let mut drops = 0;
+        for fd in self.fds.iter() {
+            let mut s = XdpStatistics::default();
+            let mut len = mem::size_of::<XdpStatistics>() as socklen_t;
+            let ret = unsafe {
+                getsockopt(
+                    *fd,
+                    SOL_XDP,
+                    XDP_STATISTICS,
+                    &mut s as *mut XdpStatistics as *mut c_void,
+                    &mut len,
+                )
+            };
+            if ret != 0 {
+                warn!("{:?}", io::Error::last_os_error());
+                return vec![];
+            }
+            drops += s.rx_dropped + s.rx_invalid_descs;
+        }
+        // statistics of xdp sockets are accumulative
+        let last = self.last_drops.swap(drops, Ordering::Relaxed);
+        vec![(
+            "kernel_drops",
+            stats::CounterType::Counted,
+            stats::CounterValue::Unsigned(drops.saturating_sub(last)),
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_per_queue() {
+        let min_memory = MIN_FRAMES * FRAME_SIZE;
+        let cases = [
+            (min_memory, 1, MIN_FRAMES),
+            (min_memory * 4, 4, MIN_FRAMES),
+            // rounded down to power of two within budget
+            (min_memory * 3, 1, MIN_FRAMES * 2),
+            (min_memory * 3 - 1, 1, MIN_FRAMES * 2),
+            (min_memory * 64, 1, MAX_FRAMES),
+            (min_memory * 64, 2, MAX_FRAMES),
+            (min_memory * 64, 8, MIN_FRAMES * 8),
+        ];
+        for (memory, queue_count, frames) in cases {
+            let r = XdpSocket::frames_per_queue(memory, queue_count).unwrap();
+            assert_eq!(r, frames, "memory {} queues {}", memory, queue_count);
+            assert!(r * FRAME_SIZE * queue_count <= memory);
+        }
+        assert!(XdpSocket::frames_per_queue(min_memory - 1, 1).is_err());
+        assert!(XdpSocket::frames_per_queue(min_memory * 4, 5).is_err());
+    }
+}
@@ -80,9 +80,13 @@ impl BpfBuilder {
 }
 
 pub(crate) struct Builder {
-    pub is_ipv6: bool,
+    pub controller_is_ipv6: bool,
+    // address family of `analyzer_source_ip`, which may differ from the controller
+    pub analyzer_is_ipv6: bool,
     pub vxlan_flags: u8,
     pub npb_port: u16,
+    // extra VXLAN ports filtered together with `npb_port`
+    pub vxlan_ports: Vec<u16>,
     pub controller_port: u16,
     pub analyzer_port: u16,
     pub proxy_controller_port: u16,
@@ -108,9 +112,9 @@ impl Builder {
     }
 
     // 仅过滤单个vlan头
-    fn skip_ethernet(&self) -> BpfBuilder {
+    fn skip_ethernet(&self, is_ipv6: bool) -> BpfBuilder {
         let mut bpf_builder = BpfBuilder::default();
-        let eth_type = if self.is_ipv6 {
+        let eth_type = if is_ipv6 {
             u16::from(EthernetType::IPV6) as u32
         } else {
             u16::from(EthernetType::IPV4) as u32
@@ -143,7 +147,7 @@ impl Builder {
                 },
                 Self::bypass_modifier,
             );
-        if !self.is_ipv6 {
+        if !is_ipv6 {
             bpf_builder
                 .append(BpfSyntax::LoadIndirect(LoadIndirect {
                     off: IPV4_FLAGS_OFFSET as u32,
@@ -165,8 +169,33 @@ impl Builder {
         return bpf_builder;
     }
 
+    // jumps to the next instruction if the loaded udp dst port is any of `ports`,
+    // otherwise bypasses the rest of the block
+    fn match_vxlan_ports(bpf_builder: &mut BpfBuilder, ports: &[u16]) {
+        for (i, port) in ports.iter().enumerate() {
+            if i + 1 < ports.len() {
+                bpf_builder.append(BpfSyntax::JumpIf(JumpIf {
+                    cond: JumpTest::JumpEqual,
+                    val: *port as u32,
+                    skip_true: (ports.len() - 1 - i) as u8,
+                    ..Default::default()
+                }));
+            } else {
+                bpf_builder.branch(
+                    JumpIf {
+                        cond: JumpTest::JumpNotEqual,
+                        val: *port as u32,
+                        ..Default::default()
+                    },
+                    Self::bypass_modifier,
+                );
+            }
+        }
+    }
+
     fn skip_ipv4_npb(&self) -> Vec<BpfSyntax> {
         let mut bpf_builder = BpfBuilder::default();
+        let vxlan_ports = self.vxlan_dst_ports();
 
         bpf_builder
             .append(BpfSyntax::LoadIndirect(LoadIndirect {
@@ -176,21 +205,15 @@ impl Builder {
             .append(BpfSyntax::JumpIf(JumpIf {
                 cond: JumpTest::JumpNotEqual,
                 val: u8::from(IpProtocol::UDP) as u32,
-                skip_true: 4,
+                skip_true: (vxlan_ports.len() + 3) as u8,
                 ..Default::default()
             }))
             .append(BpfSyntax::LoadIndirect(LoadIndirect {
                 off: UDP_DST_OFFSET as u32,
                 size: PORT_LEN as u32,
-            }))
-            .branch(
-                JumpIf {
-                    cond: JumpTest::JumpNotEqual,
-                    val: self.npb_port as u32,
-                    ..Default::default()
-                },
-                Self::bypass_modifier,
-            )
+            }));
+        Self::match_vxlan_ports(&mut bpf_builder, &vxlan_ports);
+        bpf_builder
             .append(BpfSyntax::LoadIndirect(LoadIndirect {
                 off: VXLAN_FLAGS_OFFSET as u32,
                 size: 1,
@@ -252,6 +275,7 @@ impl Builder {
 
     fn skip_ipv6_npb(&self) -> Vec<BpfSyntax> {
         let mut bpf_builder = BpfBuilder::default();
+        let vxlan_ports = self.vxlan_dst_ports();
 
         bpf_builder
             .append(BpfSyntax::LoadIndirect(LoadIndirect {
@@ -261,21 +285,15 @@ impl Builder {
             .append(BpfSyntax::JumpIf(JumpIf {
                 cond: JumpTest::JumpNotEqual,
                 val: u8::from(IpProtocol::UDP) as u32,
-                skip_true: 4,
+                skip_true: (vxlan_ports.len() + 3) as u8,
                 ..Default::default()
             }))
             .append(BpfSyntax::LoadIndirect(LoadIndirect {
                 off: UDP6_DST_OFFSET as u32,
                 size: PORT_LEN as u32,
-            }))
-            .branch(
-                JumpIf {
-                    cond: JumpTest::JumpNotEqual,
-                    val: self.npb_port as u32,
-                    ..Default::default()
-                },
-                Self::bypass_modifier,
-            )
+            }));
+        Self::match_vxlan_ports(&mut bpf_builder, &vxlan_ports);
+        bpf_builder
             .append(BpfSyntax::LoadIndirect(LoadIndirect {
                 off: VXLAN6_FLAGS_OFFSET as u32,
                 size: 1,
@@ -337,7 +355,7 @@ impl Builder {
 
     fn skip_controller(&self) -> Vec<BpfSyntax> {
         let mut bpf_builder = BpfBuilder::default();
-        let (protocol_offset, tcp_src_port, tcp_dst_port) = if self.is_ipv6 {
+        let (protocol_offset, tcp_src_port, tcp_dst_port) = if self.controller_is_ipv6 {
             (
                 IPV6_PROTO_OFFSET as u32,
                 TCP6_SRC_OFFSET as u32,
@@ -719,70 +737,102 @@ impl Builder {
         return lo_tx_builder.build();
     }
 
-    fn build_ipv4_syntax(self, bpf_builder: &mut BpfBuilder) -> Vec<BpfSyntax> {
-        // 不采集和控制器通信的流量
-        bpf_builder.appends(&mut self.skip_controller());
-        // 不采集和TSDB通信的流量
-        bpf_builder.appends(&mut self.skip_ipv4_tsdb());
-        // 不采集分发流量
-        bpf_builder.appends(&mut self.skip_ipv4_npb());
-
-        return bpf_builder.build();
-    }
-
-    fn build_ipv6_syntax(self, bpf_builder: &mut BpfBuilder) -> Vec<BpfSyntax> {
-        // 不采集和控制器通信的流量
-        bpf_builder.appends(&mut self.skip_controller());
-        // 不采集和TSDB通信的流量
-        bpf_builder.appends(&mut self.skip_ipv6_tsdb());
+    // filters packets of one address family, packets of the other family bypass the block
+    fn build_family_syntax(&self, is_ipv6: bool) -> Vec<BpfSyntax> {
+        let mut bpf_builder = self.skip_ethernet(is_ipv6);
+        if self.controller_is_ipv6 == is_ipv6 {
+            // 不采集和控制器通信的流量
+            bpf_builder.appends(&mut self.skip_controller());
+        }
+        if self.analyzer_is_ipv6 == is_ipv6 {
+            // 不采集和TSDB通信的流量
+            if is_ipv6 {
+                bpf_builder.appends(&mut self.skip_ipv6_tsdb());
+            } else {
+                bpf_builder.appends(&mut self.skip_ipv4_tsdb());
+            }
+        }
         // 不采集分发流量
-        bpf_builder.appends(&mut self.skip_ipv6_npb());
+        if is_ipv6 {
+            bpf_builder.appends(&mut self.skip_ipv6_npb());
+        } else {
+            bpf_builder.appends(&mut self.skip_ipv4_npb());
+        }
 
         return bpf_builder.build();
     }
 
     pub fn build_pcap_syntax(self) -> Vec<BpfSyntax> {
-        let mut bpf_builder = self.skip_ethernet();
         // 不采集器lo TX方向流量
         let mut lo_bpf = self.skip_lo_tx();
-        if self.is_ipv6 {
-            lo_bpf.append(&mut self.build_ipv6_syntax(&mut bpf_builder));
-        } else {
-            lo_bpf.append(&mut self.build_ipv4_syntax(&mut bpf_builder));
+        lo_bpf.append(&mut self.build_family_syntax(self.analyzer_is_ipv6));
+        if self.controller_is_ipv6 != self.analyzer_is_ipv6 {
+            // the controller is reached in the other address family
+            lo_bpf.append(&mut self.build_family_syntax(self.controller_is_ipv6));
         }
         return lo_bpf;
     }
 }
 
 impl Builder {
+    // Maximum of VXLAN ports, limited by the 8-bit jump offset of bpf
+    const MAX_VXLAN_PORTS: usize = 64;
+
+    fn vxlan_dst_ports(&self) -> Vec<u16> {
+        let mut ports = vec![self.npb_port];
+        for port in self.vxlan_ports.iter() {
+            if ports.len() >= Self::MAX_VXLAN_PORTS {
+                break;
+            }
+            if !ports.contains(port) {
+                ports.push(*port);
+            }
+        }
+        ports
+    }
+
     pub fn build_pcap_syntax_to_str(&self) -> String {
         let mut conditions = vec![];
-        let ip_version = if self.is_ipv6 { "ip6" } else { "ip" };
+        let ip_version = |is_ipv6: bool| if is_ipv6 { "ip6" } else { "ip" };
 
         // 不采集和控制器通信的流量
+        let controller_ip_version = ip_version(self.controller_is_ipv6);
         conditions.push(format!(
             "not ({} and tcp and (src port {} or {} or {}))",
-            ip_version, self.controller_port, self.controller_tls_port, self.proxy_controller_port
+            controller_ip_version,
+            self.controller_port,
+            self.controller_tls_port,
+            self.proxy_controller_port
         ));
         conditions.push(format!(
             "not ({} and tcp and (dst port {} or {} or {}))",
-            ip_version, self.controller_port, self.controller_tls_port, self.proxy_controller_port
+            controller_ip_version,
+            self.controller_port,
+            self.controller_tls_port,
+            self.proxy_controller_port
         ));
 
         // 不采集和TSDB通信的流量
+        let analyzer_ip_version = ip_version(self.analyzer_is_ipv6);
         conditions.push(format!(
             "not ({} and src host {} and dst port {})",
-            ip_version, self.analyzer_source_ip, self.analyzer_port
+            analyzer_ip_version, self.analyzer_source_ip, self.analyzer_port
         ));
         conditions.push(format!(
             "not ({} and dst host {} and src port {})",
-            ip_version, self.analyzer_source_ip, self.analyzer_port
+            analyzer_ip_version, self.analyzer_source_ip, self.analyzer_port
         ));
 
         // 不采集分发的VXLAN流量
+        let vxlan_ports = self
+            .vxlan_dst_ports()
+            .iter()
+            .map(|port| format!("dst port {}", port))
+            .collect::<Vec<_>>();
         conditions.push(format!(
-            "not (udp and dst port {} and udp[8:1]={:#x})",
-            self.npb_port, self.vxlan_flags
+            "not (udp and ({}) and udp[8:1]={:#x})",
+            vxlan_ports.join(" or "),
+            self.vxlan_flags
         ));
 
         // 不采集分发的TCP流量
@@ -812,9 +862,11 @@ mod tests {
     #[test]
     fn ipv4_bpf_syntax() {
         let builder = Builder {
-            is_ipv6: false,
+            controller_is_ipv6: false,
+            analyzer_is_ipv6: false,
             vxlan_flags: 0xff,
             npb_port: 1122,
+            vxlan_ports: vec![],
             controller_port: 3344,
             controller_tls_port: 5566,
             proxy_controller_port: 7788,
@@ -899,9 +951,11 @@ mod tests {
     #[test]
     fn ipv6_bpf_syntax() {
         let builder = Builder {
-            is_ipv6: true,
+            controller_is_ipv6: true,
+            analyzer_is_ipv6: true,
             vxlan_flags: 0xff,
             npb_port: 1122,
+            vxlan_ports: vec![],
             controller_port: 3344,
             controller_tls_port: 5566,
             proxy_controller_port: 7788,
@@ -995,4 +1049,213 @@ mod tests {
             assert_eq!(line, except[i]);
         }
     }
+
+    #[test]
+    fn vxlan_ports_bpf_syntax() {
+        let builder = Builder {
+            controller_is_ipv6: false,
+            analyzer_is_ipv6: false,
+            vxlan_flags: 0xff,
+            npb_port: 1122,
+            vxlan_ports: vec![4789, 1122],
+            controller_port: 3344,
+            controller_tls_port: 5566,
+            proxy_controller_port: 7788,
+            analyzer_port: 8899,
+            analyzer_source_ip: "1.2.3.4".parse::<IpAddr>().unwrap(),
+        };
+
+        assert!(builder
+            .build_pcap_syntax_to_str()
+            .contains("not (udp and (dst port 1122 or dst port 4789) and udp[8:1]=0xff)"));
+
+        let syntax = builder.build_pcap_syntax();
+        let output = syntax
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+
+        let except = [
+            "ldb [x + 23]",
+            "jneq #17,5",
+            "ldh [x + 36]",
+            "jeq #1122,1",
+            "jneq #4789,11",
+            "ldb [x + 42]",
+            "jeq #255,8,9",
+            "jneq #47,2",
+            "ldh [x + 36]",
+            "jeq #8939,5,6",
+            "jneq #6,5",
+            "ldh [x + 36]",
+            "jeq #1122,2",
+            "ldh [x + 34]",
+            "jneq #1122,1",
+            "ret #0",
+        ];
+
+        assert_eq!(&output[output.len() - except.len()..], &except[..]);
+    }
+
+    #[test]
+    fn mixed_family_bpf_syntax() {
+        let builder = Builder {
+            controller_is_ipv6: false,
+            analyzer_is_ipv6: true,
+            vxlan_flags: 0xff,
+            npb_port: 1122,
+            vxlan_ports: vec![],
+            controller_port: 3344,
+            controller_tls_port: 5566,
+            proxy_controller_port: 7788,
+            analyzer_port: 8899,
+            analyzer_source_ip: "9999:aaaa:bbbb:cccc:dddd:eeee:ffff:0000"
+                .parse::<IpAddr>()
+                .unwrap(),
+        };
+
+        let syntax_str = builder.build_pcap_syntax_to_str();
+        assert!(syntax_str.contains("not (ip and tcp and (src port 3344 or 5566 or 7788))"));
+        assert!(syntax_str.contains(
+            "not (ip6 and src host 9999:aaaa:bbbb:cccc:dddd:eeee:ffff:0 and dst port 8899)"
+        ));
+
+        let syntax = builder.build_pcap_syntax();
+        let output = syntax
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+
+        // the ipv6 block filters analyzer traffic, the ipv4 block filters controller traffic
+        let except = [
+            "ld #ifidx",
+            "jneq #1,3",
+            "ld #type",
+            "jneq #4,1",
+            "ret #0",
+            "ldh [12]",
+            "jneq #33024,2",
+            "ldx #4",
+            "ldh [x + 12]",
+            "jneq #34525,47",
+            "ld [x + 22]",
+            "jneq #2576984746,14",
+            "ld [x + 26]",
+            "jneq #3149647052,12",
+            "ld [x + 30]",
+            "jneq #3722309358,10",
+            "ld [x + 34]",
+            "jneq #4294901760,8",
+            "ldb [x + 20]",
+            "jneq #6,2",
+            "ldh [x + 56]",
+            "jneq #8899,4,3",
+            "jneq #17,3",
+            "ldh [x + 56]",
+            "jneq #8899,1",
+            "ret #0",
+            "ld [x + 38]",
+            "jneq #2576984746,14",
+            "ld [x + 42]",
+            "jneq #3149647052,12",
+            "ld [x + 46]",
+            "jneq #3722309358,10",
+            "ld [x + 50]",
+            "jneq #4294901760,8",
+            "ldb [x + 20]",
+            "jneq #6,2",
+            "ldh [x + 54]",
+            "jneq #8899,4,3",
+            "jneq #17,3",
+            "ldh [x + 54]",
+            "jneq #8899,1",
+            "ret #0",
+            "ldb [x + 20]",
+            "jneq #17,4",
+            "ldh [x + 56]",
+            "jneq #1122,11",
+            "ldb [x + 62]",
+            "jeq #255,8,9",
+            "jneq #47,2",
+            "ldh [x + 56]",
+            "jeq #8939,5,6",
+            "jneq #6,5",
+            "ldh [x + 56]",
+            "jeq #1122,2",
+            "ldh [x + 54]",
+            "jneq #1122,1",
+            "ret #0",
+            "ldh [12]",
+            "jneq #33024,2",
+            "ldx #4",
+            "ldh [x + 12]",
+            "jneq #2048,30",
+            "ldh [x + 20]",
+            "and #8191",
+            "jneq #0,27",
+            "ldb [x + 23]",
+            "jeq #6,1",
+            "jneq #17,9",
+            "ldh [x + 34]",
+            "jeq #3344,6",
+            "jeq #5566,5",
+            "jeq #7788,4",
+            "ldh [x + 36]",
+            "jeq #3344,2",
+            "jeq #5566,1",
+            "jneq #7788,1",
+            "ret #0",
+            "ldb [x + 23]",
+            "jneq #17,4",
+            "ldh [x + 36]",
+            "jneq #1122,11",
+            "ldb [x + 42]",
+            "jeq #255,8,9",
+            "jneq #47,2",
+            "ldh [x + 36]",
+            "jeq #8939,5,6",
+            "jneq #6,5",
+            "ldh [x + 36]",
+            "jeq #1122,2",
+            "ldh [x + 34]",
+            "jneq #1122,1",
+            "ret #0",
+        ];
+
+        assert_eq!(output, except);
+    }
+
+    #[test]
+    fn ipv6_only_bpf_syntax_str() {
+        let builder = Builder {
+            controller_is_ipv6: true,
+            analyzer_is_ipv6: true,
+            vxlan_flags: 0xff,
+            npb_port: 1122,
+            vxlan_ports: vec![],
+            controller_port: 3344,
+            controller_tls_port: 5566,
+            proxy_controller_port: 7788,
+            analyzer_port: 8899,
+            analyzer_source_ip: "9999::1".parse::<IpAddr>().unwrap(),
+        };
+
+        let syntax_str = builder.build_pcap_syntax_to_str();
+        assert!(syntax_str.contains("not (ip6 and tcp and (src port 3344 or 5566 or 7788))"));
+        assert!(syntax_str.contains("not (ip6 and tcp and (dst port 3344 or 5566 or 7788))"));
+        assert!(syntax_str.contains("not (ip6 and src host 9999::1 and dst port 8899)"));
+        assert!(syntax_str.contains("not (ip6 and dst host 9999::1 and src port 8899)"));
+        assert!(!syntax_str.contains("not (ip and"));
+
+        let syntax = builder.build_pcap_syntax();
+        // only ipv6 packets are filtered
+        assert_eq!(
+            syntax
+                .iter()
+                .filter(|x| x.to_string().starts_with("ldh [12]"))
+                .count(),
+            1
+        );
+        assert!(syntax.iter().any(|x| x.to_string() == "jneq #34525,59"));
+    }
 }
@@ -15,6 +15,8 @@
  */
 
 pub mod af_packet;
+#[cfg(target_os = "linux")]
+pub mod af_xdp;
 pub(crate) mod bpf;
 
 use std::ffi::CStr;
@@ -22,11 +24,13 @@ use std::sync::{atomic::AtomicU64, Arc};
 use std::time::Duration;
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
-use af_packet::{options::Options, tpacket::Tpacket};
+use af_packet::{options::Options, tpacket::Tpacket, tpacket::TpacketGroup};
+#[cfg(target_os = "linux")]
+use af_xdp::XdpSocket;
 pub use public::error::{Error, Result};
 use public::packet;
 
-use crate::utils::stats;
+use crate::{config::CaptureTimestampSource, utils::stats};
 
 #[cfg(target_os = "linux")]
 pub use special_recv_engine::Dpdk;
@@ -40,6 +44,10 @@ pub const POLL_TIMEOUT: Duration = Duration::from_millis(100);
 pub enum RecvEngine {
     #[cfg(any(target_os = "linux", target_os = "android"))]
     AfPacket(Tpacket),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    AfPacketGroup(TpacketGroup),
+    #[cfg(target_os = "linux")]
+    AfXdp(XdpSocket),
     #[cfg(target_os = "linux")]
     Dpdk(Dpdk),
     Libpcap(Option<Libpcap>),
@@ -52,6 +60,10 @@ impl RecvEngine {
         match self {
             #[cfg(any(target_os = "linux", target_os = "android"))]
             Self::AfPacket(_) => Ok(()),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::AfPacketGroup(_) => Ok(()),
+            #[cfg(target_os = "linux")]
+            Self::AfXdp(_) => Ok(()),
             #[cfg(target_os = "linux")]
             Self::Dpdk(_) => Ok(()),
             Self::Libpcap(_) => Ok(()),
@@ -75,6 +87,16 @@ impl RecvEngine {
                 Some(p) => Ok(p),
                 None => Err(Error::Timeout),
             },
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::AfPacketGroup(e) => match e.read() {
+                Some(p) => Ok(p),
+                None => Err(Error::Timeout),
+            },
+            #[cfg(target_os = "linux")]
+            Self::AfXdp(e) => match e.read() {
+                Some(p) => Ok(p),
+                None => Err(Error::Timeout),
+            },
             #[cfg(target_os = "linux")]
             Self::Dpdk(d) => match d.read() {
                 Ok(p) => Ok(p),
@@ -92,19 +114,42 @@ impl RecvEngine {
         match self {
             #[cfg(any(target_os = "linux", target_os = "android"))]
             Self::AfPacket(e) => e.set_bpf(ins).map_err(|e| e.into()),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::AfPacketGroup(e) => e.set_bpf(ins).map_err(|e| e.into()),
             Self::Libpcap(w) => w
                 .as_mut()
                 .ok_or(Error::LibpcapError(Self::LIBPCAP_NONE.to_string()))
                 .and_then(|e| e.set_bpf(syntax.to_str().unwrap())),
+            // packets are not filtered by the AF_XDP engine
+            #[cfg(target_os = "linux")]
+            Self::AfXdp(_) => Ok(()),
             #[cfg(target_os = "linux")]
             Self::Dpdk(_) => Ok(()),
         }
     }
 
+    // libpcap and dpdk always use software timestamps
+    pub fn timestamp_source(&self) -> CaptureTimestampSource {
+        match self {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::AfPacket(e) => e.timestamp_source(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::AfPacketGroup(e) => e.timestamp_source(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            _ => CaptureTimestampSource::Software,
+            #[cfg(target_os = "windows")]
+            Self::Libpcap(_) => CaptureTimestampSource::Software,
+        }
+    }
+
     pub fn get_counter_handle(&self) -> Arc<dyn stats::RefCountable> {
         match self {
             #[cfg(any(target_os = "linux", target_os = "android"))]
             Self::AfPacket(e) => Arc::new(e.get_counter_handle()),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::AfPacketGroup(e) => Arc::new(e.get_counter_handle()),
+            #[cfg(target_os = "linux")]
+            Self::AfXdp(e) => Arc::new(e.get_counter_handle()),
             #[cfg(target_os = "linux")]
             Self::Dpdk(d) => d.get_counter_handle(),
             Self::Libpcap(w) => match w {
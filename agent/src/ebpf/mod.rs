@@ -455,6 +455,12 @@ extern "C" {
     pub fn set_go_tracing_timeout(timeout: c_int) -> c_int;
     pub fn set_io_event_collect_mode(mode: c_int) -> c_int;
     pub fn set_io_event_minimal_duration(duration: c_ulonglong) -> c_int;
+    /*
+     * Skip the syscall kprobes and tracepoints of the socket tracer, only
+     * process events and uprobes are attached. Must be called before
+     * running_socket_tracer().
+     */
+    pub fn disable_syscall_probes() -> c_int;
     pub fn set_allow_port_bitmap(bitmap: *const c_uchar) -> c_int;
     pub fn set_bypass_port_bitmap(bitmap: *const c_uchar) -> c_int;
     pub fn enable_ebpf_protocol(protocol: c_int) -> c_int;
@@ -132,10 +132,25 @@ static bool bpf_stats_map_update(struct bpf_tracer *tracer,
 				 int conflict_count,
 				 int max_delay,
 				 int total_time, int event_count);
+/*
+ * When set, the socket tracer does not attach the syscall kprobes and
+ * tracepoints, only the process events and uprobes are kept.
+ */
+static bool syscall_probes_disabled;
+
+int disable_syscall_probes(void)
+{
+	syscall_probes_disabled = true;
+	return 0;
+}
+
 static void socket_tracer_set_probes(struct tracer_probes_conf *tps)
 {
 	int index = 0, curr_idx;
 
+	if (syscall_probes_disabled)
+		goto process_probes;
+
 	probes_set_enter_symbol(tps, "__sys_sendmsg");
 	probes_set_enter_symbol(tps, "__sys_sendmmsg");
 	probes_set_enter_symbol(tps, "__sys_recvmsg");
@@ -153,6 +168,7 @@ static void socket_tracer_set_probes(struct tracer_probes_conf *tps)
 		probes_set_enter_symbol(tps, "do_readv");
 	}
 
+process_probes:
 	if (access(SYSCALL_FORK_TP_PATH, F_OK))
 		probes_set_exit_symbol(tps, "sys_fork");
 	if (access(SYSCALL_CLONE_TP_PATH, F_OK))
@@ -163,6 +179,9 @@ static void socket_tracer_set_probes(struct tracer_probes_conf *tps)
 	/* tracepoints */
 	index = 0;
 
+	if (syscall_probes_disabled)
+		goto process_tracepoints;
+
 	/*
 	 * 由于在Linux 4.17+ sys_write, sys_read, sys_sendto, sys_recvfrom
 	 * 接口会发生变化为了避免对内核的依赖采用tracepoints方式
@@ -187,6 +206,11 @@ static void socket_tracer_set_probes(struct tracer_probes_conf *tps)
 	tps_set_symbol(tps, "tracepoint/syscalls/sys_exit_readv");
 	tps_set_symbol(tps, "tracepoint/syscalls/sys_exit_accept");
 	tps_set_symbol(tps, "tracepoint/syscalls/sys_exit_accept4");
+
+	// clear trace connection & fetch close info
+	tps_set_symbol(tps, "tracepoint/syscalls/sys_enter_close");
+
+process_tracepoints:
 	// process execute
 	if (!access(SYSCALL_FORK_TP_PATH, F_OK))
 		tps_set_symbol(tps, "tracepoint/syscalls/sys_exit_fork");
@@ -196,9 +220,6 @@ static void socket_tracer_set_probes(struct tracer_probes_conf *tps)
 	// process exit
 	tps_set_symbol(tps, "tracepoint/sched/sched_process_exit");
 
-	// clear trace connection & fetch close info
-	tps_set_symbol(tps, "tracepoint/syscalls/sys_enter_close");
-
 	tps->tps_nr = index;
 
 	// 收集go可执行文件uprobe符号信息
@@ -363,6 +363,7 @@ prefetch_and_process_data(struct bpf_tracer *t, int nb_rx, void **datas_burst)
 int set_data_limit_max(int limit_size);
 int set_go_tracing_timeout(int timeout);
 int set_io_event_collect_mode(uint32_t mode);
+int disable_syscall_probes(void);
 int set_io_event_minimal_duration(uint64_t duration);
 struct socket_trace_stats socket_tracer_stats(void);
 int running_socket_tracer(tracer_callback_t handle,
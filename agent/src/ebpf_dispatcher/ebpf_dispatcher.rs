@@ -15,10 +15,11 @@
  */
 
 use std::ffi::{CStr, CString};
+use std::fmt;
 use std::ptr::{self, null_mut};
 use std::slice;
 use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
@@ -37,7 +38,7 @@ use crate::common::meta_packet::{MetaPacket, SegmentFlags};
 use crate::common::proc_event::{BoxedProcEvents, EventType, ProcEvent};
 use crate::common::{FlowAclListener, FlowAclListenerId, TaggedFlow};
 use crate::config::handler::{CollectorAccess, EbpfAccess, EbpfConfig, LogParserAccess};
-use crate::config::FlowAccess;
+use crate::config::{EbpfYamlConfig, FlowAccess};
 use crate::ebpf;
 use crate::exception::ExceptionHandler;
 use crate::flow_generator::{flow_map::Config, AppProto, FlowMap};
@@ -48,7 +49,7 @@ use crate::utils::stats;
 
 use public::{
     buffer::BatchedBox,
-    counter::{Countable, Counter, CounterType, CounterValue, OwnedCountable},
+    counter::{Countable, Counter, CounterType, CounterValue, OwnedCountable, RefCountable},
     debug::QueueDebugger,
     l7_protocol::{L7Protocol, L7ProtocolChecker},
     leaky_bucket::LeakyBucket,
@@ -58,9 +59,49 @@ use public::{
 };
 use reorder::{Reorder, ReorderCounter, StatsReorderCounter};
 
+// Probes actually attached by ebpf_init, filtered by the `probes` allow-list
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AttachedProbes {
+    pub syscall: bool,
+    pub golang_uprobe: bool,
+    pub golang_symbol_uprobe: bool,
+    pub openssl_uprobe: bool,
+    pub on_cpu_profile: bool,
+    pub off_cpu_profile: bool,
+}
+
+impl AttachedProbes {
+    fn to_vec(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            (EbpfYamlConfig::PROBE_SYSCALL, self.syscall),
+            (EbpfYamlConfig::PROBE_GOLANG_UPROBE, self.golang_uprobe),
+            (
+                EbpfYamlConfig::PROBE_GOLANG_SYMBOL_UPROBE,
+                self.golang_symbol_uprobe,
+            ),
+            (EbpfYamlConfig::PROBE_OPENSSL_UPROBE, self.openssl_uprobe),
+            (EbpfYamlConfig::PROBE_ON_CPU_PROFILE, self.on_cpu_profile),
+            (EbpfYamlConfig::PROBE_OFF_CPU_PROFILE, self.off_cpu_profile),
+        ]
+    }
+}
+
+impl fmt::Display for AttachedProbes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let attached = self
+            .to_vec()
+            .into_iter()
+            .filter_map(|(name, attached)| attached.then_some(name))
+            .collect::<Vec<_>>();
+        write!(f, "[{}]", attached.join(", "))
+    }
+}
+
 pub struct EbpfCounter {
     rx: AtomicU64,
     get_token_failed: AtomicU64,
+    poll_budget_exhausted: AtomicU64,
+    attached_probes: AttachedProbes,
 }
 
 pub struct SyncEbpfCounter {
@@ -71,7 +112,12 @@ impl OwnedCountable for SyncEbpfCounter {
     fn get_counters(&self) -> Vec<Counter> {
         let rx = self.counter.rx.swap(0, Ordering::Relaxed);
         let get_token_failed = self.counter.get_token_failed.swap(0, Ordering::Relaxed);
+        let poll_budget_exhausted = self
+            .counter
+            .poll_budget_exhausted
+            .swap(0, Ordering::Relaxed);
         let ebpf_counter = unsafe { ebpf::socket_tracer_stats() };
+        let probes = &self.counter.attached_probes;
 
         vec![
             (
@@ -84,6 +130,11 @@ impl OwnedCountable for SyncEbpfCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(get_token_failed),
             ),
+            (
+                "poll_budget_exhausted",
+                CounterType::Counted,
+                CounterValue::Unsigned(poll_budget_exhausted),
+            ),
             (
                 "perf_pages_count",
                 CounterType::Counted,
@@ -194,6 +245,36 @@ impl OwnedCountable for SyncEbpfCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(ebpf_counter.proc_exit_event_count as u64),
             ),
+            (
+                "syscall_probe_attached",
+                CounterType::Gauged,
+                CounterValue::Unsigned(probes.syscall as u64),
+            ),
+            (
+                "golang_uprobe_attached",
+                CounterType::Gauged,
+                CounterValue::Unsigned(probes.golang_uprobe as u64),
+            ),
+            (
+                "golang_symbol_uprobe_attached",
+                CounterType::Gauged,
+                CounterValue::Unsigned(probes.golang_symbol_uprobe as u64),
+            ),
+            (
+                "openssl_uprobe_attached",
+                CounterType::Gauged,
+                CounterValue::Unsigned(probes.openssl_uprobe as u64),
+            ),
+            (
+                "on_cpu_profile_attached",
+                CounterType::Gauged,
+                CounterValue::Unsigned(probes.on_cpu_profile as u64),
+            ),
+            (
+                "off_cpu_profile_attached",
+                CounterType::Gauged,
+                CounterValue::Unsigned(probes.off_cpu_profile as u64),
+            ),
         ]
     }
     // EbpfCollector不会重复创建，这里都是false
@@ -333,9 +414,15 @@ impl EbpfDispatcher {
             self.stats_collector.clone(),
             true, // from_ebpf
         );
-        let leaky_bucket = LeakyBucket::new(Some(ebpf_config.ebpf.global_ebpf_pps_threshold));
-        const QUEUE_BATCH_SIZE: usize = 1024;
-        let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
+        let leaky_bucket = Arc::new(LeakyBucket::new(Some(
+            ebpf_config.ebpf.global_ebpf_pps_threshold,
+        )));
+        self.stats_collector.register_countable(
+            &stats::SingleTagModule("leaky_bucket", "name", "ebpf"),
+            Countable::Ref(Arc::downgrade(&leaky_bucket) as Weak<dyn RefCountable>),
+        );
+        let poll_batch_size = ebpf_config.ebpf.poll_batch_size;
+        let mut batch = Vec::with_capacity(poll_batch_size);
         while unsafe { SWITCH } {
             let config = Config {
                 flow: &self.flow_map_config.load(),
@@ -362,6 +449,13 @@ impl EbpfDispatcher {
                 continue;
             }
 
+            // more events are pending in the queue, ring-size or poll-batch-size may be too small
+            if batch.len() >= poll_batch_size {
+                counter
+                    .poll_budget_exhausted
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+
             for mut packet in batch.drain(..) {
                 if !leaky_bucket.acquire(1) {
                     counter.get_token_failed.fetch_add(1, Ordering::Relaxed);
@@ -413,6 +507,8 @@ pub struct EbpfCollector {
 }
 
 static mut SWITCH: bool = false;
+// l7 events are discarded in kernel callback when paused, bpf programs are kept attached
+static PAUSED: AtomicBool = AtomicBool::new(false);
 static mut SENDER: Option<DebugSender<Box<MetaPacket>>> = None;
 static mut PROC_EVENT_SENDER: Option<DebugSender<BoxedProcEvents>> = None;
 static mut EBPF_PROFILE_SENDER: Option<DebugSender<Profile>> = None;
@@ -435,7 +531,7 @@ impl EbpfCollector {
 
     extern "C" fn ebpf_l7_callback(sd: *mut ebpf::SK_BPF_DATA) {
         unsafe {
-            if !SWITCH || SENDER.is_none() {
+            if !SWITCH || SENDER.is_none() || PAUSED.load(Ordering::Relaxed) {
                 return;
             }
 
@@ -533,10 +629,16 @@ impl EbpfCollector {
         l7_protocol_enabled_bitmap: L7ProtocolBitmap,
         policy_getter: PolicyGetter,
         time_diff: Arc<AtomicI64>,
-    ) -> Result<()> {
+    ) -> Result<AttachedProbes> {
+        let mut probes = AttachedProbes::default();
         // ebpf内核模块初始化
         unsafe {
-            if !config.ebpf.uprobe_proc_regexp.golang.is_empty() {
+            if !config
+                .ebpf
+                .probe_allowed(EbpfYamlConfig::PROBE_GOLANG_UPROBE)
+            {
+                info!("ebpf golang uprobe is not in probes allow-list, skip set")
+            } else if !config.ebpf.uprobe_proc_regexp.golang.is_empty() {
                 info!(
                     "ebpf set golang uprobe proc regexp: {}",
                     config.ebpf.uprobe_proc_regexp.golang.as_str()
@@ -548,11 +650,17 @@ impl EbpfCollector {
                         .as_c_str()
                         .as_ptr(),
                 );
+                probes.golang_uprobe = true;
             } else {
                 info!("ebpf golang uprobe proc regexp is empty, skip set")
             }
 
-            if !config.ebpf.uprobe_proc_regexp.openssl.is_empty() {
+            if !config
+                .ebpf
+                .probe_allowed(EbpfYamlConfig::PROBE_OPENSSL_UPROBE)
+            {
+                info!("ebpf openssl uprobe is not in probes allow-list, skip set")
+            } else if !config.ebpf.uprobe_proc_regexp.openssl.is_empty() {
                 info!(
                     "ebpf set openssl uprobe proc regexp: {}",
                     config.ebpf.uprobe_proc_regexp.openssl.as_str()
@@ -564,11 +672,17 @@ impl EbpfCollector {
                         .as_c_str()
                         .as_ptr(),
                 );
+                probes.openssl_uprobe = true;
             } else {
                 info!("ebpf openssl uprobe proc regexp is empty, skip set")
             }
 
-            if !config.ebpf.uprobe_proc_regexp.golang_symbol.is_empty() {
+            if !config
+                .ebpf
+                .probe_allowed(EbpfYamlConfig::PROBE_GOLANG_SYMBOL_UPROBE)
+            {
+                info!("ebpf golang symbol uprobe is not in probes allow-list, skip set")
+            } else if !config.ebpf.uprobe_proc_regexp.golang_symbol.is_empty() {
                 info!(
                     "ebpf set golang symbol uprobe proc regexp: {}",
                     config.ebpf.uprobe_proc_regexp.golang_symbol.as_str()
@@ -587,10 +701,18 @@ impl EbpfCollector {
                     .as_c_str()
                     .as_ptr(),
                 );
+                probes.golang_symbol_uprobe = true;
             } else {
                 info!("ebpf golang symbol proc regexp is empty, skip set")
             }
 
+            if config.ebpf.probe_allowed(EbpfYamlConfig::PROBE_SYSCALL) {
+                probes.syscall = true;
+            } else {
+                info!("ebpf syscall probes are not in probes allow-list, skip attach");
+                ebpf::disable_syscall_probes();
+            }
+
             for i in get_all_protocol().into_iter() {
                 if l7_protocol_enabled_bitmap.is_enabled(i.protocol()) {
                     info!("l7 protocol {:?} parse enabled", i.protocol());
@@ -702,18 +824,22 @@ impl EbpfCollector {
             let ebpf_conf = &config.ebpf;
             let on_cpu = &ebpf_conf.on_cpu_profile;
             let off_cpu = &ebpf_conf.off_cpu_profile;
+            let on_cpu_enabled =
+                !on_cpu.disabled && ebpf_conf.probe_allowed(EbpfYamlConfig::PROBE_ON_CPU_PROFILE);
+            let off_cpu_enabled = cfg!(feature = "off_cpu")
+                && !off_cpu.disabled
+                && ebpf_conf.probe_allowed(EbpfYamlConfig::PROBE_OFF_CPU_PROFILE);
 
-            let profiler_enabled =
-                !on_cpu.disabled || (cfg!(feature = "off_cpu") && !off_cpu.disabled);
+            let profiler_enabled = on_cpu_enabled || off_cpu_enabled;
             if profiler_enabled {
-                if !on_cpu.disabled {
+                if on_cpu_enabled {
                     ebpf::enable_oncpu_profiler();
                 } else {
                     ebpf::disable_oncpu_profiler();
                 }
 
                 #[cfg(feature = "off_cpu")]
-                if !off_cpu.disabled {
+                if off_cpu_enabled {
                     ebpf::enable_offcpu_profiler();
                 } else {
                     ebpf::disable_offcpu_profiler();
@@ -730,7 +856,7 @@ impl EbpfCollector {
                     return Err(Error::EbpfInitError);
                 }
 
-                if !on_cpu.disabled {
+                if on_cpu_enabled {
                     ebpf::set_profiler_regex(
                         CString::new(on_cpu.regex.as_bytes())
                             .unwrap()
@@ -743,7 +869,7 @@ impl EbpfCollector {
                 }
 
                 #[cfg(feature = "off_cpu")]
-                if !off_cpu.disabled {
+                if off_cpu_enabled {
                     ebpf::set_offcpu_profiler_regex(
                         CString::new(off_cpu.regex.as_bytes())
                             .unwrap()
@@ -754,6 +880,9 @@ impl EbpfCollector {
                     ebpf::set_offcpu_cpuid_aggregation(off_cpu.cpu as i32);
                     ebpf::set_offcpu_minblock_time(off_cpu.min_block.as_micros() as u32);
                 }
+
+                probes.on_cpu_profile = on_cpu_enabled;
+                probes.off_cpu_profile = off_cpu_enabled;
             }
 
             ebpf::bpf_tracer_finish();
@@ -769,7 +898,7 @@ impl EbpfCollector {
             TIME_DIFF = Some(time_diff);
         }
 
-        Ok(())
+        Ok(probes)
     }
 
     fn ebpf_on_config_change(l7_log_packet_size: usize) {
@@ -849,7 +978,12 @@ impl EbpfCollector {
             info!("ebpf collector disabled.");
             return Err(Error::EbpfDisabled);
         }
-        info!("ebpf collector init...");
+        info!(
+            "ebpf collector init with perf-pages-count {} ring-size {} poll-batch-size {}...",
+            ebpf_config.ebpf.perf_pages_count,
+            ebpf_config.ebpf.ring_size,
+            ebpf_config.ebpf.poll_batch_size
+        );
         let queue_name = "0-ebpf-to-ebpf-collector";
         let (sender, receiver, counter) =
             bounded_with_debug(ebpf_config.queue_size, queue_name, queue_debugger);
@@ -861,7 +995,7 @@ impl EbpfCollector {
             Countable::Owned(Box::new(counter)),
         );
 
-        Self::ebpf_init(
+        let attached_probes = Self::ebpf_init(
             &ebpf_config,
             sender,
             proc_event_output,
@@ -872,7 +1006,10 @@ impl EbpfCollector {
         )?;
         Self::ebpf_on_config_change(ebpf::CAP_LEN_MAX);
 
-        info!("ebpf collector initialized.");
+        info!(
+            "ebpf collector initialized with probes {}.",
+            attached_probes
+        );
         Ok(Box::new(EbpfCollector {
             thread_dispatcher: EbpfDispatcher {
                 dispatcher_id,
@@ -893,6 +1030,8 @@ impl EbpfCollector {
             counter: Arc::new(EbpfCounter {
                 rx: AtomicU64::new(0),
                 get_token_failed: AtomicU64::new(0),
+                poll_budget_exhausted: AtomicU64::new(0),
+                attached_probes,
             }),
             exception_handler,
         }))
@@ -910,7 +1049,24 @@ impl EbpfCollector {
         }
     }
 
+    pub fn pause(&self) {
+        if !PAUSED.swap(true, Ordering::Relaxed) {
+            info!("ebpf collector paused.");
+        }
+    }
+
+    pub fn resume(&self) {
+        if PAUSED.swap(false, Ordering::Relaxed) {
+            info!("ebpf collector resumed.");
+        }
+    }
+
     pub fn on_config_change(&mut self, config: &EbpfConfig) {
+        if config.enabled {
+            self.resume();
+        } else {
+            self.pause();
+        }
         if config.l7_log_enabled() {
             unsafe {
                 if SWITCH {
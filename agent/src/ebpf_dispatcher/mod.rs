@@ -18,6 +18,8 @@ pub mod ebpf_dispatcher;
 
 use thiserror::Error;
 
+use public::counter::{Counter, CounterType, CounterValue, RefCountable};
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("ebpf init error.")]
@@ -36,6 +38,20 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 pub use ebpf_dispatcher::EbpfCollector;
 
+// Registered when eBPF is enabled but the collector fails to initialize, the agent keeps
+// running without eBPF
+pub struct EbpfInitErrorCounter;
+
+impl RefCountable for EbpfInitErrorCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![(
+            "disabled_by_error",
+            CounterType::Gauged,
+            CounterValue::Unsigned(1),
+        )]
+    }
+}
+
 /* example
 
 ```
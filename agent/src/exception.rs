@@ -31,6 +31,10 @@ impl ExceptionHandler {
         | Exception::RxPpsThresholdExceeded as u64
         | Exception::ProcessThresholdExceeded as u64
         | Exception::ThreadThresholdExceeded as u64
+        | Exception::CpuThresholdExceeded as u64
+        | Exception::MemoryThresholdExceeded as u64
+        | Exception::FdThresholdExceeded as u64
+        | Exception::AgentStateChanged as u64
         | Exception::LogFileExceeded as u64
         | Exception::ControllerSocketError as u64
         | Exception::AnalyzerSocketError as u64
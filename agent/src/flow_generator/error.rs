@@ -84,6 +84,8 @@ pub enum Error {
     L7ProtocolCheckLimit,
     #[error("l7 protocol parse limit")]
     L7ProtocolParseLimit,
+    #[error("l7 protocol disabled")]
+    L7ProtocolDisabled,
     #[error("l7 protocol can not merge")]
     L7ProtocolCanNotMerge(L7ProtocolInfo),
     #[error("l7 log can not merge")]
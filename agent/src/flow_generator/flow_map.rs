@@ -62,7 +62,8 @@ use crate::{
         },
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
         l7_protocol_log::{
-            L7PerfCache, L7ProtocolBitmap, L7ProtocolParser, L7ProtocolParserInterface,
+            get_all_protocol, L7PerfCache, L7ProtocolBitmap, L7ProtocolParser,
+            L7ProtocolParserInterface,
         },
         lookup_key::LookupKey,
         meta_packet::{MetaPacket, MetaPacketTcpHeader, ProtocolData},
@@ -128,9 +129,9 @@ impl stats::Module for PluginStats<'_> {
     }
 }
 
-struct AllocatorStats {
-    id: u32,
-    obj_type: &'static str,
+pub(crate) struct AllocatorStats {
+    pub(crate) id: u32,
+    pub(crate) obj_type: &'static str,
 }
 
 impl stats::Module for AllocatorStats {
@@ -146,6 +147,24 @@ impl stats::Module for AllocatorStats {
     }
 }
 
+struct L7ParseStats {
+    id: u32,
+    protocol: &'static str,
+}
+
+impl stats::Module for L7ParseStats {
+    fn name(&self) -> &'static str {
+        "l7-parse"
+    }
+
+    fn tags(&self) -> Vec<StatsOption> {
+        vec![
+            StatsOption::Tag("id", self.id.to_string()),
+            StatsOption::Tag("protocol", self.protocol.to_owned()),
+        ]
+    }
+}
+
 // not thread-safe
 pub struct FlowMap {
     // The original std HashMap uses SipHash-1-3 and is slow.
@@ -191,6 +210,8 @@ pub struct FlowMap {
     system_time: Duration,
 
     l7_protocol_checker: L7ProtocolChecker,
+    // indexed by L7Protocol
+    l7_parse_counters: Vec<Option<Arc<L7ParseCounter>>>,
 
     time_key_buffer: Option<Vec<(u64, FlowMapKey)>>,
 
@@ -223,7 +244,8 @@ impl FlowMap {
     ) -> Self {
         let flow_perf_counter = Arc::new(FlowPerfCounter::default());
         let stats_counter = Arc::new(FlowMapCounter::default());
-        let packet_sequence_enabled = config.packet_sequence_flag > 0 && !from_ebpf;
+        let packet_sequence_enabled =
+            config.packet_sequence_flag > 0 && packet_sequence_queue.is_some() && !from_ebpf;
         let time_window_size = {
             let max_timeout = config.flow_timeout.max;
             let size = config.packet_delay.as_secs() + max_timeout.as_secs() + 1;
@@ -239,6 +261,28 @@ impl FlowMap {
             &stats::SingleTagModule("flow-perf", "id", id),
             Countable::Ref(Arc::downgrade(&flow_perf_counter) as Weak<dyn RefCountable>),
         );
+        let mut l7_parse_counters = vec![None; u8::MAX as usize + 1];
+        for parser in get_all_protocol() {
+            let counter = Arc::new(L7ParseCounter::default());
+            stats_collector.register_countable(
+                &L7ParseStats {
+                    id,
+                    protocol: parser.as_str(),
+                },
+                Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+            );
+            l7_parse_counters[parser.protocol() as usize] = Some(counter);
+        }
+        // failed protocol inference is counted as `failed` of Unknown
+        let counter = Arc::new(L7ParseCounter::default());
+        stats_collector.register_countable(
+            &L7ParseStats {
+                id,
+                protocol: "Unknown",
+            },
+            Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+        );
+        l7_parse_counters[L7Protocol::Unknown as usize] = Some(counter);
         let system_time = get_timestamp(ntp_diff.load(Ordering::Relaxed));
         let start_time = system_time - config.packet_delay - Duration::from_secs(1);
         let time_set_slot_size = config.hash_slots as usize / time_window_size;
@@ -268,7 +312,7 @@ impl FlowMap {
             time_set_slot_size,
             tagged_flow_allocator: {
                 let n = (config.batched_buffer_size_limit - 1) / mem::size_of::<TaggedFlow>();
-                let allocator = Allocator::new(n.max(1));
+                let allocator = Allocator::with_pool(n.max(1), config.batched_buffer_pool_size);
                 stats_collector.register_countable(
                     &AllocatorStats {
                         id,
@@ -280,7 +324,7 @@ impl FlowMap {
             },
             l7_stats_allocator: {
                 let n = (config.batched_buffer_size_limit - 1) / mem::size_of::<L7Stats>();
-                let allocator = Allocator::new(n.max(1));
+                let allocator = Allocator::with_pool(n.max(1), config.batched_buffer_pool_size);
                 stats_collector.register_countable(
                     &AllocatorStats {
                         id,
@@ -315,6 +359,7 @@ impl FlowMap {
                     })
                     .collect(),
             ),
+            l7_parse_counters,
             time_key_buffer: None,
             plugin_digest: 0, // force initial load
             wasm_vm: Default::default(),
@@ -1644,7 +1689,8 @@ impl FlowMap {
             } else {
                 (0, local_epc_id)
             };
-            match log.parse(
+            let truncated = meta_packet.is_l4_payload_truncated();
+            let result = log.parse(
                 flow_config,
                 log_parser_config,
                 meta_packet,
@@ -1655,7 +1701,9 @@ impl FlowMap {
                 local_epc,
                 remote_epc,
                 &self.l7_protocol_checker,
-            ) {
+            );
+            self.count_l7_parse(log.l7_protocol_enum.get_l7_protocol(), truncated, &result);
+            match result {
                 Ok(info) => {
                     if node.tagged_flow.flow.direction_score != ServiceTable::MAX_SCORE {
                         // After perf.parse() success, meta_packet's direction is determined.
@@ -1686,6 +1734,48 @@ impl FlowMap {
         }
     }
 
+    fn count_l7_parse(
+        &self,
+        protocol: L7Protocol,
+        truncated: bool,
+        result: &Result<crate::common::l7_protocol_log::L7ParseResult, Error>,
+    ) {
+        let Some(c) = self.l7_parse_counters[protocol as usize].as_ref() else {
+            return;
+        };
+        match (protocol, result) {
+            (L7Protocol::Unknown, Err(Error::L7ProtocolUnknown)) => {
+                c.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            (L7Protocol::Unknown, _) => return,
+            (_, Ok(_)) => {
+                c.parsed.fetch_add(1, Ordering::Relaxed);
+            }
+            (_, Err(Error::L7ProtocolDisabled)) => {
+                c.skipped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            // not l7 parse errors
+            (
+                _,
+                Err(
+                    Error::InvalidPacketTimestamp
+                    | Error::RetransPacket
+                    | Error::ZeroPayloadLen
+                    | Error::InvalidIpProtocol
+                    | Error::L7ProtocolCheckLimit
+                    | Error::L7ProtocolParseLimit,
+                ),
+            ) => return,
+            (_, Err(_)) => {
+                c.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if truncated {
+            c.truncated.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     fn new_tcp_node(&mut self, config: &Config, meta_packet: &mut MetaPacket) -> Box<FlowNode> {
         let flow_config = &config.flow;
         let collector_config = &config.collector;
@@ -2383,6 +2473,41 @@ pub struct FlowMapCounter {
     pub l7_timeout_cache_len: AtomicU64, // the number of struct L7PerfCache::timeout_cache length
 }
 
+#[derive(Default)]
+pub struct L7ParseCounter {
+    parsed: AtomicU64,    // payloads parsed successfully
+    skipped: AtomicU64,   // payloads skipped because the protocol is not in l7-protocol-enabled
+    failed: AtomicU64,    // payloads failed to parse
+    truncated: AtomicU64, // parsed or failed payloads truncated by capture, consider raising snap_len
+}
+
+impl RefCountable for L7ParseCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "parsed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.parsed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "skipped",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.skipped.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "failed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.failed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "truncated",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.truncated.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
 impl RefCountable for FlowMapCounter {
     fn get_counters(&self) -> Vec<Counter> {
         let concurrent = self.concurrent.load(Ordering::Relaxed);
@@ -2508,7 +2633,11 @@ pub fn _new_flow_map_and_receiver(
         Arc::new(AtomicI64::new(0)),
         &config.flow,
         Some(packet_sequence_queue), // Enterprise Edition Feature: packet-sequence
-        Arc::new(stats::Collector::new("", Arc::new(AtomicI64::new(0)))),
+        Arc::new(stats::Collector::new(
+            "",
+            vec![],
+            Arc::new(AtomicI64::new(0)),
+        )),
         false,
     );
 
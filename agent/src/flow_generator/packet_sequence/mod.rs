@@ -18,4 +18,4 @@
 mod consts;
 mod parser;
 
-pub use parser::PacketSequenceParser;
+pub use parser::{PacketSequenceCounter, PacketSequenceParser};
@@ -16,10 +16,11 @@
 
 use std::{
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Mutex,
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use log::{info, warn};
@@ -28,14 +29,43 @@ use packet_sequence_block::BoxedPacketSequenceBlock;
 // Enterprise Edition Feature: packet-sequence
 use super::consts;
 
-use public::queue::{DebugSender, Error, Receiver};
+use public::{
+    counter::{Counter, CounterType, CounterValue, RefCountable},
+    queue::{DebugSender, Error, Receiver},
+};
+
+#[derive(Default)]
+pub struct PacketSequenceCounter {
+    blocks: AtomicU64, // blocks sent to output queue
+    bytes: AtomicU64,  // total size of blocks sent, divided by blocks for bytes per block
+}
+
+impl RefCountable for PacketSequenceCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "blocks",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.blocks.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.bytes.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
 
 pub struct PacketSequenceParser {
     input_queue: Arc<Receiver<Box<packet_sequence_block::PacketSequenceBlock>>>,
     output_queue: DebugSender<BoxedPacketSequenceBlock>,
     id: u32,
+    // zero to send blocks as soon as they are received
+    flush_interval: Duration,
     running: Arc<AtomicBool>,
     thread: Mutex<Option<JoinHandle<()>>>,
+    counter: Arc<PacketSequenceCounter>,
 }
 
 impl PacketSequenceParser {
@@ -43,14 +73,21 @@ impl PacketSequenceParser {
         input_queue: Receiver<Box<packet_sequence_block::PacketSequenceBlock>>,
         output_queue: DebugSender<BoxedPacketSequenceBlock>,
         id: u32,
-    ) -> Self {
-        PacketSequenceParser {
-            input_queue: Arc::new(input_queue),
-            output_queue,
-            id,
-            running: Default::default(),
-            thread: Mutex::new(None),
-        }
+        flush_interval: Duration,
+    ) -> (Self, Arc<PacketSequenceCounter>) {
+        let counter: Arc<PacketSequenceCounter> = Default::default();
+        (
+            PacketSequenceParser {
+                input_queue: Arc::new(input_queue),
+                output_queue,
+                id,
+                flush_interval,
+                running: Default::default(),
+                thread: Mutex::new(None),
+                counter: counter.clone(),
+            },
+            counter,
+        )
     }
 
     pub fn start(&self) {
@@ -61,26 +98,47 @@ impl PacketSequenceParser {
         let running = self.running.clone();
         let input_queue = self.input_queue.clone();
         let output_queue = self.output_queue.clone();
+        let flush_interval = self.flush_interval;
+        let counter = self.counter.clone();
 
         let thread = thread::Builder::new()
             .name("packet-sequence-parser".to_owned())
             .spawn(move || {
                 let mut blocks = Vec::with_capacity(consts::QUEUE_BATCH_SIZE);
                 let mut batch = Vec::new();
+                let mut last_flush = Instant::now();
+                let timeout = if flush_interval.is_zero() {
+                    consts::RCV_TIMEOUT
+                } else {
+                    flush_interval.min(consts::RCV_TIMEOUT)
+                };
                 while running.load(Ordering::Relaxed) {
-                    match input_queue.recv_all(&mut blocks, Some(consts::RCV_TIMEOUT)) {
+                    match input_queue.recv_all(&mut blocks, Some(timeout)) {
                         Ok(_) => {
                             batch.reserve(blocks.len());
-                            batch.extend(blocks.drain(..).map(|f| BoxedPacketSequenceBlock(f)));
-                            if let Err(e) = output_queue.send_all(&mut batch) {
-                                warn!("packet sequence block to queue failed, because {:?}", e);
-                                batch.clear();
+                            for block in blocks.drain(..) {
+                                counter.blocks.fetch_add(1, Ordering::Relaxed);
+                                counter
+                                    .bytes
+                                    .fetch_add(block.size() as u64, Ordering::Relaxed);
+                                batch.push(BoxedPacketSequenceBlock(block));
                             }
                         }
-                        Err(Error::Timeout) => continue,
+                        Err(Error::Timeout) => (),
                         Err(Error::Terminated(..)) => break,
                         Err(Error::BatchTooLarge(_)) => unreachable!(),
                     };
+                    if batch.is_empty()
+                        || (batch.len() < consts::QUEUE_BATCH_SIZE
+                            && last_flush.elapsed() < flush_interval)
+                    {
+                        continue;
+                    }
+                    last_flush = Instant::now();
+                    if let Err(e) = output_queue.send_all(&mut batch) {
+                        warn!("packet sequence block to queue failed, because {:?}", e);
+                        batch.clear();
+                    }
                 }
             })
             .unwrap();
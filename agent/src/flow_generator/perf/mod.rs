@@ -454,6 +454,12 @@ impl FlowLog {
         }
 
         if self.l7_protocol_log_parser.is_some() {
+            // protocols from eBPF or app table are not filtered by L7ProtocolChecker
+            if let L7ProtocolEnum::L7Protocol(p) = &self.l7_protocol_enum {
+                if log_parser_config.l7_protocol_enabled_bitmap.is_disabled(*p) {
+                    return Err(Error::L7ProtocolDisabled);
+                }
+            }
             return self.l7_parse_log(
                 flow_config,
                 log_parser_config,
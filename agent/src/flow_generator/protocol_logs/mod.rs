@@ -67,9 +67,10 @@ use crate::{
         Timestamp,
     },
     metric::document::TapSide,
+    sender::{flow_balance_key, hash_field_bytes, hash_field_str, hash_field_u32},
 };
 use public::proto::flow_log;
-use public::sender::{SendMessageType, Sendable};
+use public::sender::{FieldFilter, SendMessageType, Sendable};
 use public::utils::net::MacAddr;
 
 const NANOS_PER_MICRO: u64 = 1000;
@@ -383,11 +384,42 @@ impl AppProtoLogsBaseInfo {
     }
 }
 
+// Optional l7_flow_log fields which can be excluded from export
+pub const L7_EXPORT_FIELDS: &[&str] = &[
+    "request_type",
+    "request_domain",
+    "request_resource",
+    "endpoint",
+    "response_exception",
+    "response_result",
+    "trace_id",
+    "span_id",
+    "parent_span_id",
+    "http_proxy_client",
+    "x_request_id",
+    "user_agent",
+    "referer",
+    "attributes",
+    "metrics",
+];
+// l7_flow_log fields which can be hashed before export
+pub const L7_HASHABLE_FIELDS: &[&str] = &[
+    "ip_src",
+    "ip_dst",
+    "request_domain",
+    "request_resource",
+    "endpoint",
+    "http_proxy_client",
+    "x_request_id",
+    "user_agent",
+    "referer",
+];
+
 #[derive(Debug)]
 pub struct BoxAppProtoLogsData(pub Box<MetaAppProto>);
 
-impl Sendable for BoxAppProtoLogsData {
-    fn encode(self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+impl BoxAppProtoLogsData {
+    fn into_pb(self) -> flow_log::AppProtoLogsData {
         let mut pb_proto_logs_data = flow_log::AppProtoLogsData {
             base: Some(self.0.base_info.into()),
             direction_score: self.0.direction_score as u32,
@@ -396,11 +428,126 @@ impl Sendable for BoxAppProtoLogsData {
 
         let log: L7ProtocolSendLog = self.0.l7_info.into();
         log.fill_app_proto_log(&mut pb_proto_logs_data);
+        pb_proto_logs_data
+    }
+
+    fn filter_fields(pb: &mut flow_log::AppProtoLogsData, filter: &FieldFilter) {
+        let key = filter.hash_key();
+        if let Some(base) = pb.base.as_mut() {
+            if filter.hashed("ip_src") {
+                hash_field_u32(key, &mut base.ip_src);
+                hash_field_bytes(key, &mut base.ip6_src);
+            }
+            if filter.hashed("ip_dst") {
+                hash_field_u32(key, &mut base.ip_dst);
+                hash_field_bytes(key, &mut base.ip6_dst);
+            }
+        }
+        if let Some(req) = pb.req.as_mut() {
+            if filter.excluded("request_type") {
+                req.req_type.clear();
+            }
+            if filter.excluded("request_domain") {
+                req.domain.clear();
+            } else if filter.hashed("request_domain") {
+                hash_field_str(key, &mut req.domain);
+            }
+            if filter.excluded("request_resource") {
+                req.resource.clear();
+            } else if filter.hashed("request_resource") {
+                hash_field_str(key, &mut req.resource);
+            }
+            if filter.excluded("endpoint") {
+                req.endpoint.clear();
+            } else if filter.hashed("endpoint") {
+                hash_field_str(key, &mut req.endpoint);
+            }
+        }
+        if let Some(resp) = pb.resp.as_mut() {
+            if filter.excluded("response_exception") {
+                resp.exception.clear();
+            }
+            if filter.excluded("response_result") {
+                resp.result.clear();
+            }
+        }
+        if let Some(trace_info) = pb.trace_info.as_mut() {
+            if filter.excluded("trace_id") {
+                trace_info.trace_id.clear();
+            }
+            if filter.excluded("span_id") {
+                trace_info.span_id.clear();
+            }
+            if filter.excluded("parent_span_id") {
+                trace_info.parent_span_id.clear();
+            }
+        }
+        if let Some(ext_info) = pb.ext_info.as_mut() {
+            if filter.excluded("http_proxy_client") {
+                ext_info.client_ip.clear();
+            } else if filter.hashed("http_proxy_client") {
+                hash_field_str(key, &mut ext_info.client_ip);
+            }
+            if filter.excluded("x_request_id") {
+                ext_info.x_request_id_0.clear();
+                ext_info.x_request_id_1.clear();
+            } else if filter.hashed("x_request_id") {
+                hash_field_str(key, &mut ext_info.x_request_id_0);
+                hash_field_str(key, &mut ext_info.x_request_id_1);
+            }
+            if filter.excluded("user_agent") {
+                ext_info.http_user_agent.clear();
+            } else if filter.hashed("user_agent") {
+                hash_field_str(key, &mut ext_info.http_user_agent);
+            }
+            if filter.excluded("referer") {
+                ext_info.http_referer.clear();
+            } else if filter.hashed("referer") {
+                hash_field_str(key, &mut ext_info.http_referer);
+            }
+            if filter.excluded("attributes") {
+                ext_info.attribute_names.clear();
+                ext_info.attribute_values.clear();
+            }
+            if filter.excluded("metrics") {
+                ext_info.metrics_names.clear();
+                ext_info.metrics_values.clear();
+            }
+        }
+    }
+}
+
+impl Sendable for BoxAppProtoLogsData {
+    fn encode(self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        let pb_proto_logs_data = self.into_pb();
+        pb_proto_logs_data
+            .encode(buf)
+            .map(|_| pb_proto_logs_data.encoded_len())
+    }
+
+    fn encode_filtered(
+        self,
+        buf: &mut Vec<u8>,
+        filter: &FieldFilter,
+    ) -> Result<usize, prost::EncodeError> {
+        let mut pb_proto_logs_data = self.into_pb();
+        Self::filter_fields(&mut pb_proto_logs_data, filter);
         pb_proto_logs_data
             .encode(buf)
             .map(|_| pb_proto_logs_data.encoded_len())
     }
 
+    fn balance_key(&self) -> Option<u64> {
+        let info = &self.0.base_info;
+        Some(flow_balance_key(
+            info.ip_src,
+            info.ip_dst,
+            info.port_src,
+            info.port_dst,
+            u8::from(info.protocol),
+        ))
+    }
+
     fn file_name(&self) -> &str {
         "l7_flow_log"
     }
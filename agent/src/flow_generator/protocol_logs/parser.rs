@@ -22,7 +22,6 @@ use std::{
         atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
         Arc, Mutex,
     },
-    thread,
     thread::JoinHandle,
     time::Duration,
 };
@@ -46,7 +45,10 @@ use crate::{
     flow_generator::{error::Result, FLOW_METRICS_PEER_DST, FLOW_METRICS_PEER_SRC},
     metric::document::TapSide,
     rpc::get_timestamp,
-    utils::stats::{Counter, CounterType, CounterValue, RefCountable},
+    utils::{
+        stats::{Counter, CounterType, CounterValue, RefCountable},
+        thread_builder,
+    },
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use public::utils::string::get_string_from_chars;
@@ -312,8 +314,10 @@ pub struct SessionAggrCounter {
     merge: AtomicU64,
     cached: AtomicU64, // It is used to record the number of logs that exist in session queue
     cached_request_resource: AtomicU64, // It is used to record the cache request-resource occupation space, the unit is B
+    throttle_admit: AtomicU64,
     throttle_drop: AtomicU64,
     over_limit: AtomicU64, // It is used to record the number of logs that exceed the limit to the forced flush
+    sampling_drop: AtomicU64,
 }
 
 impl RefCountable for SessionAggrCounter {
@@ -344,6 +348,11 @@ impl RefCountable for SessionAggrCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.cached_request_resource.load(Ordering::Relaxed)),
             ),
+            (
+                "throttle-admit",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.throttle_admit.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "throttle-drop",
                 CounterType::Counted,
@@ -354,6 +363,11 @@ impl RefCountable for SessionAggrCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.over_limit.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "sampling-drop",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.sampling_drop.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
@@ -783,16 +797,29 @@ impl SessionQueue {
             return;
         }
 
+        let sampling_rate = self.config.load().l7_log_sampling_rate;
+        if sampling_rate > 1 && !Self::sampled(item.base_info.flow_id, sampling_rate) {
+            self.counter.sampling_drop.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
         if !self.throttle.acquire(item.base_info.start_time.into()) {
             self.counter.throttle_drop.fetch_add(1, Ordering::Relaxed);
             return;
         }
+        self.counter.throttle_admit.fetch_add(1, Ordering::Relaxed);
 
         if let Err(e) = self.output_queue.send(BoxAppProtoLogsData(item)) {
             warn!("output queue failed to send data, because: {:?}", e);
         }
     }
 
+    // 1 in `rate` flows is sampled, stable for the same flow
+    fn sampled(flow_id: u64, rate: u32) -> bool {
+        // flow ids are mostly sequential, mix the bits before taking modulo
+        (flow_id.wrapping_mul(0x9e3779b97f4a7c15) >> 32) % rate as u64 == 0
+    }
+
     fn send_all(&mut self, items: Vec<Box<MetaAppProto>>) {
         for item in items {
             self.send(item);
@@ -848,8 +875,8 @@ impl SessionAggregator {
         let config = self.config.clone();
         let ntp_diff = self.ntp_diff.clone();
 
-        let thread = thread::Builder::new()
-            .name("protocol-logs-parser".to_owned())
+        let name = format!("log-parser-{}", self.id);
+        let thread = thread_builder(name, config.load().thread_stack_size)
             .spawn(move || {
                 let mut session_queue =
                     SessionQueue::new(counter, output_queue, config.clone(), ntp_diff);
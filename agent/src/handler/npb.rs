@@ -14,11 +14,13 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 use std::vec;
 
-use log::info;
+use log::{info, warn};
 use npb_pcap_policy::NpbTunnelType;
 use pnet::packet::{
     ethernet::{EtherTypes, MutableEthernetPacket},
@@ -37,14 +39,14 @@ use crate::common::{
     VXLAN_HEADER_SIZE,
 };
 use crate::config::NpbConfig;
-use crate::sender::npb_sender::{NpbArpTable, NpbPacketSender};
+use crate::sender::npb_sender::{NpbArpTable, NpbEncapsulation, NpbPacketSender};
 use crate::utils::stats::{self, QueueStats, StatsOption};
 use npb_handler::{NpbHandler, NpbHandlerCounter, NpbHeader, StatsNpbHandlerCounter, NOT_SUPPORT};
 use public::{
     counter::Countable,
     debug::QueueDebugger,
     leaky_bucket::LeakyBucket,
-    proto::trident::VlanMode,
+    proto::trident::{SocketType, VlanMode},
     queue::{bounded_with_debug, DebugSender},
     utils::net::MacAddr,
 };
@@ -200,6 +202,49 @@ impl NpbBuilder {
         vec![u8::from(IpProtocol::TCP); packet_size]
     }
 
+    fn select_encapsulations(config: &NpbConfig) -> HashMap<IpAddr, NpbEncapsulation> {
+        let mut encapsulations = HashMap::new();
+        for c in config.encapsulations.iter() {
+            let (destination, encapsulation) = match NpbEncapsulation::parse(c) {
+                Ok(e) => e,
+                Err(e) => {
+                    warn!("Ignore npb encapsulation: {}.", e);
+                    continue;
+                }
+            };
+            match encapsulation {
+                NpbEncapsulation::Vlan { .. } if config.socket_type != SocketType::RawUdp => {
+                    warn!(
+                        "Ignore npb encapsulation {} of {}: requires raw udp socket.",
+                        encapsulation, destination
+                    );
+                    continue;
+                }
+                _ if config.socket_type == SocketType::Tcp => {
+                    warn!(
+                        "Ignore npb encapsulation {} of {}: not supported by tcp socket.",
+                        encapsulation, destination
+                    );
+                    continue;
+                }
+                _ => {}
+            }
+            if encapsulations.contains_key(&destination) {
+                warn!(
+                    "Ignore npb encapsulation {} of {}: duplicated destination.",
+                    encapsulation, destination
+                );
+                continue;
+            }
+            info!(
+                "Npb destination {} uses encapsulation {}.",
+                destination, encapsulation
+            );
+            encapsulations.insert(destination, encapsulation);
+        }
+        encapsulations
+    }
+
     pub fn on_config_change(&mut self, config: &NpbConfig, queue_debugger: &QueueDebugger) {
         if self.npb_packet_sender.is_none() {
             return;
@@ -223,6 +268,8 @@ impl NpbBuilder {
             self.id,
             receiver,
             config,
+            self.bps_limit.clone(),
+            Self::select_encapsulations(config),
             self.arp.clone(),
             self.stats_collector.clone(),
         ));
@@ -278,6 +325,8 @@ impl NpbBuilder {
                 id,
                 receiver,
                 &config,
+                npb_bps_limit.clone(),
+                Self::select_encapsulations(config),
                 arp.clone(),
                 stats_collector.clone(),
             ))),
@@ -438,4 +487,54 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn test_select_encapsulations() {
+        use crate::config::{NpbEncapsulationConfig, NpbEncapsulationType};
+
+        let config = NpbConfig {
+            socket_type: SocketType::RawUdp,
+            output_vlan: 0,
+            encapsulations: vec![
+                NpbEncapsulationConfig {
+                    destination: "10.1.1.1".to_string(),
+                    encapsulation_type: NpbEncapsulationType::Vxlan,
+                    vni: Some(100),
+                    ..Default::default()
+                },
+                NpbEncapsulationConfig {
+                    destination: "10.1.1.2".to_string(),
+                    encapsulation_type: NpbEncapsulationType::Erspan,
+                    vni: Some(100),
+                    erspan_session_id: Some(10),
+                    ..Default::default()
+                },
+                NpbEncapsulationConfig {
+                    destination: "10.1.1.3".to_string(),
+                    encapsulation_type: NpbEncapsulationType::Vlan,
+                    vlan_id: Some(10),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let encapsulations = NpbBuilder::select_encapsulations(&config);
+        assert_eq!(encapsulations.len(), 2);
+        assert_eq!(
+            encapsulations.get(&"10.1.1.1".parse::<IpAddr>().unwrap()),
+            Some(&NpbEncapsulation::Vxlan { vni: 100 })
+        );
+        assert_eq!(
+            encapsulations.get(&"10.1.1.3".parse::<IpAddr>().unwrap()),
+            Some(&NpbEncapsulation::Vlan { vlan_id: 10 })
+        );
+
+        let config = NpbConfig {
+            socket_type: SocketType::Udp,
+            ..config
+        };
+        let encapsulations = NpbBuilder::select_encapsulations(&config);
+        assert_eq!(encapsulations.len(), 1);
+        assert!(encapsulations.contains_key(&"10.1.1.1".parse::<IpAddr>().unwrap()));
+    }
 }
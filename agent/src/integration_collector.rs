@@ -17,7 +17,7 @@
 use std::collections::HashMap;
 use std::fmt::{self, Debug, Formatter};
 use std::io::{Read, Write};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
 use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU16, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
@@ -27,7 +27,7 @@ use flate2::{read::GzDecoder, write::ZlibEncoder, Compression};
 use http::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use http::HeaderMap;
 use hyper::{
-    body::{aggregate, Buf},
+    body::{Buf, Bytes, HttpBody},
     server::conn::AddrStream,
     service::{make_service_fn, service_fn},
     Body, Method, Request, Response, Server, StatusCode,
@@ -75,7 +75,7 @@ use public::{
         trident::Exception,
     },
     queue::DebugSender,
-    utils::net::ipv6_enabled,
+    utils::net::{addr_list, ipv6_enabled},
 };
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
@@ -181,15 +181,71 @@ impl Sendable for Profile {
     }
 }
 
-fn decode_metric(mut whole_body: impl Buf, headers: &HeaderMap) -> Result<Vec<u8>, GenericError> {
+fn payload_too_large(counter: &CompressedMetric) -> Response<Body> {
+    counter.body_too_large.fetch_add(1, Ordering::Relaxed);
+    Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BodySizeLimit {
+    pub max_body_size: usize,
+    pub max_decompressed_size: usize,
+    // 0 means no limit on decompression ratio
+    pub max_decompression_ratio: usize,
+}
+
+impl BodySizeLimit {
+    // bodies are always allowed to decompress to max_body_size, larger ones are limited by
+    // both the decompression ratio and max_decompressed_size
+    fn decompressed_limit(&self, compressed_size: usize) -> usize {
+        if self.max_decompression_ratio == 0 {
+            return self.max_decompressed_size;
+        }
+        compressed_size
+            .saturating_mul(self.max_decompression_ratio)
+            .max(self.max_body_size)
+            .min(self.max_decompressed_size)
+    }
+}
+
+// decompression is aborted once the output exceeds the limit to protect against decompression bombs
+fn decode_metric(
+    mut whole_body: impl Buf,
+    headers: &HeaderMap,
+    body_limit: BodySizeLimit,
+    counter: &CompressedMetric,
+) -> Result<Vec<u8>, Response<Body>> {
     let metric = if headers
         .get(CONTENT_ENCODING)
         .filter(|&v| v == GZIP)
         .is_some()
     {
+        let compressed_size = whole_body.remaining();
+        let limit = body_limit.decompressed_limit(compressed_size);
         let mut metric = vec![];
-        let mut gz = GzDecoder::new(whole_body.reader());
-        gz.read_to_end(&mut metric)?;
+        let mut gz = GzDecoder::new(whole_body.reader()).take(limit as u64 + 1);
+        if let Err(e) = gz.read_to_end(&mut metric) {
+            return Err(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(e.to_string().into())
+                .unwrap());
+        }
+        if metric.len() > limit {
+            counter
+                .decompression_aborted
+                .fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "decompressed body exceeds {} bytes from {} bytes, aborted",
+                limit, compressed_size
+            );
+            return Err(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body("decompressed body too large".into())
+                .unwrap());
+        }
         metric
     } else {
         let mut metric = vec![0u8; whole_body.remaining()];
@@ -201,24 +257,38 @@ fn decode_metric(mut whole_body: impl Buf, headers: &HeaderMap) -> Result<Vec<u8
 }
 
 async fn aggregate_with_catch_exception(
-    body: Body,
+    mut body: Body,
+    max_size: usize,
+    counter: &CompressedMetric,
     exception_handler: &ExceptionHandler,
 ) -> Result<impl Buf, Response<Body>> {
-    aggregate(body).await.map_err(|e| {
-        if e.is_user() {
-            Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body(e.to_string().into())
-                .unwrap()
-        } else {
-            error!("integration collector error: {}", e);
-            exception_handler.set(Exception::IntegrationSocketError);
-            Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(e.to_string().into())
-                .unwrap()
+    // rejects early if content-length is known
+    if body.size_hint().lower() > max_size as u64 {
+        return Err(payload_too_large(counter));
+    }
+    let mut whole_body = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| {
+            if e.is_user() {
+                Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(e.to_string().into())
+                    .unwrap()
+            } else {
+                error!("integration collector error: {}", e);
+                exception_handler.set(Exception::IntegrationSocketError);
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(e.to_string().into())
+                    .unwrap()
+            }
+        })?;
+        if whole_body.len() + chunk.len() > max_size {
+            return Err(payload_too_large(counter));
         }
-    })
+        whole_body.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(whole_body))
 }
 
 // for log capture from vector
@@ -594,6 +664,7 @@ async fn handler(
     application_log_sender: DebugSender<ApplicationLog>,
     exception_handler: ExceptionHandler,
     compressed: bool,
+    body_limit: BodySizeLimit,
     counter: Arc<CompressedMetric>,
     local_epc_id: u32,
     policy_getter: Arc<PolicyGetter>,
@@ -620,13 +691,24 @@ async fn handler(
                 return Ok(Response::builder().body(Body::empty()).unwrap());
             }
             let (part, body) = req.into_parts();
-            let whole_body = match aggregate_with_catch_exception(body, &exception_handler).await {
+            let whole_body = match aggregate_with_catch_exception(
+                body,
+                body_limit.max_body_size,
+                &counter,
+                &exception_handler,
+            )
+            .await
+            {
                 Ok(b) => b,
                 Err(e) => {
                     return Ok(e);
                 }
             };
-            let tracing_data = decode_metric(whole_body, &part.headers)?;
+            let tracing_data = match decode_metric(whole_body, &part.headers, body_limit, &counter)
+            {
+                Ok(m) => m,
+                Err(e) => return Ok(e),
+            };
             let time_diff = time_diff.load(Ordering::Relaxed);
             let mut decode_data = decode_otel_trace_data(
                 peer_addr,
@@ -705,13 +787,19 @@ async fn handler(
                 }
             }
 
-            let mut whole_body =
-                match aggregate_with_catch_exception(req.into_body(), &exception_handler).await {
-                    Ok(b) => b,
-                    Err(e) => {
-                        return Ok(e);
-                    }
-                };
+            let mut whole_body = match aggregate_with_catch_exception(
+                req.into_body(),
+                body_limit.max_body_size,
+                &counter,
+                &exception_handler,
+            )
+            .await
+            {
+                Ok(b) => b,
+                Err(e) => {
+                    return Ok(e);
+                }
+            };
             let mut metric = vec![0u8; whole_body.remaining()];
             whole_body.copy_to_slice(metric.as_mut_slice());
 
@@ -734,13 +822,23 @@ async fn handler(
                 return Ok(Response::builder().body(Body::empty()).unwrap());
             }
             let (part, body) = req.into_parts();
-            let whole_body = match aggregate_with_catch_exception(body, &exception_handler).await {
+            let whole_body = match aggregate_with_catch_exception(
+                body,
+                body_limit.max_body_size,
+                &counter,
+                &exception_handler,
+            )
+            .await
+            {
                 Ok(b) => b,
                 Err(e) => {
                     return Ok(e);
                 }
             };
-            let metric = decode_metric(whole_body, &part.headers)?;
+            let metric = match decode_metric(whole_body, &part.headers, body_limit, &counter) {
+                Ok(m) => m,
+                Err(e) => return Ok(e),
+            };
             if log_enabled!(Level::Debug) {
                 if let Ok(r) = String::from_utf8(metric.clone()) {
                     debug!("telegraf metric: {}", r)
@@ -761,13 +859,23 @@ async fn handler(
                 parse_profile_query(query, &mut profile);
             }
             let (part, body) = req.into_parts();
-            let whole_body = match aggregate_with_catch_exception(body, &exception_handler).await {
+            let whole_body = match aggregate_with_catch_exception(
+                body,
+                body_limit.max_body_size,
+                &counter,
+                &exception_handler,
+            )
+            .await
+            {
                 Ok(b) => b,
                 Err(e) => {
                     return Ok(e);
                 }
             };
-            profile.data = decode_metric(whole_body, &part.headers)?;
+            profile.data = match decode_metric(whole_body, &part.headers, body_limit, &counter) {
+                Ok(m) => m,
+                Err(e) => return Ok(e),
+            };
             profile.ip = match peer_addr.ip() {
                 IpAddr::V4(ip4) => ip4.octets().to_vec(),
                 IpAddr::V6(ip6) => ip6.octets().to_vec(),
@@ -789,13 +897,23 @@ async fn handler(
                 return Ok(Response::builder().body(Body::empty()).unwrap());
             }
             let (part, body) = req.into_parts();
-            let whole_body = match aggregate_with_catch_exception(body, &exception_handler).await {
+            let whole_body = match aggregate_with_catch_exception(
+                body,
+                body_limit.max_body_size,
+                &counter,
+                &exception_handler,
+            )
+            .await
+            {
                 Ok(b) => b,
                 Err(e) => {
                     return Ok(e);
                 }
             };
-            let log_data = decode_metric(whole_body, &part.headers)?;
+            let log_data = match decode_metric(whole_body, &part.headers, body_limit, &counter) {
+                Ok(m) => m,
+                Err(e) => return Ok(e),
+            };
             if let Err(e) = application_log_sender.send(ApplicationLog(log_data)) {
                 warn!(
                     "application_log_sender failed to send data, because {:?}",
@@ -851,6 +969,8 @@ fn parse_profile_query(query: &str, profile: &mut metric::Profile) {
 struct CompressedMetric {
     compressed: AtomicU64,   // unit (bytes)
     uncompressed: AtomicU64, // unit (bytes)
+    body_too_large: AtomicU64,
+    decompression_aborted: AtomicU64,
 }
 
 #[derive(Default)]
@@ -860,9 +980,13 @@ pub struct IntegrationCounter {
 
 impl OwnedCountable for IntegrationCounter {
     fn get_counters(&self) -> Vec<Counter> {
-        let (compressed, uncomressed) = (
+        let (compressed, uncomressed, body_too_large, decompression_aborted) = (
             self.metrics.compressed.swap(0, Ordering::Relaxed),
             self.metrics.uncompressed.swap(0, Ordering::Relaxed),
+            self.metrics.body_too_large.swap(0, Ordering::Relaxed),
+            self.metrics
+                .decompression_aborted
+                .swap(0, Ordering::Relaxed),
         );
         vec![
             (
@@ -885,6 +1009,16 @@ impl OwnedCountable for IntegrationCounter {
                     uncomressed as f64 / compressed as f64
                 }),
             ),
+            (
+                "body_too_large",
+                CounterType::Counted,
+                CounterValue::Unsigned(body_too_large),
+            ),
+            (
+                "decompression_aborted",
+                CounterType::Counted,
+                CounterValue::Unsigned(decompression_aborted),
+            ),
         ]
     }
 
@@ -905,11 +1039,13 @@ pub struct MetricServer {
     telegraf_sender: DebugSender<TelegrafMetric>,
     profile_sender: DebugSender<Profile>,
     application_log_sender: DebugSender<ApplicationLog>,
+    bind_address: Arc<Mutex<Option<IpAddr>>>,
     port: Arc<AtomicU16>,
     exception_handler: ExceptionHandler,
     server_shutdown_tx: Mutex<Option<mpsc::Sender<()>>>,
     counter: Arc<CompressedMetric>,
     compressed: Arc<AtomicBool>,
+    body_limit: BodySizeLimit,
     local_epc_id: u32,
     policy_getter: Arc<PolicyGetter>,
     time_diff: Arc<AtomicI64>,
@@ -931,9 +1067,11 @@ impl MetricServer {
         telegraf_sender: DebugSender<TelegrafMetric>,
         profile_sender: DebugSender<Profile>,
         application_log_sender: DebugSender<ApplicationLog>,
+        bind_address: Option<IpAddr>,
         port: u16,
         exception_handler: ExceptionHandler,
         compressed: bool,
+        body_limit: BodySizeLimit,
         local_epc_id: u32,
         policy_getter: PolicyGetter,
         time_diff: Arc<AtomicI64>,
@@ -951,12 +1089,14 @@ impl MetricServer {
                 runtime,
                 thread: Arc::new(Mutex::new(None)),
                 compressed: Arc::new(AtomicBool::new(compressed)),
+                body_limit,
                 otel_sender,
                 compressed_otel_sender,
                 prometheus_sender,
                 telegraf_sender,
                 profile_sender,
                 application_log_sender,
+                bind_address: Arc::new(Mutex::new(bind_address)),
                 port: Arc::new(AtomicU16::new(port)),
                 exception_handler,
                 server_shutdown_tx: Default::default(),
@@ -976,10 +1116,79 @@ impl MetricServer {
         )
     }
 
+    fn listen_addr(bind_address: Option<IpAddr>, port: u16) -> SocketAddr {
+        match bind_address {
+            Some(ip) => (ip, port).into(),
+            None if ipv6_enabled() => (Ipv6Addr::UNSPECIFIED, port).into(),
+            None => (Ipv4Addr::UNSPECIFIED, port).into(),
+        }
+    }
+
+    // Checks if the bind address is assigned to any interface of this host
+    pub fn check_bind_address(&self) -> Result<(), String> {
+        Self::validate_bind_address(*self.bind_address.lock().unwrap())
+    }
+
+    fn validate_bind_address(bind_address: Option<IpAddr>) -> Result<(), String> {
+        let Some(ip) = bind_address else {
+            return Ok(());
+        };
+        if ip.is_unspecified() || ip.is_loopback() {
+            return Ok(());
+        }
+        match addr_list() {
+            Ok(addrs) if addrs.iter().any(|a| a.ip_addr == ip) => Ok(()),
+            Ok(_) => Err(format!("bind address {} is not a local address", ip)),
+            Err(e) => Err(format!("check bind address {} failed: {}", ip, e)),
+        }
+    }
+
+    // Checks if the server is able to listen before start, the server retries in background
+    // if it fails to listen after start.
+    pub fn check_listen(&self) -> std::io::Result<()> {
+        let addr = Self::listen_addr(
+            *self.bind_address.lock().unwrap(),
+            self.port.load(Ordering::Acquire),
+        );
+        let mut tries = 0;
+        loop {
+            match TcpListener::bind(addr) {
+                Ok(_) => return Ok(()),
+                // the port may not have been released by a stopped server yet
+                Err(_) if tries < 2 => {
+                    tries += 1;
+                    sleep(Duration::from_secs(1));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     pub fn enable_compressed(&self, enable: bool) {
         self.compressed.store(enable, Ordering::Relaxed);
     }
 
+    pub fn set_bind_address(&self, bind_address: Option<IpAddr>) {
+        if let Err(e) = Self::validate_bind_address(bind_address) {
+            warn!(
+                "integration collector {}, keep bind address {:?}",
+                e,
+                self.bind_address.lock().unwrap()
+            );
+            self.exception_handler
+                .set(Exception::IntegrationSocketError);
+            return;
+        }
+        let old = std::mem::replace(&mut *self.bind_address.lock().unwrap(), bind_address);
+        if old != bind_address {
+            // bind address changes, resets server
+            info!("bind address changes to {:?}", bind_address);
+            if let Some(tx) = self.server_shutdown_tx.lock().unwrap().as_ref() {
+                let _ = self.runtime.block_on(tx.send(()));
+            }
+        }
+    }
+
     pub fn set_port(&self, port: u16) {
         if self.port.swap(port, Ordering::Release) != port {
             // port changes, resets server
@@ -1002,6 +1211,7 @@ impl MetricServer {
         let telegraf_sender = self.telegraf_sender.clone();
         let profile_sender = self.profile_sender.clone();
         let application_log_sender = self.application_log_sender.clone();
+        let bind_address = self.bind_address.clone();
         let port = self.port.clone();
         let monitor_port = Arc::new(AtomicU16::new(port.load(Ordering::Acquire)));
         let (mon_tx, mon_rx) = oneshot::channel();
@@ -1009,6 +1219,7 @@ impl MetricServer {
         let running = self.running.clone();
         let counter = self.counter.clone();
         let compressed = self.compressed.clone();
+        let body_limit = self.body_limit;
         let local_epc_id = self.local_epc_id.clone();
         let policy_getter = self.policy_getter.clone();
         let time_diff = self.time_diff.clone();
@@ -1019,8 +1230,12 @@ impl MetricServer {
         let external_metric_integration_disabled = self.external_metric_integration_disabled;
         let external_log_integration_disabled = self.external_log_integration_disabled;
         let (tx, mut rx) = mpsc::channel(8);
-        self.runtime
-            .spawn(Self::alive_check(monitor_port.clone(), tx.clone(), mon_rx));
+        self.runtime.spawn(Self::alive_check(
+            bind_address.clone(),
+            monitor_port.clone(),
+            tx.clone(),
+            mon_rx,
+        ));
         self.server_shutdown_tx.lock().unwrap().replace(tx);
 
         self.thread
@@ -1036,11 +1251,7 @@ impl MetricServer {
                         }
                         while let Ok(_) = rx.try_recv() {} // drain useless messages
                         let port = port.load(Ordering::Acquire);
-                        let addr = if ipv6_enabled() {
-                            (Ipv6Addr::UNSPECIFIED, port).into()
-                        } else {
-                            (Ipv4Addr::UNSPECIFIED, port).into()
-                        };
+                        let addr = Self::listen_addr(*bind_address.lock().unwrap(), port);
                         match Server::try_bind(&addr) {
                             Ok(s) => {
                                 monitor_port.store(port, Ordering::Release);
@@ -1113,6 +1324,7 @@ impl MetricServer {
                                     application_log_sender.clone(),
                                     exception_handler.clone(),
                                     compressed.load(Ordering::Relaxed),
+                                    body_limit,
                                     counter.clone(),
                                     local_epc_id,
                                     policy_getter.clone(),
@@ -1168,6 +1380,7 @@ impl MetricServer {
     // lose monitoring after a period of time. So first detect whether the issued port is listening,
     // If not listening, restart the collector and listen again. After finding the root cause, remove the following code
     async fn alive_check(
+        bind_address: Arc<Mutex<Option<IpAddr>>>,
         port: Arc<AtomicU16>,
         server_shutdown_tx: mpsc::Sender<()>,
         mut mon_rx: oneshot::Receiver<()>,
@@ -1177,7 +1390,11 @@ impl MetricServer {
             select! {
                 _ = ticker.tick() => {
                     let p = port.load(Ordering::Relaxed);
-                    if let Err(_) = TcpStream::connect(("localhost", p)) {
+                    let connected = match *bind_address.lock().unwrap() {
+                        Some(ip) if !ip.is_unspecified() => TcpStream::connect((ip, p)),
+                        _ => TcpStream::connect(("localhost", p)),
+                    };
+                    if let Err(_) = connected {
                         warn!(
                             "the port=({}) listen by the integration collector lost, restart the collector",
                             p
@@ -1190,3 +1407,138 @@ impl MetricServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use http::HeaderValue;
+
+    const LIMIT: BodySizeLimit = BodySizeLimit {
+        max_body_size: 1024,
+        max_decompressed_size: 8192,
+        max_decompression_ratio: 4,
+    };
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gzip_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(GZIP));
+        headers
+    }
+
+    #[test]
+    fn decompressed_limit() {
+        // small bodies can always decompress to max_body_size
+        assert_eq!(LIMIT.decompressed_limit(10), 1024);
+        assert_eq!(LIMIT.decompressed_limit(1000), 4000);
+        assert_eq!(LIMIT.decompressed_limit(4096), 8192);
+        assert_eq!(LIMIT.decompressed_limit(usize::MAX), 8192);
+        let unlimited_ratio = BodySizeLimit {
+            max_decompression_ratio: 0,
+            ..LIMIT
+        };
+        assert_eq!(unlimited_ratio.decompressed_limit(10), 8192);
+    }
+
+    #[test]
+    fn decode_metric_within_limit() {
+        let counter = CompressedMetric::default();
+        let data = vec![1u8; 1024];
+        let body = Bytes::from(gzip(&data));
+        assert_eq!(
+            decode_metric(body, &gzip_headers(), LIMIT, &counter).unwrap(),
+            data
+        );
+        let body = Bytes::from(data.clone());
+        assert_eq!(
+            decode_metric(body, &HeaderMap::new(), LIMIT, &counter).unwrap(),
+            data
+        );
+        assert_eq!(counter.decompression_aborted.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn decode_metric_decompressed_too_large() {
+        let counter = CompressedMetric::default();
+        // highly compressible data exceeds the decompression ratio
+        let body = Bytes::from(gzip(&[0u8; 1025]));
+        assert!(body.len() * LIMIT.max_decompression_ratio < 1025);
+        let resp = decode_metric(body, &gzip_headers(), LIMIT, &counter).unwrap_err();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        // incompressible data within the ratio exceeds max_decompressed_size
+        let mut seed = 1u32;
+        let data: Vec<u8> = (0..8193)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let body = Bytes::from(gzip(&data));
+        assert_eq!(
+            LIMIT.decompressed_limit(body.len()),
+            LIMIT.max_decompressed_size
+        );
+        let resp = decode_metric(body, &gzip_headers(), LIMIT, &counter).unwrap_err();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(counter.decompression_aborted.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn aggregate_body_size() {
+        let runtime = Runtime::new().unwrap();
+        let counter = CompressedMetric::default();
+        let exception_handler = ExceptionHandler::default();
+        let max_size = LIMIT.max_body_size;
+
+        let body = Body::from(vec![0u8; max_size]);
+        let whole_body = runtime
+            .block_on(aggregate_with_catch_exception(
+                body,
+                max_size,
+                &counter,
+                &exception_handler,
+            ))
+            .unwrap();
+        assert_eq!(whole_body.remaining(), max_size);
+
+        // rejected by content length
+        let body = Body::from(vec![0u8; max_size + 1]);
+        let resp = runtime
+            .block_on(aggregate_with_catch_exception(
+                body,
+                max_size,
+                &counter,
+                &exception_handler,
+            ))
+            .err()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // rejected while reading chunks without content length
+        let resp = runtime
+            .block_on(async {
+                let (mut tx, body) = Body::channel();
+                tokio::spawn(async move {
+                    for _ in 0..2 {
+                        if tx.send_data(Bytes::from(vec![0u8; 600])).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                aggregate_with_catch_exception(body, max_size, &counter, &exception_handler).await
+            })
+            .err()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(counter.body_too_large.load(Ordering::Relaxed), 2);
+    }
+}
@@ -37,6 +37,7 @@ mod plugin;
 mod policy;
 pub mod rpc;
 mod sender;
+pub mod self_test;
 pub mod trident;
 pub mod utils;
 
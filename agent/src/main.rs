@@ -14,8 +14,10 @@
  * limitations under the License.
  */
 
+use std::env;
 use std::panic;
 use std::path::Path;
+use std::process;
 
 use anyhow::Result;
 use clap::{ArgAction, Parser};
@@ -27,7 +29,7 @@ use ::deepflow_agent::*;
 
 #[derive(Parser)]
 struct Opts {
-    /// Specify config file location
+    /// Specify config file location, or a directory of *.yaml fragments merged in lexical order
     #[clap(
         short = 'f',
         visible_short_alias = 'c',
@@ -70,8 +72,19 @@ struct Opts {
     /// optionally `K8S_POD_IP_FOR_DEEPFLOW` can be set to override ip address.
     #[clap(long)]
     sidecar: bool,
+
+    /// Fail on invalid config file instead of falling back to legacy trident.yaml.
+    /// Can also be enabled by setting environment variable `DEEPFLOW_AGENT_NO_LEGACY_CONFIG` to `true`
+    #[clap(long)]
+    no_legacy_config: bool,
+
+    /// Run startup checks, print a pass/fail table and exit without starting capture
+    #[clap(long)]
+    self_test: bool,
 }
 
+const NO_LEGACY_CONFIG_ENV: &str = "DEEPFLOW_AGENT_NO_LEGACY_CONFIG";
+
 #[cfg(unix)]
 fn wait_on_signals() {
     let mut signals = Signals::new(TERM_SIGNALS).unwrap();
@@ -89,6 +102,14 @@ const VERSION_INFO: &'static trident::VersionInfo = &trident::VersionInfo {
     rev_count: env!("REV_COUNT"),
     compiler: env!("RUSTC_VERSION"),
     compile_time: env!("COMPILE_TIME"),
+    features: &[
+        #[cfg(target_os = "linux")]
+        "ebpf",
+        #[cfg(feature = "enterprise")]
+        "enterprise",
+        #[cfg(feature = "off_cpu")]
+        "off_cpu",
+    ],
 
     revision: concat!(
         env!("BRANCH"),
@@ -108,15 +129,24 @@ fn main() -> Result<()> {
         println!("{}", VERSION_INFO);
         return Ok(());
     }
+    let agent_mode = if opts.standalone {
+        trident::RunningMode::Standalone
+    } else {
+        trident::RunningMode::Managed
+    };
+    if opts.self_test {
+        let results = self_test::run(&opts.config_file, agent_mode)?;
+        process::exit(if self_test::print(&results) { 0 } else { 1 });
+    }
     let mut t = trident::Trident::start(
         &Path::new(&opts.config_file),
         VERSION_INFO,
-        if opts.standalone {
-            trident::RunningMode::Standalone
-        } else {
-            trident::RunningMode::Managed
-        },
+        agent_mode,
         opts.sidecar,
+        !opts.no_legacy_config
+            && !env::var(NO_LEGACY_CONFIG_ENV)
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or_default(),
     )?;
     wait_on_signals();
     t.stop();
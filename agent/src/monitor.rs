@@ -18,8 +18,9 @@ use std::{
     collections::HashMap,
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
-        Arc, Mutex, Weak,
+        Arc, Condvar, Mutex, Weak,
     },
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
@@ -29,9 +30,12 @@ use log::{debug, info, warn};
 use sysinfo::NetworkExt;
 use sysinfo::{get_current_pid, Pid, ProcessExt, ProcessRefreshKind, System, SystemExt};
 
-use crate::config::handler::EnvironmentAccess;
+use crate::config::handler::{EnvironmentAccess, EnvironmentConfig};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::utils::process::{get_fd_limit, get_fd_num};
 use crate::{
     error::{Error, Result},
+    exception::ExceptionHandler,
     utils::{
         process::{get_current_sys_free_memory_percentage, get_file_and_size_sum},
         stats::{
@@ -42,7 +46,10 @@ use crate::{
 };
 #[cfg(target_os = "linux")]
 use public::netns::{self, NsFile};
-use public::utils::net::{link_list, Link};
+use public::{
+    proto::trident::Exception,
+    utils::net::{link_list, Link},
+};
 
 #[derive(Default)]
 struct NetMetricArg {
@@ -251,6 +258,32 @@ impl RefCountable for SysStatusBroker {
                 warn!("get file and size sum failed: {:?}", e);
             }
         }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            match get_fd_num() {
+                Ok(fd_num) => metrics.push((
+                    "fd_count",
+                    CounterType::Gauged,
+                    CounterValue::Unsigned(fd_num as u64),
+                )),
+                Err(e) => warn!("get fd num failed: {}", e),
+            }
+            match get_fd_limit() {
+                Ok((soft, hard)) => {
+                    metrics.push((
+                        "fd_soft_limit",
+                        CounterType::Gauged,
+                        CounterValue::Unsigned(soft),
+                    ));
+                    metrics.push((
+                        "fd_hard_limit",
+                        CounterType::Gauged,
+                        CounterValue::Unsigned(hard),
+                    ));
+                }
+                Err(e) => warn!("{}", e),
+            }
+        }
         match system_guard.process(self.pid) {
             Some(process) => {
                 let cpu_usage = process.cpu_usage() as f64;
@@ -321,6 +354,102 @@ impl stats::Module for NetStats<'_> {
     }
 }
 
+// Checks process resource usage against monitor thresholds, raising exceptions when exceeded
+struct Sampler {
+    system: System,
+    pid: Pid,
+    config: EnvironmentAccess,
+    exception_handler: ExceptionHandler,
+}
+
+impl Sampler {
+    fn update(&self, exception: Exception, exceeded: bool) {
+        if exceeded {
+            self.exception_handler.set(exception);
+        } else {
+            self.exception_handler.clear(exception);
+        }
+    }
+
+    fn sample(&mut self) {
+        let config = self.config.load();
+        let EnvironmentConfig {
+            max_memory,
+            max_millicpus,
+            monitor,
+            ..
+        } = *config;
+        if !self
+            .system
+            .refresh_process_specifics(self.pid, ProcessRefreshKind::new().with_cpu())
+        {
+            warn!("refresh process failed, monitor sampling skipped");
+            return;
+        }
+        let Some(process) = self.system.process(self.pid) else {
+            return;
+        };
+
+        // cpu_usage is in percentage of one core
+        let cpu_usage = process.cpu_usage() as f64;
+        let cpu_limit = max_millicpus as f64 / 10.0 * monitor.cpu_threshold as f64 / 100.0;
+        let exceeded = monitor.cpu_threshold > 0 && max_millicpus > 0 && cpu_usage > cpu_limit;
+        if exceeded {
+            warn!(
+                "cpu usage exceeds the monitor threshold({:.2}% > {:.2}%)",
+                cpu_usage, cpu_limit
+            );
+        }
+        self.update(Exception::CpuThresholdExceeded, exceeded);
+
+        let memory = process.memory();
+        let memory_limit = max_memory * monitor.memory_threshold as u64 / 100;
+        let exceeded = monitor.memory_threshold > 0 && max_memory > 0 && memory > memory_limit;
+        if exceeded {
+            warn!(
+                "memory exceeds the monitor threshold({} > {})",
+                memory, memory_limit
+            );
+        }
+        self.update(Exception::MemoryThresholdExceeded, exceeded);
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if monitor.fd_threshold > 0 || monitor.fd_limit_ratio > 0 {
+            match get_fd_num() {
+                Ok(fd_num) => {
+                    let mut exceeded = monitor.fd_threshold > 0 && fd_num > monitor.fd_threshold;
+                    if exceeded {
+                        warn!(
+                            "the number of fd exceeds the monitor threshold({} > {})",
+                            fd_num, monitor.fd_threshold
+                        );
+                    }
+                    if monitor.fd_limit_ratio > 0 {
+                        match get_fd_limit() {
+                            Ok((soft, _)) if soft != u64::MAX => {
+                                let limit = soft * monitor.fd_limit_ratio as u64 / 100;
+                                if fd_num as u64 > limit {
+                                    warn!(
+                                        "the number of fd is approaching the limit({} > {}% of {})",
+                                        fd_num, monitor.fd_limit_ratio, soft
+                                    );
+                                    exceeded = true;
+                                }
+                            }
+                            Ok(_) => (),
+                            Err(e) => warn!("{}", e),
+                        }
+                    }
+                    self.update(Exception::FdThresholdExceeded, exceeded);
+                }
+                Err(e) => warn!("get fd num failed: {}", e),
+            }
+        } else {
+            self.exception_handler.clear(Exception::FdThresholdExceeded);
+        }
+    }
+}
+
 pub struct Monitor {
     stats: Arc<Collector>,
     running: AtomicBool,
@@ -328,10 +457,19 @@ pub struct Monitor {
     sys_load: Arc<SysLoad>,
     link_map: Arc<Mutex<HashMap<String, Arc<LinkStatusBroker>>>>,
     system: Arc<Mutex<System>>,
+    config: EnvironmentAccess,
+    exception_handler: ExceptionHandler,
+    sampler_running: Arc<(Mutex<bool>, Condvar)>,
+    sampler_thread: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Monitor {
-    pub fn new(stats: Arc<Collector>, log_dir: String, config: EnvironmentAccess) -> Result<Self> {
+    pub fn new(
+        stats: Arc<Collector>,
+        log_dir: String,
+        config: EnvironmentAccess,
+        exception_handler: ExceptionHandler,
+    ) -> Result<Self> {
         let mut system = System::new();
         system.refresh_cpu();
         let system = Arc::new(Mutex::new(system));
@@ -347,9 +485,66 @@ impl Monitor {
             sys_load: Arc::new(SysLoad(system.clone())),
             link_map: Arc::new(Mutex::new(HashMap::new())),
             system,
+            config,
+            exception_handler,
+            sampler_running: Arc::new((Mutex::new(false), Condvar::new())),
+            sampler_thread: Mutex::new(None),
         })
     }
 
+    fn start_sampler(&self) {
+        let pid = match get_current_pid() {
+            Ok(pid) => pid,
+            Err(e) => {
+                warn!("get current pid failed: {}, monitor sampler not started", e);
+                return;
+            }
+        };
+        *self.sampler_running.0.lock().unwrap() = true;
+
+        let mut sampler = Sampler {
+            system: System::new(),
+            pid,
+            config: self.config.clone(),
+            exception_handler: self.exception_handler.clone(),
+        };
+        let running = self.sampler_running.clone();
+        let handle = thread::Builder::new()
+            .name("monitor".to_owned())
+            .spawn(move || {
+                let (running, timer) = &*running;
+                loop {
+                    sampler.sample();
+
+                    // interval is loaded on every round, on_config_change() wakes up the thread
+                    // to reschedule with the new interval
+                    let interval = sampler.config.load().monitor.interval;
+                    let guard = running.lock().unwrap();
+                    if !*guard {
+                        break;
+                    }
+                    let (guard, _) = timer
+                        .wait_timeout_while(guard, interval, |r| {
+                            *r && sampler.config.load().monitor.interval == interval
+                        })
+                        .unwrap();
+                    if !*guard {
+                        break;
+                    }
+                }
+                info!("monitor sampler exited");
+            })
+            .unwrap();
+        self.sampler_thread.lock().unwrap().replace(handle);
+    }
+
+    // Called after config is updated, reschedules sampling if the interval is changed
+    pub fn on_config_change(&self) {
+        let (running, timer) = &*self.sampler_running;
+        let _guard = running.lock().unwrap();
+        timer.notify_one();
+    }
+
     pub fn start(&self) {
         if self.running.swap(true, Ordering::Relaxed) {
             debug!("monitor has already started");
@@ -467,6 +662,8 @@ impl Monitor {
             Countable::Ref(Arc::downgrade(&self.sys_load) as Weak<dyn RefCountable>),
         );
 
+        self.start_sampler();
+
         info!("monitor started");
     }
 
@@ -476,6 +673,14 @@ impl Monitor {
             return;
         }
         // tear down
+        {
+            let (running, timer) = &*self.sampler_running;
+            *running.lock().unwrap() = false;
+            timer.notify_one();
+        }
+        if let Some(handle) = self.sampler_thread.lock().unwrap().take() {
+            let _ = handle.join();
+        }
         self.link_map
             .lock()
             .unwrap()
@@ -484,3 +689,52 @@ impl Monitor {
         info!("monitor stopped");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::atomic::AtomicI64, time::Instant};
+
+    use arc_swap::{access::Map, ArcSwap};
+
+    use crate::config::handler::ModuleConfig;
+
+    #[test]
+    fn reschedule_on_config_change() {
+        let mut module_config = ModuleConfig::default();
+        module_config.environment.monitor.interval = Duration::from_secs(3600);
+        module_config.environment.monitor.memory_threshold = 0;
+        let current_config = Arc::new(ArcSwap::from_pointee(module_config.clone()));
+        let environment: EnvironmentAccess =
+            Map::new(current_config.clone(), |config| -> &EnvironmentConfig {
+                &config.environment
+            });
+        let exception_handler = ExceptionHandler::default();
+        let monitor = Monitor::new(
+            Arc::new(Collector::new("test", vec![], Arc::new(AtomicI64::new(0)))),
+            "/tmp".to_owned(),
+            environment,
+            exception_handler.clone(),
+        )
+        .unwrap();
+        monitor.start();
+
+        // any process exceeds 100% of 1 byte
+        module_config.environment.max_memory = 1;
+        module_config.environment.monitor.memory_threshold = 100;
+        module_config.environment.monitor.interval = Duration::from_millis(10);
+        current_config.store(Arc::new(module_config));
+        monitor.on_config_change();
+
+        let start = Instant::now();
+        while !exception_handler.has(Exception::MemoryThresholdExceeded) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "monitor sampler not rescheduled"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        monitor.stop();
+    }
+}
@@ -93,6 +93,10 @@ impl SocketSynchronizer {
         }
     }
 
+    pub fn lru_toa_info(&self) -> Arc<Mutex<Lru<SocketAddr, SocketAddr>>> {
+        self.lru_toa_info.clone()
+    }
+
     pub fn start(&mut self) {
         let conf_guard = self.config.load();
         if !process_info_enabled(conf_guard.trident_type) {
@@ -18,7 +18,7 @@ mod ntp;
 mod session;
 mod synchronizer;
 
-pub use session::{Session, DEFAULT_TIMEOUT};
+pub use session::Session;
 pub(crate) use synchronizer::{StaticConfig, Status, Synchronizer};
 
 cfg_if::cfg_if! {
@@ -14,33 +14,37 @@
  * limitations under the License.
  */
 
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::{
-    atomic::{AtomicU64, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc, Weak,
 };
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use md5::{Digest, Md5};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use tokio::time;
 use tonic::transport::Channel;
 
 use crate::{
     common::{DEFAULT_CONTROLLER_PORT, DEFAULT_CONTROLLER_TLS_PORT},
+    config::ControllerTimeouts,
     exception::ExceptionHandler,
     trident::AgentId,
     utils::stats::{self, AtomicTimeStats},
 };
-use grpc::dial as grpc_dial;
+use grpc::{dial as grpc_dial, Proxy as GrpcProxy, TlsVerify as GrpcTlsVerify};
 use public::proto::trident::{self, Exception, Status};
 use public::{
     counter::{Countable, Counter, CounterType, CounterValue, RefCountable},
     proto::trident::PluginType,
 };
 
-pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
 pub const SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+const INSECURE_TLS_WARNING_INTERVAL: Duration = Duration::from_secs(300);
 
 const GRPC_CALL_ENDPOINTS: [&str; 10] = [
     "push",
@@ -68,11 +72,13 @@ const PROMETHEUS_API_SYNC_ENDPOINT: usize = 9;
 
 struct Config {
     ips: Vec<String>,
+    // controller ports resolved from SRV records, overriding port and tls_port
+    ports: HashMap<String, u16>,
     port: u16,
     tls_port: u16,
     proxy_ip: Option<String>,
     proxy_port: u16,
-    timeout: Duration,
+    timeouts: ControllerTimeouts,
     enable_tls: bool,
 }
 
@@ -80,11 +86,12 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             ips: vec![],
+            ports: HashMap::new(),
             proxy_ip: None,
             port: DEFAULT_CONTROLLER_PORT,
             tls_port: DEFAULT_CONTROLLER_TLS_PORT,
             proxy_port: DEFAULT_CONTROLLER_PORT,
-            timeout: DEFAULT_TIMEOUT,
+            timeouts: Default::default(),
             enable_tls: false,
         }
     }
@@ -108,11 +115,30 @@ impl Config {
     fn get_proxy_port(&self) -> u16 {
         return self.proxy_port;
     }
+
+    fn get_controller_port(&self, ip: &str) -> u16 {
+        self.ports
+            .get(ip)
+            .copied()
+            .unwrap_or_else(|| self.get_port(false))
+    }
+
+    fn get_timeout(&self, endpoint: usize) -> Duration {
+        match endpoint {
+            SYNC_ENDPOINT | NTP_ENDPOINT => self.timeouts.sync,
+            PUSH_ENDPOINT | UPGRADE_ENDPOINT | PLUGIN_ENDPOINT => self.timeouts.config_fetch,
+            _ => self.timeouts.resource_sync,
+        }
+    }
 }
 
 pub struct Session {
     config: Arc<RwLock<Config>>,
     controller_cert_file_prefix: String,
+    tls_verify: GrpcTlsVerify,
+    last_insecure_tls_warning: Mutex<Option<Instant>>,
+    // proxy to tunnel grpc connections to controller through, not to be confused with proxy controller
+    grpc_proxy: Option<GrpcProxy>,
 
     server_dispatcher: RwLock<ServerDispatcher>,
 
@@ -120,6 +146,7 @@ pub struct Session {
     client: RwLock<Option<Channel>>,
     exception_handler: ExceptionHandler,
     counters: Vec<Arc<GrpcCallCounter>>,
+    connection_counter: Arc<ConnectionCounter>,
 }
 
 macro_rules! response_size {
@@ -163,7 +190,7 @@ macro_rules! sync_grpc_call {
         let request_len = $request.encoded_len();
         let now = Instant::now();
         log::trace!("{} send request", prefix);
-        let response = client.$func($request).await;
+        let response = $self.with_timeout($enpoint, client.$func($request)).await;
         log::trace!("{} receive response", prefix);
         let now_elapsed = now.elapsed();
         $self.counters[$enpoint].delay.update(now_elapsed);
@@ -184,9 +211,12 @@ impl Session {
     pub fn new(
         port: u16,
         tls_port: u16,
-        timeout: Duration,
+        timeouts: ControllerTimeouts,
         controller_cert_file_prefix: String,
+        tls_verify: GrpcTlsVerify,
         controller_ips: Vec<String>,
+        controller_ports: HashMap<String, u16>,
+        grpc_proxy: Option<GrpcProxy>,
         exception_handler: ExceptionHandler,
         stats_collector: &stats::Collector,
     ) -> Session {
@@ -202,11 +232,18 @@ impl Session {
             );
         }
 
+        let connection_counter = Arc::new(ConnectionCounter::default());
+        stats_collector.register_countable(
+            &stats::NoTagModule("controller_connection"),
+            Countable::Ref(Arc::downgrade(&connection_counter) as Weak<dyn RefCountable>),
+        );
+
         let config = Arc::new(RwLock::new(Config {
             ips: controller_ips,
+            ports: controller_ports,
             port,
             tls_port,
-            timeout,
+            timeouts,
             enable_tls: controller_cert_file_prefix.len() > 0,
             ..Default::default()
         }));
@@ -218,14 +255,41 @@ impl Session {
             client: RwLock::new(None),
             exception_handler,
             counters,
+            connection_counter,
             controller_cert_file_prefix,
+            tls_verify,
+            last_insecure_tls_warning: Mutex::new(None),
+            grpc_proxy,
         }
     }
 
-    pub fn reset_server_ip(&self, controller_ips: Vec<String>) {
+    // Does nothing if the set of controller ips and ports is unchanged, otherwise the current
+    // connection is kept if its controller is still in controller_ips with the same port
+    pub fn reset_server_ip(
+        &self,
+        controller_ips: Vec<String>,
+        controller_ports: HashMap<String, u16>,
+    ) {
+        {
+            let config = self.config.read();
+            let current = config.ips.iter().collect::<HashSet<_>>();
+            if current == controller_ips.iter().collect::<HashSet<_>>()
+                && config.ports == controller_ports
+            {
+                debug!("controller ips {:?} unchanged, skip reset", controller_ips);
+                return;
+            }
+        }
+        info!(
+            "reset controller ips to {:?} ports {:?}",
+            controller_ips, controller_ports
+        );
         self.server_dispatcher
             .write()
-            .update_controller_ips(controller_ips);
+            .update_controller_ips(controller_ips, controller_ports);
+        self.connection_counter
+            .session_resets
+            .fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn reset(&self) {
@@ -233,8 +297,32 @@ impl Session {
         self.server_dispatcher.write().reset();
     }
 
+    // Warns on every dial and periodically after that, skipping verification must never go unnoticed
+    fn warn_insecure_tls(&self, force: bool) {
+        if self.tls_verify != GrpcTlsVerify::InsecureSkipVerify || !self.config.read().enable_tls {
+            return;
+        }
+        let mut last = self.last_insecure_tls_warning.lock();
+        if !force && last.map_or(false, |t| t.elapsed() < INSECURE_TLS_WARNING_INTERVAL) {
+            return;
+        }
+        *last = Some(Instant::now());
+        warn!("controller certificate is NOT verified (controller-tls-verify: insecure-skip-verify), connections to controller can be intercepted, never use it in production");
+    }
+
     async fn dial(&self, remote: &str, remote_port: u16, controller_cert_file_prefix: String) {
-        match grpc_dial(remote, remote_port, controller_cert_file_prefix).await {
+        self.warn_insecure_tls(true);
+        match grpc_dial(
+            remote,
+            remote_port,
+            controller_cert_file_prefix,
+            self.tls_verify,
+            self.grpc_proxy.as_ref(),
+            // per class timeouts are applied on each call, the channel only caps them
+            self.config.read().timeouts.max(),
+        )
+        .await
+        {
             Ok(channel) => *self.client.write() = Some(channel),
             Err(e) => {
                 self.exception_handler.set(Exception::ControllerSocketError);
@@ -254,6 +342,7 @@ impl Session {
 
     pub async fn update_current_server(&self) -> bool {
         let changed = self.server_dispatcher.write().update_current_ip();
+        self.warn_insecure_tls(false);
         if changed || self.get_client().is_none() {
             let (ip, port) = self.server_dispatcher.read().get_current_ip();
             self.dial(&ip, port, self.controller_cert_file_prefix.clone())
@@ -276,7 +365,64 @@ impl Session {
     }
 
     pub fn set_request_failed(&self, failed: bool) {
-        self.server_dispatcher.write().set_request_failed(failed);
+        let mut server_dispatcher = self.server_dispatcher.write();
+        server_dispatcher.set_request_failed(failed);
+
+        let counter = &self.connection_counter;
+        let kind = if server_dispatcher.is_proxy_ip() {
+            "proxy controller"
+        } else {
+            "controller"
+        };
+        let (ip, port) = server_dispatcher.get_current_ip();
+        if failed {
+            if counter.connected.swap(false, Ordering::Relaxed) {
+                warn!("disconnected from {} {} {}", kind, ip, port);
+            }
+            return;
+        }
+        if counter.connected.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let index = server_dispatcher.current_ip_index;
+        counter
+            .controller_index
+            .store(index as u64, Ordering::Relaxed);
+        counter
+            .proxied
+            .store(server_dispatcher.is_proxy_ip(), Ordering::Relaxed);
+        // the first connection is not a reconnection
+        if counter.ever_connected.swap(true, Ordering::Relaxed) {
+            counter.reconnects.fetch_add(1, Ordering::Relaxed);
+            info!(
+                "reconnected to {} {} {} (controller index {})",
+                kind, ip, port, index
+            );
+        } else {
+            info!(
+                "connected to {} {} {} (controller index {})",
+                kind, ip, port, index
+            );
+        }
+    }
+
+    async fn with_timeout<T, F>(&self, endpoint: usize, f: F) -> Result<T, tonic::Status>
+    where
+        F: Future<Output = Result<T, tonic::Status>>,
+    {
+        let timeout = self.config.read().get_timeout(endpoint);
+        match time::timeout(timeout, f).await {
+            Ok(r) => r,
+            Err(_) => {
+                self.counters[endpoint]
+                    .timeouts
+                    .fetch_add(1, Ordering::Relaxed);
+                Err(tonic::Status::deadline_exceeded(format!(
+                    "grpc {} timeout after {:?}",
+                    GRPC_CALL_ENDPOINTS[endpoint], timeout
+                )))
+            }
+        }
     }
 
     pub fn get_proxy_server(&self) -> (Option<String>, u16) {
@@ -317,13 +463,13 @@ impl Session {
 
         if !with_statsd {
             log::trace!("grpc sync send request");
-            let response = client.sync(request).await;
+            let response = self.with_timeout(SYNC_ENDPOINT, client.sync(request)).await;
             log::trace!("grpc sync receive response");
             response
         } else {
             let now = Instant::now();
             log::trace!("grpc sync send request");
-            let response = client.sync(request).await;
+            let response = self.with_timeout(SYNC_ENDPOINT, client.sync(request)).await;
             log::trace!("grpc sync receive response");
             let now_elapsed = now.elapsed();
             self.counters[SYNC_ENDPOINT].delay.update(now_elapsed);
@@ -509,9 +655,27 @@ impl ServerDispatcher {
         self.request_failed = false;
     }
 
-    fn update_controller_ips(&mut self, controller_ips: Vec<String>) {
-        self.reset();
-        self.config.write().ips = controller_ips;
+    fn update_controller_ips(
+        &mut self,
+        controller_ips: Vec<String>,
+        controller_ports: HashMap<String, u16>,
+    ) {
+        let mut config = self.config.write();
+        let current_ip = config.ips.get(self.current_ip_index).cloned();
+        let index = current_ip.and_then(|ip| {
+            if config.ports.get(&ip) != controller_ports.get(&ip) {
+                return None;
+            }
+            controller_ips.iter().position(|i| i == &ip)
+        });
+        config.ips = controller_ips;
+        config.ports = controller_ports;
+        drop(config);
+        match index {
+            // keep the connection to current controller or proxy, only the index moves
+            Some(index) if !self.current_ip.is_empty() => self.current_ip_index = index,
+            _ => self.reset(),
+        }
     }
 
     fn get_current_ip(&self) -> (String, u16) {
@@ -557,7 +721,7 @@ impl ServerDispatcher {
     fn update_current_ip(&mut self) -> bool {
         if self.current_ip.len() == 0 {
             self.current_ip = self.get_current_controller_ip();
-            self.current_port = self.config.read().get_port(false);
+            self.current_port = self.config.read().get_controller_port(&self.current_ip);
             // 第一次访问，直接返回
             return true;
         }
@@ -570,8 +734,8 @@ impl ServerDispatcher {
                     "rpc IP changed to controller {} from unavailable proxy {}",
                     new_ip, self.current_ip
                 );
+                self.current_port = self.config.read().get_controller_port(&new_ip);
                 self.current_ip = new_ip;
-                self.current_port = self.config.read().get_port(false);
                 self.proxied = false;
                 true
             }
@@ -595,8 +759,8 @@ impl ServerDispatcher {
             // 访问控制器失败，更新控制器IP地址
             (false, true) => {
                 self.next_controller_ip();
-                let port = self.config.read().get_port(false);
                 let ip = self.get_current_controller_ip();
+                let port = self.config.read().get_controller_port(&ip);
                 info!(
                     "rpc IP changed to controller {} {} from unavailable controller {} {}",
                     ip, port, self.current_ip, self.current_port
@@ -635,9 +799,53 @@ impl ServerDispatcher {
     }
 }
 
+#[derive(Default)]
+struct ConnectionCounter {
+    reconnects: AtomicU64,
+    session_resets: AtomicU64,
+    connected: AtomicBool,
+    ever_connected: AtomicBool,
+    // index in controller-ips of the controller last connected to or proxied by
+    controller_index: AtomicU64,
+    proxied: AtomicBool,
+}
+
+impl RefCountable for ConnectionCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "reconnects",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.reconnects.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "session_resets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.session_resets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "connected",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.connected.load(Ordering::Relaxed) as u64),
+            ),
+            (
+                "controller_index",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.controller_index.load(Ordering::Relaxed)),
+            ),
+            (
+                "proxied",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.proxied.load(Ordering::Relaxed) as u64),
+            ),
+        ]
+    }
+}
+
 #[derive(Default)]
 pub struct GrpcCallCounter {
     pub delay: AtomicTimeStats,
+    pub timeouts: AtomicU64,
 }
 
 impl RefCountable for GrpcCallCounter {
@@ -666,6 +874,11 @@ impl RefCountable for GrpcCallCounter {
                 CounterType::Gauged,
                 CounterValue::Unsigned(delay_count),
             ),
+            (
+                "timeouts",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.timeouts.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
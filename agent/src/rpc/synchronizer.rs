@@ -27,7 +27,7 @@ use std::sync::{
     atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
     Arc, Condvar, Weak,
 };
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 #[cfg(unix)]
 use std::{fs::Permissions, os::unix::fs::PermissionsExt};
 
@@ -42,7 +42,7 @@ use log::{debug, error, info, warn};
 use md5::{Digest, Md5};
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
 use prost::Message;
-use rand::RngCore;
+use rand::{rngs::SmallRng, Rng, RngCore, SeedableRng};
 use sysinfo::{System, SystemExt};
 use tokio::runtime::Runtime;
 use tokio::sync::{
@@ -65,7 +65,9 @@ use crate::common::{FlowAclListener, PlatformData as VInterface, DEFAULT_CONTROL
 use crate::config::RuntimeConfig;
 use crate::exception::ExceptionHandler;
 use crate::rpc::session::Session;
-use crate::trident::{self, AgentId, ChangedConfig, RunningMode, TridentState, VersionInfo};
+use crate::trident::{
+    self, AgentId, ChangedConfig, RunningMode, StateTrigger, TridentState, VersionInfo,
+};
 #[cfg(any(target_os = "linux"))]
 use crate::utils::environment::{get_current_k8s_image, get_k8s_namespace};
 use crate::utils::{
@@ -94,7 +96,8 @@ pub struct StaticConfig {
     pub boot_time: SystemTime,
 
     pub tap_mode: tp::TapMode,
-    pub vtap_group_id_request: String,
+    // updated at runtime by vtap-group-id-request in agent group config
+    pub vtap_group_id_request: RwLock<String>,
     pub controller_ip: String,
 
     pub env: RuntimeEnvironment,
@@ -104,6 +107,8 @@ pub struct StaticConfig {
     pub override_os_hostname: Option<String>,
     pub agent_unique_identifier: crate::config::AgentIdType,
     pub current_k8s_image: Option<String>,
+    // percentage of random jitter added to sync interval
+    pub sync_interval_jitter: u8,
 }
 
 const EMPTY_VERSION_INFO: &'static trident::VersionInfo = &trident::VersionInfo {
@@ -113,6 +118,7 @@ const EMPTY_VERSION_INFO: &'static trident::VersionInfo = &trident::VersionInfo
     rev_count: "",
     compiler: "",
     compile_time: "",
+    features: &[],
     revision: "",
 };
 
@@ -130,6 +136,7 @@ impl Default for StaticConfig {
             override_os_hostname: None,
             agent_unique_identifier: Default::default(),
             current_k8s_image: None,
+            sync_interval_jitter: 0,
         }
     }
 }
@@ -152,6 +159,8 @@ pub struct Status {
 
     // GRPC数据
     pub local_epc: i32,
+    // vtap group id the controller assigned this agent to
+    pub vtap_group_id: String,
 
     pub version_platform_data: u64,
     pub version_acls: u64,
@@ -183,6 +192,7 @@ impl Default for Status {
             ntp_max_interval: Duration::from_secs(300),
 
             local_epc: EPC_INTERNET,
+            vtap_group_id: Default::default(),
             version_platform_data: 0,
             version_acls: 0,
             version_groups: 0,
@@ -455,6 +465,9 @@ pub struct Synchronizer {
 
     max_memory: Arc<AtomicU64>,
     ntp_diff: Arc<AtomicI64>,
+    // unix timestamp in seconds of the last successful exchange with a controller, 0 if never
+    last_contact: Arc<AtomicU64>,
+    start_time: Instant,
     agent_mode: RunningMode,
     standalone_runtime_config: Option<PathBuf>,
     agent_id_tx: Arc<broadcast::Sender<AgentId>>,
@@ -475,6 +488,7 @@ impl Synchronizer {
         kubernetes_cluster_name: Option<String>,
         override_os_hostname: Option<String>,
         agent_unique_identifier: crate::config::AgentIdType,
+        sync_interval_jitter: u8,
         exception_handler: ExceptionHandler,
         agent_mode: RunningMode,
         standalone_runtime_config: Option<PathBuf>,
@@ -486,7 +500,7 @@ impl Synchronizer {
                 version_info,
                 boot_time: SystemTime::now(),
                 tap_mode: tp::TapMode::Local,
-                vtap_group_id_request,
+                vtap_group_id_request: RwLock::new(vtap_group_id_request),
                 controller_ip,
                 env: RuntimeEnvironment::new(),
                 kubernetes_cluster_id,
@@ -497,6 +511,7 @@ impl Synchronizer {
                 current_k8s_image: runtime.block_on(get_current_k8s_image()),
                 #[cfg(any(target_os = "windows", target_os = "android"))]
                 current_k8s_image: None,
+                sync_interval_jitter,
             }),
             agent_id: Arc::new(RwLock::new(agent_id)),
             trident_state,
@@ -511,6 +526,8 @@ impl Synchronizer {
 
             max_memory: Default::default(),
             ntp_diff,
+            last_contact: Default::default(),
+            start_time: Instant::now(),
             agent_mode,
             standalone_runtime_config,
             agent_id_tx,
@@ -543,6 +560,27 @@ impl Synchronizer {
         self.reset_version();
     }
 
+    // Registers the listener in place of the one with the same id, used by components rebuilt
+    // in the same process whose listeners keep their ids
+    pub fn replace_flow_acl_listener(&self, module: Box<dyn FlowAclListener>) {
+        let mut listeners = self.flow_acl_listener.lock().unwrap();
+        match listeners.iter_mut().find(|item| item.id() == module.id()) {
+            Some(item) => *item = module,
+            None => listeners.push(module),
+        }
+        // see add_flow_acl_listener
+        drop(listeners);
+
+        self.reset_version();
+    }
+
+    pub fn remove_flow_acl_listener(&self, id: usize) {
+        self.flow_acl_listener
+            .lock()
+            .unwrap()
+            .retain(|item| item.id() != id);
+    }
+
     pub fn max_memory(&self) -> Arc<AtomicU64> {
         self.max_memory.clone()
     }
@@ -615,7 +653,7 @@ impl Synchronizer {
             arch: Some(static_config.env.arch.clone()),
             os: Some(static_config.env.os.clone()),
             kernel_version: Some(static_config.env.kernel_version.clone()),
-            vtap_group_id_request: Some(static_config.vtap_group_id_request.clone()),
+            vtap_group_id_request: Some(static_config.vtap_group_id_request.read().clone()),
             kubernetes_cluster_id: Some(static_config.kubernetes_cluster_id.clone()),
             kubernetes_cluster_name: static_config.kubernetes_cluster_name.clone(),
             kubernetes_force_watch: Some(running_in_only_watch_k8s_mode()),
@@ -717,6 +755,23 @@ impl Synchronizer {
         return (segments, macs, gateway_vmacs);
     }
 
+    // The new group id is sent in following sync requests for the controller to move
+    // this agent into the group, no restart is required
+    fn update_vtap_group_id_request(static_config: &StaticConfig, runtime_config: &RuntimeConfig) {
+        let new_request = &runtime_config.yaml_config.vtap_group_id_request;
+        if new_request.is_empty() {
+            return;
+        }
+        let mut request = static_config.vtap_group_id_request.write();
+        if &*request != new_request {
+            info!(
+                "vtap group id request changed from {:?} to {:?}",
+                *request, new_request
+            );
+            *request = new_request.clone();
+        }
+    }
+
     // Note that both 'status' and 'flow_acl_listener' will be locked here, and other places where 'status'
     // and 'flow_acl_listener' are used need to be careful to avoid deadlocks
     fn on_response(
@@ -776,7 +831,16 @@ impl Synchronizer {
         }
         let (_, macs, gateway_vmac_addrs) = Self::parse_segment(runtime_config.tap_mode, &resp);
 
+        Self::update_vtap_group_id_request(static_config, &runtime_config);
+
         let mut status_guard = status.write();
+        if status_guard.vtap_group_id != runtime_config.vtap_group_id {
+            info!(
+                "vtap group id changed from {:?} to {:?}",
+                status_guard.vtap_group_id, runtime_config.vtap_group_id
+            );
+            status_guard.vtap_group_id = runtime_config.vtap_group_id.clone();
+        }
         status_guard.proxy_ip = if runtime_config.proxy_controller_ip.len() > 0 {
             Some(runtime_config.proxy_controller_ip.clone())
         } else {
@@ -840,17 +904,27 @@ impl Synchronizer {
         drop(status_guard);
 
         let (trident_state, cvar) = &**trident_state;
-        if !runtime_config.enabled || exception_handler.has(Exception::SystemLoadCircuitBreaker) {
-            *trident_state.lock().unwrap() = trident::State::Disabled(Some(runtime_config));
+        let circuit_breaker = exception_handler.has(Exception::SystemLoadCircuitBreaker);
+        let trigger = if circuit_breaker {
+            StateTrigger::CircuitBreaker
+        } else {
+            StateTrigger::ServerPush
+        };
+        let new_state = if !runtime_config.enabled || circuit_breaker {
+            trident::State::Disabled(Some(runtime_config))
         } else {
-            *trident_state.lock().unwrap() = trident::State::ConfigChanged(ChangedConfig {
+            trident::State::ConfigChanged(ChangedConfig {
                 runtime_config,
                 blacklist,
                 vm_mac_addrs: macs,
                 gateway_vmac_addrs,
                 tap_types: resp.tap_types,
-            });
-        }
+            })
+        };
+        trident_state
+            .lock()
+            .unwrap()
+            .transit(new_state, trigger, exception_handler);
         cvar.notify_one();
     }
 
@@ -875,8 +949,11 @@ impl Synchronizer {
         let exception_handler = self.exception_handler.clone();
         let ntp_diff = self.ntp_diff.clone();
         let ntp_state = self.ntp_state.clone();
+        let last_contact = self.last_contact.clone();
         self.threads.lock().push(self.runtime.spawn(async move {
             let mut grpc_failed_count = 0;
+            // seeded by ctrl_mac so that intervals are stable for an agent but vary across agents
+            let mut jitter_rng = SmallRng::seed_from_u64(u64::from(agent_id.read().mac));
             while running.load(Ordering::SeqCst) {
                 let response = session
                     .grpc_push_with_statsd(Synchronizer::generate_sync_request(
@@ -934,10 +1011,12 @@ impl Synchronizer {
                             continue;
                         }
                         tp::Status::Heartbeat => {
+                            Self::record_contact(&last_contact);
                             continue;
                         }
                         _ => (),
                     }
+                    Self::record_contact(&last_contact);
 
                     debug!("received realtime policy successfully");
                     {
@@ -974,6 +1053,7 @@ impl Synchronizer {
     fn run_escape_timer(&self) -> UnboundedSender<Duration> {
         let (tx, mut rx) = mpsc::unbounded_channel();
         let trident_state = self.trident_state.clone();
+        let exception_handler = self.exception_handler.clone();
         let running = self.running.clone();
         self.runtime.spawn(async move {
             // default escape time is 1h
@@ -985,7 +1065,11 @@ impl Synchronizer {
                     Ok(None) => return,
                     Err(_) => {
                         let (ts, cvar) = &*trident_state;
-                        *ts.lock().unwrap() = trident::State::Disabled(None);
+                        ts.lock().unwrap().transit(
+                            trident::State::Disabled(None),
+                            StateTrigger::EscapeTimeout,
+                            &exception_handler,
+                        );
                         cvar.notify_one();
                         warn!("as max escape time expired, deepflow-agent restart...");
                         // 与控制器失联的时间超过设置的逃逸时间，这里直接重启主要有两个原因：
@@ -1008,6 +1092,19 @@ impl Synchronizer {
         NtpCounter(Arc::downgrade(&self.ntp_diff()))
     }
 
+    fn record_contact(last_contact: &AtomicU64) {
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            last_contact.store(now.as_secs(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn contact_counter(&self) -> ContactCounter {
+        ContactCounter {
+            last_contact: Arc::downgrade(&self.last_contact),
+            start_time: self.start_time,
+        }
+    }
+
     fn run_ntp_sync(&self) {
         let agent_id = self.agent_id.clone();
         let session = self.session.clone();
@@ -1351,6 +1448,7 @@ impl Synchronizer {
     fn run_standalone(&self) {
         let running = self.running.clone();
         let trident_state = self.trident_state.clone();
+        let exception_handler = self.exception_handler.clone();
         let max_memory = self.max_memory.clone();
         let mut sync_interval = DEFAULT_SYNC_INTERVAL;
         let standalone_runtime_config = self.standalone_runtime_config.as_ref().unwrap().clone();
@@ -1386,14 +1484,19 @@ impl Synchronizer {
                 max_memory.store(runtime_config.max_memory, Ordering::Relaxed);
                 let new_sync_interval = Duration::from_secs(runtime_config.sync_interval);
                 let (trident_state, cvar) = &*trident_state;
-                if !runtime_config.enabled {
-                    *trident_state.lock().unwrap() = trident::State::Disabled(Some(runtime_config));
+                let new_state = if !runtime_config.enabled {
+                    trident::State::Disabled(Some(runtime_config))
                 } else {
-                    *trident_state.lock().unwrap() = trident::State::ConfigChanged(ChangedConfig {
+                    trident::State::ConfigChanged(ChangedConfig {
                         runtime_config,
                         ..Default::default()
-                    });
-                }
+                    })
+                };
+                trident_state.lock().unwrap().transit(
+                    new_state,
+                    StateTrigger::StandaloneConfig,
+                    &exception_handler,
+                );
                 cvar.notify_one();
 
                 if sync_interval != new_sync_interval {
@@ -1418,6 +1521,7 @@ impl Synchronizer {
         let exception_handler = self.exception_handler.clone();
         let ntp_diff = self.ntp_diff.clone();
         let ntp_state = self.ntp_state.clone();
+        let last_contact = self.last_contact.clone();
         self.threads.lock().push(self.runtime.spawn(async move {
             let mut grpc_failed_count = 0;
             while running.load(Ordering::SeqCst) {
@@ -1471,6 +1575,7 @@ impl Synchronizer {
                 }
                 session.set_request_failed(false);
                 grpc_failed_count = 0;
+                Self::record_contact(&last_contact);
 
                 Self::on_response(
                     session.get_current_server(),
@@ -1513,7 +1618,11 @@ impl Synchronizer {
                         match Self::upgrade(&running, &session, &revision, &id).await {
                             Ok(_) => {
                                 let (ts, cvar) = &*trident_state;
-                                *ts.lock().unwrap() = trident::State::Terminated;
+                                ts.lock().unwrap().transit(
+                                    trident::State::Terminated,
+                                    StateTrigger::Upgrade,
+                                    &exception_handler,
+                                );
                                 cvar.notify_one();
                                 warn!("agent upgrade is successful and restarts normally, deepflow-agent restart...");
                                 crate::utils::notify_exit(NORMAL_EXIT_WITH_RESTART);
@@ -1535,14 +1644,32 @@ impl Synchronizer {
 
                 if sync_interval != new_sync_interval {
                     sync_interval = new_sync_interval;
-                    info!("sync interval set to {:?}", sync_interval);
+                    info!(
+                        "sync interval set to {:?} with jitter {}%",
+                        sync_interval, static_config.sync_interval_jitter
+                    );
                 }
 
-                time::sleep(sync_interval).await;
+                time::sleep(Self::jittered_interval(
+                    sync_interval,
+                    static_config.sync_interval_jitter,
+                    &mut jitter_rng,
+                ))
+                .await;
             }
         }));
     }
 
+    // Returns interval with random jitter in [-jitter%, +jitter%], jitter is at most 100
+    fn jittered_interval(interval: Duration, jitter: u8, rng: &mut SmallRng) -> Duration {
+        if jitter == 0 {
+            return interval;
+        }
+        let range = interval.as_millis() as u64 * jitter.min(100) as u64 / 100;
+        let offset = rng.gen_range(0..=range * 2);
+        interval + Duration::from_millis(offset) - Duration::from_millis(range)
+    }
+
     async fn watch_agent_id(
         mut agent_id_rx: broadcast::Receiver<AgentId>,
         agent_id: Arc<RwLock<AgentId>>,
@@ -1656,3 +1783,186 @@ impl stats::OwnedCountable for NtpCounter {
         self.0.strong_count() == 0
     }
 }
+
+pub struct ContactCounter {
+    last_contact: Weak<AtomicU64>,
+    start_time: Instant,
+}
+
+impl stats::OwnedCountable for ContactCounter {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        let Some(last_contact) = self.last_contact.upgrade() else {
+            return vec![];
+        };
+        let last_contact = last_contact.load(Ordering::Relaxed);
+        // timestamp is 0 and time since startup is reported if never contacted
+        let since_last_contact = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(now) if last_contact > 0 => now.as_secs().saturating_sub(last_contact),
+            _ => self.start_time.elapsed().as_secs(),
+        };
+        vec![
+            (
+                "last_contact_timestamp",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(last_contact),
+            ),
+            (
+                "since_last_contact",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(since_last_contact),
+            ),
+        ]
+    }
+
+    fn closed(&self) -> bool {
+        self.last_contact.strong_count() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::AtomicUsize;
+
+    use crate::common::FlowAclListenerId;
+    use crate::config::ControllerTimeouts;
+
+    static VERSION_INFO: VersionInfo = VersionInfo {
+        name: "test",
+        branch: "",
+        commit_id: "",
+        rev_count: "",
+        compiler: "",
+        compile_time: "",
+        features: &[],
+        revision: "",
+    };
+
+    struct CountingListener {
+        id: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl FlowAclListener for CountingListener {
+        fn flow_acl_change(
+            &mut self,
+            _: TridentType,
+            _: i32,
+            _: &Vec<Arc<IpGroupData>>,
+            _: &Vec<Arc<VInterface>>,
+            _: &Vec<Arc<PeerConnection>>,
+            _: &Vec<Arc<Cidr>>,
+            _: &Vec<Arc<Acl>>,
+        ) -> Result<(), String> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn id(&self) -> usize {
+            self.id
+        }
+    }
+
+    fn new_synchronizer() -> Synchronizer {
+        let runtime = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let exception_handler = ExceptionHandler::default();
+        let ntp_diff = Arc::new(AtomicI64::new(0));
+        let stats_collector = stats::Collector::new("test", vec![], ntp_diff.clone());
+        let session = Arc::new(Session::new(
+            DEFAULT_CONTROLLER_PORT,
+            DEFAULT_CONTROLLER_PORT,
+            ControllerTimeouts::default(),
+            String::new(),
+            Default::default(),
+            vec!["127.0.0.1".to_owned()],
+            Default::default(),
+            None,
+            exception_handler.clone(),
+            &stats_collector,
+        ));
+        Synchronizer::new(
+            runtime,
+            session,
+            Arc::new((sync::Mutex::new(trident::State::Running), Condvar::new())),
+            &VERSION_INFO,
+            AgentId {
+                ip: IpAddr::from([127, 0, 0, 1]),
+                mac: MacAddr::ZERO,
+                team_id: String::new(),
+            },
+            "127.0.0.1".to_owned(),
+            String::new(),
+            String::new(),
+            None,
+            None,
+            Default::default(),
+            0,
+            exception_handler,
+            RunningMode::Managed,
+            None,
+            Arc::new(broadcast::channel(1).0),
+            ntp_diff,
+        )
+    }
+
+    // dispatches policies to listeners the same way on_response does
+    fn trigger_listeners(synchronizer: &Synchronizer) {
+        let status = synchronizer.status.read();
+        for listener in synchronizer.flow_acl_listener.lock().unwrap().iter_mut() {
+            status
+                .trigger_flow_acl(TridentType::TtProcess, listener)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn replace_flow_acl_listener_of_rebuilt_component() {
+        let synchronizer = new_synchronizer();
+        let policy_id = FlowAclListenerId::Policy as usize;
+        let old_calls = Arc::new(AtomicUsize::new(0));
+        synchronizer.add_flow_acl_listener(Box::new(CountingListener {
+            id: policy_id,
+            calls: old_calls.clone(),
+        }));
+        let dispatcher_id = FlowAclListenerId::Dispatcher as usize;
+        synchronizer.add_flow_acl_listener(Box::new(CountingListener {
+            id: dispatcher_id,
+            calls: Arc::new(AtomicUsize::new(0)),
+        }));
+        {
+            let mut status = synchronizer.status.write();
+            status.version_acls = 10;
+            status.version_groups = 10;
+            status.version_platform_data = 10;
+        }
+
+        // rebuilt components drop the listeners of the old ones and register new listeners
+        // with the same ids
+        synchronizer.remove_flow_acl_listener(dispatcher_id);
+        let new_calls = Arc::new(AtomicUsize::new(0));
+        synchronizer.add_flow_acl_listener(Box::new(CountingListener {
+            id: policy_id,
+            calls: Arc::new(AtomicUsize::new(0)),
+        }));
+        synchronizer.replace_flow_acl_listener(Box::new(CountingListener {
+            id: policy_id,
+            calls: new_calls.clone(),
+        }));
+        assert_eq!(synchronizer.flow_acl_listener.lock().unwrap().len(), 1);
+        {
+            let status = synchronizer.status.read();
+            assert_eq!(status.version_acls, 0);
+            assert_eq!(status.version_groups, 0);
+            assert_eq!(status.version_platform_data, 0);
+        }
+
+        trigger_listeners(&synchronizer);
+        assert_eq!(old_calls.load(Ordering::Relaxed), 0);
+        assert_eq!(new_calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use bytesize::ByteSize;
+
+use public::{proto::trident::TapMode, utils::net::links_by_name_regex};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::dispatcher::BpfOptions;
+use crate::{
+    common::{FREE_SPACE_REQUIREMENT, PROCESS_NAME},
+    config::{Config, RuntimeConfig},
+    exception::ExceptionHandler,
+    trident::{src_interfaces_to_regex, RunningMode},
+    utils::{
+        environment::{controller_ips_check, free_memory_check, free_space_check, kernel_check},
+        process::get_process_num_by_name,
+    },
+};
+
+const SELF_TEST_FILE: &str = ".deepflow-agent-self-test";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    Skip,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pass => write!(f, "PASS"),
+            Self::Fail => write!(f, "FAIL"),
+            Self::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, result: Result<String, String>) -> Self {
+        let (status, detail) = match result {
+            Ok(detail) => (CheckStatus::Pass, detail),
+            Err(detail) => (CheckStatus::Fail, detail),
+        };
+        Self {
+            name,
+            status,
+            detail,
+        }
+    }
+
+    fn skip(name: &'static str, detail: &str) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Skip,
+            detail: detail.to_owned(),
+        }
+    }
+}
+
+fn process_check(process_threshold: u32) -> Result<String, String> {
+    let num = get_process_num_by_name(PROCESS_NAME).map_err(|e| e.to_string())?;
+    if num > process_threshold {
+        return Err(format!(
+            "{} {} processes exceed the limit {}",
+            num, PROCESS_NAME, process_threshold
+        ));
+    }
+    Ok(format!(
+        "{} {} processes, limit {}",
+        num, PROCESS_NAME, process_threshold
+    ))
+}
+
+fn interface_check(runtime_config: &RuntimeConfig) -> Result<String, String> {
+    let src_interfaces = &runtime_config.yaml_config.src_interfaces;
+    let (regex, field) =
+        if runtime_config.tap_mode == TapMode::Analyzer && !src_interfaces.is_empty() {
+            (src_interfaces_to_regex(src_interfaces), "src-interfaces")
+        } else {
+            (
+                runtime_config.tap_interface_regex.clone(),
+                "tap-interface-regex",
+            )
+        };
+    let links = links_by_name_regex(&regex).map_err(|e| format!("{} {}", field, e))?;
+    if links.is_empty() {
+        return Err(format!("{} {} matches no interface", field, regex));
+    }
+    Ok(format!(
+        "{} matches {}",
+        field,
+        links
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    ))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bpf_check(capture_bpf: &str) -> Result<String, String> {
+    if capture_bpf.is_empty() {
+        return Ok("capture-bpf is empty".to_owned());
+    }
+    let options = BpfOptions {
+        capture_bpf: capture_bpf.to_owned(),
+        ..Default::default()
+    };
+    if !options.capture_bpf_valid() {
+        return Err(format!("capture-bpf {} can not be compiled", capture_bpf));
+    }
+    Ok(format!("capture-bpf {} compiled", capture_bpf))
+}
+
+fn log_dir_check(log_file: &str) -> Result<String, String> {
+    let Some(dir) = Path::new(log_file).parent() else {
+        return Err(format!("log file {} has no parent directory", log_file));
+    };
+    fs::create_dir_all(dir).map_err(|e| format!("create {} failed: {}", dir.display(), e))?;
+    let path = dir.join(SELF_TEST_FILE);
+    fs::write(&path, b"").map_err(|e| format!("{} is not writable: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&path);
+    Ok(format!("{} is writable", dir.display()))
+}
+
+// Runs the checks done when agent components are created, without starting capture.
+// Runtime config is loaded from `config_path` in standalone mode, in managed mode it is
+// delivered by the controller and its defaults are checked instead.
+pub fn run<P: AsRef<Path>>(config_path: P, agent_mode: RunningMode) -> Result<Vec<CheckResult>> {
+    let (config, runtime_config) = match agent_mode {
+        RunningMode::Managed => (
+            Config::load_from_file(config_path.as_ref())?,
+            RuntimeConfig::default(),
+        ),
+        RunningMode::Standalone => {
+            let rc = RuntimeConfig::load_from_file(config_path.as_ref())?;
+            let mut conf = Config::default();
+            conf.log_file = rc.yaml_config.log_file.clone();
+            (conf, rc)
+        }
+    };
+    let exception_handler = ExceptionHandler::default();
+
+    let mut results = vec![
+        CheckResult::new("process", process_check(runtime_config.process_threshold)),
+        match agent_mode {
+            RunningMode::Managed => CheckResult::new(
+                "controller-ip",
+                controller_ips_check(&config.controller_ips)
+                    .map(|_| format!("{:?}", config.controller_ips))
+                    .map_err(|e| e.to_string()),
+            ),
+            RunningMode::Standalone => {
+                CheckResult::skip("controller-ip", "no controller in standalone mode")
+            }
+        },
+        CheckResult::new(
+            "kernel",
+            kernel_check(
+                runtime_config.tap_mode,
+                !runtime_config.yaml_config.ebpf.disabled,
+            )
+            .map(|_| format!("{:?} mode", runtime_config.tap_mode))
+            .map_err(|e| e.to_string()),
+        ),
+        CheckResult::new("interface", interface_check(&runtime_config)),
+    ];
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    results.push(CheckResult::new(
+        "capture-bpf",
+        bpf_check(&runtime_config.capture_bpf),
+    ));
+    #[cfg(target_os = "windows")]
+    results.push(CheckResult::skip(
+        "capture-bpf",
+        "capture-bpf is compiled by pcap on windows",
+    ));
+    results.push(CheckResult::new("log-dir", log_dir_check(&config.log_file)));
+    results.push(CheckResult::new(
+        "free-space",
+        free_space_check(&config.log_file, FREE_SPACE_REQUIREMENT, &exception_handler)
+            .map(|_| {
+                format!(
+                    "at least {} available",
+                    ByteSize::b(FREE_SPACE_REQUIREMENT).to_string_as(true)
+                )
+            })
+            .map_err(|e| e.to_string()),
+    ));
+    results.push(CheckResult::new(
+        "free-memory",
+        free_memory_check(runtime_config.max_memory, &exception_handler)
+            .map(|_| {
+                format!(
+                    "max-memory {}",
+                    ByteSize::b(runtime_config.max_memory).to_string_as(true)
+                )
+            })
+            .map_err(|e| e.to_string()),
+    ));
+    Ok(results)
+}
+
+// Prints results as a table, returns false if any check failed
+pub fn print(results: &[CheckResult]) -> bool {
+    let width = results.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    println!(
+        "{:width$}  {:6}  {}",
+        "CHECK",
+        "RESULT",
+        "DETAIL",
+        width = width
+    );
+    for r in results {
+        println!(
+            "{:width$}  {:6}  {}",
+            r.name,
+            r.status.to_string(),
+            r.detail,
+            width = width
+        );
+    }
+    results.iter().all(|r| r.status != CheckStatus::Fail)
+}
@@ -14,10 +14,16 @@
  * limitations under the License.
  */
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicU8, Ordering};
 
+use ring::hmac;
+
 // NpbBandwidthWatcher NewFragmenterBuilder NewCompressorBuilder NewPCapBuilder NewUniformCollectSender
 pub mod npb_sender;
+pub(crate) mod pcap_writer;
 mod tcp_packet;
 pub(crate) mod uniform_sender;
 
@@ -29,3 +35,100 @@ pub fn get_sender_id() -> u8 {
 }
 
 pub(crate) const QUEUE_BATCH_SIZE: usize = 1024;
+
+// Fields hashed with the same salt map to the same values, so that they can still be
+// correlated after export
+fn hash_field(key: &hmac::Key, value: &[u8]) -> hmac::Tag {
+    hmac::sign(key, value)
+}
+
+// replaced by 16 hex digits, empty strings are kept
+pub(crate) fn hash_field_str(key: &hmac::Key, value: &mut String) {
+    if value.is_empty() {
+        return;
+    }
+    let tag = hash_field(key, value.as_bytes());
+    *value = hex::encode(&tag.as_ref()[..8]);
+}
+
+// ipv4 address stays a 4 bytes value, zero is kept as not set
+pub(crate) fn hash_field_u32(key: &hmac::Key, value: &mut u32) {
+    if *value == 0 {
+        return;
+    }
+    let tag = hash_field(key, &value.to_be_bytes());
+    *value = u32::from_be_bytes(tag.as_ref()[..4].try_into().unwrap());
+}
+
+// keeps the length, for ipv6 addresses
+pub(crate) fn hash_field_bytes(key: &hmac::Key, value: &mut Vec<u8>) {
+    if value.is_empty() {
+        return;
+    }
+    let tag = hash_field(key, value);
+    let len = value.len().min(tag.as_ref().len());
+    value.truncate(len);
+    value.copy_from_slice(&tag.as_ref()[..len]);
+}
+
+// both directions of a flow have the same key
+pub(crate) fn flow_balance_key(
+    ip_src: IpAddr,
+    ip_dst: IpAddr,
+    port_src: u16,
+    port_dst: u16,
+    protocol: u8,
+) -> u64 {
+    let (a, b) = if (ip_src, port_src) <= (ip_dst, port_dst) {
+        ((ip_src, port_src), (ip_dst, port_dst))
+    } else {
+        ((ip_dst, port_dst), (ip_src, port_src))
+    };
+    let mut hasher = DefaultHasher::new();
+    (a, b, protocol).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_fields() {
+        let salt = hmac::Key::new(hmac::HMAC_SHA256, b"salt");
+        let pepper = hmac::Key::new(hmac::HMAC_SHA256, b"pepper");
+        let mut a = "10.1.2.3".to_owned();
+        let mut b = a.clone();
+        hash_field_str(&salt, &mut a);
+        hash_field_str(&salt, &mut b);
+        assert_eq!(a.len(), 16);
+        assert_eq!(a, b);
+        let mut c = "10.1.2.3".to_owned();
+        hash_field_str(&pepper, &mut c);
+        assert_ne!(a, c);
+
+        let mut ip = 0x0a010203;
+        hash_field_u32(&salt, &mut ip);
+        assert_ne!(ip, 0x0a010203);
+        let mut unset = 0;
+        hash_field_u32(&salt, &mut unset);
+        assert_eq!(unset, 0);
+
+        let mut ip6 = vec![0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        hash_field_bytes(&salt, &mut ip6);
+        assert_eq!(ip6.len(), 16);
+    }
+
+    #[test]
+    fn flow_balance_keys() {
+        let (a, b): (IpAddr, IpAddr) = ("10.1.2.3".parse().unwrap(), "10.1.2.4".parse().unwrap());
+        assert_eq!(
+            flow_balance_key(a, b, 12345, 80, 6),
+            flow_balance_key(b, a, 80, 12345, 6)
+        );
+        assert_ne!(
+            flow_balance_key(a, b, 12345, 80, 6),
+            flow_balance_key(a, b, 12346, 80, 6)
+        );
+    }
+}
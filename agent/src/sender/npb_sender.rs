@@ -15,6 +15,7 @@
  */
 
 use std::collections::HashMap;
+use std::fmt;
 use std::io::{Error as IOError, ErrorKind, Result as IOResult};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddrV4, SocketAddrV6};
 #[cfg(unix)]
@@ -31,7 +32,7 @@ use std::time::{Duration, SystemTime};
 
 #[cfg(unix)]
 use libc::{c_int, socket, AF_INET, AF_INET6, SOCK_RAW};
-use log::{info, warn};
+use log::{debug, info, warn};
 use socket2::{Domain, SockAddr, Socket, Type};
 #[cfg(windows)]
 use windows::Win32::Networking::WinSock::socket;
@@ -39,22 +40,25 @@ use windows::Win32::Networking::WinSock::socket;
 use super::QUEUE_BATCH_SIZE;
 
 use crate::common::{
-    enums::IpProtocol, erspan, vxlan, IPV4_ADDR_LEN, IPV4_DST_OFFSET, IPV4_PACKET_SIZE,
-    IPV4_PROTO_OFFSET, IPV6_ADDR_LEN, IPV6_DST_OFFSET, IPV6_PACKET_SIZE, IPV6_PROTO_OFFSET,
-    TCP6_PACKET_SIZE, TCP_PACKET_SIZE, UDP6_PACKET_SIZE, UDP_PACKET_SIZE,
+    enums::{EthernetType, IpProtocol},
+    erspan, udp, vxlan, ERSPAN_HEADER_SIZE, ETH_HEADER_SIZE, ETH_TYPE_OFFSET, GRE_HEADER_SIZE,
+    IPV4_ADDR_LEN, IPV4_DST_OFFSET, IPV4_PACKET_SIZE, IPV4_PROTO_OFFSET, IPV4_TOTAL_LENGTH_OFFSET,
+    IPV6_ADDR_LEN, IPV6_DST_OFFSET, IPV6_PACKET_SIZE, IPV6_PAYLOAD_LENGTH_OFFSET,
+    IPV6_PROTO_OFFSET, TCP6_PACKET_SIZE, TCP_PACKET_SIZE, UDP6_PACKET_SIZE, UDP_HEADER_SIZE,
+    UDP_PACKET_SIZE, VLAN_HEADER_SIZE, VXLAN_HEADER_SIZE,
 };
 #[cfg(unix)]
 use crate::common::{
-    ETH_HEADER_SIZE, IPV4_CSUM_OFFSET, IPV4_HEADER_SIZE, IPV4_SRC_OFFSET, IPV6_SRC_OFFSET,
-    UDP6_CHKSUM_OFFSET,
+    IPV4_CSUM_OFFSET, IPV4_HEADER_SIZE, IPV4_SRC_OFFSET, IPV6_SRC_OFFSET, UDP6_CHKSUM_OFFSET,
 };
-use crate::config::NpbConfig;
+use crate::config::{NpbConfig, NpbEncapsulationConfig, NpbEncapsulationType};
 #[cfg(unix)]
 use crate::dispatcher::af_packet::{Options, Tpacket};
 use crate::exception::ExceptionHandler;
 use crate::utils::stats;
 use npb_handler::{NpbHeader, NOT_SUPPORT};
-use public::counter::{Countable, CounterType, CounterValue, OwnedCountable};
+use public::counter::{Countable, CounterType, CounterValue, OwnedCountable, RefCountable};
+use public::leaky_bucket::LeakyBucket;
 use public::proto::trident::{Exception, SocketType};
 use public::queue::Receiver;
 #[cfg(unix)]
@@ -517,6 +521,330 @@ impl OwnedCountable for StatsNpbSenderCounter {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NpbEncapsulation {
+    Vxlan { vni: u32 },
+    Erspan { session_id: u16 },
+    Vlan { vlan_id: u16 },
+}
+
+impl fmt::Display for NpbEncapsulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Vxlan { vni } => write!(f, "vxlan vni {}", vni),
+            Self::Erspan { session_id } => write!(f, "erspan session id {}", session_id),
+            Self::Vlan { vlan_id } => write!(f, "vlan id {}", vlan_id),
+        }
+    }
+}
+
+impl NpbEncapsulation {
+    const VXLAN_VNI_MAX: u32 = (1 << 24) - 1;
+    const ERSPAN_SESSION_ID_MASK: u16 = 0x3ff;
+    const VLAN_ID_MAX: u16 = 4094;
+
+    pub fn parse(config: &NpbEncapsulationConfig) -> Result<(IpAddr, Self), String> {
+        let destination = config.destination.parse::<IpAddr>().map_err(|_| {
+            format!(
+                "invalid npb encapsulation destination {:?}",
+                config.destination
+            )
+        })?;
+        let (vni, vlan_id, session_id) = (config.vni, config.vlan_id, config.erspan_session_id);
+        let encapsulation = match config.encapsulation_type {
+            NpbEncapsulationType::Vxlan if vlan_id.is_none() && session_id.is_none() => match vni {
+                Some(vni) if vni <= Self::VXLAN_VNI_MAX => Self::Vxlan { vni },
+                _ => {
+                    return Err(format!(
+                        "vxlan vni of {} is missing or invalid",
+                        destination
+                    ))
+                }
+            },
+            NpbEncapsulationType::Erspan if vni.is_none() && vlan_id.is_none() => {
+                match session_id {
+                    Some(session_id) if session_id <= Self::ERSPAN_SESSION_ID_MASK => {
+                        Self::Erspan { session_id }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "erspan session id of {} is missing or invalid",
+                            destination
+                        ))
+                    }
+                }
+            }
+            NpbEncapsulationType::Vlan if vni.is_none() && session_id.is_none() => match vlan_id {
+                Some(vlan_id) if vlan_id > 0 && vlan_id <= Self::VLAN_ID_MAX => {
+                    Self::Vlan { vlan_id }
+                }
+                _ => return Err(format!("vlan id of {} is missing or invalid", destination)),
+            },
+            t => {
+                return Err(format!(
+                    "{:?} encapsulation of {} sets mutually exclusive parameters",
+                    t, destination
+                ))
+            }
+        };
+        Ok((destination, encapsulation))
+    }
+
+    const VXLAN_SRC_PORT: u16 = 49152;
+    const GRE_FLAGS_KEY_SEQ: u16 = 0x3000;
+    const ERSPAN_TYPE3_VER: u8 = 0x20;
+    const ERSPAN_TYPE3_FLAGS: u8 = 6;
+
+    fn vxlan_header(npb_port: u16, vxlan_flags: u8, vni: u32) -> Vec<u8> {
+        let mut header = vec![0u8; UDP_HEADER_SIZE + VXLAN_HEADER_SIZE];
+        header[udp::SRC_OFFSET..udp::SRC_OFFSET + 2]
+            .copy_from_slice(&Self::VXLAN_SRC_PORT.to_be_bytes());
+        header[udp::DST_OFFSET..udp::DST_OFFSET + 2].copy_from_slice(&npb_port.to_be_bytes());
+        let offset = UDP_HEADER_SIZE;
+        header[offset + vxlan::FLAGS_OFFSET] = vxlan_flags;
+        header[offset + vxlan::VNI_OFFSET..offset + vxlan::VNI_OFFSET + 3]
+            .copy_from_slice(&vni.to_be_bytes()[1..]);
+        header
+    }
+
+    fn erspan_header(session_id: u16) -> Vec<u8> {
+        let mut header = vec![0u8; GRE_HEADER_SIZE + ERSPAN_HEADER_SIZE];
+        header[..2].copy_from_slice(&Self::GRE_FLAGS_KEY_SEQ.to_be_bytes());
+        header[erspan::GRE_PROTO_OFFSET..erspan::GRE_PROTO_OFFSET + 2]
+            .copy_from_slice(&(erspan::GRE_PROTO_ERSPAN_III as u16).to_be_bytes());
+        let offset = GRE_HEADER_SIZE;
+        header[offset + erspan::TYPE3_VER_OFFSET] = Self::ERSPAN_TYPE3_VER;
+        header[offset + erspan::TYPE3_SESSION_ID_OFFSET
+            ..offset + erspan::TYPE3_SESSION_ID_OFFSET + 2]
+            .copy_from_slice(&session_id.to_be_bytes());
+        header[offset + erspan::TYPE3_FLAGS_OFFSET] = Self::ERSPAN_TYPE3_FLAGS;
+        header
+    }
+
+    // Rebuilds the outer header of a packet built by the npb handler with the configured
+    // encapsulation and returns the new underlay l2 option size.
+    // A tunnel of the same type only has its parameter rewritten, a tunnel of another type is
+    // replaced, and vlan inserts an 802.1Q header if the underlay has none.
+    // IPv4 checksum and IPv6 udp checksum are filled by the sender.
+    fn apply(
+        &self,
+        underlay_is_ipv6: bool,
+        underlay_l2_opt_size: usize,
+        npb_port: u16,
+        vxlan_flags: u8,
+        packet: &mut Vec<u8>,
+    ) -> IOResult<usize> {
+        let (protocol_offset, ip_end) = if underlay_is_ipv6 {
+            (
+                underlay_l2_opt_size + IPV6_PROTO_OFFSET,
+                underlay_l2_opt_size + IPV6_PACKET_SIZE,
+            )
+        } else {
+            (
+                underlay_l2_opt_size + IPV4_PROTO_OFFSET,
+                underlay_l2_opt_size + IPV4_PACKET_SIZE,
+            )
+        };
+        let protocol = IpProtocol::from(packet.get(protocol_offset).copied().unwrap_or_default());
+        let tunnel_size = match protocol {
+            IpProtocol::UDP => UDP_HEADER_SIZE + VXLAN_HEADER_SIZE,
+            IpProtocol::GRE => GRE_HEADER_SIZE + ERSPAN_HEADER_SIZE,
+            _ => 0,
+        };
+        if tunnel_size == 0 || packet.len() < ip_end + tunnel_size {
+            return Err(IOError::new(
+                ErrorKind::InvalidData,
+                format!("npb packet has no tunnel header for encapsulation {}", self),
+            ));
+        }
+
+        let tunnel = match self {
+            Self::Vxlan { vni } if protocol == IpProtocol::UDP => {
+                let offset = ip_end + UDP_HEADER_SIZE + vxlan::VNI_OFFSET;
+                packet[offset..offset + 3].copy_from_slice(&vni.to_be_bytes()[1..]);
+                None
+            }
+            Self::Vxlan { vni } => Some((
+                IpProtocol::UDP,
+                Self::vxlan_header(npb_port, vxlan_flags, *vni),
+            )),
+            Self::Erspan { session_id } if protocol == IpProtocol::GRE => {
+                let offset = ip_end + GRE_HEADER_SIZE + erspan::TYPE3_SESSION_ID_OFFSET;
+                let value = (u16::from_be_bytes([packet[offset], packet[offset + 1]])
+                    & !Self::ERSPAN_SESSION_ID_MASK)
+                    | session_id;
+                packet[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+                None
+            }
+            Self::Erspan { session_id } => {
+                Some((IpProtocol::GRE, Self::erspan_header(*session_id)))
+            }
+            Self::Vlan { vlan_id } if underlay_l2_opt_size >= VLAN_HEADER_SIZE => {
+                let offset = ETH_HEADER_SIZE;
+                // keep the priority code point and drop eligible indicator
+                let value =
+                    (u16::from_be_bytes([packet[offset], packet[offset + 1]]) & 0xf000) | vlan_id;
+                packet[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+                return Ok(underlay_l2_opt_size);
+            }
+            Self::Vlan { vlan_id } => {
+                let mut vlan_header = [0u8; VLAN_HEADER_SIZE];
+                vlan_header[..2].copy_from_slice(&u16::from(EthernetType::DOT1Q).to_be_bytes());
+                vlan_header[2..].copy_from_slice(&vlan_id.to_be_bytes());
+                packet.splice(ETH_TYPE_OFFSET..ETH_TYPE_OFFSET, vlan_header);
+                return Ok(underlay_l2_opt_size + VLAN_HEADER_SIZE);
+            }
+        };
+
+        if let Some((protocol, header)) = tunnel {
+            packet.splice(ip_end..ip_end + tunnel_size, header);
+            packet[protocol_offset] = u8::from(protocol);
+            if protocol == IpProtocol::UDP {
+                let offset = ip_end + udp::LENGTH_OFFSET;
+                let udp_length = (packet.len() - ip_end) as u16;
+                packet[offset..offset + 2].copy_from_slice(&udp_length.to_be_bytes());
+            }
+            if underlay_is_ipv6 {
+                let offset = underlay_l2_opt_size + IPV6_PAYLOAD_LENGTH_OFFSET;
+                let payload_length = (packet.len() - ip_end) as u16;
+                packet[offset..offset + 2].copy_from_slice(&payload_length.to_be_bytes());
+            } else {
+                let offset = underlay_l2_opt_size + IPV4_TOTAL_LENGTH_OFFSET;
+                let total_length = (packet.len() - underlay_l2_opt_size - ETH_HEADER_SIZE) as u16;
+                packet[offset..offset + 2].copy_from_slice(&total_length.to_be_bytes());
+            }
+        }
+        Ok(underlay_l2_opt_size)
+    }
+}
+
+#[derive(Default)]
+pub struct NpbDestinationCounter {
+    pub tx_bytes: AtomicUsize,
+    pub dropped_bytes: AtomicUsize,
+}
+
+pub struct StatsNpbDestinationCounter(Weak<NpbDestinationCounter>);
+
+impl OwnedCountable for StatsNpbDestinationCounter {
+    fn closed(&self) -> bool {
+        return self.0.strong_count() == 0;
+    }
+
+    fn get_counters(&self) -> Vec<public::counter::Counter> {
+        match self.0.upgrade() {
+            Some(x) => {
+                vec![
+                    (
+                        "tx_bytes",
+                        CounterType::Counted,
+                        CounterValue::Unsigned(x.tx_bytes.swap(0, Ordering::Relaxed) as u64),
+                    ),
+                    (
+                        "dropped_bytes",
+                        CounterType::Counted,
+                        CounterValue::Unsigned(x.dropped_bytes.swap(0, Ordering::Relaxed) as u64),
+                    ),
+                ]
+            }
+            None => vec![],
+        }
+    }
+}
+
+struct NpbDestinationStats {
+    id: usize,
+    remote: IpAddr,
+}
+
+impl stats::Module for NpbDestinationStats {
+    fn name(&self) -> &'static str {
+        "npb_destination"
+    }
+
+    fn tags(&self) -> Vec<stats::StatsOption> {
+        vec![
+            stats::StatsOption::Tag("id", self.id.to_string()),
+            stats::StatsOption::Tag("remote", self.remote.to_string()),
+        ]
+    }
+}
+
+struct NpbBpsLimitStats {
+    id: usize,
+    remote: IpAddr,
+}
+
+impl stats::Module for NpbBpsLimitStats {
+    fn name(&self) -> &'static str {
+        "leaky_bucket"
+    }
+
+    fn tags(&self) -> Vec<stats::StatsOption> {
+        vec![
+            stats::StatsOption::Tag("name", "npb".to_owned()),
+            stats::StatsOption::Tag("id", self.id.to_string()),
+            stats::StatsOption::Tag("remote", self.remote.to_string()),
+        ]
+    }
+}
+
+// Every destination gets its own bucket with an equal share of the global npb bps limit,
+// so a slow collector only exhausts its own budget.
+struct NpbDestination {
+    bps_limit: Arc<LeakyBucket>,
+    counter: Arc<NpbDestinationCounter>,
+    last_timestamp: u64,
+}
+
+impl NpbDestination {
+    fn new(
+        id: usize,
+        remote: &IpAddr,
+        rate: Option<u64>,
+        stats_collector: &Arc<stats::Collector>,
+    ) -> Self {
+        let counter = Arc::new(NpbDestinationCounter::default());
+        stats_collector.register_countable(
+            &NpbDestinationStats {
+                id,
+                remote: *remote,
+            },
+            Countable::Owned(Box::new(StatsNpbDestinationCounter(Arc::downgrade(
+                &counter,
+            )))),
+        );
+        let bps_limit = Arc::new(LeakyBucket::new(rate));
+        stats_collector.register_countable(
+            &NpbBpsLimitStats {
+                id,
+                remote: *remote,
+            },
+            Countable::Ref(Arc::downgrade(&bps_limit) as Weak<dyn RefCountable>),
+        );
+        Self {
+            bps_limit,
+            counter,
+            last_timestamp: 0,
+        }
+    }
+
+    fn acquire(&mut self, timestamp: u64, rate: Option<u64>, bytes: usize) -> bool {
+        self.last_timestamp = timestamp;
+        // follow rate changes of the global bucket, including npb fuse
+        if self.bps_limit.rate() != rate {
+            self.bps_limit.set_rate(rate);
+        }
+        if !self.bps_limit.acquire((bytes << 3) as u64) {
+            self.counter
+                .dropped_bytes
+                .fetch_add(bytes, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+}
+
 #[derive(Debug)]
 pub struct ArpEntry {
     counter: AtomicU32,
@@ -775,22 +1103,37 @@ impl NpbArpTable {
 }
 
 pub struct NpbConnectionPool {
+    id: usize,
     connections: HashMap<(u128, u8), NpbSender>,
+    destinations: HashMap<IpAddr, NpbDestination>,
+    last_expire_timestamp: u64,
+    encapsulations: HashMap<IpAddr, NpbEncapsulation>,
     socket_type: SocketType,
     npb_port: u16,
+    vxlan_flags: u8,
     underlay_is_ipv6: bool,
 
     counter: Arc<NpbSenderCounter>,
 
+    bps_limit: Arc<LeakyBucket>,
     arp: Arc<NpbArpTable>,
+    stats_collector: Arc<stats::Collector>,
 }
 
 impl NpbConnectionPool {
+    // Every destination holds a leaky bucket with its own thread
+    const MAX_DESTINATIONS: usize = 64;
+    // Destinations no longer referenced by npb policies stop receiving packets and expire
+    const DESTINATION_EXPIRE_TIMEOUT: u64 = 60 * 1000000000;
+
     pub fn new(
         id: usize,
         underlay_is_ipv6: bool,
         socket_type: SocketType,
         npb_port: u16,
+        vxlan_flags: u8,
+        bps_limit: Arc<LeakyBucket>,
+        encapsulations: HashMap<IpAddr, NpbEncapsulation>,
         arp: Arc<NpbArpTable>,
         stats_collector: Arc<stats::Collector>,
     ) -> Self {
@@ -811,12 +1154,19 @@ impl NpbConnectionPool {
         }
 
         Self {
+            id,
             connections: HashMap::new(),
+            destinations: HashMap::new(),
+            last_expire_timestamp: 0,
+            encapsulations,
             socket_type,
             npb_port,
+            vxlan_flags,
             underlay_is_ipv6,
             counter,
+            bps_limit,
             arp,
+            stats_collector,
         }
     }
 
@@ -845,13 +1195,8 @@ impl NpbConnectionPool {
         }
     }
 
-    fn send_to(
-        &mut self,
-        timestamp: u64,
-        underlay_l2_opt_size: usize,
-        packet: Vec<u8>,
-    ) -> IOResult<usize> {
-        let (remote, key) = if self.underlay_is_ipv6 {
+    fn parse_remote(&self, underlay_l2_opt_size: usize, packet: &[u8]) -> (IpAddr, (u128, u8)) {
+        if self.underlay_is_ipv6 {
             let offset = IPV6_DST_OFFSET + underlay_l2_opt_size;
             let ip = Ipv6Addr::from(
                 *<&[u8; 16]>::try_from(&packet[offset..offset + IPV6_ADDR_LEN]).unwrap(),
@@ -875,8 +1220,17 @@ impl NpbConnectionPool {
                     packet[IPV4_PROTO_OFFSET + underlay_l2_opt_size],
                 ),
             )
-        };
+        }
+    }
 
+    fn send_to(
+        &mut self,
+        remote: IpAddr,
+        key: (u128, u8),
+        timestamp: u64,
+        underlay_l2_opt_size: usize,
+        packet: Vec<u8>,
+    ) -> IOResult<usize> {
         let mut conn = self.connections.get_mut(&key);
         if conn.is_some() {
             return conn
@@ -895,20 +1249,114 @@ impl NpbConnectionPool {
         return ret;
     }
 
+    fn remove_destination(&mut self, remote: &IpAddr) {
+        self.destinations.remove(remote);
+        let ip = match remote {
+            IpAddr::V4(ip) => u32::from(*ip) as u128,
+            IpAddr::V6(ip) => u128::from(*ip),
+        };
+        self.connections.retain(|(k, _), _| *k != ip);
+    }
+
+    fn expire_destinations(&mut self, timestamp: u64) {
+        if self.last_expire_timestamp + Self::DESTINATION_EXPIRE_TIMEOUT > timestamp {
+            return;
+        }
+        self.last_expire_timestamp = timestamp;
+        let expired = self
+            .destinations
+            .iter()
+            .filter(|(_, d)| d.last_timestamp + Self::DESTINATION_EXPIRE_TIMEOUT <= timestamp)
+            .map(|(remote, _)| *remote)
+            .collect::<Vec<_>>();
+        for remote in expired {
+            debug!("Npb destination {} expired.", remote);
+            self.remove_destination(&remote);
+        }
+    }
+
+    fn add_destination(&mut self, remote: IpAddr) {
+        if self.destinations.len() >= Self::MAX_DESTINATIONS {
+            let oldest = self
+                .destinations
+                .iter()
+                .min_by_key(|(_, d)| d.last_timestamp)
+                .map(|(remote, _)| *remote)
+                .unwrap();
+            warn!(
+                "Npb destinations exceed {}, evict least recently used {}.",
+                Self::MAX_DESTINATIONS,
+                oldest
+            );
+            self.remove_destination(&oldest);
+        }
+        self.destinations.insert(
+            remote,
+            NpbDestination::new(self.id, &remote, None, &self.stats_collector),
+        );
+    }
+
     pub fn send(
         &mut self,
         timestamp: u64,
-        underlay_l2_opt_size: usize,
-        packet: Vec<u8>,
+        mut underlay_l2_opt_size: usize,
+        mut packet: Vec<u8>,
     ) -> IOResult<usize> {
         let bytes = packet.len();
-        let ret = self.send_to(timestamp, underlay_l2_opt_size, packet);
+        let (remote, mut key) = self.parse_remote(underlay_l2_opt_size, &packet);
+
+        self.expire_destinations(timestamp);
+        if !self.destinations.contains_key(&remote) {
+            self.add_destination(remote);
+        }
+        // split the global rate evenly among active destinations
+        let rate = self
+            .bps_limit
+            .rate()
+            .map(|r| 1.max(r / self.destinations.len() as u64));
+        let destination = self.destinations.get_mut(&remote).unwrap();
+        if !destination.acquire(timestamp, rate, bytes) {
+            self.counter.tx_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        let destination_counter = destination.counter.clone();
+
+        if let Some(encapsulation) = self.encapsulations.get(&remote) {
+            match encapsulation.apply(
+                self.underlay_is_ipv6,
+                underlay_l2_opt_size,
+                self.npb_port,
+                self.vxlan_flags,
+                &mut packet,
+            ) {
+                Ok(size) => {
+                    underlay_l2_opt_size = size;
+                    // the tunnel protocol may have been changed
+                    key = self.parse_remote(underlay_l2_opt_size, &packet).1;
+                }
+                Err(e) => {
+                    self.counter.tx_dropped.fetch_add(1, Ordering::Relaxed);
+                    destination_counter
+                        .dropped_bytes
+                        .fetch_add(bytes, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+
+        let ret = self.send_to(remote, key, timestamp, underlay_l2_opt_size, packet);
         if ret.is_err() {
             self.counter.tx_dropped.fetch_add(1, Ordering::Relaxed);
+            destination_counter
+                .dropped_bytes
+                .fetch_add(bytes, Ordering::Relaxed);
             return ret;
         }
         self.counter.tx.fetch_add(1, Ordering::Relaxed);
         self.counter.tx_bytes.fetch_add(bytes, Ordering::Relaxed);
+        destination_counter
+            .tx_bytes
+            .fetch_add(bytes, Ordering::Relaxed);
         return ret;
     }
 
@@ -929,6 +1377,8 @@ impl NpbPacketSender {
         id: usize,
         receiver: Receiver<(u64, usize, Vec<u8>)>,
         config: &NpbConfig,
+        bps_limit: Arc<LeakyBucket>,
+        encapsulations: HashMap<IpAddr, NpbEncapsulation>,
         arp: Arc<NpbArpTable>,
         stats_collector: Arc<stats::Collector>,
     ) -> Self {
@@ -938,6 +1388,9 @@ impl NpbPacketSender {
                 config.underlay_is_ipv6,
                 config.socket_type,
                 config.npb_port,
+                config.vxlan_flags,
+                bps_limit,
+                encapsulations,
                 arp.clone(),
                 stats_collector,
             )),
@@ -984,3 +1437,170 @@ impl NpbPacketSender {
         self.disable.store(true, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use public::consts::{
+        ERSPAN6_SESSION_ID_OFFSET, ERSPAN_PACKET_SIZE, ERSPAN_SESSION_ID_OFFSET, GRE4_PROTO_OFFSET,
+        IPV4_HEADER_SIZE, IPV6_HEADER_SIZE, NPB_DEFAULT_PORT, UDP6_DST_OFFSET, UDP6_LENGTH_OFFSET,
+        VXLAN6_FLAGS_OFFSET, VXLAN6_PACKET_SIZE, VXLAN6_VNI_OFFSET, VXLAN_VNI_OFFSET,
+    };
+
+    const PAYLOAD: [u8; 20] = [0xaa; 20];
+
+    fn npb_packet(underlay_is_ipv6: bool, protocol: IpProtocol) -> Vec<u8> {
+        let mut packet = vec![0u8; ETH_HEADER_SIZE];
+        if underlay_is_ipv6 {
+            packet[ETH_TYPE_OFFSET..].copy_from_slice(&u16::from(EthernetType::IPV6).to_be_bytes());
+            let mut ip = vec![0u8; IPV6_HEADER_SIZE];
+            ip[0] = 0x60;
+            ip[IPV6_PROTO_OFFSET - ETH_HEADER_SIZE] = u8::from(protocol);
+            packet.append(&mut ip);
+        } else {
+            packet[ETH_TYPE_OFFSET..].copy_from_slice(&u16::from(EthernetType::IPV4).to_be_bytes());
+            let mut ip = vec![0u8; IPV4_HEADER_SIZE];
+            ip[0] = 0x45;
+            ip[IPV4_PROTO_OFFSET - ETH_HEADER_SIZE] = u8::from(protocol);
+            packet.append(&mut ip);
+        }
+        if protocol == IpProtocol::UDP {
+            packet.append(&mut NpbEncapsulation::vxlan_header(
+                NPB_DEFAULT_PORT,
+                0xff,
+                1,
+            ));
+        } else {
+            packet.append(&mut NpbEncapsulation::erspan_header(1));
+        }
+        packet.extend_from_slice(&PAYLOAD);
+        packet
+    }
+
+    #[test]
+    fn encapsulation_vxlan() {
+        let encapsulation = NpbEncapsulation::Vxlan { vni: 0x123456 };
+
+        let mut packet = npb_packet(false, IpProtocol::UDP);
+        let size = packet.len();
+        let opt_size = encapsulation
+            .apply(false, 0, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .unwrap();
+        assert_eq!(opt_size, 0);
+        assert_eq!(packet.len(), size);
+        assert_eq!(
+            &packet[VXLAN_VNI_OFFSET..VXLAN_VNI_OFFSET + 3],
+            &[0x12, 0x34, 0x56]
+        );
+
+        // erspan replaced by vxlan
+        let mut packet = npb_packet(true, IpProtocol::GRE);
+        encapsulation
+            .apply(true, 0, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .unwrap();
+        assert_eq!(packet.len(), VXLAN6_PACKET_SIZE + PAYLOAD.len());
+        assert_eq!(packet[IPV6_PROTO_OFFSET], IpProtocol::UDP);
+        assert_eq!(
+            &packet[IPV6_PAYLOAD_LENGTH_OFFSET..IPV6_PAYLOAD_LENGTH_OFFSET + 2],
+            &((UDP_HEADER_SIZE + VXLAN_HEADER_SIZE + PAYLOAD.len()) as u16).to_be_bytes()
+        );
+        assert_eq!(
+            &packet[UDP6_DST_OFFSET..UDP6_DST_OFFSET + 2],
+            &NPB_DEFAULT_PORT.to_be_bytes()
+        );
+        assert_eq!(
+            &packet[UDP6_LENGTH_OFFSET..UDP6_LENGTH_OFFSET + 2],
+            &((UDP_HEADER_SIZE + VXLAN_HEADER_SIZE + PAYLOAD.len()) as u16).to_be_bytes()
+        );
+        assert_eq!(packet[VXLAN6_FLAGS_OFFSET], 0xff);
+        assert_eq!(
+            &packet[VXLAN6_VNI_OFFSET..VXLAN6_VNI_OFFSET + 3],
+            &[0x12, 0x34, 0x56]
+        );
+        assert_eq!(&packet[VXLAN6_PACKET_SIZE..], &PAYLOAD);
+    }
+
+    #[test]
+    fn encapsulation_erspan() {
+        let encapsulation = NpbEncapsulation::Erspan { session_id: 0x3ff };
+
+        let mut packet = npb_packet(true, IpProtocol::GRE);
+        let size = packet.len();
+        encapsulation
+            .apply(true, 0, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .unwrap();
+        assert_eq!(packet.len(), size);
+        assert_eq!(
+            &packet[ERSPAN6_SESSION_ID_OFFSET..ERSPAN6_SESSION_ID_OFFSET + 2],
+            &0x3ffu16.to_be_bytes()
+        );
+
+        // vxlan replaced by erspan
+        let mut packet = npb_packet(false, IpProtocol::UDP);
+        encapsulation
+            .apply(false, 0, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .unwrap();
+        assert_eq!(packet.len(), ERSPAN_PACKET_SIZE + PAYLOAD.len());
+        assert_eq!(packet[IPV4_PROTO_OFFSET], IpProtocol::GRE);
+        assert_eq!(
+            &packet[IPV4_TOTAL_LENGTH_OFFSET..IPV4_TOTAL_LENGTH_OFFSET + 2],
+            &((packet.len() - ETH_HEADER_SIZE) as u16).to_be_bytes()
+        );
+        assert_eq!(
+            &packet[GRE4_PROTO_OFFSET..GRE4_PROTO_OFFSET + 2],
+            &(erspan::GRE_PROTO_ERSPAN_III as u16).to_be_bytes()
+        );
+        assert_eq!(
+            &packet[ERSPAN_SESSION_ID_OFFSET..ERSPAN_SESSION_ID_OFFSET + 2],
+            &0x3ffu16.to_be_bytes()
+        );
+        assert_eq!(&packet[ERSPAN_PACKET_SIZE..], &PAYLOAD);
+    }
+
+    #[test]
+    fn encapsulation_vlan() {
+        let encapsulation = NpbEncapsulation::Vlan { vlan_id: 100 };
+
+        // vlan header inserted
+        let mut packet = npb_packet(false, IpProtocol::UDP);
+        let size = packet.len();
+        let opt_size = encapsulation
+            .apply(false, 0, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .unwrap();
+        assert_eq!(opt_size, VLAN_HEADER_SIZE);
+        assert_eq!(packet.len(), size + VLAN_HEADER_SIZE);
+        assert_eq!(
+            &packet[ETH_TYPE_OFFSET..ETH_HEADER_SIZE + VLAN_HEADER_SIZE],
+            &[0x81, 0x00, 0, 100, 0x08, 0x00]
+        );
+        assert_eq!(packet[opt_size + IPV4_PROTO_OFFSET], IpProtocol::UDP);
+
+        // vlan id rewritten
+        let encapsulation = NpbEncapsulation::Vlan { vlan_id: 200 };
+        let opt_size = encapsulation
+            .apply(false, opt_size, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .unwrap();
+        assert_eq!(opt_size, VLAN_HEADER_SIZE);
+        assert_eq!(packet.len(), size + VLAN_HEADER_SIZE);
+        assert_eq!(
+            &packet[ETH_HEADER_SIZE..ETH_HEADER_SIZE + 2],
+            &200u16.to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn encapsulation_without_tunnel() {
+        let mut packet = npb_packet(false, IpProtocol::UDP);
+        packet[IPV4_PROTO_OFFSET] = u8::from(IpProtocol::TCP);
+        assert!(NpbEncapsulation::Vxlan { vni: 1 }
+            .apply(false, 0, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .is_err());
+
+        let mut packet = npb_packet(false, IpProtocol::GRE);
+        packet.truncate(ERSPAN_PACKET_SIZE - 1);
+        assert!(NpbEncapsulation::Vxlan { vni: 1 }
+            .apply(false, 0, NPB_DEFAULT_PORT, 0xff, &mut packet)
+            .is_err());
+    }
+}
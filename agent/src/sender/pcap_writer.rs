@@ -0,0 +1,387 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Weak,
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use arc_swap::access::Access;
+use log::{info, warn};
+
+use super::{uniform_sender::SenderCounter, QUEUE_BATCH_SIZE};
+
+use crate::config::handler::PcapAccess;
+use crate::utils::stats::{self, Collector, Countable, RefCountable};
+use pcap_assembler::BoxedPcapBatch;
+use public::consts::PCAP_MAGIC;
+use public::queue::{Error, Receiver};
+
+const PCAP_FILE_PREFIX: &str = "deepflow-agent-";
+const PCAP_FILE_SUFFIX: &str = ".pcap";
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const PCAP_SNAP_LEN: u32 = 65535;
+const PCAP_LINK_TYPE_ETHERNET: u32 = 1;
+
+// Writes the pcap global header, records in `Pcap.packet_records` already carry
+// their own record headers and can be appended as is. The magic tells the timestamp
+// precision of the records.
+fn write_pcap_header<W: Write>(w: &mut W, magic: u32) -> std::io::Result<()> {
+    let mut header = Vec::with_capacity(24);
+    header.extend_from_slice(&magic.to_le_bytes());
+    header.extend_from_slice(&PCAP_VERSION_MAJOR.to_le_bytes());
+    header.extend_from_slice(&PCAP_VERSION_MINOR.to_le_bytes());
+    header.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+    header.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+    header.extend_from_slice(&PCAP_SNAP_LEN.to_le_bytes());
+    header.extend_from_slice(&PCAP_LINK_TYPE_ETHERNET.to_le_bytes());
+    w.write_all(&header)
+}
+
+pub struct PcapFileWriterThread {
+    name: &'static str,
+    input: Arc<Receiver<BoxedPcapBatch>>,
+    config: PcapAccess,
+    stats: Arc<Collector>,
+
+    thread_handle: Option<JoinHandle<()>>,
+    running: Arc<AtomicBool>,
+}
+
+impl PcapFileWriterThread {
+    pub fn new(
+        name: &'static str,
+        input: Arc<Receiver<BoxedPcapBatch>>,
+        config: PcapAccess,
+        stats: Arc<Collector>,
+    ) -> Self {
+        Self {
+            name,
+            input,
+            config,
+            stats,
+            thread_handle: None,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn start(&mut self) {
+        if self.running.swap(true, Ordering::Relaxed) {
+            warn!(
+                "{} pcap file writer already started, do nothing.",
+                self.name
+            );
+            return;
+        }
+
+        let mut writer = PcapFileWriter::new(
+            self.name,
+            self.input.clone(),
+            self.config.clone(),
+            self.running.clone(),
+        );
+        self.stats.register_countable(
+            &stats::SingleTagModule("pcap_file_writer", "name", self.name),
+            Countable::Ref(Arc::downgrade(&writer.counter) as Weak<dyn RefCountable>),
+        );
+        self.thread_handle = Some(
+            thread::Builder::new()
+                .name("pcap-file-writer".to_owned())
+                .spawn(move || writer.process())
+                .unwrap(),
+        );
+        info!("{} pcap file writer started", self.name);
+    }
+
+    pub fn notify_stop(&mut self) -> Option<JoinHandle<()>> {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            warn!(
+                "{} pcap file writer already stopped, do nothing.",
+                self.name
+            );
+            return None;
+        }
+        info!("notified stopping {} pcap file writer", self.name);
+        self.thread_handle.take()
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.notify_stop() {
+            let _ = handle.join();
+            info!("stopped {} pcap file writer", self.name);
+        }
+    }
+}
+
+struct PcapFileWriter {
+    name: &'static str,
+    input: Arc<Receiver<BoxedPcapBatch>>,
+    config: PcapAccess,
+    running: Arc<AtomicBool>,
+    counter: Arc<SenderCounter>,
+
+    buf_writer: Option<BufWriter<File>>,
+    // magic of the current file
+    magic: u32,
+    written_size: u64,
+    opened_at: Instant,
+}
+
+impl PcapFileWriter {
+    const QUEUE_READ_TIMEOUT: Duration = Duration::from_secs(3);
+
+    fn new(
+        name: &'static str,
+        input: Arc<Receiver<BoxedPcapBatch>>,
+        config: PcapAccess,
+        running: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            name,
+            input,
+            config,
+            running,
+            counter: Arc::new(SenderCounter::default()),
+            buf_writer: None,
+            magic: PCAP_MAGIC,
+            written_size: 0,
+            opened_at: Instant::now(),
+        }
+    }
+
+    fn process(&mut self) {
+        let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
+        while self.running.load(Ordering::Relaxed) {
+            match self
+                .input
+                .recv_all(&mut batch, Some(Self::QUEUE_READ_TIMEOUT))
+            {
+                Ok(_) => {
+                    for item in batch.drain(..) {
+                        self.counter.rx.fetch_add(1, Ordering::Relaxed);
+                        if let Err(e) = self.write_batch(item) {
+                            if self.counter.dropped.load(Ordering::Relaxed) == 0 {
+                                warn!("{} pcap file writer write failed: {}", self.name, e);
+                            }
+                            self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+                            // reopen a new file on next write
+                            let _ = self.buf_writer.take();
+                        }
+                    }
+                }
+                Err(Error::Timeout) => {
+                    if let Err(e) = self.flush() {
+                        warn!("{} pcap file writer flush failed: {}", self.name, e);
+                        let _ = self.buf_writer.take();
+                    }
+                }
+                Err(Error::Terminated(..)) => break,
+                Err(Error::BatchTooLarge(_)) => unreachable!(),
+            }
+        }
+        if let Some(mut w) = self.buf_writer.take() {
+            let _ = w.flush();
+        }
+    }
+
+    fn write_batch(&mut self, item: BoxedPcapBatch) -> std::io::Result<()> {
+        let magic = match item.0.magic() {
+            0 => PCAP_MAGIC,
+            m => m,
+        };
+        self.rotate_if_needed()?;
+        // records of different timestamp precisions can not be mixed in one file
+        if self.buf_writer.is_some() && self.magic != magic {
+            if let Some(mut w) = self.buf_writer.take() {
+                w.flush()?;
+            }
+        }
+        if self.buf_writer.is_none() {
+            self.open_new_file(magic)?;
+        }
+        let w = self.buf_writer.as_mut().unwrap();
+        let mut size = 0;
+        for pcap in item.0.batches.iter() {
+            let records = pcap.packet_records();
+            w.write_all(records)?;
+            size += records.len() as u64;
+        }
+        self.written_size += size;
+        self.counter.tx.fetch_add(1, Ordering::Relaxed);
+        self.counter.tx_bytes.fetch_add(size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let Some(w) = self.buf_writer.as_mut() {
+            w.flush()?;
+        }
+        self.rotate_if_needed()
+    }
+
+    // closes the current file if it exceeds size or age limit, a new file is opened on next write
+    fn rotate_if_needed(&mut self) -> std::io::Result<()> {
+        if self.buf_writer.is_none() {
+            return Ok(());
+        }
+        let config = self.config.load();
+        if self.written_size >= (config.file_size as u64) << 20
+            || self.opened_at.elapsed() >= config.file_rotate_interval
+        {
+            if let Some(mut w) = self.buf_writer.take() {
+                w.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn open_new_file(&mut self, magic: u32) -> std::io::Result<()> {
+        let config = self.config.load();
+        let dir = Path::new(&config.file_dir);
+        create_dir_all(dir)?;
+        // keep at most `file_max_count` files including the one about to be created
+        Self::remove_oldest_files(dir, config.file_max_count.saturating_sub(1) as usize)?;
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let path = dir.join(format!(
+            "{}{}{:09}{}",
+            PCAP_FILE_PREFIX,
+            now.as_secs(),
+            now.subsec_nanos(),
+            PCAP_FILE_SUFFIX
+        ));
+        let f = OpenOptions::new().create(true).write(true).open(&path)?;
+        let mut w = BufWriter::new(f);
+        write_pcap_header(&mut w, magic)?;
+        info!("{} pcap file writer opened {}", self.name, path.display());
+        self.buf_writer = Some(w);
+        self.magic = magic;
+        self.written_size = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+
+    fn remove_oldest_files(dir: &Path, keep: usize) -> std::io::Result<()> {
+        // file names contain the creation timestamp, so sorting by name sorts by age
+        let mut files = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .map(|n| n.starts_with(PCAP_FILE_PREFIX) && n.ends_with(PCAP_FILE_SUFFIX))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<PathBuf>>();
+        if files.len() <= keep {
+            return Ok(());
+        }
+        files.sort();
+        for path in files.drain(..files.len() - keep) {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("remove pcap file {} failed: {}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arc_swap::{access::Map, ArcSwap};
+    use public::consts::PCAP_MAGIC_NANO;
+    use public::proto::trident::{Pcap, PcapBatch};
+    use public::queue;
+
+    use crate::config::{handler::ModuleConfig, PcapConfig};
+
+    fn new_writer(dir: &Path) -> PcapFileWriter {
+        let mut module_config = ModuleConfig::default();
+        module_config.pcap.file_dir = dir.to_str().unwrap().to_owned();
+        let current_config = Arc::new(ArcSwap::from_pointee(module_config));
+        let config: PcapAccess = Map::new(current_config, |config| -> &PcapConfig { &config.pcap });
+        let (_, receiver, _) = queue::bounded(1);
+        PcapFileWriter::new(
+            "test",
+            Arc::new(receiver),
+            config,
+            Arc::new(AtomicBool::new(true)),
+        )
+    }
+
+    fn batch(magic: Option<u32>, records: &[u8]) -> BoxedPcapBatch {
+        BoxedPcapBatch(Box::new(PcapBatch {
+            magic,
+            batches: vec![Pcap {
+                packet_records: Some(records.to_vec()),
+                ..Default::default()
+            }],
+        }))
+    }
+
+    fn pcap_files(dir: &Path) -> Vec<Vec<u8>> {
+        let mut files = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        files.into_iter().map(|p| fs::read(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn pcap_header_precision() {
+        for magic in [PCAP_MAGIC, PCAP_MAGIC_NANO] {
+            let mut header = vec![];
+            write_pcap_header(&mut header, magic).unwrap();
+            assert_eq!(header.len(), 24);
+            assert_eq!(header[..4], magic.to_le_bytes());
+            assert_eq!(header[20..], PCAP_LINK_TYPE_ETHERNET.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn rotate_on_magic_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = new_writer(dir.path());
+
+        // microsecond precision if magic is not set
+        writer.write_batch(batch(None, b"usec1")).unwrap();
+        writer
+            .write_batch(batch(Some(PCAP_MAGIC), b"usec2"))
+            .unwrap();
+        writer
+            .write_batch(batch(Some(PCAP_MAGIC_NANO), b"nsec1"))
+            .unwrap();
+        writer.buf_writer.take().unwrap().flush().unwrap();
+
+        let files = pcap_files(dir.path());
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0][..4], PCAP_MAGIC.to_le_bytes());
+        assert_eq!(&files[0][24..], b"usec1usec2");
+        assert_eq!(files[1][..4], PCAP_MAGIC_NANO.to_le_bytes());
+        assert_eq!(&files[1][24..], b"nsec1");
+    }
+}
@@ -17,42 +17,105 @@
 use std::fs::{create_dir_all, rename, File, OpenOptions};
 use std::io::{BufWriter, ErrorKind, Write};
 use std::marker::PhantomData;
-use std::net::{Shutdown, TcpStream};
+use std::net::{IpAddr, Shutdown, TcpStream, ToSocketAddrs};
 use std::path::Path;
 use std::sync::{
     atomic::{AtomicBool, AtomicU64, Ordering},
     Arc, Weak,
 };
 use std::thread::{self, JoinHandle};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use arc_swap::access::Access;
 use log::{debug, error, info, warn};
-use public::sender::{SendMessageType, Sendable};
+use public::sender::{FieldFilter, SendMessageType, Sendable};
 use rand::{thread_rng, RngCore};
 
 use super::{get_sender_id, QUEUE_BATCH_SIZE};
 
-use crate::config::handler::SenderAccess;
+use crate::config::{
+    handler::{ReconnectBackoff, ReconnectBackoffAccess, SenderAccess, SenderConfig},
+    SenderBalance, SenderBatchConfig,
+};
 use crate::exception::ExceptionHandler;
 use crate::utils::stats::{
-    self, Collector, Countable, Counter, CounterType, CounterValue, RefCountable,
+    self, Collector, Countable, Counter, CounterType, CounterValue, Module, RefCountable,
+    StatsOption,
 };
 use public::proto::trident::{Exception, SocketType};
 use public::queue::{Error, Receiver};
 
 const PRE_FILE_SUFFIX: &str = ".pre";
 
+struct TargetStats {
+    message_type: SendMessageType,
+    target: String,
+}
+
+impl Module for TargetStats {
+    fn name(&self) -> &'static str {
+        "collect_sender_target"
+    }
+
+    fn tags(&self) -> Vec<StatsOption> {
+        vec![
+            StatsOption::Tag("type", self.message_type.to_string()),
+            StatsOption::Tag("target", self.target.clone()),
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TargetCounter {
+    pub tx: AtomicU64,
+    pub tx_bytes: AtomicU64,
+    pub dropped: AtomicU64,
+}
+
+impl RefCountable for TargetCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "tx",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.tx.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "tx-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.tx_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "dropped",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.dropped.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SenderCounter {
     pub rx: AtomicU64,
     pub tx: AtomicU64,
     pub tx_bytes: AtomicU64,
     pub dropped: AtomicU64,
+    // index of the ingester in use, 0 is the primary and others are fallbacks
+    pub current_target: AtomicU64,
+    pub failovers: AtomicU64,
+    // connection attempts to ingesters, including the first one
+    pub reconnects: AtomicU64,
+    // dropped without sending in observe mode
+    pub observed: AtomicU64,
+    // batches flushed and items in them, used for tuning sender-batches
+    pub batches: AtomicU64,
+    pub batch_items: AtomicU64,
 }
 
 impl RefCountable for SenderCounter {
     fn get_counters(&self) -> Vec<Counter> {
+        let batches = self.batches.swap(0, Ordering::Relaxed);
+        let batch_items = self.batch_items.swap(0, Ordering::Relaxed);
         vec![
             (
                 "rx",
@@ -74,6 +137,40 @@ impl RefCountable for SenderCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.dropped.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "current-target",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.current_target.load(Ordering::Relaxed)),
+            ),
+            (
+                "failovers",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.failovers.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "reconnects",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.reconnects.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "observed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.observed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "batches",
+                CounterType::Counted,
+                CounterValue::Unsigned(batches),
+            ),
+            (
+                "avg-batch-items",
+                CounterType::Gauged,
+                CounterValue::Float(if batches > 0 {
+                    batch_items as f64 / batches as f64
+                } else {
+                    0.0
+                }),
+            ),
         ]
     }
 }
@@ -120,17 +217,29 @@ impl Header {
 struct Encoder<T> {
     id: usize,
     header: Header,
+    field_filter: FieldFilter,
 
     buffer: Vec<u8>,
+    // items in buffer and when the first of them was cached
+    items: usize,
+    batch_start: Option<Instant>,
     _marker: PhantomData<T>,
 }
 
 impl<T: Sendable> Encoder<T> {
     const BUFFER_LEN: usize = 8192;
-    pub fn new(id: usize, msg_type: SendMessageType, agent_id: u16) -> Self {
+    pub fn new(
+        id: usize,
+        msg_type: SendMessageType,
+        agent_id: u16,
+        field_filter: FieldFilter,
+    ) -> Self {
         Self {
             id,
+            field_filter,
             buffer: Vec::with_capacity(Self::BUFFER_LEN),
+            items: 0,
+            batch_start: None,
             header: Header {
                 msg_type,
                 frame_size: 0,
@@ -154,12 +263,19 @@ impl<T: Sendable> Encoder<T> {
         if self.buffer.is_empty() {
             self.set_msg_type(&s);
             self.add_header();
+            self.batch_start = Some(Instant::now());
         }
+        self.items += 1;
 
         // Reserve 4 bytes pb length
         let offset = self.buffer.len();
         self.buffer.extend_from_slice([0u8; 4].as_slice());
-        match s.encode(&mut self.buffer) {
+        let result = if self.field_filter.is_empty() {
+            s.encode(&mut self.buffer)
+        } else {
+            s.encode_filtered(&mut self.buffer, &self.field_filter)
+        };
+        match result {
             Ok(size) => self.buffer[offset..offset + 4]
                 .copy_from_slice((size as u32).to_le_bytes().as_slice()),
             Err(e) => debug!("encode failed {}", e),
@@ -192,6 +308,13 @@ impl<T: Sendable> Encoder<T> {
             self.header.team_id = config.team_id;
             self.header.organization_id = config.organize_id as u16;
         }
+        if self.field_filter != config.export_field_filter {
+            info!(
+                "{} id {} update export field filter from {:?} to {:?}.",
+                name, id, self.field_filter, config.export_field_filter
+            );
+            self.field_filter = config.export_field_filter.clone();
+        }
     }
 
     pub fn buffer_len(&self) -> usize {
@@ -204,6 +327,44 @@ impl<T: Sendable> Encoder<T> {
 
     pub fn reset_buffer(&mut self) {
         self.buffer.clear();
+        self.items = 0;
+        self.batch_start = None;
+    }
+
+    // full when the encoded size or any limit of `batch` is exceeded
+    fn batch_full(&self, batch: &SenderBatchConfig) -> bool {
+        self.buffer.len() > Self::BUFFER_LEN
+            || (batch.max_items > 0 && self.items >= batch.max_items)
+            || self.batch_expired(batch)
+    }
+
+    fn batch_expired(&self, batch: &SenderBatchConfig) -> bool {
+        self.batch_remaining(batch)
+            .map(|d| d.is_zero())
+            .unwrap_or(false)
+    }
+
+    // time left before the cached batch expires, None if nothing is cached or max-delay is unlimited
+    fn batch_remaining(&self, batch: &SenderBatchConfig) -> Option<Duration> {
+        if batch.max_delay.is_zero() {
+            return None;
+        }
+        self.batch_start
+            .map(|t| batch.max_delay.saturating_sub(t.elapsed()))
+    }
+}
+
+// Requests a sender to flush its cached data without waiting for the queue read timeout
+#[derive(Clone)]
+pub struct SenderFlusher {
+    pub id: usize,
+    pub name: &'static str,
+    flush: Arc<AtomicBool>,
+}
+
+impl SenderFlusher {
+    pub fn flush(&self) {
+        self.flush.store(true, Ordering::Relaxed);
     }
 }
 
@@ -216,8 +377,12 @@ pub struct UniformSenderThread<T> {
     thread_handle: Option<JoinHandle<()>>,
 
     running: Arc<AtomicBool>,
+    flush: Arc<AtomicBool>,
+    reconnect_backoff: Option<ReconnectBackoffAccess>,
     stats: Arc<Collector>,
     exception_handler: ExceptionHandler,
+    // drop data in observe mode, only for senders of collected data
+    observable: bool,
 
     // if true, cache message for batch sending
     // can be turned off if message already cached
@@ -241,12 +406,37 @@ impl<T: Sendable> UniformSenderThread<T> {
             config,
             thread_handle: None,
             running,
+            flush: Default::default(),
+            reconnect_backoff: None,
             stats,
             exception_handler,
+            observable: false,
             cached,
         }
     }
 
+    // reconnect with exponential backoff instead of the default interval, takes effect on start
+    pub fn set_reconnect_backoff(&mut self, reconnect_backoff: ReconnectBackoffAccess) {
+        self.reconnect_backoff = Some(reconnect_backoff);
+    }
+
+    // data is counted and dropped instead of sent in observe mode, takes effect on start
+    pub fn enable_observe_mode(&mut self) {
+        self.observable = true;
+    }
+
+    pub fn input(&self) -> Arc<Receiver<T>> {
+        self.input.clone()
+    }
+
+    pub fn flusher(&self) -> SenderFlusher {
+        SenderFlusher {
+            id: self.id,
+            name: self.name,
+            flush: self.flush.clone(),
+        }
+    }
+
     pub fn start(&mut self) {
         if self.running.swap(true, Ordering::Relaxed) {
             warn!(
@@ -262,8 +452,11 @@ impl<T: Sendable> UniformSenderThread<T> {
             self.input.clone(),
             self.config.clone(),
             self.running.clone(),
+            self.flush.clone(),
+            self.reconnect_backoff.clone(),
             self.stats.clone(),
             self.exception_handler.clone(),
+            self.observable,
             self.cached,
         );
         self.thread_handle = Some(
@@ -304,15 +497,187 @@ impl<T: Sendable> UniformSenderThread<T> {
 struct Connection {
     tcp_stream: Option<TcpStream>,
 
-    reconnect_interval: u8,
+    reconnect_interval: Duration,
 
     dst_ip: String,
     dst_port: u16,
 
+    // the primary ingester followed by fallbacks, dst_ip is dst_ips[dst_index]
+    dst_ips: Vec<String>,
+    dst_index: usize,
+    // consecutive connect or write failures since the last successful send, for backoff
+    failures: u32,
+    // consecutive failures on dst_ip, for failover
+    target_failures: u32,
+    last_failback_probe: Duration,
+    // connecting to the primary ingester in background, not to block sending
+    failback_probe: Option<JoinHandle<Option<TcpStream>>>,
+
     reconnect: bool,
     last_reconnect: Duration,
 }
 
+impl Connection {
+    const FAILOVER_THRESHOLD: u32 = 3;
+    const FAILBACK_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+    const FAILBACK_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+    fn new(dst_ips: Vec<String>, dst_port: u16, reconnect_interval: Duration) -> Self {
+        Self {
+            tcp_stream: None,
+            reconnect_interval,
+            dst_ip: dst_ips[0].clone(),
+            dst_port,
+            dst_ips,
+            dst_index: 0,
+            failures: 0,
+            target_failures: 0,
+            last_failback_probe: Duration::ZERO,
+            failback_probe: None,
+            reconnect: false,
+            last_reconnect: Duration::ZERO,
+        }
+    }
+
+    // not connected and waiting for the next reconnection
+    fn is_down(&self) -> bool {
+        if self.tcp_stream.is_some() || self.failures == 0 {
+            return false;
+        }
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        self.last_reconnect + self.reconnect_interval > now
+    }
+
+    // exponential backoff with equal jitter, the interval is randomized in [base / 2, base]
+    // where base is doubled on each consecutive failure up to the max
+    fn backoff_interval(&self, backoff: &ReconnectBackoff) -> Duration {
+        let base = backoff
+            .initial
+            .saturating_mul(1 << self.failures.min(16))
+            .min(backoff.max);
+        let half = base / 2;
+        half + Duration::from_millis(thread_rng().next_u64() % (half.as_millis() as u64 + 1))
+    }
+
+    fn switch_to(&mut self, index: usize, counter: &SenderCounter) {
+        self.dst_index = index;
+        self.dst_ip = self.dst_ips[index].clone();
+        self.target_failures = 0;
+        counter
+            .current_target
+            .store(index as u64, Ordering::Relaxed);
+    }
+
+    fn on_success(&mut self) {
+        self.failures = 0;
+        self.target_failures = 0;
+    }
+
+    // switch to the next ingester after FAILOVER_THRESHOLD consecutive failures
+    fn on_failure(&mut self, name: &str, counter: &SenderCounter) {
+        self.failures = self.failures.saturating_add(1);
+        self.target_failures += 1;
+        if self.dst_ips.len() <= 1 || self.target_failures < Self::FAILOVER_THRESHOLD {
+            return;
+        }
+        let next = (self.dst_index + 1) % self.dst_ips.len();
+        warn!(
+            "{} sender failover from {}:{} to {}:{}",
+            name, self.dst_ip, self.dst_port, self.dst_ips[next], self.dst_port
+        );
+        self.switch_to(next, counter);
+        counter.failovers.fetch_add(1, Ordering::Relaxed);
+        // start failback probing one interval after the last connection attempt
+        self.last_failback_probe = self.last_reconnect;
+        // connect to the next ingester without waiting for reconnect interval
+        self.reconnect = true;
+        self.last_reconnect = Duration::ZERO;
+    }
+
+    // probe the primary ingester periodically when using a fallback, and switch back if it is reachable
+    fn try_failback(
+        &mut self,
+        name: &str,
+        counter: &SenderCounter,
+        now: Duration,
+        write_timeout: Duration,
+    ) {
+        if self.dst_index == 0 {
+            // the probe is left to finish in background
+            self.failback_probe.take();
+            return;
+        }
+        if let Some(probe) = self.failback_probe.as_ref() {
+            if !probe.is_finished() {
+                return;
+            }
+            let Some(Some(tcp_stream)) = self.failback_probe.take().and_then(|p| p.join().ok())
+            else {
+                return;
+            };
+            self.failback(name, counter, tcp_stream, write_timeout);
+            return;
+        }
+        if self.last_failback_probe > now {
+            self.last_failback_probe = now;
+        }
+        if self.last_failback_probe + Self::FAILBACK_PROBE_INTERVAL > now {
+            return;
+        }
+        self.last_failback_probe = now;
+
+        let (primary, port) = (self.dst_ips[0].clone(), self.dst_port);
+        match thread::Builder::new()
+            .name("sender-failback-probe".to_owned())
+            .spawn(move || {
+                let addr = (primary.as_str(), port)
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addrs| addrs.next())?;
+                TcpStream::connect_timeout(&addr, Self::FAILBACK_PROBE_TIMEOUT).ok()
+            }) {
+            Ok(probe) => self.failback_probe = Some(probe),
+            Err(e) => debug!("{} sender spawn failback probe failed {}", name, e),
+        }
+    }
+
+    fn failback(
+        &mut self,
+        name: &str,
+        counter: &SenderCounter,
+        tcp_stream: TcpStream,
+        write_timeout: Duration,
+    ) {
+        if let Err(e) = tcp_stream.set_write_timeout(Some(write_timeout)) {
+            debug!("{} sender tcp stream set write timeout failed {}", name, e);
+            return;
+        }
+        info!(
+            "{} sender failback from {}:{} to primary {}:{}",
+            name, self.dst_ip, self.dst_port, self.dst_ips[0], self.dst_port
+        );
+        if let Some(t) = self.tcp_stream.take() {
+            if let Err(e) = t.shutdown(Shutdown::Both) {
+                debug!("{} sender tcp stream shutdown failed {}", name, e);
+            }
+        }
+        self.switch_to(0, counter);
+        self.tcp_stream = Some(tcp_stream);
+        self.reconnect = false;
+        self.reconnect_interval = Duration::ZERO;
+    }
+}
+
+// an ingester in the pool for round-robin or hash balancing
+struct PoolTarget<T> {
+    encoder: Encoder<T>,
+    conn: Connection,
+    counter: Arc<TargetCounter>,
+    stats_registered: bool,
+}
+
 pub struct UniformSender<T> {
     id: usize,
     name: &'static str,
@@ -323,12 +688,24 @@ pub struct UniformSender<T> {
     encoder: Encoder<T>,
     conn: Connection,
 
+    // ingesters are used in the order of dest_ip and fallbacks with failover balancing,
+    // otherwise data is distributed over the pool
+    balance: SenderBalance,
+    pool: Vec<PoolTarget<T>>,
+    next_target: usize,
+
     config: SenderAccess,
+    // sender-batches entry of this sender, loaded before each queue read
+    batch: SenderBatchConfig,
 
     running: Arc<AtomicBool>,
+    // set by SenderFlusher, checked after each batch and queue read timeout
+    flush: Arc<AtomicBool>,
+    reconnect_backoff: Option<ReconnectBackoffAccess>,
     stats: Arc<Collector>,
     stats_registered: bool,
     exception_handler: ExceptionHandler,
+    observable: bool,
     buf_writer: Option<BufWriter<File>>,
     file_path: String,
     pre_file_path: String,
@@ -340,7 +717,7 @@ pub struct UniformSender<T> {
 impl<T: Sendable> UniformSender<T> {
     const TCP_WRITE_TIMEOUT: u64 = 3; // s
     const QUEUE_READ_TIMEOUT: u64 = 3; // s
-    const DEFAULT_RECONNECT_INTERVAL: u8 = 10; // s
+    const DEFAULT_RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
 
     pub fn new(
         id: usize,
@@ -348,30 +725,47 @@ impl<T: Sendable> UniformSender<T> {
         input: Arc<Receiver<T>>,
         config: SenderAccess,
         running: Arc<AtomicBool>,
+        flush: Arc<AtomicBool>,
+        reconnect_backoff: Option<ReconnectBackoffAccess>,
         stats: Arc<Collector>,
         exception_handler: ExceptionHandler,
+        observable: bool,
         cached: bool,
     ) -> Self {
         let cfg = config.load();
+        let pool = if cfg.balance == SenderBalance::Failover {
+            vec![]
+        } else {
+            Self::new_pool(&cfg)
+        };
         Self {
             id,
             name,
             input,
             counter: Arc::new(SenderCounter::default()),
-            encoder: Encoder::new(0, SendMessageType::TaggedFlow, config.load().vtap_id),
+            encoder: Encoder::new(
+                0,
+                SendMessageType::TaggedFlow,
+                cfg.vtap_id,
+                cfg.export_field_filter.clone(),
+            ),
             config,
-            conn: Connection {
-                tcp_stream: None,
-                reconnect_interval: Self::DEFAULT_RECONNECT_INTERVAL,
-                dst_ip: cfg.dest_ip.clone(),
-                dst_port: cfg.dest_port,
-                reconnect: false,
-                last_reconnect: Duration::ZERO,
-            },
+            conn: Connection::new(
+                Self::dest_ips(&cfg),
+                cfg.dest_port,
+                Self::DEFAULT_RECONNECT_INTERVAL,
+            ),
+            balance: cfg.balance,
+            pool,
+            next_target: 0,
+            batch: SenderBatchConfig::default(),
             running,
+            flush,
+            reconnect_backoff,
             stats,
             stats_registered: false,
             exception_handler,
+            observable,
             buf_writer: None,
             file_path: String::new(),
             pre_file_path: String::new(),
@@ -380,18 +774,151 @@ impl<T: Sendable> UniformSender<T> {
         }
     }
 
+    fn dest_ips(cfg: &SenderConfig) -> Vec<String> {
+        let mut ips = vec![cfg.dest_ip.clone()];
+        ips.extend(cfg.dest_fallback_ips.iter().cloned());
+        ips
+    }
+
     fn update_dst_ip_and_port(&mut self) {
         let cfg = self.config.load();
 
-        if self.conn.dst_ip != cfg.dest_ip || self.conn.dst_port != cfg.dest_port {
+        if self.conn.dst_ips[0] != cfg.dest_ip
+            || self.conn.dst_ips[1..] != cfg.dest_fallback_ips[..]
+            || self.conn.dst_port != cfg.dest_port
+        {
             info!(
-                "{} sender update dst from {}:{} to {}:{}",
-                self.name, self.conn.dst_ip, self.conn.dst_port, cfg.dest_ip, cfg.dest_port
+                "{} sender update dst from {:?}:{} to {}:{} fallbacks {:?}",
+                self.name,
+                self.conn.dst_ips,
+                self.conn.dst_port,
+                cfg.dest_ip,
+                cfg.dest_port,
+                cfg.dest_fallback_ips
             );
             self.conn.reconnect = true;
             self.conn.last_reconnect = Duration::ZERO;
-            self.conn.dst_ip = cfg.dest_ip.clone();
+            self.conn.dst_ips = Self::dest_ips(&cfg);
             self.conn.dst_port = cfg.dest_port;
+            self.conn.switch_to(0, &self.counter);
+        }
+    }
+
+    fn new_pool(cfg: &SenderConfig) -> Vec<PoolTarget<T>> {
+        Self::dest_ips(cfg)
+            .into_iter()
+            .map(|ip| PoolTarget {
+                encoder: Encoder::new(
+                    0,
+                    SendMessageType::TaggedFlow,
+                    cfg.vtap_id,
+                    cfg.export_field_filter.clone(),
+                ),
+                conn: Connection::new(vec![ip], cfg.dest_port, Duration::ZERO),
+                counter: Arc::new(TargetCounter::default()),
+                stats_registered: false,
+            })
+            .collect()
+    }
+
+    fn update_pool(&mut self) {
+        let cfg = self.config.load();
+        let changed = if cfg.balance != self.balance {
+            info!(
+                "{} sender update balance from {:?} to {:?}",
+                self.name, self.balance, cfg.balance
+            );
+            true
+        } else {
+            self.balance != SenderBalance::Failover
+                && (self.pool.len() != 1 + cfg.dest_fallback_ips.len()
+                    || self.pool[0].conn.dst_ip != cfg.dest_ip
+                    || self.pool[1..]
+                        .iter()
+                        .zip(cfg.dest_fallback_ips.iter())
+                        .any(|(t, ip)| t.conn.dst_ip != *ip)
+                    || self.pool[0].conn.dst_port != cfg.dest_port)
+        };
+        if !changed {
+            return;
+        }
+        self.flush_pool();
+        self.balance = cfg.balance;
+        self.next_target = 0;
+        self.pool = if self.balance == SenderBalance::Failover {
+            vec![]
+        } else {
+            Self::new_pool(&cfg)
+        };
+    }
+
+    // ingesters not connected are skipped until they are due to reconnect
+    fn pick_target(&mut self, send_item: &T) -> usize {
+        let len = self.pool.len();
+        let start = match self.balance {
+            SenderBalance::Hash => send_item
+                .balance_key()
+                .map(|k| (k % len as u64) as usize)
+                .unwrap_or(self.next_target),
+            _ => self.next_target,
+        };
+        let index = (0..len)
+            .map(|i| (start + i) % len)
+            .find(|i| !self.pool[*i].conn.is_down())
+            .unwrap_or(start);
+        if self.balance == SenderBalance::RoundRobin {
+            self.next_target = index;
+        }
+        index
+    }
+
+    fn flush_target(&mut self, index: usize) {
+        let target = &mut self.pool[index];
+        if target.encoder.buffer_len() == 0 {
+            return;
+        }
+        if !target.stats_registered {
+            self.stats.register_countable(
+                &TargetStats {
+                    message_type: target.encoder.header.msg_type,
+                    target: format!("{}:{}", target.conn.dst_ip, target.conn.dst_port),
+                },
+                Countable::Ref(Arc::downgrade(&target.counter) as Weak<dyn RefCountable>),
+            );
+            target.stats_registered = true;
+        }
+        target
+            .encoder
+            .update_header(self.name, self.id, &self.config);
+        target.encoder.set_header_frame_size();
+        let sent = Self::send_buffer(
+            &self.running,
+            &self.name,
+            &self.counter,
+            &self.exception_handler,
+            self.reconnect_backoff.as_ref().map(|b| *b.load()),
+            &mut target.conn,
+            &target.encoder.get_buffer(),
+        );
+        if sent {
+            target.counter.tx.fetch_add(1, Ordering::Relaxed);
+            target
+                .counter
+                .tx_bytes
+                .fetch_add(target.encoder.buffer_len() as u64, Ordering::Relaxed);
+        } else {
+            target.counter.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        self.counter.batches.fetch_add(1, Ordering::Relaxed);
+        self.counter
+            .batch_items
+            .fetch_add(target.encoder.items as u64, Ordering::Relaxed);
+        target.encoder.reset_buffer();
+    }
+
+    fn flush_pool(&mut self) {
+        for i in 0..self.pool.len() {
+            self.flush_target(i);
         }
     }
 
@@ -403,39 +930,68 @@ impl<T: Sendable> UniformSender<T> {
                 &self.name,
                 &self.counter,
                 &self.exception_handler,
+                self.reconnect_backoff.as_ref().map(|b| *b.load()),
                 &mut self.conn,
                 &self.encoder.get_buffer(),
             );
+            self.counter.batches.fetch_add(1, Ordering::Relaxed);
+            self.counter
+                .batch_items
+                .fetch_add(self.encoder.items as u64, Ordering::Relaxed);
             self.encoder.reset_buffer();
         }
     }
 
+    // batches are also checked after each queue read, so that max-delay holds when
+    // items keep coming too slowly to fill a batch
+    fn flush_expired(&mut self) {
+        if self.encoder.batch_expired(&self.batch) {
+            self.check_or_register_counterable(self.encoder.header.msg_type);
+            self.update_dst_ip_and_port();
+            self.encoder.update_header(self.name, self.id, &self.config);
+            self.flush_encoder();
+        }
+        for i in 0..self.pool.len() {
+            if self.pool[i].encoder.batch_expired(&self.batch) {
+                self.flush_target(i);
+            }
+        }
+    }
+
     fn send_buffer(
         running: &Arc<AtomicBool>,
         name: &str,
         counter: &SenderCounter,
         exception_handler: &ExceptionHandler,
+        reconnect_backoff: Option<ReconnectBackoff>,
         conn: &mut Connection,
         buffer: &[u8],
-    ) {
+    ) -> bool {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        conn.try_failback(
+            name,
+            counter,
+            now,
+            Duration::from_secs(Self::TCP_WRITE_TIMEOUT),
+        );
         if conn.reconnect || conn.tcp_stream.is_none() {
             if let Some(t) = conn.tcp_stream.take() {
                 if let Err(e) = t.shutdown(Shutdown::Both) {
                     debug!("{} sender tcp stream shutdown failed {}", name, e);
                 }
             }
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap();
             // If the local timestamp adjustment requires recalculating the interval
             if conn.last_reconnect > now {
                 conn.last_reconnect = now;
             }
-            if conn.last_reconnect + Duration::from_secs(conn.reconnect_interval as u64) > now {
-                return;
+            if conn.last_reconnect + conn.reconnect_interval > now {
+                return false;
             }
 
             conn.last_reconnect = now;
+            counter.reconnects.fetch_add(1, Ordering::Relaxed);
             conn.tcp_stream = TcpStream::connect((conn.dst_ip.clone(), conn.dst_port)).ok();
             if let Some(tcp_stream) = conn.tcp_stream.as_mut() {
                 if let Err(e) =
@@ -443,18 +999,24 @@ impl<T: Sendable> UniformSender<T> {
                 {
                     debug!("{} sender tcp stream set write timeout failed {}", name, e);
                     conn.tcp_stream.take();
-                    return;
+                    return false;
                 }
                 info!(
                     "{} sender tcp connection to {}:{} succeed.",
                     name, conn.dst_ip, conn.dst_port
                 );
                 conn.reconnect = false;
-                conn.reconnect_interval = 0;
+                conn.reconnect_interval = Duration::ZERO;
             } else {
                 if counter.dropped.load(Ordering::Relaxed) == 0 {
                     exception_handler.set(Exception::AnalyzerSocketError);
-                    if conn.dst_ip.is_empty() || conn.dst_ip == "0.0.0.0" {
+                    if conn.dst_ip.is_empty()
+                        || conn
+                            .dst_ip
+                            .parse::<IpAddr>()
+                            .map(|ip| ip.is_unspecified())
+                            .unwrap_or(false)
+                    {
                         warn!("'analyzer_ip' is not assigned, please check whether the Agent is successfully registered");
                     } else {
                         error!(
@@ -464,16 +1026,23 @@ impl<T: Sendable> UniformSender<T> {
                     }
                 }
                 counter.dropped.fetch_add(1, Ordering::Relaxed);
-                // reconnect after waiting 10 seconds + random 5 seconds to prevent frequent reconnection
-                conn.reconnect_interval =
-                    Self::DEFAULT_RECONNECT_INTERVAL + (thread_rng().next_u64() % 5) as u8;
-                return;
+                conn.reconnect_interval = match reconnect_backoff {
+                    Some(backoff) => conn.backoff_interval(&backoff),
+                    // reconnect after waiting 10 seconds + random 5 seconds to prevent frequent reconnection
+                    None => {
+                        Self::DEFAULT_RECONNECT_INTERVAL
+                            + Duration::from_secs(thread_rng().next_u64() % 5)
+                    }
+                };
+                conn.on_failure(name, counter);
+                return false;
             }
         }
 
         let tcp_stream = conn.tcp_stream.as_mut().unwrap();
 
         let mut write_offset = 0usize;
+        let mut sent = false;
         while running.load(Ordering::Relaxed) {
             let result = tcp_stream.write(&buffer[write_offset..]);
             match result {
@@ -484,6 +1053,8 @@ impl<T: Sendable> UniformSender<T> {
                         counter
                             .tx_bytes
                             .fetch_add(buffer.len() as u64, Ordering::Relaxed);
+                        conn.on_success();
+                        sent = true;
                         break;
                     }
                 }
@@ -501,10 +1072,12 @@ impl<T: Sendable> UniformSender<T> {
                     }
                     counter.dropped.fetch_add(1, Ordering::Relaxed);
                     conn.tcp_stream.take();
+                    conn.on_failure(name, counter);
                     break;
                 }
             };
         }
+        sent
     }
 
     fn check_or_register_counterable(&mut self, message_type: SendMessageType) {
@@ -522,11 +1095,20 @@ impl<T: Sendable> UniformSender<T> {
         let mut kv_string = String::with_capacity(2048);
         let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
         while self.running.load(Ordering::Relaxed) {
-            let socket_type = self.config.load().collector_socket_type;
-            match self.input.recv_all(
-                &mut batch,
-                Some(Duration::from_secs(Self::QUEUE_READ_TIMEOUT)),
-            ) {
+            let (socket_type, observe_mode) = {
+                let config = self.config.load();
+                self.batch = config.batches.get(self.name).copied().unwrap_or_default();
+                (
+                    config.collector_socket_type,
+                    self.observable && config.observe_mode,
+                )
+            };
+            // wakes up in time for the earliest cached batch to expire
+            let read_timeout = std::iter::once(&self.encoder)
+                .chain(self.pool.iter().map(|t| &t.encoder))
+                .filter_map(|e| e.batch_remaining(&self.batch))
+                .fold(Duration::from_secs(Self::QUEUE_READ_TIMEOUT), Duration::min);
+            match self.input.recv_all(&mut batch, Some(read_timeout)) {
                 Ok(_) => {
                     for send_item in batch.drain(..) {
                         if !self.running.load(Ordering::Relaxed) {
@@ -534,6 +1116,11 @@ impl<T: Sendable> UniformSender<T> {
                         }
                         let message_type = send_item.message_type();
                         self.counter.rx.fetch_add(1, Ordering::Relaxed);
+                        if observe_mode {
+                            self.check_or_register_counterable(message_type);
+                            self.counter.observed.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
                         debug!(
                             "{} sender send item {}: {:?}",
                             self.name, message_type, send_item
@@ -554,24 +1141,45 @@ impl<T: Sendable> UniformSender<T> {
                             self.counter.dropped.fetch_add(1, Ordering::Relaxed);
                         }
                     }
+                    if !observe_mode && socket_type != SocketType::File {
+                        self.flush_expired();
+                    }
                 }
+                // data cached before entering observe mode is kept until it is left
+                Err(Error::Timeout) if observe_mode => (),
                 Err(Error::Timeout) => match socket_type {
                     SocketType::File => self.flush_writer(),
                     _ => {
                         self.update_dst_ip_and_port();
                         self.encoder.update_header(self.name, self.id, &self.config);
                         self.flush_encoder();
+                        self.update_pool();
+                        self.flush_pool();
                     }
                 },
                 Err(Error::Terminated(..)) => {
                     match socket_type {
                         SocketType::File => self.flush_writer(),
-                        _ => self.flush_encoder(),
+                        _ => {
+                            self.flush_encoder();
+                            self.flush_pool();
+                        }
                     }
                     break;
                 }
                 Err(Error::BatchTooLarge(_)) => unreachable!(),
             }
+            // data cached in observe mode is kept as on timeout
+            if self.flush.swap(false, Ordering::Relaxed) && !observe_mode {
+                match socket_type {
+                    SocketType::File => self.flush_writer(),
+                    _ => {
+                        self.flush_encoder();
+                        self.flush_pool();
+                    }
+                }
+                info!("{} sender id: {} flushed on request", self.name, self.id);
+            }
         }
     }
 
@@ -627,8 +1235,11 @@ impl<T: Sendable> UniformSender<T> {
     }
 
     pub fn handle_target_server(&mut self, send_item: T) -> std::io::Result<()> {
+        if !self.pool.is_empty() {
+            return self.handle_target_pool(send_item);
+        }
         self.encoder.cache_to_sender(send_item);
-        if !self.cached || self.encoder.buffer_len() > Encoder::<T>::BUFFER_LEN {
+        if !self.cached || self.encoder.batch_full(&self.batch) {
             self.check_or_register_counterable(self.encoder.header.msg_type);
             self.update_dst_ip_and_port();
             self.encoder.update_header(self.name, self.id, &self.config);
@@ -636,4 +1247,139 @@ impl<T: Sendable> UniformSender<T> {
         }
         Ok(())
     }
+
+    fn handle_target_pool(&mut self, send_item: T) -> std::io::Result<()> {
+        let index = self.pick_target(&send_item);
+        let message_type = send_item.message_type();
+        self.pool[index].encoder.cache_to_sender(send_item);
+        if !self.cached || self.pool[index].encoder.batch_full(&self.batch) {
+            self.check_or_register_counterable(message_type);
+            self.flush_target(index);
+            // also rotates data without balance key in hash mode
+            self.next_target = (index + 1) % self.pool.len();
+            self.update_pool();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestItem(usize);
+
+    impl Sendable for TestItem {
+        fn encode(self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+            buf.extend(std::iter::repeat(0).take(self.0));
+            Ok(self.0)
+        }
+
+        fn message_type(&self) -> SendMessageType {
+            SendMessageType::Metrics
+        }
+    }
+
+    fn new_encoder() -> Encoder<TestItem> {
+        Encoder::new(0, SendMessageType::Metrics, 1, FieldFilter::default())
+    }
+
+    #[test]
+    fn batch_full() {
+        let batch = SenderBatchConfig {
+            max_items: 2,
+            max_delay: Duration::ZERO,
+        };
+        let mut encoder = new_encoder();
+        assert!(!encoder.batch_full(&batch));
+        encoder.cache_to_sender(TestItem(16));
+        assert!(!encoder.batch_full(&batch));
+        encoder.cache_to_sender(TestItem(16));
+        assert!(encoder.batch_full(&batch));
+        encoder.reset_buffer();
+        assert!(!encoder.batch_full(&batch));
+
+        // encoded size is limited even if items are unlimited
+        let batch = SenderBatchConfig::default();
+        encoder.cache_to_sender(TestItem(Encoder::<TestItem>::BUFFER_LEN / 2));
+        assert!(!encoder.batch_full(&batch));
+        encoder.cache_to_sender(TestItem(Encoder::<TestItem>::BUFFER_LEN / 2));
+        assert!(encoder.batch_full(&batch));
+    }
+
+    #[test]
+    fn batch_expired() {
+        let batch = SenderBatchConfig {
+            max_items: 0,
+            max_delay: Duration::from_millis(50),
+        };
+        let mut encoder = new_encoder();
+        assert!(!encoder.batch_expired(&batch));
+        assert_eq!(encoder.batch_remaining(&batch), None);
+
+        encoder.cache_to_sender(TestItem(16));
+        assert!(!encoder.batch_expired(&batch));
+        assert!(!encoder.batch_full(&batch));
+        let remaining = encoder.batch_remaining(&batch).unwrap();
+        assert!(!remaining.is_zero() && remaining <= batch.max_delay);
+
+        thread::sleep(batch.max_delay);
+        assert!(encoder.batch_expired(&batch));
+        assert!(encoder.batch_full(&batch));
+        assert_eq!(encoder.batch_remaining(&batch), Some(Duration::ZERO));
+        // batches never expire with unlimited max-delay
+        assert!(!encoder.batch_expired(&SenderBatchConfig::default()));
+        assert_eq!(encoder.batch_remaining(&SenderBatchConfig::default()), None);
+
+        encoder.reset_buffer();
+        assert!(!encoder.batch_expired(&batch));
+    }
+
+    #[test]
+    fn failover_cycle() {
+        let counter = SenderCounter::default();
+        let mut conn = Connection::new(
+            vec![
+                "10.1.1.1".to_owned(),
+                "10.1.1.2".to_owned(),
+                "10.1.1.3".to_owned(),
+            ],
+            30033,
+            Duration::from_secs(10),
+        );
+        let backoff = ReconnectBackoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(3600),
+        };
+
+        let mut last_interval = Duration::ZERO;
+        for round in 1..=2 {
+            for i in [1, 2, 0] {
+                for _ in 0..Connection::FAILOVER_THRESHOLD {
+                    conn.on_failure("test", &counter);
+                }
+                assert_eq!(conn.dst_index, i);
+                assert_eq!(conn.dst_ip, conn.dst_ips[i]);
+            }
+            assert_eq!(
+                counter.failovers.load(Ordering::Relaxed),
+                round * conn.dst_ips.len() as u64
+            );
+            // backoff keeps growing across failovers
+            assert_eq!(
+                conn.failures,
+                round as u32 * conn.dst_ips.len() as u32 * Connection::FAILOVER_THRESHOLD
+            );
+            let interval = conn.backoff_interval(&backoff);
+            assert!(interval > last_interval);
+            last_interval = interval;
+        }
+
+        conn.on_success();
+        assert_eq!(conn.failures, 0);
+        assert!(conn.backoff_interval(&backoff) <= Duration::from_secs(1));
+        conn.on_failure("test", &counter);
+        assert_eq!(conn.dst_index, 0);
+    }
 }
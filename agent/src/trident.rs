@@ -14,9 +14,11 @@
  * limitations under the License.
  */
 
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs;
+use std::io::Write;
 use std::mem;
 use std::net::SocketAddr;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
@@ -27,13 +29,15 @@ use std::sync::{
     Arc, Condvar, Mutex, Weak,
 };
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{anyhow, Result};
 use arc_swap::access::Access;
-use dns_lookup::lookup_host;
-use flexi_logger::{colored_opt_format, Age, Cleanup, Criterion, FileSpec, Logger, Naming};
+use flexi_logger::{
+    colored_opt_format, opt_format, Age, Cleanup, Criterion, FileSpec, Logger, Naming,
+};
 use log::{debug, info, warn};
+use rand::RngCore;
 use tokio::runtime::{Builder, Runtime};
 use tokio::sync::broadcast;
 
@@ -42,6 +46,8 @@ use crate::platform::{
     kubernetes::{GenericPoller, Poller, SidecarPoller},
     ApiWatcher, LibvirtXmlExtractor,
 };
+#[cfg(target_os = "windows")]
+use crate::utils::environment::{npf_check, NPF_CHECK_FAILED_EXIT_CODE};
 use crate::{
     collector::{
         flow_aggr::FlowAggrThread, quadruple_generator::QuadrupleGeneratorThread, CollectorThread,
@@ -52,19 +58,21 @@ use crate::{
         L7CollectorThread,
     },
     common::{
-        enums::TapType,
+        enums::{IpProtocol, TapType},
         flow::L7Stats,
         proc_event::BoxedProcEvents,
         tagged_flow::{BoxedTaggedFlow, TaggedFlow},
-        tap_types::TapTyper,
-        FeatureFlags, DEFAULT_LOG_RETENTION, DEFAULT_TRIDENT_CONF_FILE, FREE_SPACE_REQUIREMENT,
+        tap_types::{load_tap_types_from_file, TapTyper},
+        FeatureFlags, FlowAclListener, FlowAclListenerId, DEFAULT_LOG_RETENTION,
+        DEFAULT_TRIDENT_CONF_FILE, FREE_SPACE_REQUIREMENT,
     },
     config::PcapConfig,
     config::{
         handler::{ConfigHandler, DispatcherConfig, ModuleConfig},
-        Config, ConfigError, RuntimeConfig, YamlConfig,
+        resolve_controller_addrs, ComponentFailureAction, Config, ConfigError, InterfaceGroup,
+        LogTarget, RuntimeConfig, YamlConfig,
     },
-    debug::{ConstructDebugCtx, Debugger},
+    debug::{ConstructDebugCtx, Debugger, DispatcherDebugger},
     dispatcher::{
         self, recv_engine::bpf, BpfOptions, Dispatcher, DispatcherBuilder, DispatcherListener,
     },
@@ -75,34 +83,47 @@ use crate::{
     },
     handler::{NpbBuilder, PacketHandlerBuilder},
     integration_collector::{
-        ApplicationLog, BoxedPrometheusExtra, MetricServer, OpenTelemetry, OpenTelemetryCompressed,
-        Profile, TelegrafMetric,
+        ApplicationLog, BodySizeLimit, BoxedPrometheusExtra, MetricServer, OpenTelemetry,
+        OpenTelemetryCompressed, Profile, TelegrafMetric,
     },
     metric::document::BoxedDocument,
     monitor::Monitor,
     platform::synchronizer::Synchronizer as PlatformSynchronizer,
     policy::{Policy, PolicyGetter, PolicySetter},
-    rpc::{Session, Synchronizer, DEFAULT_TIMEOUT},
-    sender::{npb_sender::NpbArpTable, uniform_sender::UniformSenderThread},
+    rpc::{Session, Synchronizer},
+    sender::{
+        npb_sender::NpbArpTable,
+        pcap_writer::PcapFileWriterThread,
+        uniform_sender::{SenderFlusher, UniformSenderThread},
+    },
     utils::{
+        capture_schedule::CaptureScheduler,
         cgroups::{is_kernel_available_for_cgroups, Cgroups},
         command::get_hostname,
+        dns::Resolver,
         environment::{
             check, controller_ip_check, free_memory_check, free_space_checker, get_ctrl_ip_and_mac,
-            get_env, kernel_check, running_in_container, running_in_k8s, tap_interface_check,
-            trident_process_check,
+            get_ctrl_ip_and_mac_with_source, get_env, kernel_check, running_in_container,
+            running_in_k8s, set_prefer_route_ctrl_ip, tap_interface_check, trident_process_check,
+            CtrlIpSource, KERNEL_CHECK_FAILED_EXIT_CODE,
         },
         guard::Guard,
         logger::{LogLevelWriter, LogWriterAdapter, RemoteLogWriter},
         npb_bandwidth_watcher::NpbBandwidthWatcher,
+        process::{record_exit_cause, set_exit_cause_file, take_last_exit_cause, ProcessCounter},
         stats::{self, ArcBatch, Countable, QueueStats, RefCountable},
+        thread_builder,
     },
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::{
-    ebpf_dispatcher::EbpfCollector,
+    ebpf_dispatcher::{EbpfCollector, EbpfInitErrorCounter},
     platform::SocketSynchronizer,
-    utils::{environment::core_file_check, lru::Lru},
+    utils::{
+        diagnostic::DiagnosticHandler,
+        environment::{core_file_check, ebpf_missing_capability},
+        lru::Lru,
+    },
 };
 
 use packet_sequence_block::BoxedPacketSequenceBlock;
@@ -116,13 +137,20 @@ use public::{
     packet::MiniPacket,
     proto::trident::{self, Exception, SocketType, TapMode},
     queue::{self, DebugSender},
-    utils::net::{get_route_src_ip, Link, MacAddr},
+    utils::{
+        bitmap::parse_u16_range_list_to_bitmap,
+        net::{get_route_src_ip, Link, MacAddr},
+    },
     LeakyBucket,
 };
 
 const MINUTE: Duration = Duration::from_secs(60);
-const COMMON_DELAY: u64 = 5; // Potential delay from other processing steps in flow_map
-const QG_PROCESS_MAX_DELAY: u64 = 5; // FIXME: Potential delay from processing steps in qg, it is an estimated value and is not accurate; the data processing capability of the quadruple_generator should be optimized.
+// written to the same directory as log file
+const EFFECTIVE_CONFIG_FILE: &str = "deepflow-agent-effective.yaml";
+// exit code of the last run, in the directory of log-file
+const EXIT_CAUSE_FILE: &str = "deepflow-agent-exit-code";
+// generated export-hash-salt, in the directory of log-file
+const EXPORT_HASH_SALT_FILE: &str = "deepflow-agent-export-salt";
 
 #[derive(Debug, Default)]
 pub struct ChangedConfig {
@@ -148,6 +176,33 @@ pub enum State {
     Disabled(Option<RuntimeConfig>), // Requires runtime config to update platform config
 }
 
+// What caused a state transition, logged for auditing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateTrigger {
+    ServerPush,
+    CircuitBreaker,
+    StandaloneConfig,
+    EscapeTimeout,
+    Upgrade,
+    ConfigApplied,
+    Shutdown,
+}
+
+impl fmt::Display for StateTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::ServerPush => "server-push",
+            Self::CircuitBreaker => "circuit-breaker",
+            Self::StandaloneConfig => "standalone-config",
+            Self::EscapeTimeout => "escape-timeout",
+            Self::Upgrade => "upgrade",
+            Self::ConfigApplied => "config-applied",
+            Self::Shutdown => "shutdown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 impl State {
     fn unwrap_config(self) -> ChangedConfig {
         match self {
@@ -155,6 +210,40 @@ impl State {
             _ => panic!("{:?} not config type", &self),
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::ConfigChanged(_) => "config changed",
+            Self::Terminated => "terminated",
+            Self::Disabled(_) => "disabled",
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        matches!(self, Self::Running | Self::ConfigChanged(_))
+    }
+
+    // Replaces the state with `to` and returns the old one, all transitions should go through
+    // this to keep a complete timeline in log. Transitions other than config changes are
+    // reported to server with AgentStateChanged exception.
+    pub fn transit(
+        &mut self,
+        to: State,
+        trigger: StateTrigger,
+        exception_handler: &ExceptionHandler,
+    ) -> State {
+        info!(
+            "agent state transition: from=\"{}\" to=\"{}\" trigger={}",
+            self.name(),
+            to.name(),
+            trigger
+        );
+        if !(self.is_active() && to.is_active()) && self.name() != to.name() {
+            exception_handler.set(Exception::AgentStateChanged);
+        }
+        mem::replace(self, to)
+    }
 }
 
 pub struct VersionInfo {
@@ -164,6 +253,8 @@ pub struct VersionInfo {
     pub rev_count: &'static str,
     pub compiler: &'static str,
     pub compile_time: &'static str,
+    // compile time features the binary is built with
+    pub features: &'static [&'static str],
 
     pub revision: &'static str,
 }
@@ -178,7 +269,8 @@ Branch: {}
 CommitId: {}
 RevCount: {}
 Compiler: {}
-CompileTime: {}",
+CompileTime: {}
+Features: {}",
             self.rev_count,
             self.commit_id,
             match self.name {
@@ -190,7 +282,8 @@ CompileTime: {}",
             self.commit_id,
             self.rev_count,
             self.compiler,
-            self.compile_time
+            self.compile_time,
+            self.features.join(", ")
         )
     }
 }
@@ -227,8 +320,11 @@ impl From<&AgentId> for trident::AgentId {
 pub struct Trident {
     state: TridentState,
     handle: Option<JoinHandle<()>>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    diagnostic_handler: Option<DiagnosticHandler>,
     #[cfg(target_os = "linux")]
     pid_file: Option<crate::utils::pid_file::PidFile>,
+    exception_handler: ExceptionHandler,
 }
 
 impl Trident {
@@ -237,13 +333,16 @@ impl Trident {
         version_info: &'static VersionInfo,
         agent_mode: RunningMode,
         sidecar_mode: bool,
+        legacy_config_fallback: bool,
     ) -> Result<Trident> {
-        let config = match agent_mode {
+        let start_time = SystemTime::now();
+        let mut config = match agent_mode {
             RunningMode::Managed => {
                 match Config::load_from_file(config_path.as_ref()) {
                     Ok(conf) => conf,
                     Err(e) => {
-                        if let ConfigError::YamlConfigInvalid(_) = e {
+                        if matches!(e, ConfigError::YamlConfigInvalid(_)) && legacy_config_fallback
+                        {
                             // try to load config file from trident.yaml to support upgrading from trident
                             if let Ok(conf) = Config::load_from_file(DEFAULT_TRIDENT_CONF_FILE) {
                                 conf
@@ -276,11 +375,40 @@ impl Trident {
             None
         };
 
-        let controller_ip: IpAddr = config.controller_ips[0].parse()?;
-        let (ctrl_ip, ctrl_mac) = match get_ctrl_ip_and_mac(&controller_ip) {
-            Ok(tuple) => tuple,
-            Err(e) => return Err(anyhow!("get ctrl ip and mac failed: {}", e)),
+        // resolved once here, remote logs, stats and agent identity all use override-os-hostname
+        if let Some(name) = config.hostname_source.resolve().map_err(|e| {
+            anyhow!(
+                "resolve hostname-source {} failed: {}",
+                config.hostname_source,
+                e
+            )
+        })? {
+            config.override_os_hostname = Some(name);
+        }
+
+        let export_hash_salt_error = if config.export_hash_salt.is_empty() {
+            let path = Path::new(&config.log_file).with_file_name(EXPORT_HASH_SALT_FILE);
+            match load_or_create_export_hash_salt(&path) {
+                Ok(salt) => {
+                    config.export_hash_salt = salt.into();
+                    None
+                }
+                Err(e) => {
+                    config.export_hash_salt = random_export_hash_salt().into();
+                    Some((path, e))
+                }
+            }
+        } else {
+            None
         };
+
+        set_prefer_route_ctrl_ip(config.prefer_route_ctrl_ip);
+        let controller_ip: IpAddr = config.controller_ips[0].parse()?;
+        let (ctrl_ip, ctrl_mac, ctrl_ip_source) =
+            match get_ctrl_ip_and_mac_with_source(&controller_ip) {
+                Ok(tuple) => tuple,
+                Err(e) => return Err(anyhow!("get ctrl ip and mac failed: {}", e)),
+            };
         let mut config_handler = ConfigHandler::new(config, ctrl_ip, ctrl_mac);
 
         let config = &config_handler.static_config;
@@ -289,8 +417,25 @@ impl Trident {
             None => get_hostname().unwrap_or("Unknown".to_string()),
         };
 
+        queue::set_dwell_time_enabled(config.queue_dwell_time_enabled);
+
         let ntp_diff = Arc::new(AtomicI64::new(0));
-        let stats_collector = Arc::new(stats::Collector::new(&hostname, ntp_diff.clone()));
+        let stats_collector = if config.stats_disabled {
+            Arc::new(stats::Collector::disabled(&hostname, ntp_diff.clone()))
+        } else {
+            Arc::new(stats::Collector::new(
+                &hostname,
+                vec![
+                    ("agent_version", version_info.revision.to_owned()),
+                    ("agent_name", version_info.name.to_owned()),
+                    (
+                        "kubernetes_cluster_name",
+                        config.kubernetes_cluster_name.clone().unwrap_or_default(),
+                    ),
+                ],
+                ntp_diff.clone(),
+            ))
+        };
         let exception_handler = ExceptionHandler::default();
 
         let base_name = Path::new(&env::args().next().unwrap())
@@ -304,6 +449,7 @@ impl Trident {
             hostname.clone(),
             config_handler.log(),
             config_handler.sender(),
+            config_handler.remote_log_reconnect_backoff(),
             stats_collector.clone(),
             exception_handler.clone(),
             ntp_diff.clone(),
@@ -315,7 +461,9 @@ impl Trident {
             .format(colored_opt_format);
         // check log folder permission
         let base_path = Path::new(&config.log_file).parent().unwrap();
-        let write_to_file = if base_path.exists() {
+        let write_to_file = if config.log_to == LogTarget::Stdout {
+            false
+        } else if base_path.exists() {
             base_path
                 .metadata()
                 .ok()
@@ -324,8 +472,43 @@ impl Trident {
         } else {
             fs::create_dir_all(base_path).is_ok()
         };
+        // permission bits do not tell read-only filesystems, try writing to the directory
+        let write_to_file = if write_to_file && config.log_file_fallback {
+            match check_dir_writable(base_path) {
+                Ok(_) => true,
+                Err(e) => {
+                    eprintln!(
+                        "Log directory '{}' is not writable: {}, fall back to logging to stderr and remote only",
+                        base_path.display(),
+                        e
+                    );
+                    false
+                }
+            }
+        } else {
+            write_to_file
+        };
+        let symlink = if !write_to_file {
+            None
+        } else if config.log_file_symlink.is_empty() {
+            Some(Path::new(&config.log_file))
+        } else {
+            let symlink = Path::new(&config.log_file_symlink);
+            let dir = symlink.parent().unwrap_or(Path::new("."));
+            match fs::create_dir_all(dir).and_then(|_| check_dir_writable(dir)) {
+                Ok(_) => Some(symlink),
+                Err(e) => {
+                    eprintln!(
+                        "Log file symlink directory '{}' is not writable: {}, symlink will not be created",
+                        dir.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        };
         let logger = if write_to_file {
-            logger
+            let logger = logger
                 .log_to_file_and_writer(
                     FileSpec::try_from(&config.log_file)?,
                     Box::new(LogWriterAdapter::new(vec![
@@ -338,26 +521,45 @@ impl Trident {
                     Naming::Timestamps,
                     Cleanup::KeepLogFiles(DEFAULT_LOG_RETENTION as usize),
                 )
-                .create_symlink(&config.log_file)
-                .append()
+                .append();
+            match symlink {
+                Some(symlink) => logger.create_symlink(symlink),
+                None => logger,
+            }
+        } else if config.log_to == LogTarget::Stdout {
+            logger.log_to_writer(Box::new(LogWriterAdapter::new(vec![
+                Box::new(remote_log_writer),
+                Box::new(log_level_writer),
+            ])))
         } else {
             eprintln!(
                 "Log file path '{}' access denied, logs will not be written to file",
                 &config.log_file
             );
-            logger.log_to_writer(Box::new(LogWriterAdapter::new(vec![
-                Box::new(remote_log_writer),
-                Box::new(log_level_writer),
-            ])))
+            logger
+                .log_to_writer(Box::new(LogWriterAdapter::new(vec![
+                    Box::new(remote_log_writer),
+                    Box::new(log_level_writer),
+                ])))
+                .duplicate_to_stderr(flexi_logger::Duplicate::All)
+        };
+        let logger = if config.log_to == LogTarget::File {
+            logger
+        } else {
+            logger
+                .duplicate_to_stdout(flexi_logger::Duplicate::All)
+                .format_for_stdout(opt_format)
         };
 
         #[cfg(any(target_os = "linux", target_os = "android"))]
-        let logger = if nix::unistd::getppid().as_raw() != 1 {
+        let logger = if config.log_to == LogTarget::File && nix::unistd::getppid().as_raw() != 1 {
             logger.duplicate_to_stderr(flexi_logger::Duplicate::All)
         } else {
             logger
         };
         let logger_handle = logger.start()?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let diagnostic_logger_handle = logger_handle.clone();
         config_handler.set_logger_handle(logger_handle);
 
         let config = &config_handler.static_config;
@@ -371,39 +573,111 @@ impl Trident {
             stats::Countable::Owned(Box::new(log_level_counter)),
         );
 
+        if let Some((path, e)) = export_hash_salt_error {
+            warn!(
+                "load export hash salt from {} failed: {}, hashed export fields will change after restart",
+                path.display(),
+                e
+            );
+        }
+
+        set_exit_cause_file(Path::new(&config.log_file).with_file_name(EXIT_CAUSE_FILE));
+        let last_exit_code = take_last_exit_cause();
+        match last_exit_code {
+            Some(code) => info!("previous run exited with code {}", code),
+            None => {
+                info!("exit cause of previous run is unknown, it may have crashed or been killed")
+            }
+        }
+        stats_collector.register_countable(
+            &stats::NoTagModule("process"),
+            stats::Countable::Owned(Box::new(ProcessCounter::new(start_time, last_exit_code))),
+        );
+
+        info!(
+            "hostname {} resolved from hostname-source {}",
+            hostname, config.hostname_source
+        );
         info!("static_config {:#?}", config);
+        let effective_config = match config.to_effective_yaml() {
+            Ok(yaml) => {
+                info!("effective static_config:\n{}", yaml);
+                if config.dump_effective_config {
+                    let path = Path::new(&config.log_file).with_file_name(EFFECTIVE_CONFIG_FILE);
+                    match fs::write(&path, &yaml) {
+                        Ok(_) => info!("effective static_config written to {}", path.display()),
+                        Err(e) => warn!(
+                            "write effective static_config to {} failed: {}",
+                            path.display(),
+                            e
+                        ),
+                    }
+                }
+                yaml
+            }
+            Err(e) => {
+                warn!("serialize effective static_config failed: {}", e);
+                format!("{:#?}", config)
+            }
+        };
         let state = Arc::new((Mutex::new(State::Running), Condvar::new()));
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let diagnostic_handler = match DiagnosticHandler::start(
+            Path::new(&config.log_file).parent().unwrap().to_path_buf(),
+            version_info,
+            state.clone(),
+            effective_config,
+            stats_collector.clone(),
+            diagnostic_logger_handle,
+        ) {
+            Ok(h) => Some(h),
+            Err(e) => {
+                warn!("start diagnostic handler failed: {}", e);
+                None
+            }
+        };
+        #[cfg(target_os = "windows")]
+        let _ = effective_config;
         let state_thread = state.clone();
+        let exception_handler_thread = exception_handler.clone();
         let config_path = match agent_mode {
             RunningMode::Managed => None,
             RunningMode::Standalone => Some(config_path.as_ref().to_path_buf()),
         };
-        let handle = Some(thread::spawn(move || {
-            if let Err(e) = Self::run(
-                state_thread,
-                ctrl_ip,
-                ctrl_mac,
-                config_handler,
-                version_info,
-                stats_collector,
-                exception_handler,
-                config_path,
-                sidecar_mode,
-                ntp_diff,
-            ) {
-                warn!(
-                    "Launching deepflow-agent failed: {}, deepflow-agent restart...",
-                    e
-                );
-                crate::utils::notify_exit(1);
-            }
-        }));
+        let handle = Some(
+            thread::Builder::new()
+                .name("trident-main".to_owned())
+                .spawn(move || {
+                    if let Err(e) = Self::run(
+                        state_thread,
+                        ctrl_ip,
+                        ctrl_mac,
+                        ctrl_ip_source,
+                        config_handler,
+                        version_info,
+                        stats_collector,
+                        exception_handler_thread,
+                        config_path,
+                        sidecar_mode,
+                        ntp_diff,
+                    ) {
+                        warn!(
+                            "Launching deepflow-agent failed: {}, deepflow-agent restart...",
+                            e
+                        );
+                        crate::utils::notify_exit(1);
+                    }
+                })?,
+        );
 
         Ok(Trident {
             state,
             handle,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            diagnostic_handler,
             #[cfg(target_os = "linux")]
             pid_file,
+            exception_handler,
         })
     }
 
@@ -411,6 +685,7 @@ impl Trident {
         state: TridentState,
         ctrl_ip: IpAddr,
         ctrl_mac: MacAddr,
+        ctrl_ip_source: CtrlIpSource,
         mut config_handler: ConfigHandler,
         version_info: &'static VersionInfo,
         stats_collector: Arc<stats::Collector>,
@@ -422,12 +697,10 @@ impl Trident {
         info!("==================== Launching DeepFlow-Agent ====================");
         info!("Environment variables: {:?}", get_env());
 
-        if running_in_container() {
-            info!(
-                "use K8S_NODE_IP_FOR_DEEPFLOW env ip as destination_ip({})",
-                ctrl_ip
-            );
-        }
+        info!(
+            "use ctrl ip {} mac {} selected by {}",
+            ctrl_ip, ctrl_mac, ctrl_ip_source
+        );
 
         #[cfg(target_os = "linux")]
         let agent_id = if sidecar_mode {
@@ -467,15 +740,37 @@ impl Trident {
             agent_id, config_handler.static_config.agent_mode, ctrl_ip, ctrl_mac
         );
 
+        let grpc_proxy = config_handler
+            .static_config
+            .get_controller_proxy()
+            .and_then(|p| match grpc::Proxy::parse(&p) {
+                Ok(proxy) => {
+                    info!("controller rpc session through proxy {}", p);
+                    match config_handler.static_config.get_controller_no_proxy() {
+                        Some(no_proxy) => {
+                            info!("controllers in {} are connected without proxy", no_proxy);
+                            Some(proxy.with_no_proxy(&no_proxy))
+                        }
+                        None => Some(proxy),
+                    }
+                }
+                Err(e) => {
+                    warn!("ignore controller proxy {}: {}", p, e);
+                    None
+                }
+            });
         let session = Arc::new(Session::new(
             config_handler.static_config.controller_port,
             config_handler.static_config.controller_tls_port,
-            DEFAULT_TIMEOUT,
+            config_handler.static_config.controller_timeouts.clone(),
             config_handler
                 .static_config
                 .controller_cert_file_prefix
                 .clone(),
+            config_handler.static_config.get_controller_tls_verify(),
             config_handler.static_config.controller_ips.clone(),
+            config_handler.static_config.controller_ports.clone(),
+            grpc_proxy,
             exception_handler.clone(),
             &stats_collector,
         ));
@@ -502,11 +797,21 @@ impl Trident {
                 .kubernetes_cluster_id
                 .is_empty()
         {
-            config_handler.static_config.kubernetes_cluster_id =
-                Config::get_k8s_cluster_id(&runtime, &session, &config_handler.static_config)
-                    .unwrap_or_default();
+            config_handler.static_config.kubernetes_cluster_id = Config::get_k8s_cluster_id(
+                &runtime,
+                &session,
+                &config_handler.static_config,
+                &exception_handler,
+            )
+            .unwrap_or_default();
         }
 
+        stats_collector.set_identity_tag("ctrl_mac", agent_id.mac.to_string());
+        stats_collector.set_identity_tag(
+            "kubernetes_cluster_id",
+            config_handler.static_config.kubernetes_cluster_id.clone(),
+        );
+
         let (agent_id_tx, _) = broadcast::channel::<AgentId>(1);
         let agent_id_tx = Arc::new(agent_id_tx);
 
@@ -522,6 +827,7 @@ impl Trident {
             config_handler.static_config.kubernetes_cluster_name.clone(),
             config_handler.static_config.override_os_hostname.clone(),
             config_handler.static_config.agent_unique_identifier,
+            config_handler.static_config.sync_interval_jitter,
             exception_handler.clone(),
             config_handler.static_config.agent_mode,
             config_path,
@@ -532,6 +838,10 @@ impl Trident {
             &stats::NoTagModule("ntp"),
             stats::Countable::Owned(Box::new(synchronizer.ntp_counter())),
         );
+        stats_collector.register_countable(
+            &stats::NoTagModule("controller_contact"),
+            stats::Countable::Owned(Box::new(synchronizer.contact_counter())),
+        );
         synchronizer.start();
 
         #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -549,8 +859,18 @@ impl Trident {
             session.clone(),
             config_handler.static_config.controller_domain_name.clone(),
             config_handler.static_config.controller_ips.clone(),
+            config_handler.static_config.controller_ports.clone(),
+            config_handler
+                .static_config
+                .controller_srv_lookup
+                .then(|| config_handler.static_config.controller_addrs.clone()),
+            config_handler.static_config.controller_resolver(),
             config_handler.static_config.team_id.clone(),
             sidecar_mode,
+            config_handler
+                .candidate_config
+                .yaml_config
+                .thread_stack_size,
             agent_id_tx,
         );
         domain_name_listener.start();
@@ -604,6 +924,7 @@ impl Trident {
             stats_collector.clone(),
             log_dir.to_string(),
             config_handler.environment(),
+            exception_handler.clone(),
         )?;
         monitor.start();
 
@@ -663,6 +984,11 @@ impl Trident {
         let mut state_guard = state.lock().unwrap();
         let mut components: Option<Components> = None;
         let mut yaml_conf: Option<YamlConfig> = None;
+        // last tap types loaded from tap-types-file, kept when reloading fails
+        let mut local_tap_types: Vec<trident::TapType> = vec![];
+        // set once EbpfCollector is created, after which eBPF collector config changes require restart
+        #[cfg_attr(target_os = "windows", allow(unused_mut))]
+        let mut ebpf_initialized = false;
 
         loop {
             match &mut *state_guard {
@@ -700,10 +1026,41 @@ impl Trident {
                     }
                     return Ok(());
                 }
-                State::Disabled(config) => {
+                State::Disabled(_) => {
                     if let Some(ref mut c) = components {
+                        let grace_period = config_handler
+                            .candidate_config
+                            .yaml_config
+                            .disable_grace_period;
+                        let agent_mode = config_handler.static_config.agent_mode;
+                        if !grace_period.is_zero()
+                            && c.is_running()
+                            && disabled_by_server(&state_guard, agent_mode, &exception_handler)
+                        {
+                            info!(
+                                "agent disabled by server, stop dispatchers and stop components after {:?} grace period",
+                                grace_period
+                            );
+                            c.stop_dispatchers();
+                            state_guard = cond
+                                .wait_timeout_while(state_guard, grace_period, |s| {
+                                    disabled_by_server(s, agent_mode, &exception_handler)
+                                })
+                                .unwrap()
+                                .0;
+                            if !matches!(&*state_guard, State::Disabled(_)) {
+                                info!(
+                                    "agent state changed in grace period, components not stopped"
+                                );
+                                c.start_dispatchers();
+                                continue;
+                            }
+                        }
                         c.stop();
                     }
+                    let State::Disabled(config) = &mut *state_guard else {
+                        unreachable!();
+                    };
                     if let Some(c) = config.take() {
                         let agent_id = synchronizer.agent_id.read().clone();
                         let callbacks = config_handler.on_config(
@@ -743,14 +1100,18 @@ impl Trident {
                             stats_collector
                                 .set_min_interval(config_handler.candidate_config.stats.interval);
                         }
+                        monitor.on_config_change();
                     }
                     state_guard = cond.wait(state_guard).unwrap();
                     continue;
                 }
                 _ => (),
             }
-            let mut new_state = State::Running;
-            mem::swap(&mut new_state, &mut *state_guard);
+            let new_state = state_guard.transit(
+                State::Running,
+                StateTrigger::ConfigApplied,
+                &exception_handler,
+            );
             mem::drop(state_guard);
 
             let ChangedConfig {
@@ -758,24 +1119,51 @@ impl Trident {
                 blacklist,
                 vm_mac_addrs,
                 gateway_vmac_addrs,
-                tap_types,
+                mut tap_types,
             } = new_state.unwrap_config();
+            stats_collector.set_identity_tag("vtap_group_id", runtime_config.vtap_group_id.clone());
+
+            let tap_types_file = &config_handler.static_config.tap_types_file;
+            if !tap_types_file.is_empty() {
+                match load_tap_types_from_file(tap_types_file) {
+                    Ok(t) => local_tap_types = t,
+                    Err(e) => warn!("load tap types from {} failed: {}", tap_types_file, e),
+                }
+                tap_types = local_tap_types.clone();
+            }
 
             if let Some(old_yaml) = yaml_conf {
-                if old_yaml != runtime_config.yaml_config {
-                    if let Some(mut c) = components.take() {
-                        c.stop();
+                match yaml_config_change(&old_yaml, &runtime_config.yaml_config, ebpf_initialized) {
+                    Some(YamlConfigChange::Restart(fields)) => {
+                        if let Some(mut c) = components.take() {
+                            c.stop();
+                        }
+                        // EbpfCollector does not support recreation because it calls bpf_tracer_init, which can only be called once in a process
+                        // Work around this problem by exiting and restart trident
+                        let info = "yaml_config updated, deepflow-agent restart...";
+                        warn!("yaml_config fields {:?} require restart", fields);
+                        warn!("{}", info);
+                        thread::sleep(Duration::from_secs(1));
+                        return Err(anyhow!(info));
+                    }
+                    Some(YamlConfigChange::Rebuild) => {
+                        info!("yaml_config updated, rebuilding components without restart");
+                        // running EbpfCollector is kept by agent components rebuild, and flow acl
+                        // listeners of the rebuilt components replace the stopped ones
+                        if let Some(Components::Agent(_)) = components.as_ref() {
+                            config_handler.rebuild_required = true;
+                        } else if let Some(mut c) = components.take() {
+                            c.stop();
+                        }
                     }
-                    // EbpfCollector does not support recreation because it calls bpf_tracer_init, which can only be called once in a process
-                    // Work around this problem by exiting and restart trident
-                    let info = "yaml_config updated, deepflow-agent restart...";
-                    warn!("{}", info);
-                    thread::sleep(Duration::from_secs(1));
-                    return Err(anyhow!(info));
+                    None => (),
                 }
             }
             yaml_conf = Some(runtime_config.yaml_config.clone());
+            domain_name_listener
+                .set_thread_stack_size(runtime_config.yaml_config.thread_stack_size);
             let agent_id = synchronizer.agent_id.read().clone();
+            let mut rebuild = None;
             match components.as_mut() {
                 None => {
                     let callbacks = config_handler.on_config(
@@ -818,10 +1206,16 @@ impl Trident {
                         gateway_vmac_addrs,
                         config_handler.static_config.agent_mode,
                         runtime.clone(),
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        None,
                     )?;
 
                     comp.start();
 
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    if let Components::Agent(components) = &comp {
+                        ebpf_initialized |= components.ebpf_dispatcher_component.is_some();
+                    }
                     if let Components::Agent(components) = &mut comp {
                         if config_handler.candidate_config.dispatcher.tap_mode == TapMode::Analyzer
                         {
@@ -834,6 +1228,15 @@ impl Trident {
                     }
 
                     components.replace(comp);
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    if !config_handler
+                        .candidate_config
+                        .yaml_config
+                        .control_plane_cpu_affinity
+                        .is_empty()
+                    {
+                        config_handler.bind_control_plane_threads();
+                    }
                 }
                 Some(Components::Agent(components)) => {
                     let callbacks: Vec<fn(&ConfigHandler, &mut AgentComponents)> = config_handler
@@ -859,27 +1262,51 @@ impl Trident {
                         api_watcher.stop();
                     }
 
-                    components.config = config_handler.candidate_config.clone();
-                    components.start();
-
-                    component_on_config_change(
-                        &config_handler,
-                        components,
-                        blacklist,
-                        vm_mac_addrs,
-                        gateway_vmac_addrs,
-                        tap_types,
-                        &synchronizer,
-                        #[cfg(target_os = "linux")]
-                        libvirt_xml_extractor.clone(),
-                    );
-                    for callback in callbacks {
-                        callback(&config_handler, components);
-                    }
+                    if mem::take(&mut config_handler.rebuild_required) {
+                        rebuild = Some((callbacks, vm_mac_addrs, gateway_vmac_addrs, tap_types));
+                    } else {
+                        components.config = config_handler.candidate_config.clone();
+                        components.start();
+
+                        component_on_config_change(
+                            &config_handler,
+                            components,
+                            blacklist,
+                            vm_mac_addrs,
+                            gateway_vmac_addrs,
+                            tap_types,
+                            &synchronizer,
+                            #[cfg(target_os = "linux")]
+                            libvirt_xml_extractor.clone(),
+                        );
+                        for callback in callbacks {
+                            callback(&config_handler, components);
+                        }
 
-                    for d in components.dispatcher_components.iter_mut() {
-                        d.dispatcher_listener
-                            .on_config_change(&config_handler.candidate_config.dispatcher);
+                        for d in components.dispatcher_components.iter_mut() {
+                            d.dispatcher_listener
+                                .on_config_change(&config_handler.candidate_config.dispatcher);
+                        }
+                        // recv engines size their frames only at creation, the next config
+                        // rebuilds dispatchers if the interface mtu grows in auto mode
+                        if components
+                            .dispatcher_components
+                            .iter()
+                            .any(|d| d.dispatcher_listener.rebuild_required())
+                        {
+                            info!("Interface mtu exceeds frame size of dispatchers, rebuild components");
+                            config_handler.rebuild_required = true;
+                        }
+                        // dispatchers may have been rebuilt from this thread
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        if !config_handler
+                            .candidate_config
+                            .yaml_config
+                            .control_plane_cpu_affinity
+                            .is_empty()
+                        {
+                            config_handler.bind_control_plane_threads();
+                        }
                     }
                 }
                 _ => {
@@ -906,6 +1333,51 @@ impl Trident {
                     }
                 }
             }
+            if let Some((callbacks, vm_mac_addrs, gateway_vmac_addrs, tap_types)) = rebuild {
+                let comp = components.as_mut().unwrap();
+                info!("rebuilding components without restart");
+                comp.rebuild(
+                    &version_info,
+                    &config_handler,
+                    stats_collector.clone(),
+                    &session,
+                    &synchronizer,
+                    exception_handler.clone(),
+                    #[cfg(target_os = "linux")]
+                    libvirt_xml_extractor.clone(),
+                    platform_synchronizer.clone(),
+                    #[cfg(target_os = "linux")]
+                    sidecar_poller.clone(),
+                    #[cfg(target_os = "linux")]
+                    api_watcher.clone(),
+                    vm_mac_addrs,
+                    gateway_vmac_addrs,
+                    config_handler.static_config.agent_mode,
+                    runtime.clone(),
+                )?;
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                if let Components::Agent(components) = &*comp {
+                    ebpf_initialized |= components.ebpf_dispatcher_component.is_some();
+                }
+                if let Components::Agent(components) = comp {
+                    if config_handler.candidate_config.dispatcher.tap_mode == TapMode::Analyzer {
+                        parse_tap_type(components, tap_types);
+                    }
+                    for callback in callbacks {
+                        callback(&config_handler, components);
+                    }
+                }
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                if !config_handler
+                    .candidate_config
+                    .yaml_config
+                    .control_plane_cpu_affinity
+                    .is_empty()
+                {
+                    config_handler.bind_control_plane_threads();
+                }
+            }
+            monitor.on_config_change();
             state_guard = state.lock().unwrap();
         }
     }
@@ -915,14 +1387,102 @@ impl Trident {
         let (state, cond) = &*self.state;
 
         let mut state_guard = state.lock().unwrap();
-        *state_guard = State::Terminated;
+        state_guard.transit(
+            State::Terminated,
+            StateTrigger::Shutdown,
+            &self.exception_handler,
+        );
         cond.notify_one();
         mem::drop(state_guard);
         self.handle.take().unwrap().join().unwrap();
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(h) = self.diagnostic_handler.as_mut() {
+            h.stop();
+        }
+        // no-op if exiting by notify_exit, which has recorded its exit code
+        record_exit_cause(0);
         info!("Gracefully stopped");
     }
 }
 
+// Only agents disabled by server wait for the grace period before stopping components, other
+// triggers like circuit breaker and escape timeout stop them immediately
+fn disabled_by_server(
+    state: &State,
+    agent_mode: RunningMode,
+    exception_handler: &ExceptionHandler,
+) -> bool {
+    match state {
+        State::Disabled(Some(c)) => {
+            agent_mode == RunningMode::Managed
+                && !c.enabled
+                && !exception_handler.has(Exception::SystemLoadCircuitBreaker)
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum YamlConfigChange {
+    // components are stopped and rebuilt with the new config, a running EbpfCollector is kept
+    Rebuild,
+    // fields used to initialize a running EbpfCollector changed
+    Restart(Vec<&'static str>),
+}
+
+fn yaml_config_change(
+    old: &YamlConfig,
+    new: &YamlConfig,
+    ebpf_initialized: bool,
+) -> Option<YamlConfigChange> {
+    // vtap-group-id-request is applied by synchronizer without restart
+    if old.vtap_group_id_request != new.vtap_group_id_request {
+        let mut old = old.clone();
+        old.vtap_group_id_request = new.vtap_group_id_request.clone();
+        return yaml_config_change(&old, new, ebpf_initialized);
+    }
+    if old == new {
+        return None;
+    }
+    let restart_fields = old.restart_required_fields(new);
+    if ebpf_initialized && !restart_fields.is_empty() {
+        Some(YamlConfigChange::Restart(restart_fields))
+    } else {
+        Some(YamlConfigChange::Rebuild)
+    }
+}
+
+fn check_dir_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(
+        ".deepflow-agent-write-check-{}",
+        std::process::id()
+    ));
+    fs::File::create(&probe)?;
+    fs::remove_file(&probe)
+}
+
+fn random_export_hash_salt() -> String {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    hex::encode(salt)
+}
+
+// the salt is generated on first run and kept, so that hashed fields are stable across restarts
+fn load_or_create_export_hash_salt(path: &Path) -> std::io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(s) if !s.trim().is_empty() => return Ok(s.trim().to_owned()),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    let salt = random_export_hash_salt();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(salt.as_bytes())?;
+    Ok(salt)
+}
+
 fn get_listener_links(
     conf: &DispatcherConfig,
     #[cfg(target_os = "linux")] netns: &netns::NsFile,
@@ -964,6 +1524,46 @@ fn get_listener_links(
     }
 }
 
+const SRC_INTERFACE_REGEX_PREFIX: &str = "regex:";
+
+// Builds a regex matching any of the src-interfaces entries, entries are globs supporting
+// `*` and `?`, or regexes if prefixed with `regex:`
+pub(crate) fn src_interfaces_to_regex(src_interfaces: &[String]) -> String {
+    let patterns = src_interfaces
+        .iter()
+        .map(|s| match s.strip_prefix(SRC_INTERFACE_REGEX_PREFIX) {
+            Some(re) => re.to_owned(),
+            None => regex::escape(s).replace(r"\*", ".*").replace(r"\?", "."),
+        })
+        .collect::<Vec<_>>();
+    format!("^(?:{})$", patterns.join("|"))
+}
+
+// Expands src-interfaces against present interfaces in the root network namespace,
+// falls back to tap-interface-regex if src-interfaces is empty
+fn get_analyzer_links(conf: &DispatcherConfig, src_interfaces: &[String]) -> Vec<Link> {
+    if src_interfaces.is_empty() {
+        return get_listener_links(
+            conf,
+            #[cfg(target_os = "linux")]
+            &netns::NsFile::Root,
+        );
+    }
+    match public::utils::net::links_by_name_regex(src_interfaces_to_regex(src_interfaces)) {
+        Err(e) => {
+            warn!(
+                "get interfaces by src-interfaces {:?} failed: {}",
+                src_interfaces, e
+            );
+            vec![]
+        }
+        Ok(links) => {
+            debug!("src interfaces: {:?}", links);
+            links
+        }
+    }
+}
+
 fn component_on_config_change(
     config_handler: &ConfigHandler,
     components: &mut AgentComponents,
@@ -996,46 +1596,83 @@ fn component_on_config_change(
         }
         TapMode::Mirror | TapMode::Analyzer => {
             // Obtain the currently configured network interfaces
-            let mut current_interfaces = get_listener_links(
-                conf,
-                #[cfg(target_os = "linux")]
-                &netns::NsFile::Root,
-            );
+            let mut current_interfaces = if conf.tap_mode == TapMode::Analyzer {
+                get_analyzer_links(
+                    conf,
+                    &config_handler.candidate_config.yaml_config.src_interfaces,
+                )
+            } else {
+                get_listener_links(
+                    conf,
+                    #[cfg(target_os = "linux")]
+                    &netns::NsFile::Root,
+                )
+            };
             current_interfaces.sort();
 
             if current_interfaces == components.tap_interfaces {
                 return;
             }
 
-            // By comparing current_interfaces and components.tap_interfaces, we can determine which
-            // dispatcher_components should be closed and which dispatcher_components should be built
-            let interfaces_to_build: Vec<_> = current_interfaces
-                .iter()
-                .filter(|i| !components.tap_interfaces.contains(i))
-                .cloned()
-                .collect();
+            // Dispatchers of unchanged interfaces or interface groups are kept, the others are
+            // closed and dispatchers of the remaining entries are built
+            let yaml_config = &config_handler.candidate_config.yaml_config;
+            let mut links_to_build = group_links(
+                current_interfaces.clone(),
+                tap_interface_groups(yaml_config),
+                fanout_dispatchers(yaml_config),
+            );
 
+            let dispatcher_debugger = components.debugger.clone_dispatcher();
             components.dispatcher_components.retain_mut(|d| {
-                let retain = current_interfaces.contains(&d.src_link);
-                if !retain {
-                    d.stop();
+                match links_to_build.iter().position(|l| l == &d.src_links) {
+                    Some(i) => {
+                        links_to_build.remove(i);
+                        true
+                    }
+                    None => {
+                        d.stop();
+                        dispatcher_debugger.remove_timestamp_source(d.id);
+                        false
+                    }
                 }
-                retain
             });
 
-            let mut id = components.last_dispatcher_component_id;
-            components
-                .policy_setter
-                .reset_queue_size(id + interfaces_to_build.len() + 1);
-            let debugger_queue = components.debugger.clone_queue();
-            for i in interfaces_to_build {
-                id += 1;
+            let analyzer_ip = if conf.tap_mode == TapMode::Analyzer {
+                match dispatcher::resolve_analyzer_ip(
+                    &conf.analyzer_ip,
+                    config_handler.ctrl_ip.is_ipv6(),
+                ) {
+                    Ok(ip) => Some(ip),
+                    Err(e) => {
+                        warn!("Dns lookup {} error: {:?}", conf.analyzer_ip, e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            let mut id = components.last_dispatcher_component_id;
+            components
+                .policy_setter
+                .reset_queue_size(id + links_to_build.len() + 1);
+            let debugger_queue = components.debugger.clone_queue();
+            for links in links_to_build {
+                id += 1;
+                let bpf_options = match (analyzer_ip.as_ref(), links.get(0)) {
+                    (Some(analyzer_ip), Some(link)) => {
+                        build_analyzer_bpf_options(config_handler, analyzer_ip, link)
+                    }
+                    _ => components.bpf_options.clone(),
+                };
                 match build_dispatchers(
                     id,
-                    vec![i],
+                    links,
                     components.stats_collector.clone(),
                     config_handler,
                     debugger_queue.clone(),
+                    &dispatcher_debugger,
                     components.is_ce_version,
                     synchronizer,
                     components.npb_bps_limit.clone(),
@@ -1044,7 +1681,7 @@ fn component_on_config_change(
                     components.policy_getter,
                     components.exception_handler.clone(),
                     0,
-                    components.bpf_options.clone(),
+                    bpf_options,
                     components.packet_sequence_uniform_output.clone(),
                     components.proto_log_sender.clone(),
                     components.pcap_batch_sender.clone(),
@@ -1054,6 +1691,7 @@ fn component_on_config_change(
                     components.toa_info_sender.clone(),
                     components.l4_flow_aggr_sender.clone(),
                     components.metrics_sender.clone(),
+                    components.capture_scheduler.paused(),
                     #[cfg(target_os = "linux")]
                     netns::NsFile::Root,
                     #[cfg(target_os = "linux")]
@@ -1073,6 +1711,15 @@ fn component_on_config_change(
                 }
             }
             components.last_dispatcher_component_id = id;
+            if let Some(watcher) = components.analyzer_ip_watcher.as_ref() {
+                watcher.set_listeners(
+                    components
+                        .dispatcher_components
+                        .iter()
+                        .map(|d| d.dispatcher_listener.clone())
+                        .collect(),
+                );
+            }
             if conf.tap_mode == TapMode::Analyzer {
                 parse_tap_type(components, tap_types);
             }
@@ -1083,6 +1730,71 @@ fn component_on_config_change(
     }
 }
 
+// Number of dispatchers capturing each tap interface in mirror and analyzer mode
+#[cfg_attr(target_os = "windows", allow(unused_variables))]
+fn fanout_dispatchers(yaml_config: &YamlConfig) -> usize {
+    #[cfg(target_os = "linux")]
+    if yaml_config.af_xdp_enabled {
+        return 1;
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if yaml_config.af_packet_fanout.enabled()
+        && !yaml_config.dpdk_enabled
+        && !yaml_config.libpcap_enabled
+    {
+        return yaml_config.af_packet_fanout.dispatchers;
+    }
+    1
+}
+
+// Interface groups only take effect with AF_PACKET capture
+#[cfg_attr(target_os = "windows", allow(unused_variables))]
+fn tap_interface_groups(yaml_config: &YamlConfig) -> &[InterfaceGroup] {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if !yaml_config.dpdk_enabled && !yaml_config.libpcap_enabled {
+        return &yaml_config.tap_interface_groups;
+    }
+    &[]
+}
+
+// Links of each dispatcher in mirror and analyzer mode, each link is captured by `fanout`
+// dispatchers. Links matching the same interface group are captured by a single dispatcher
+// instead, without fanout because AF_PACKET fanout groups can not span interfaces.
+fn group_links(links: Vec<Link>, groups: &[InterfaceGroup], fanout: usize) -> Vec<Vec<Link>> {
+    let regexes = groups
+        .iter()
+        .filter(|g| !g.interfaces.is_empty())
+        .filter_map(
+            |g| match regex::Regex::new(&src_interfaces_to_regex(&g.interfaces)) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    warn!("invalid tap-interface-groups {:?}: {}", g.interfaces, e);
+                    None
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    let mut grouped = vec![vec![]; regexes.len()];
+    let mut entries = vec![];
+    for link in links {
+        match regexes.iter().position(|re| re.is_match(&link.name)) {
+            Some(i) => grouped[i].push(link),
+            None => entries.extend(std::iter::repeat(vec![link]).take(fanout)),
+        }
+    }
+    for mut links in grouped {
+        match links.len() {
+            0 => (),
+            1 => entries.extend(std::iter::repeat(links).take(fanout)),
+            _ => {
+                links.sort();
+                entries.push(links);
+            }
+        }
+    }
+    entries
+}
+
 fn parse_tap_type(components: &mut AgentComponents, tap_types: Vec<trident::TapType>) {
     let mut updated = false;
     if components.cur_tap_types.len() != tap_types.len() {
@@ -1106,12 +1818,18 @@ pub struct DomainNameListener {
     stats_collector: Arc<stats::Collector>,
     session: Arc<Session>,
     ips: Vec<String>,
+    ports: HashMap<String, u16>,
     domain_names: Vec<String>,
+    // controller-ips as configured, re-resolved with SRV records if set
+    srv_addrs: Option<Vec<String>>,
+    resolver: Resolver,
     team_id: String,
 
     sidecar_mode: bool,
+    thread_stack_size: usize,
 
-    thread_handler: Option<JoinHandle<()>>,
+    // the thread returns controllers it resolved to, so that a restarted thread continues with them
+    thread_handler: Option<JoinHandle<(Vec<String>, HashMap<String, u16>)>>,
     stopped: Arc<AtomicBool>,
     agent_id_tx: Arc<broadcast::Sender<AgentId>>,
 }
@@ -1124,8 +1842,12 @@ impl DomainNameListener {
         session: Arc<Session>,
         domain_names: Vec<String>,
         ips: Vec<String>,
+        ports: HashMap<String, u16>,
+        srv_addrs: Option<Vec<String>>,
+        resolver: Resolver,
         team_id: String,
         sidecar_mode: bool,
+        thread_stack_size: usize,
         agent_id_tx: Arc<broadcast::Sender<AgentId>>,
     ) -> DomainNameListener {
         Self {
@@ -1133,8 +1855,12 @@ impl DomainNameListener {
             session,
             domain_names,
             ips,
+            ports,
+            srv_addrs,
+            resolver,
             team_id,
             sidecar_mode,
+            thread_stack_size,
             thread_handler: None,
             stopped: Arc::new(AtomicBool::new(false)),
             agent_id_tx,
@@ -1155,7 +1881,27 @@ impl DomainNameListener {
         }
         self.stopped.store(true, Ordering::Relaxed);
         if let Some(handler) = self.thread_handler.take() {
-            let _ = handler.join();
+            if let Ok((ips, ports)) = handler.join() {
+                self.ips = ips;
+                self.ports = ports;
+            }
+        }
+    }
+
+    // the listener starts before any config is received, its thread is respawned when
+    // thread-stack-size in the config differs
+    fn set_thread_stack_size(&mut self, thread_stack_size: usize) {
+        if self.thread_stack_size == thread_stack_size {
+            return;
+        }
+        self.thread_stack_size = thread_stack_size;
+        if self.thread_handler.is_some() {
+            info!(
+                "domain-name-listener restarts with thread stack size {}",
+                thread_stack_size
+            );
+            self.stop();
+            self.start();
         }
     }
 
@@ -1165,47 +1911,70 @@ impl DomainNameListener {
         }
 
         let mut ips = self.ips.clone();
+        let mut ports = self.ports.clone();
+        let srv_addrs = self.srv_addrs.clone();
+        let resolver = self.resolver.clone();
         let domain_names = self.domain_names.clone();
         let team_id = self.team_id.clone();
         let stopped = self.stopped.clone();
         let agent_id_tx = self.agent_id_tx.clone();
         let session = self.session.clone();
+        let stats_collector = self.stats_collector.clone();
 
         #[cfg(target_os = "linux")]
         let sidecar_mode = self.sidecar_mode;
 
         info!(
-            "Resolve controller domain name {} {}",
-            domain_names[0], ips[0]
+            "Resolve controller domain name {} {} with {}",
+            domain_names[0], ips[0], resolver
         );
 
         self.thread_handler = Some(
-            thread::Builder::new()
-                .name("domain-name-listener".to_owned())
+            thread_builder("domain-name-listener", self.thread_stack_size)
                 .spawn(move || {
                     while !stopped.swap(false, Ordering::Relaxed) {
                         thread::sleep(Self::INTERVAL);
 
                         let mut changed = false;
-                        for i in 0..domain_names.len() {
-                            let current = lookup_host(domain_names[i].as_str());
-                            if current.is_err() {
+                        if let Some(addrs) = srv_addrs.as_ref() {
+                            let Ok(resolved) = resolve_controller_addrs(addrs, true, &resolver) else {
                                 continue;
-                            }
-                            let current = current.unwrap();
-
-                            changed = current.iter().find(|&&x| x.to_string() == ips[i]).is_none();
-                            if changed {
+                            };
+                            // order of controllers with the same priority is random by weight
+                            if resolved.ips.iter().collect::<HashSet<_>>()
+                                != ips.iter().collect::<HashSet<_>>()
+                                || resolved.ports != ports
+                            {
+                                changed = true;
                                 info!(
-                                    "Domain name {} ip {} change to {}",
-                                    domain_names[i], ips[i], current[0]
+                                    "Controllers {:?} {:?} change to {:?} {:?}",
+                                    ips, ports, resolved.ips, resolved.ports
                                 );
-                                ips[i] = current[0].to_string();
+                                ips = resolved.ips;
+                                ports = resolved.ports;
+                            }
+                        } else {
+                            for i in 0..domain_names.len() {
+                                let current = resolver.lookup_host(domain_names[i].as_str());
+                                if current.is_err() {
+                                    continue;
+                                }
+                                let current = current.unwrap();
+
+                                // a change of an earlier domain must not be overwritten by later ones
+                                if current.iter().find(|&&x| x.to_string() == ips[i]).is_none() {
+                                    changed = true;
+                                    info!(
+                                        "Domain name {} ip {} change to {}",
+                                        domain_names[i], ips[i], current[0]
+                                    );
+                                    ips[i] = current[0].to_string();
+                                }
                             }
                         }
 
                         if changed {
-                            let (ctrl_ip, ctrl_mac) = match get_ctrl_ip_and_mac(&ips[0].parse().unwrap()) {
+                            let (ctrl_ip, ctrl_mac, ctrl_ip_source) = match get_ctrl_ip_and_mac_with_source(&ips[0].parse().unwrap()) {
                                 Ok(tuple) => tuple,
                                 Err(e) => {
                                     warn!("get ctrl ip and mac failed with error: {}", e);
@@ -1215,8 +1984,8 @@ impl DomainNameListener {
                                 }
                             };
                             info!(
-                                "use K8S_NODE_IP_FOR_DEEPFLOW env ip as destination_ip({})",
-                                ctrl_ip
+                                "use ctrl ip {} mac {} selected by {}",
+                                ctrl_ip, ctrl_mac, ctrl_ip_source
                             );
                             #[cfg(target_os = "linux")]
                             let agent_id = if sidecar_mode {
@@ -1250,10 +2019,12 @@ impl DomainNameListener {
                             #[cfg(any(target_os = "windows", target_os = "android"))]
                             let agent_id = AgentId { ip: ctrl_ip.clone(), mac: ctrl_mac, team_id: team_id.clone() };
 
-                            session.reset_server_ip(ips.clone());
+                            session.reset_server_ip(ips.clone(), ports.clone());
+                            stats_collector.set_identity_tag("ctrl_mac", agent_id.mac.to_string());
                             let _ = agent_id_tx.send(agent_id);
                         }
                     }
+                    (ips, ports)
                 })
                 .unwrap(),
         );
@@ -1324,14 +2095,34 @@ impl EbpfDispatcherComponent {
         self.ebpf_collector.start();
     }
 
-    pub fn stop(&mut self) {
+    pub fn notify_stop(&mut self) -> Option<JoinHandle<()>> {
         self.session_aggregator.stop();
         self.collector.stop();
         self.l7_collector.stop();
-        self.ebpf_collector.notify_stop();
+        self.ebpf_collector.notify_stop()
     }
 }
 
+type SenderQueue<T> = (DebugSender<T>, Arc<queue::Receiver<T>>);
+
+// Queues to senders the eBPF collector writes into
+#[derive(Default)]
+struct EbpfSenderQueues {
+    metrics: Option<SenderQueue<BoxedDocument>>,
+    proto_log: Option<SenderQueue<BoxAppProtoLogsData>>,
+    proc_event: Option<SenderQueue<BoxedProcEvents>>,
+    profile: Option<SenderQueue<Profile>>,
+}
+
+// EbpfCollector can not be recreated in the same process, so it is kept when rebuilding
+// components, together with the sender queues and toa cache it writes into
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub struct RetainedEbpf {
+    component: EbpfDispatcherComponent,
+    lru_toa_info: Arc<Mutex<Lru<SocketAddr, SocketAddr>>>,
+    queues: EbpfSenderQueues,
+}
+
 pub struct MetricsServerComponent {
     pub external_metrics_server: MetricServer,
     pub l7_collector: L7CollectorThread,
@@ -1356,10 +2147,11 @@ pub struct DispatcherComponent {
     pub session_aggregator: SessionAggregator,
     pub collector: CollectorThread,
     pub l7_collector: L7CollectorThread,
-    pub packet_sequence_parser: PacketSequenceParser,
+    pub packet_sequence_parser: Option<PacketSequenceParser>, // Enterprise Edition Feature: packet-sequence
     pub pcap_assembler: PcapAssembler,
     pub handler_builders: Arc<Mutex<Vec<PacketHandlerBuilder>>>,
-    pub src_link: Link, // The original src_interface
+    pub src_link: Link,       // The original src_interface
+    pub src_links: Vec<Link>, // All interfaces captured, src_link is the first one
 }
 
 impl DispatcherComponent {
@@ -1368,7 +2160,9 @@ impl DispatcherComponent {
         self.session_aggregator.start();
         self.collector.start();
         self.l7_collector.start();
-        self.packet_sequence_parser.start();
+        if let Some(p) = self.packet_sequence_parser.as_mut() {
+            p.start();
+        }
         self.pcap_assembler.start();
         self.handler_builders
             .lock()
@@ -1383,7 +2177,9 @@ impl DispatcherComponent {
         self.session_aggregator.stop();
         self.collector.stop();
         self.l7_collector.stop();
-        self.packet_sequence_parser.stop();
+        if let Some(p) = self.packet_sequence_parser.as_mut() {
+            p.stop();
+        }
         self.pcap_assembler.stop();
         self.handler_builders
             .lock()
@@ -1404,7 +2200,8 @@ pub struct AgentComponents {
     pub l4_flow_uniform_sender: UniformSenderThread<BoxedTaggedFlow>,
     pub metrics_uniform_sender: UniformSenderThread<BoxedDocument>,
     pub l7_flow_uniform_sender: UniformSenderThread<BoxAppProtoLogsData>,
-    pub stats_sender: UniformSenderThread<ArcBatch>,
+    // None if stats collection is disabled
+    pub stats_sender: Option<UniformSenderThread<ArcBatch>>,
     pub platform_synchronizer: Arc<PlatformSynchronizer>,
     #[cfg(target_os = "linux")]
     pub kubernetes_poller: Arc<GenericPoller>,
@@ -1413,6 +2210,8 @@ pub struct AgentComponents {
     pub debugger: Debugger,
     #[cfg(any(target_os = "linux", target_os = "android"))]
     pub ebpf_dispatcher_component: Option<EbpfDispatcherComponent>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub ebpf_init_error: Option<Arc<EbpfInitErrorCounter>>,
     pub running: AtomicBool,
     pub stats_collector: Arc<stats::Collector>,
     pub metrics_server_component: MetricsServerComponent,
@@ -1420,8 +2219,8 @@ pub struct AgentComponents {
     pub prometheus_uniform_sender: UniformSenderThread<BoxedPrometheusExtra>,
     pub telegraf_uniform_sender: UniformSenderThread<TelegrafMetric>,
     pub profile_uniform_sender: UniformSenderThread<Profile>,
-    pub packet_sequence_uniform_output: DebugSender<BoxedPacketSequenceBlock>, // Enterprise Edition Feature: packet-sequence
-    pub packet_sequence_uniform_sender: UniformSenderThread<BoxedPacketSequenceBlock>, // Enterprise Edition Feature: packet-sequence
+    pub packet_sequence_uniform_output: Option<DebugSender<BoxedPacketSequenceBlock>>, // Enterprise Edition Feature: packet-sequence
+    pub packet_sequence_uniform_sender: Option<UniformSenderThread<BoxedPacketSequenceBlock>>, // Enterprise Edition Feature: packet-sequence
     pub proc_event_uniform_sender: UniformSenderThread<BoxedProcEvents>,
     pub application_log_uniform_sender: UniformSenderThread<ApplicationLog>,
     pub exception_handler: ExceptionHandler,
@@ -1433,18 +2232,28 @@ pub struct AgentComponents {
     pub npb_bps_limit: Arc<LeakyBucket>,
     pub compressed_otel_uniform_sender: UniformSenderThread<OpenTelemetryCompressed>,
     pub pcap_batch_uniform_sender: UniformSenderThread<BoxedPcapBatch>,
+    // pcap batches are written to local rotating files instead of being sent in standalone mode
+    pub pcap_batch_file_writer: Option<PcapFileWriterThread>,
+    #[cfg(target_os = "windows")]
+    pub pcap_interface_watcher: Option<dispatcher::PcapInterfaceWatcher>,
+    pub analyzer_ip_watcher: Option<dispatcher::AnalyzerIpWatcher>,
     pub policy_setter: PolicySetter,
     pub policy_getter: PolicyGetter,
     pub npb_bandwidth_watcher: Box<Arc<NpbBandwidthWatcher>>,
+    pub capture_scheduler: CaptureScheduler,
     pub npb_arp_table: Arc<NpbArpTable>,
     pub is_ce_version: bool, // Determine whether the current version is a ce version, CE-AGENT always set pcap-assembler disabled
     pub tap_interfaces: Vec<Link>,
     pub bpf_options: Arc<Mutex<BpfOptions>>,
     pub last_dispatcher_component_id: usize,
 
+    proc_event_sender: DebugSender<BoxedProcEvents>,
+    profile_sender: DebugSender<Profile>,
+
     max_memory: u64,
     tap_mode: TapMode,
     agent_mode: RunningMode,
+    session: Arc<Session>,
 
     runtime: Arc<Runtime>,
 }
@@ -1456,15 +2265,38 @@ impl AgentComponents {
         //   - Inherent delay in flow statistics data in flow_map: second_flow_extra_delay + packet_delay
         //   - Additional delay in inject_flush_ticker in flow_map: TIME_UNIT
         //   - Delay in flushing the output queue in flow_map: flow.flush_interval
-        //   - Potential delay from other processing steps in flow_map: COMMON_DELAY 5 seconds
+        //   - Potential delay from other processing steps in flow_map: common_delay, 5 seconds by default
         //   - The delay caused by the time window being pushed ahead in flow_map: flow.flush_interval
         yaml_config.packet_delay.as_secs()
             + TIME_UNIT.as_secs()
             + yaml_config.flow.flush_interval.as_secs()
-            + COMMON_DELAY
+            + yaml_config.common_delay.as_secs()
             + yaml_config.second_flow_extra_delay.as_secs()
             + yaml_config.flow.flush_interval.as_secs() // The flow_map may send data to qg ahead of time due to the output_buffer exceeding its limit. This can result in the time_window of qg being advanced prematurely, with the maximum advancement time being the flush_interval.
     }
+
+    // minute QG window is also pushed forward by flow stat time,
+    // therefore its delay should be 60 + second delay (including extra flow delay)
+    fn get_minute_tolerable_delay(yaml_config: &YamlConfig) -> u64 {
+        let delay = 60
+            + Self::get_flowgen_tolerable_delay(yaml_config)
+            + yaml_config.minute_flow_extra_delay.as_secs();
+        // minute quadruple generator only supports delay in [60, 120)
+        if delay >= 120 {
+            warn!(
+                "minute tolerable delay {}s exceeds the limit, use 119s instead",
+                delay
+            );
+            return 119;
+        }
+        delay
+    }
+
+    // FIXME: quadruple_process_delay is an estimated value and is not accurate; the data
+    // processing capability of the quadruple_generator should be optimized.
+    fn get_collector_tolerable_delay(yaml_config: &YamlConfig, qg_delay: u64) -> u64 {
+        qg_delay + yaml_config.quadruple_process_delay.as_secs()
+    }
     fn new_collector(
         id: usize,
         stats_collector: Arc<stats::Collector>,
@@ -1481,17 +2313,16 @@ impl AgentComponents {
         let yaml_config = &config_handler.candidate_config.yaml_config;
 
         let flowgen_tolerable_delay = Self::get_flowgen_tolerable_delay(yaml_config);
-        // minute QG window is also pushed forward by flow stat time,
-        // therefore its delay should be 60 + second delay (including extra flow delay)
-        let minute_quadruple_tolerable_delay = 60 + flowgen_tolerable_delay;
+        let minute_quadruple_tolerable_delay = Self::get_minute_tolerable_delay(yaml_config);
 
         let mut l4_flow_aggr_outer = None;
         let mut l4_log_sender_outer = None;
         if l4_flow_aggr_sender.is_some() {
-            let (l4_log_sender, l4_log_receiver, counter) = queue::bounded_with_debug(
+            let (l4_log_sender, l4_log_receiver, counter) = queue::bounded_with_debug_and_policy(
                 yaml_config.flow.aggr_queue_size as usize,
                 "2-second-flow-to-minute-aggrer",
                 queue_debugger,
+                yaml_config.get_queue_overflow_policy("2-second-flow-to-minute-aggrer"),
             );
             l4_log_sender_outer = Some(l4_log_sender);
             stats_collector.register_countable(
@@ -1516,30 +2347,43 @@ impl AgentComponents {
             );
         }
 
-        let (second_sender, second_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.quadruple_queue_size,
-            "2-flow-with-meter-to-second-collector",
-            queue_debugger,
-        );
-        stats_collector.register_countable(
-            &QueueStats {
-                id,
-                module: "2-flow-with-meter-to-second-collector",
-            },
-            Countable::Owned(Box::new(counter)),
-        );
-        let (minute_sender, minute_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.quadruple_queue_size,
-            "2-flow-with-meter-to-minute-collector",
-            queue_debugger,
-        );
-        stats_collector.register_countable(
-            &QueueStats {
-                id,
-                module: "2-flow-with-meter-to-minute-collector",
-            },
-            Countable::Owned(Box::new(counter)),
-        );
+        // queues and collectors of disabled metrics types are not constructed
+        let (second_sender, second_receiver) = if metrics_type.contains(MetricsType::SECOND) {
+            let (sender, receiver, counter) = queue::bounded_with_debug_and_policy(
+                yaml_config.quadruple_queue_size,
+                "2-flow-with-meter-to-second-collector",
+                queue_debugger,
+                yaml_config.get_queue_overflow_policy("2-flow-with-meter-to-second-collector"),
+            );
+            stats_collector.register_countable(
+                &QueueStats {
+                    id,
+                    module: "2-flow-with-meter-to-second-collector",
+                },
+                Countable::Owned(Box::new(counter)),
+            );
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
+        let (minute_sender, minute_receiver) = if metrics_type.contains(MetricsType::MINUTE) {
+            let (sender, receiver, counter) = queue::bounded_with_debug_and_policy(
+                yaml_config.quadruple_queue_size,
+                "2-flow-with-meter-to-minute-collector",
+                queue_debugger,
+                yaml_config.get_queue_overflow_policy("2-flow-with-meter-to-minute-collector"),
+            );
+            stats_collector.register_countable(
+                &QueueStats {
+                    id,
+                    module: "2-flow-with-meter-to-minute-collector",
+                },
+                Countable::Owned(Box::new(counter)),
+            );
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
 
         let quadruple_generator = QuadrupleGeneratorThread::new(
             id,
@@ -1548,7 +2392,7 @@ impl AgentComponents {
             minute_sender,
             toa_info_sender,
             l4_log_sender_outer,
-            (yaml_config.flow.hash_slots << 3) as usize, // connection_lru_capacity
+            yaml_config.collector_connection_lru_capacity,
             metrics_type,
             flowgen_tolerable_delay,
             minute_quadruple_tolerable_delay,
@@ -1559,30 +2403,34 @@ impl AgentComponents {
         );
 
         let (mut second_collector, mut minute_collector) = (None, None);
-        if metrics_type.contains(MetricsType::SECOND) {
+        if let Some(second_receiver) = second_receiver {
             second_collector = Some(Collector::new(
                 id as u32,
                 second_receiver,
                 metrics_sender.clone(),
                 MetricsType::SECOND,
-                flowgen_tolerable_delay + QG_PROCESS_MAX_DELAY,
+                Self::get_collector_tolerable_delay(yaml_config, flowgen_tolerable_delay),
                 &stats_collector,
                 config_handler.collector(),
                 synchronizer.ntp_diff(),
                 agent_mode,
+                yaml_config.collector_stash_capacity,
+                yaml_config.collector_stash_max_len,
             ));
         }
-        if metrics_type.contains(MetricsType::MINUTE) {
+        if let Some(minute_receiver) = minute_receiver {
             minute_collector = Some(Collector::new(
                 id as u32,
                 minute_receiver,
                 metrics_sender,
                 MetricsType::MINUTE,
-                minute_quadruple_tolerable_delay + QG_PROCESS_MAX_DELAY,
+                Self::get_collector_tolerable_delay(yaml_config, minute_quadruple_tolerable_delay),
                 &stats_collector,
                 config_handler.collector(),
                 synchronizer.ntp_diff(),
                 agent_mode,
+                yaml_config.collector_stash_capacity,
+                yaml_config.collector_stash_max_len,
             ));
         }
 
@@ -1607,35 +2455,46 @@ impl AgentComponents {
     ) -> L7CollectorThread {
         let yaml_config = &config_handler.candidate_config.yaml_config;
 
-        let (l7_second_sender, l7_second_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.quadruple_queue_size,
-            "2-flow-with-meter-to-l7-second-collector",
-            queue_debugger,
-        );
-        stats_collector.register_countable(
-            &QueueStats {
-                id,
-                module: "2-flow-with-meter-to-l7-second-collector",
-            },
-            Countable::Owned(Box::new(counter)),
-        );
-        let (l7_minute_sender, l7_minute_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.quadruple_queue_size,
-            "2-flow-with-meter-to-l7-minute-collector",
-            queue_debugger,
-        );
-        stats_collector.register_countable(
-            &QueueStats {
-                id,
-                module: "2-flow-with-meter-to-l7-minute-collector",
-            },
-            Countable::Owned(Box::new(counter)),
-        );
+        // queues and collectors of disabled metrics types are not constructed
+        let (l7_second_sender, l7_second_receiver) = if metrics_type.contains(MetricsType::SECOND) {
+            let (sender, receiver, counter) = queue::bounded_with_debug_and_policy(
+                yaml_config.quadruple_queue_size,
+                "2-flow-with-meter-to-l7-second-collector",
+                queue_debugger,
+                yaml_config.get_queue_overflow_policy("2-flow-with-meter-to-l7-second-collector"),
+            );
+            stats_collector.register_countable(
+                &QueueStats {
+                    id,
+                    module: "2-flow-with-meter-to-l7-second-collector",
+                },
+                Countable::Owned(Box::new(counter)),
+            );
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
+        let (l7_minute_sender, l7_minute_receiver) = if metrics_type.contains(MetricsType::MINUTE) {
+            let (sender, receiver, counter) = queue::bounded_with_debug_and_policy(
+                yaml_config.quadruple_queue_size,
+                "2-flow-with-meter-to-l7-minute-collector",
+                queue_debugger,
+                yaml_config.get_queue_overflow_policy("2-flow-with-meter-to-l7-minute-collector"),
+            );
+            stats_collector.register_countable(
+                &QueueStats {
+                    id,
+                    module: "2-flow-with-meter-to-l7-minute-collector",
+                },
+                Countable::Owned(Box::new(counter)),
+            );
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
 
         let second_quadruple_tolerable_delay = Self::get_flowgen_tolerable_delay(yaml_config);
-        // minute QG window is also pushed forward by flow stat time,
-        // therefore its delay should be 60 + second delay (including extra flow delay)
-        let minute_quadruple_tolerable_delay = 60 + second_quadruple_tolerable_delay;
+        let minute_quadruple_tolerable_delay = Self::get_minute_tolerable_delay(yaml_config);
 
         let quadruple_generator = L7QuadrupleGeneratorThread::new(
             id,
@@ -1652,30 +2511,34 @@ impl AgentComponents {
         );
 
         let (mut second_collector, mut minute_collector) = (None, None);
-        if metrics_type.contains(MetricsType::SECOND) {
+        if let Some(l7_second_receiver) = l7_second_receiver {
             second_collector = Some(L7Collector::new(
                 id as u32,
                 l7_second_receiver,
                 metrics_sender.clone(),
                 MetricsType::SECOND,
-                second_quadruple_tolerable_delay + QG_PROCESS_MAX_DELAY,
+                Self::get_collector_tolerable_delay(yaml_config, second_quadruple_tolerable_delay),
                 &stats_collector,
                 config_handler.collector(),
                 synchronizer.ntp_diff(),
                 agent_mode,
+                yaml_config.collector_stash_capacity,
+                yaml_config.collector_stash_max_len,
             ));
         }
-        if metrics_type.contains(MetricsType::MINUTE) {
+        if let Some(l7_minute_receiver) = l7_minute_receiver {
             minute_collector = Some(L7Collector::new(
                 id as u32,
                 l7_minute_receiver,
                 metrics_sender,
                 MetricsType::MINUTE,
-                minute_quadruple_tolerable_delay + QG_PROCESS_MAX_DELAY,
+                Self::get_collector_tolerable_delay(yaml_config, minute_quadruple_tolerable_delay),
                 &stats_collector,
                 config_handler.collector(),
                 synchronizer.ntp_diff(),
                 agent_mode,
+                yaml_config.collector_stash_capacity,
+                yaml_config.collector_stash_max_len,
             ));
         }
 
@@ -1697,10 +2560,19 @@ impl AgentComponents {
         gateway_vmac_addrs: Vec<MacAddr>,
         agent_mode: RunningMode,
         runtime: Arc<Runtime>,
+        #[cfg(any(target_os = "linux", target_os = "android"))] retained_ebpf: Option<RetainedEbpf>,
     ) -> Result<Self> {
         let static_config = &config_handler.static_config;
         let candidate_config = &config_handler.candidate_config;
         let yaml_config = &candidate_config.yaml_config;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let (retained_ebpf_component, retained_lru_toa_info, mut ebpf_sender_queues) =
+            match retained_ebpf {
+                Some(r) => (Some(r.component), Some(r.lru_toa_info), r.queues),
+                None => (None, None, EbpfSenderQueues::default()),
+            };
+        #[cfg(target_os = "windows")]
+        let mut ebpf_sender_queues = EbpfSenderQueues::default();
         let ctrl_ip = config_handler.ctrl_ip;
         let max_memory = config_handler.candidate_config.environment.max_memory;
         let process_threshold = config_handler
@@ -1709,19 +2581,36 @@ impl AgentComponents {
             .process_threshold;
         let feature_flags = FeatureFlags::from(&yaml_config.feature_flags);
 
-        if !yaml_config.src_interfaces.is_empty() {
-            warn!("src_interfaces is not empty, but this has already been deprecated, instead, the tap_interface_regex should be set");
+        let second_tolerable_delay = Self::get_flowgen_tolerable_delay(yaml_config);
+        let minute_tolerable_delay = Self::get_minute_tolerable_delay(yaml_config);
+        info!(
+            "Tolerable delays: second quadruple {}s collector {}s, minute quadruple {}s collector {}s",
+            second_tolerable_delay,
+            Self::get_collector_tolerable_delay(yaml_config, second_tolerable_delay),
+            minute_tolerable_delay,
+            Self::get_collector_tolerable_delay(yaml_config, minute_tolerable_delay),
+        );
+
+        if !yaml_config.src_interfaces.is_empty() && candidate_config.tap_mode != TapMode::Analyzer
+        {
+            warn!("src_interfaces is not empty, but it is only used in analyzer mode, the tap_interface_regex should be set");
         }
 
-        let mut stats_sender = UniformSenderThread::new(
-            "stats",
-            stats_collector.get_receiver(),
-            config_handler.sender(),
-            stats_collector.clone(),
-            exception_handler.clone(),
-            true,
-        );
-        stats_sender.start();
+        let stats_sender = if stats_collector.is_disabled() {
+            info!("Stats collection is disabled, stats sender is not started");
+            None
+        } else {
+            let mut stats_sender = UniformSenderThread::new(
+                "stats",
+                stats_collector.get_receiver(),
+                config_handler.sender(),
+                stats_collector.clone(),
+                exception_handler.clone(),
+                true,
+            );
+            stats_sender.start();
+            Some(stats_sender)
+        };
 
         info!("Start check process...");
         trident_process_check(process_threshold);
@@ -1771,17 +2660,41 @@ impl AgentComponents {
         let local_dispatcher_count = 1;
 
         if interfaces_and_ns.is_empty() {
-            let links = get_listener_links(
-                &candidate_config.dispatcher,
-                #[cfg(target_os = "linux")]
-                &netns::NsFile::Root,
-            );
+            let links = if candidate_config.tap_mode == TapMode::Analyzer
+                && !yaml_config.src_interfaces.is_empty()
+            {
+                let links =
+                    get_analyzer_links(&candidate_config.dispatcher, &yaml_config.src_interfaces);
+                if links.is_empty() {
+                    warn!(
+                        "src-interfaces({:?}) do not match any interface, in analyzer mode",
+                        yaml_config.src_interfaces
+                    );
+                } else {
+                    info!(
+                        "src-interfaces({:?}) expanded to {:?}",
+                        yaml_config.src_interfaces,
+                        links.iter().map(|l| &l.name).collect::<Vec<_>>()
+                    );
+                }
+                links
+            } else {
+                get_listener_links(
+                    &candidate_config.dispatcher,
+                    #[cfg(target_os = "linux")]
+                    &netns::NsFile::Root,
+                )
+            };
             if candidate_config.tap_mode != TapMode::Local {
-                for l in links {
+                for links in group_links(
+                    links,
+                    tap_interface_groups(yaml_config),
+                    fanout_dispatchers(yaml_config),
+                ) {
                     #[cfg(target_os = "linux")]
-                    interfaces_and_ns.push((vec![l], netns::NsFile::Root));
+                    interfaces_and_ns.push((links, netns::NsFile::Root));
                     #[cfg(any(target_os = "windows", target_os = "android"))]
-                    interfaces_and_ns.push(vec![l]);
+                    interfaces_and_ns.push(links);
                 }
             } else {
                 for _ in 0..local_dispatcher_count {
@@ -1793,30 +2706,36 @@ impl AgentComponents {
             }
         }
 
+        info!("Start check kernel...");
+        if let Err(e) = kernel_check(candidate_config.tap_mode, !yaml_config.ebpf.disabled) {
+            log::error!("{}, deepflow-agent exit...", e);
+            crate::utils::notify_exit(KERNEL_CHECK_FAILED_EXIT_CODE);
+        }
         match candidate_config.tap_mode {
             TapMode::Analyzer => {
-                info!("Start check kernel...");
-                kernel_check();
                 info!("Start check tap interface...");
                 #[cfg(target_os = "linux")]
                 let tap_interfaces: Vec<_> = interfaces_and_ns
                     .iter()
-                    .filter_map(|i| i.0.get(0).map(|l| l.name.clone()))
+                    .flat_map(|i| i.0.iter().map(|l| l.name.clone()))
                     .collect();
                 #[cfg(any(target_os = "windows", target_os = "android"))]
                 let tap_interfaces: Vec<_> = interfaces_and_ns
                     .iter()
-                    .filter_map(|i| i.get(0).map(|l| l.name.clone()))
+                    .flat_map(|i| i.iter().map(|l| l.name.clone()))
                     .collect();
 
                 tap_interface_check(&tap_interfaces);
             }
             _ => {
                 // NPF服务检查
-                // TODO: npf (only on windows)
-                if candidate_config.tap_mode == TapMode::Mirror {
-                    info!("Start check kernel...");
-                    kernel_check();
+                #[cfg(target_os = "windows")]
+                {
+                    info!("Start check npf service...");
+                    if let Err(e) = npf_check() {
+                        log::error!("{}, deepflow-agent restart...", e);
+                        crate::utils::notify_exit(NPF_CHECK_FAILED_EXIT_CODE);
+                    }
                 }
             }
         }
@@ -1837,7 +2756,7 @@ impl AgentComponents {
             yaml_config.forward_capacity,
             yaml_config.fast_path_disabled,
         );
-        synchronizer.add_flow_acl_listener(Box::new(policy_setter));
+        synchronizer.replace_flow_acl_listener(Box::new(policy_setter));
         policy_setter.set_memory_limit(max_memory);
 
         // TODO: collector enabled
@@ -1875,16 +2794,18 @@ impl AgentComponents {
         let queue_debugger = debugger.clone_queue();
 
         #[cfg(any(target_os = "linux", target_os = "android"))]
-        let (toa_sender, toa_recv, _) = queue::bounded_with_debug(
+        let (toa_sender, toa_recv, _) = queue::bounded_with_debug_and_policy(
             yaml_config.toa_sender_queue_size,
             "1-socket-sync-toa-info-queue",
             &queue_debugger,
+            yaml_config.get_queue_overflow_policy("1-socket-sync-toa-info-queue"),
         );
         #[cfg(target_os = "windows")]
-        let (toa_sender, _, _) = queue::bounded_with_debug(
+        let (toa_sender, _, _) = queue::bounded_with_debug_and_policy(
             yaml_config.toa_sender_queue_size,
             "1-socket-sync-toa-info-queue",
             &queue_debugger,
+            yaml_config.get_queue_overflow_policy("1-socket-sync-toa-info-queue"),
         );
         #[cfg(any(target_os = "linux", target_os = "android"))]
         let socket_synchronizer = SocketSynchronizer::new(
@@ -1895,24 +2816,33 @@ impl AgentComponents {
             policy_setter,
             session.clone(),
             toa_recv,
-            Arc::new(Mutex::new(Lru::with_capacity(
-                yaml_config.toa_lru_cache_size >> 5,
-                yaml_config.toa_lru_cache_size,
-            ))),
+            retained_lru_toa_info.unwrap_or_else(|| {
+                Arc::new(Mutex::new(Lru::with_capacity(
+                    yaml_config.toa_lru_cache_size >> 5,
+                    yaml_config.toa_lru_cache_size,
+                )))
+            }),
         );
 
-        let rx_leaky_bucket = Arc::new(LeakyBucket::new(match candidate_config.tap_mode {
-            TapMode::Analyzer => None,
-            _ => Some(
-                config_handler
-                    .candidate_config
-                    .dispatcher
-                    .global_pps_threshold,
-            ),
-        }));
+        let rx_leaky_bucket = Arc::new(LeakyBucket::new(
+            candidate_config.dispatcher.rx_pps_threshold(),
+        ));
+        stats_collector.register_countable(
+            &stats::SingleTagModule("leaky_bucket", "name", "rx"),
+            Countable::Ref(Arc::downgrade(&rx_leaky_bucket) as Weak<dyn RefCountable>),
+        );
 
         let tap_typer = Arc::new(TapTyper::new());
 
+        // entries are validated when loading yaml_config
+        let capture_scheduler = CaptureScheduler::new(
+            yaml_config
+                .capture_schedule
+                .iter()
+                .filter_map(|s| s.parse().ok())
+                .collect(),
+        );
+
         // TODO: collector enabled
         let mut dispatcher_components = vec![];
 
@@ -1922,11 +2852,13 @@ impl AgentComponents {
             yaml_config.analyzer_ip, candidate_config.sender.dest_ip
         );
         let l4_flow_aggr_queue_name = "3-flowlog-to-collector-sender";
-        let (l4_flow_aggr_sender, l4_flow_aggr_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.flow_sender_queue_size as usize,
-            l4_flow_aggr_queue_name,
-            &queue_debugger,
-        );
+        let (l4_flow_aggr_sender, l4_flow_aggr_receiver, counter) =
+            queue::bounded_with_debug_and_policy(
+                yaml_config.flow_sender_queue_size as usize,
+                l4_flow_aggr_queue_name,
+                &queue_debugger,
+                yaml_config.get_queue_overflow_policy(l4_flow_aggr_queue_name),
+            );
         stats_collector.register_countable(
             &QueueStats {
                 module: l4_flow_aggr_queue_name,
@@ -1934,7 +2866,7 @@ impl AgentComponents {
             },
             Countable::Owned(Box::new(counter)),
         );
-        let l4_flow_uniform_sender = UniformSenderThread::new(
+        let mut l4_flow_uniform_sender = UniformSenderThread::new(
             l4_flow_aggr_queue_name,
             Arc::new(l4_flow_aggr_receiver),
             config_handler.sender(),
@@ -1942,73 +2874,66 @@ impl AgentComponents {
             exception_handler.clone(),
             true,
         );
+        l4_flow_uniform_sender.enable_observe_mode();
 
         let metrics_queue_name = "3-doc-to-collector-sender";
-        let (metrics_sender, metrics_receiver, counter) = queue::bounded_with_debug(
+        let (metrics_sender, metrics_receiver) = sender_queue(
+            ebpf_sender_queues.metrics.take(),
             yaml_config.collector_sender_queue_size,
             metrics_queue_name,
+            yaml_config,
             &queue_debugger,
+            &stats_collector,
         );
-        stats_collector.register_countable(
-            &QueueStats {
-                module: metrics_queue_name,
-                ..Default::default()
-            },
-            Countable::Owned(Box::new(counter)),
-        );
-        let metrics_uniform_sender = UniformSenderThread::new(
+        let mut metrics_uniform_sender = UniformSenderThread::new(
             metrics_queue_name,
-            Arc::new(metrics_receiver),
+            metrics_receiver,
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
             true,
         );
+        metrics_uniform_sender.enable_observe_mode();
 
         let proto_log_queue_name = "2-protolog-to-collector-sender";
-        let (proto_log_sender, proto_log_receiver, counter) = queue::bounded_with_debug(
+        let (proto_log_sender, proto_log_receiver) = sender_queue(
+            ebpf_sender_queues.proto_log.take(),
             yaml_config.flow_sender_queue_size,
             proto_log_queue_name,
+            yaml_config,
             &queue_debugger,
+            &stats_collector,
         );
-        stats_collector.register_countable(
-            &QueueStats {
-                module: proto_log_queue_name,
-                ..Default::default()
-            },
-            Countable::Owned(Box::new(counter)),
-        );
-        let l7_flow_uniform_sender = UniformSenderThread::new(
+        let mut l7_flow_uniform_sender = UniformSenderThread::new(
             proto_log_queue_name,
-            Arc::new(proto_log_receiver),
+            proto_log_receiver,
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
             true,
         );
+        l7_flow_uniform_sender.enable_observe_mode();
 
-        let analyzer_ip = if candidate_config
-            .dispatcher
-            .analyzer_ip
-            .parse::<IpAddr>()
-            .is_ok()
-        {
-            candidate_config
-                .dispatcher
-                .analyzer_ip
-                .parse::<IpAddr>()
-                .unwrap()
-        } else {
-            let ips = lookup_host(&candidate_config.dispatcher.analyzer_ip)?;
-            ips[0]
-        };
+        // dispatchers can not tell traffic to the analyzer from others without its
+        // address, so resolve failures always fail the start
+        let analyzer_ip = dispatcher::resolve_analyzer_ip(
+            &candidate_config.dispatcher.analyzer_ip,
+            ctrl_ip.is_ipv6(),
+        )
+        .map_err(|e| {
+            anyhow!(
+                "resolve analyzer {} failed: {}",
+                candidate_config.dispatcher.analyzer_ip,
+                e
+            )
+        })?;
 
         // Dispatcher
         let source_ip = match get_route_src_ip(&analyzer_ip) {
             Ok(ip) => ip,
             Err(e) => {
                 warn!("get route to '{}' failed: {:?}", &analyzer_ip, e);
-                if ctrl_ip.is_ipv6() {
+                if analyzer_ip.is_ipv6() {
                     Ipv6Addr::UNSPECIFIED.into()
                 } else {
                     Ipv4Addr::UNSPECIFIED.into()
@@ -2019,6 +2944,10 @@ impl AgentComponents {
         let npb_bps_limit = Arc::new(LeakyBucket::new(Some(
             config_handler.candidate_config.sender.npb_bps_threshold,
         )));
+        stats_collector.register_countable(
+            &stats::SingleTagModule("leaky_bucket", "name", "npb"),
+            Countable::Ref(Arc::downgrade(&npb_bps_limit) as Weak<dyn RefCountable>),
+        );
         let npb_arp_table = Arc::new(NpbArpTable::new(
             config_handler.candidate_config.npb.socket_type == SocketType::RawUdp,
             exception_handler.clone(),
@@ -2026,10 +2955,11 @@ impl AgentComponents {
 
         let pcap_batch_queue = "2-pcap-batch-to-sender";
         let (pcap_batch_sender, pcap_batch_receiver, pcap_batch_counter) =
-            queue::bounded_with_debug(
+            queue::bounded_with_debug_and_policy(
                 yaml_config.pcap.queue_size as usize,
                 pcap_batch_queue,
                 &queue_debugger,
+                yaml_config.get_queue_overflow_policy(pcap_batch_queue),
             );
         stats_collector.register_countable(
             &QueueStats {
@@ -2038,44 +2968,70 @@ impl AgentComponents {
             },
             Countable::Owned(Box::new(pcap_batch_counter)),
         );
-        let pcap_batch_uniform_sender = UniformSenderThread::new(
+        let pcap_batch_receiver = Arc::new(pcap_batch_receiver);
+        let pcap_batch_file_writer = if agent_mode == RunningMode::Standalone {
+            Some(PcapFileWriterThread::new(
+                pcap_batch_queue,
+                pcap_batch_receiver.clone(),
+                config_handler.pcap(),
+                stats_collector.clone(),
+            ))
+        } else {
+            None
+        };
+        let mut pcap_batch_uniform_sender = UniformSenderThread::new(
             pcap_batch_queue,
-            Arc::new(pcap_batch_receiver),
+            pcap_batch_receiver,
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
             false,
         );
+        pcap_batch_uniform_sender.enable_observe_mode();
         // Enterprise Edition Feature: packet-sequence
-        let packet_sequence_queue_name = "2-packet-sequence-block-to-sender";
-        let (packet_sequence_uniform_output, packet_sequence_uniform_input, counter) =
-            queue::bounded_with_debug(
-                yaml_config.packet_sequence_queue_size,
-                packet_sequence_queue_name,
-                &queue_debugger,
-            );
+        // queues and threads are only built when enabled, toggling it rebuilds components
+        let (packet_sequence_uniform_output, packet_sequence_uniform_sender) =
+            if yaml_config.packet_sequence_enabled() {
+                let packet_sequence_queue_name = "2-packet-sequence-block-to-sender";
+                let (packet_sequence_uniform_output, packet_sequence_uniform_input, counter) =
+                    queue::bounded_with_debug_and_policy(
+                        yaml_config.packet_sequence_queue_size,
+                        packet_sequence_queue_name,
+                        &queue_debugger,
+                        yaml_config.get_queue_overflow_policy(packet_sequence_queue_name),
+                    );
 
-        stats_collector.register_countable(
-            &QueueStats {
-                module: packet_sequence_queue_name,
-                ..Default::default()
-            },
-            Countable::Owned(Box::new(counter)),
-        );
+                stats_collector.register_countable(
+                    &QueueStats {
+                        module: packet_sequence_queue_name,
+                        ..Default::default()
+                    },
+                    Countable::Owned(Box::new(counter)),
+                );
 
-        let packet_sequence_uniform_sender = UniformSenderThread::new(
-            packet_sequence_queue_name,
-            Arc::new(packet_sequence_uniform_input),
-            config_handler.sender(),
-            stats_collector.clone(),
-            exception_handler.clone(),
-            true,
-        );
+                let mut packet_sequence_uniform_sender = UniformSenderThread::new(
+                    packet_sequence_queue_name,
+                    Arc::new(packet_sequence_uniform_input),
+                    config_handler.sender(),
+                    stats_collector.clone(),
+                    exception_handler.clone(),
+                    true,
+                );
+                packet_sequence_uniform_sender.enable_observe_mode();
+                (
+                    Some(packet_sequence_uniform_output),
+                    Some(packet_sequence_uniform_sender),
+                )
+            } else {
+                (None, None)
+            };
 
         let bpf_builder = bpf::Builder {
-            is_ipv6: ctrl_ip.is_ipv6(),
+            controller_is_ipv6: ctrl_ip.is_ipv6(),
+            analyzer_is_ipv6: source_ip.is_ipv6(),
             vxlan_flags: yaml_config.vxlan_flags,
             npb_port: yaml_config.npb_port,
+            vxlan_ports: yaml_config.vxlan_ports.clone(),
             controller_port: static_config.controller_port,
             controller_tls_port: static_config.controller_tls_port,
             proxy_controller_port: candidate_config.dispatcher.proxy_controller_port,
@@ -2102,12 +3058,19 @@ impl AgentComponents {
             tap_interfaces.extend(links.clone());
             #[cfg(target_os = "linux")]
             let netns = entry.1;
+            let dispatcher_bpf_options = match links.get(0) {
+                Some(link) if candidate_config.tap_mode == TapMode::Analyzer => {
+                    build_analyzer_bpf_options(config_handler, &analyzer_ip, link)
+                }
+                _ => bpf_options.clone(),
+            };
             let dispatcher_component = build_dispatchers(
                 i,
                 links,
                 stats_collector.clone(),
                 config_handler,
                 queue_debugger.clone(),
+                &debugger.clone_dispatcher(),
                 version_info.name != env!("AGENT_NAME"),
                 synchronizer,
                 npb_bps_limit.clone(),
@@ -2116,7 +3079,7 @@ impl AgentComponents {
                 policy_getter,
                 exception_handler.clone(),
                 local_dispatcher_count,
-                bpf_options.clone(),
+                dispatcher_bpf_options,
                 packet_sequence_uniform_output.clone(),
                 proto_log_sender.clone(),
                 pcap_batch_sender.clone(),
@@ -2126,6 +3089,7 @@ impl AgentComponents {
                 toa_sender.clone(),
                 l4_flow_aggr_sender.clone(),
                 metrics_sender.clone(),
+                capture_scheduler.paused(),
                 #[cfg(target_os = "linux")]
                 netns,
                 #[cfg(target_os = "linux")]
@@ -2136,56 +3100,82 @@ impl AgentComponents {
             dispatcher_components.push(dispatcher_component);
         }
         tap_interfaces.sort();
+        // interfaces are captured by multiple dispatchers with af-packet-fanout
+        tap_interfaces.dedup();
+        #[cfg(target_os = "windows")]
+        let pcap_interface_watcher = if candidate_config.tap_mode == TapMode::Local
+            && yaml_config.libpcap_enabled
+            && !yaml_config.pcap_interface_refresh_interval.is_zero()
+        {
+            Some(dispatcher::PcapInterfaceWatcher::new(
+                config_handler.dispatcher(),
+                yaml_config.pcap_interface_refresh_interval,
+                dispatcher_components
+                    .iter()
+                    .map(|d| d.dispatcher_listener.clone())
+                    .collect(),
+            ))
+        } else {
+            None
+        };
+        let analyzer_ip_watcher = if !yaml_config.analyzer_ip_refresh_interval.is_zero() {
+            Some(dispatcher::AnalyzerIpWatcher::new(
+                config_handler.dispatcher(),
+                yaml_config.analyzer_ip_refresh_interval,
+                ctrl_ip.is_ipv6(),
+                dispatcher_components
+                    .iter()
+                    .map(|d| d.dispatcher_listener.clone())
+                    .collect(),
+            ))
+        } else {
+            None
+        };
         let proc_event_queue_name = "1-proc-event-to-sender";
-        #[allow(unused)]
-        let (proc_event_sender, proc_event_receiver, counter) = queue::bounded_with_debug(
+        let (proc_event_sender, proc_event_receiver) = sender_queue(
+            ebpf_sender_queues.proc_event.take(),
             yaml_config.ebpf_collector_queue_size,
             proc_event_queue_name,
+            yaml_config,
             &queue_debugger,
+            &stats_collector,
         );
-        stats_collector.register_countable(
-            &QueueStats {
-                module: proc_event_queue_name,
-                ..Default::default()
-            },
-            Countable::Owned(Box::new(counter)),
-        );
-        let proc_event_uniform_sender = UniformSenderThread::new(
+        let mut proc_event_uniform_sender = UniformSenderThread::new(
             proc_event_queue_name,
-            Arc::new(proc_event_receiver),
+            proc_event_receiver,
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
             true,
         );
+        proc_event_uniform_sender.enable_observe_mode();
 
         let profile_queue_name = "1-profile-to-sender";
-        let (profile_sender, profile_receiver, counter) = queue::bounded_with_debug(
+        let (profile_sender, profile_receiver) = sender_queue(
+            ebpf_sender_queues.profile.take(),
             yaml_config.ebpf_collector_queue_size,
             profile_queue_name,
+            yaml_config,
             &queue_debugger,
+            &stats_collector,
         );
-        stats_collector.register_countable(
-            &QueueStats {
-                module: profile_queue_name,
-                ..Default::default()
-            },
-            Countable::Owned(Box::new(counter)),
-        );
-        let profile_uniform_sender = UniformSenderThread::new(
+        let mut profile_uniform_sender = UniformSenderThread::new(
             profile_queue_name,
-            Arc::new(profile_receiver),
+            profile_receiver,
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
             true,
         );
+        profile_uniform_sender.enable_observe_mode();
         let application_log_queue_name = "1-application-log-to-sender";
-        let (application_log_sender, application_log_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.external_metrics_sender_queue_size,
-            application_log_queue_name,
-            &queue_debugger,
-        );
+        let (application_log_sender, application_log_receiver, counter) =
+            queue::bounded_with_debug_and_policy(
+                yaml_config.external_metrics_sender_queue_size,
+                application_log_queue_name,
+                &queue_debugger,
+                yaml_config.get_queue_overflow_policy(application_log_queue_name),
+            );
         stats_collector.register_countable(
             &QueueStats {
                 module: application_log_queue_name,
@@ -2193,7 +3183,7 @@ impl AgentComponents {
             },
             Countable::Owned(Box::new(counter)),
         );
-        let application_log_uniform_sender = UniformSenderThread::new(
+        let mut application_log_uniform_sender = UniformSenderThread::new(
             application_log_queue_name,
             Arc::new(application_log_receiver),
             config_handler.sender(),
@@ -2201,18 +3191,25 @@ impl AgentComponents {
             exception_handler.clone(),
             true,
         );
+        application_log_uniform_sender.enable_observe_mode();
 
         let ebpf_dispatcher_id = dispatcher_components.len();
         #[cfg(any(target_os = "linux", target_os = "android"))]
         let mut ebpf_dispatcher_component = None;
         #[cfg(any(target_os = "linux", target_os = "android"))]
-        if !config_handler.ebpf().load().ebpf.disabled
+        let mut ebpf_init_error = None;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(component) = retained_ebpf_component {
+            info!("keep ebpf collector of previous components");
+            ebpf_dispatcher_component = Some(component);
+        } else if !config_handler.ebpf().load().ebpf.disabled
             && candidate_config.tap_mode != TapMode::Analyzer
         {
-            let (flow_sender, flow_receiver, counter) = queue::bounded_with_debug(
+            let (flow_sender, flow_receiver, counter) = queue::bounded_with_debug_and_policy(
                 yaml_config.flow_queue_size,
                 "1-tagged-flow-to-quadruple-generator",
                 &queue_debugger,
+                yaml_config.get_queue_overflow_policy("1-tagged-flow-to-quadruple-generator"),
             );
             stats_collector.register_countable(
                 &QueueStats {
@@ -2222,11 +3219,13 @@ impl AgentComponents {
                 Countable::Owned(Box::new(counter)),
             );
 
-            let (l7_stats_sender, l7_stats_receiver, counter) = queue::bounded_with_debug(
-                yaml_config.flow_queue_size,
-                "1-l7-stats-to-quadruple-generator",
-                &queue_debugger,
-            );
+            let (l7_stats_sender, l7_stats_receiver, counter) =
+                queue::bounded_with_debug_and_policy(
+                    yaml_config.flow_queue_size,
+                    "1-l7-stats-to-quadruple-generator",
+                    &queue_debugger,
+                    yaml_config.get_queue_overflow_policy("1-l7-stats-to-quadruple-generator"),
+                );
             stats_collector.register_countable(
                 &QueueStats {
                     id: ebpf_dispatcher_id,
@@ -2241,16 +3240,17 @@ impl AgentComponents {
                 toa_sender.clone(),
                 None,
                 metrics_sender.clone(),
-                MetricsType::SECOND | MetricsType::MINUTE,
+                MetricsType::from(yaml_config.collector_metrics_type),
                 config_handler,
                 &queue_debugger,
                 &synchronizer,
                 agent_mode,
             );
-            let (log_sender, log_receiver, counter) = queue::bounded_with_debug(
+            let (log_sender, log_receiver, counter) = queue::bounded_with_debug_and_policy(
                 yaml_config.flow_queue_size,
                 "1-tagged-flow-to-app-protocol-logs",
                 &queue_debugger,
+                yaml_config.get_queue_overflow_policy("1-tagged-flow-to-app-protocol-logs"),
             );
             stats_collector.register_countable(
                 &QueueStats {
@@ -2275,7 +3275,7 @@ impl AgentComponents {
                 stats_collector.clone(),
                 l7_stats_receiver,
                 metrics_sender.clone(),
-                MetricsType::SECOND | MetricsType::MINUTE,
+                MetricsType::from(yaml_config.collector_metrics_type),
                 config_handler,
                 &queue_debugger,
                 &synchronizer,
@@ -2292,15 +3292,16 @@ impl AgentComponents {
                 log_sender,
                 flow_sender,
                 l7_stats_sender,
-                proc_event_sender,
+                proc_event_sender.clone(),
                 profile_sender.clone(),
                 &queue_debugger,
                 stats_collector.clone(),
                 exception_handler.clone(),
             ) {
                 Ok(ebpf_collector) => {
+                    exception_handler.clear(Exception::EbpfInitError);
                     synchronizer
-                        .add_flow_acl_listener(Box::new(ebpf_collector.get_sync_dispatcher()));
+                        .replace_flow_acl_listener(Box::new(ebpf_collector.get_sync_dispatcher()));
                     stats_collector.register_countable(
                         &stats::NoTagModule("ebpf-collector"),
                         Countable::Owned(Box::new(ebpf_collector.get_sync_counter())),
@@ -2313,16 +3314,33 @@ impl AgentComponents {
                     });
                 }
                 Err(e) => {
-                    log::error!("ebpf collector error: {:?}", e);
+                    match ebpf_missing_capability() {
+                        Some(capability) => warn!(
+                            "ebpf collector init failed: {}, missing capability: {}, agent runs without ebpf",
+                            e, capability
+                        ),
+                        None => warn!(
+                            "ebpf collector init failed: {}, no missing capability detected, agent runs without ebpf",
+                            e
+                        ),
+                    }
+                    exception_handler.set(Exception::EbpfInitError);
+                    let counter = Arc::new(EbpfInitErrorCounter);
+                    stats_collector.register_countable(
+                        &stats::NoTagModule("ebpf-collector"),
+                        Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+                    );
+                    ebpf_init_error = Some(counter);
                 }
             };
         }
 
         let otel_queue_name = "1-otel-to-sender";
-        let (otel_sender, otel_receiver, counter) = queue::bounded_with_debug(
+        let (otel_sender, otel_receiver, counter) = queue::bounded_with_debug_and_policy(
             yaml_config.external_metrics_sender_queue_size,
             otel_queue_name,
             &queue_debugger,
+            yaml_config.get_queue_overflow_policy(otel_queue_name),
         );
         stats_collector.register_countable(
             &QueueStats {
@@ -2331,7 +3349,7 @@ impl AgentComponents {
             },
             Countable::Owned(Box::new(counter)),
         );
-        let otel_uniform_sender = UniformSenderThread::new(
+        let mut otel_uniform_sender = UniformSenderThread::new(
             otel_queue_name,
             Arc::new(otel_receiver),
             config_handler.sender(),
@@ -2339,13 +3357,15 @@ impl AgentComponents {
             exception_handler.clone(),
             true,
         );
+        otel_uniform_sender.enable_observe_mode();
 
         let otel_dispatcher_id = ebpf_dispatcher_id + 1;
 
-        let (l7_stats_sender, l7_stats_receiver, counter) = queue::bounded_with_debug(
+        let (l7_stats_sender, l7_stats_receiver, counter) = queue::bounded_with_debug_and_policy(
             yaml_config.flow_queue_size,
             "1-l7-stats-to-quadruple-generator",
             &queue_debugger,
+            yaml_config.get_queue_overflow_policy("1-l7-stats-to-quadruple-generator"),
         );
         stats_collector.register_countable(
             &QueueStats {
@@ -2359,7 +3379,7 @@ impl AgentComponents {
             stats_collector.clone(),
             l7_stats_receiver,
             metrics_sender.clone(),
-            MetricsType::SECOND | MetricsType::MINUTE,
+            MetricsType::from(yaml_config.collector_metrics_type),
             config_handler,
             &queue_debugger,
             &synchronizer,
@@ -2367,11 +3387,13 @@ impl AgentComponents {
         );
 
         let prometheus_queue_name = "1-prometheus-to-sender";
-        let (prometheus_sender, prometheus_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.external_metrics_sender_queue_size,
-            prometheus_queue_name,
-            &queue_debugger,
-        );
+        let (prometheus_sender, prometheus_receiver, counter) =
+            queue::bounded_with_debug_and_policy(
+                yaml_config.external_metrics_sender_queue_size,
+                prometheus_queue_name,
+                &queue_debugger,
+                yaml_config.get_queue_overflow_policy(prometheus_queue_name),
+            );
         stats_collector.register_countable(
             &QueueStats {
                 module: prometheus_queue_name,
@@ -2379,7 +3401,7 @@ impl AgentComponents {
             },
             Countable::Owned(Box::new(counter)),
         );
-        let prometheus_uniform_sender = UniformSenderThread::new(
+        let mut prometheus_uniform_sender = UniformSenderThread::new(
             prometheus_queue_name,
             Arc::new(prometheus_receiver),
             config_handler.sender(),
@@ -2387,12 +3409,14 @@ impl AgentComponents {
             exception_handler.clone(),
             true,
         );
+        prometheus_uniform_sender.enable_observe_mode();
 
         let telegraf_queue_name = "1-telegraf-to-sender";
-        let (telegraf_sender, telegraf_receiver, counter) = queue::bounded_with_debug(
+        let (telegraf_sender, telegraf_receiver, counter) = queue::bounded_with_debug_and_policy(
             yaml_config.external_metrics_sender_queue_size,
             telegraf_queue_name,
             &queue_debugger,
+            yaml_config.get_queue_overflow_policy(telegraf_queue_name),
         );
         stats_collector.register_countable(
             &QueueStats {
@@ -2401,7 +3425,7 @@ impl AgentComponents {
             },
             Countable::Owned(Box::new(counter)),
         );
-        let telegraf_uniform_sender = UniformSenderThread::new(
+        let mut telegraf_uniform_sender = UniformSenderThread::new(
             telegraf_queue_name,
             Arc::new(telegraf_receiver),
             config_handler.sender(),
@@ -2409,13 +3433,16 @@ impl AgentComponents {
             exception_handler.clone(),
             true,
         );
+        telegraf_uniform_sender.enable_observe_mode();
 
         let compressed_otel_queue_name = "1-compressed-otel-to-sender";
-        let (compressed_otel_sender, compressed_otel_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.external_metrics_sender_queue_size,
-            compressed_otel_queue_name,
-            &queue_debugger,
-        );
+        let (compressed_otel_sender, compressed_otel_receiver, counter) =
+            queue::bounded_with_debug_and_policy(
+                yaml_config.external_metrics_sender_queue_size,
+                compressed_otel_queue_name,
+                &queue_debugger,
+                yaml_config.get_queue_overflow_policy(compressed_otel_queue_name),
+            );
         stats_collector.register_countable(
             &QueueStats {
                 module: compressed_otel_queue_name,
@@ -2423,7 +3450,7 @@ impl AgentComponents {
             },
             Countable::Owned(Box::new(counter)),
         );
-        let compressed_otel_uniform_sender = UniformSenderThread::new(
+        let mut compressed_otel_uniform_sender = UniformSenderThread::new(
             compressed_otel_queue_name,
             Arc::new(compressed_otel_receiver),
             config_handler.sender(),
@@ -2431,6 +3458,7 @@ impl AgentComponents {
             exception_handler.clone(),
             true,
         );
+        compressed_otel_uniform_sender.enable_observe_mode();
 
         let (external_metrics_server, external_metrics_counter) = MetricServer::new(
             runtime.clone(),
@@ -2439,11 +3467,19 @@ impl AgentComponents {
             l7_stats_sender,
             prometheus_sender,
             telegraf_sender,
-            profile_sender,
+            profile_sender.clone(),
             application_log_sender,
+            candidate_config.metric_server.bind_address,
             candidate_config.metric_server.port,
             exception_handler.clone(),
             candidate_config.metric_server.compressed,
+            BodySizeLimit {
+                max_body_size: yaml_config.external_agent_http_proxy_max_body_size,
+                max_decompressed_size: yaml_config
+                    .external_agent_http_proxy_max_decompressed_body_size,
+                max_decompression_ratio: yaml_config
+                    .external_agent_http_proxy_max_decompression_ratio,
+            },
             candidate_config.platform.epc_id,
             policy_getter,
             synchronizer.ntp_diff(),
@@ -2467,6 +3503,23 @@ impl AgentComponents {
             &stats::NoTagModule("integration_collector"),
             Countable::Owned(Box::new(external_metrics_counter)),
         );
+        if candidate_config.metric_server.enabled {
+            // the server never listens on an address not assigned to this host
+            if let Err(e) = external_metrics_server.check_bind_address() {
+                return Err(anyhow!("integration collector {}", e));
+            }
+            // the server logs and retries in background if it fails to listen, check in advance
+            // only when the agent is required to abort
+            if yaml_config.non_essential_component_failure == ComponentFailureAction::Abort {
+                if let Err(e) = external_metrics_server.check_listen() {
+                    return Err(anyhow!(
+                        "integration collector listen on port {} failed: {}",
+                        candidate_config.metric_server.port,
+                        e
+                    ));
+                }
+            }
+        }
 
         let sender_config = config_handler.sender().load();
         let (npb_bandwidth_watcher, npb_bandwidth_watcher_counter) = NpbBandwidthWatcher::new(
@@ -2476,7 +3529,7 @@ impl AgentComponents {
             npb_bps_limit.clone(),
             exception_handler.clone(),
         );
-        synchronizer.add_flow_acl_listener(npb_bandwidth_watcher.clone());
+        synchronizer.replace_flow_acl_listener(npb_bandwidth_watcher.clone());
         stats_collector.register_countable(
             &stats::NoTagModule("npb_bandwidth_watcher"),
             Countable::Ref(Arc::downgrade(&npb_bandwidth_watcher_counter) as Weak<dyn RefCountable>),
@@ -2499,6 +3552,8 @@ impl AgentComponents {
             debugger,
             #[cfg(any(target_os = "linux", target_os = "android"))]
             ebpf_dispatcher_component,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ebpf_init_error,
             stats_collector,
             running: AtomicBool::new(false),
             metrics_server_component: MetricsServerComponent {
@@ -2519,15 +3574,23 @@ impl AgentComponents {
             npb_bps_limit,
             compressed_otel_uniform_sender,
             pcap_batch_uniform_sender,
+            pcap_batch_file_writer,
+            #[cfg(target_os = "windows")]
+            pcap_interface_watcher,
+            analyzer_ip_watcher,
             proto_log_sender,
             pcap_batch_sender,
             toa_info_sender: toa_sender,
             l4_flow_aggr_sender,
             metrics_sender,
+            proc_event_sender,
+            profile_sender,
             agent_mode,
+            session: session.clone(),
             policy_setter,
             policy_getter,
             npb_bandwidth_watcher,
+            capture_scheduler,
             npb_arp_table,
             runtime,
             dispatcher_components,
@@ -2538,6 +3601,37 @@ impl AgentComponents {
         })
     }
 
+    fn sender_flushers(&self) -> Vec<SenderFlusher> {
+        let mut flushers = vec![
+            self.l4_flow_uniform_sender.flusher(),
+            self.metrics_uniform_sender.flusher(),
+            self.l7_flow_uniform_sender.flusher(),
+            self.otel_uniform_sender.flusher(),
+            self.compressed_otel_uniform_sender.flusher(),
+            self.prometheus_uniform_sender.flusher(),
+            self.telegraf_uniform_sender.flusher(),
+            self.profile_uniform_sender.flusher(),
+            self.proc_event_uniform_sender.flusher(),
+            self.application_log_uniform_sender.flusher(),
+            self.pcap_batch_uniform_sender.flusher(),
+        ];
+        if let Some(s) = self.stats_sender.as_ref() {
+            flushers.push(s.flusher());
+        }
+        // Enterprise Edition Feature: packet-sequence
+        if let Some(s) = self.packet_sequence_uniform_sender.as_ref() {
+            flushers.push(s.flusher());
+        }
+        flushers
+    }
+
+    // Senders flush cached data after the batch being processed, or within the queue read
+    // timeout if the queue is idle
+    pub fn flush_senders(&self) {
+        info!("flush all senders");
+        self.sender_flushers().iter().for_each(|f| f.flush());
+    }
+
     pub fn clear_dispatcher_components(&mut self) {
         self.dispatcher_components.iter_mut().for_each(|d| d.stop());
         self.dispatcher_components.clear();
@@ -2558,13 +3652,51 @@ impl AgentComponents {
             self.kubernetes_poller.start();
         }
         self.debugger.start();
+        self.debugger
+            .clone_sender()
+            .set_flushers(self.sender_flushers());
         self.metrics_uniform_sender.start();
         self.l7_flow_uniform_sender.start();
         self.l4_flow_uniform_sender.start();
 
         // Enterprise Edition Feature: packet-sequence
-        self.packet_sequence_uniform_sender.start();
+        if let Some(s) = self.packet_sequence_uniform_sender.as_mut() {
+            s.start();
+        }
 
+        let warm_up = self.config.yaml_config.capture_warm_up;
+        if warm_up.is_zero() {
+            self.start_capture();
+        }
+        if matches!(self.agent_mode, RunningMode::Managed) {
+            self.otel_uniform_sender.start();
+            self.compressed_otel_uniform_sender.start();
+            self.prometheus_uniform_sender.start();
+            self.telegraf_uniform_sender.start();
+            self.profile_uniform_sender.start();
+            self.proc_event_uniform_sender.start();
+            self.application_log_uniform_sender.start();
+            if self.config.metric_server.enabled {
+                self.metrics_server_component.start();
+            }
+            match self.pcap_batch_file_writer.as_mut() {
+                Some(writer) => writer.start(),
+                None => self.pcap_batch_uniform_sender.start(),
+            }
+        }
+
+        self.npb_bandwidth_watcher.start();
+        self.npb_arp_table.start();
+        self.capture_scheduler.start();
+
+        if !warm_up.is_zero() {
+            self.wait_for_warm_up(warm_up);
+            self.start_capture();
+        }
+        info!("Started agent components.");
+    }
+
+    fn start_capture(&mut self) {
         // When tap_mode is Analyzer mode and agent is not running in container and agent
         // in the environment where cgroup is not supported, we need to check free memory
         if self.tap_mode != TapMode::Analyzer
@@ -2587,27 +3719,44 @@ impl AgentComponents {
             }
         }
 
+        #[cfg(target_os = "windows")]
+        if let Some(watcher) = self.pcap_interface_watcher.as_mut() {
+            watcher.start();
+        }
+        if let Some(watcher) = self.analyzer_ip_watcher.as_mut() {
+            watcher.start();
+        }
         #[cfg(any(target_os = "linux", target_os = "android"))]
         if let Some(ebpf_dispatcher_component) = self.ebpf_dispatcher_component.as_mut() {
             ebpf_dispatcher_component.start();
         }
-        if matches!(self.agent_mode, RunningMode::Managed) {
-            self.otel_uniform_sender.start();
-            self.compressed_otel_uniform_sender.start();
-            self.prometheus_uniform_sender.start();
-            self.telegraf_uniform_sender.start();
-            self.profile_uniform_sender.start();
-            self.proc_event_uniform_sender.start();
-            self.application_log_uniform_sender.start();
-            if self.config.metric_server.enabled {
-                self.metrics_server_component.start();
+    }
+
+    // Waits until controller session is established or timeout before capturing,
+    // standalone mode has no controller and only waits for the timeout
+    fn wait_for_warm_up(&self, timeout: Duration) {
+        info!(
+            "capture warm-up: waiting at most {:?} for controller session before starting dispatchers",
+            timeout
+        );
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if matches!(self.agent_mode, RunningMode::Managed)
+                && self.session.get_client().is_some()
+                && !self.session.get_request_failed()
+            {
+                info!(
+                    "capture warm-up: controller session established after {:?}",
+                    start.elapsed()
+                );
+                return;
             }
-            self.pcap_batch_uniform_sender.start();
+            thread::sleep(Duration::from_millis(100));
         }
-
-        self.npb_bandwidth_watcher.start();
-        self.npb_arp_table.start();
-        info!("Started agent components.");
+        warn!(
+            "capture warm-up: controller session not established in {:?}, start dispatchers anyway",
+            timeout
+        );
     }
 
     fn stop(&mut self) {
@@ -2640,8 +3789,12 @@ impl AgentComponents {
         self.debugger.stop();
 
         #[cfg(any(target_os = "linux", target_os = "android"))]
-        if let Some(d) = self.ebpf_dispatcher_component.as_mut() {
-            d.stop();
+        if let Some(h) = self
+            .ebpf_dispatcher_component
+            .as_mut()
+            .and_then(|d| d.notify_stop())
+        {
+            join_handles.push(h);
         }
 
         self.metrics_server_component.stop();
@@ -2663,20 +3816,52 @@ impl AgentComponents {
         if let Some(h) = self.proc_event_uniform_sender.notify_stop() {
             join_handles.push(h);
         }
-        if let Some(h) = self.pcap_batch_uniform_sender.notify_stop() {
-            join_handles.push(h);
+        match self.pcap_batch_file_writer.as_mut() {
+            Some(writer) => {
+                if let Some(h) = writer.notify_stop() {
+                    join_handles.push(h);
+                }
+            }
+            None => {
+                if let Some(h) = self.pcap_batch_uniform_sender.notify_stop() {
+                    join_handles.push(h);
+                }
+            }
         }
         if let Some(h) = self.application_log_uniform_sender.notify_stop() {
             join_handles.push(h);
         }
         // Enterprise Edition Feature: packet-sequence
-        if let Some(h) = self.packet_sequence_uniform_sender.notify_stop() {
+        if let Some(h) = self
+            .packet_sequence_uniform_sender
+            .as_mut()
+            .and_then(|s| s.notify_stop())
+        {
+            join_handles.push(h);
+        }
+
+        #[cfg(target_os = "windows")]
+        if let Some(h) = self
+            .pcap_interface_watcher
+            .as_mut()
+            .and_then(|w| w.notify_stop())
+        {
+            join_handles.push(h);
+        }
+        if let Some(h) = self
+            .analyzer_ip_watcher
+            .as_mut()
+            .and_then(|w| w.notify_stop())
+        {
             join_handles.push(h);
         }
 
         if let Some(h) = self.npb_bandwidth_watcher.notify_stop() {
             join_handles.push(h);
         }
+        if let Some(h) = self.capture_scheduler.notify_stop() {
+            join_handles.push(h);
+        }
 
         if let Some(h) = self.npb_arp_table.notify_stop() {
             join_handles.push(h);
@@ -2697,9 +3882,111 @@ impl AgentComponents {
 
         info!("Stopped agent components.")
     }
+
+    // Stops capturing while senders keep running to drain the queues
+    fn stop_dispatchers(&mut self) {
+        for d in self.dispatcher_components.iter_mut() {
+            d.stop();
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(h) = self
+            .ebpf_dispatcher_component
+            .as_mut()
+            .and_then(|d| d.notify_stop())
+        {
+            let _ = h.join();
+        }
+    }
+
+    fn start_dispatchers(&mut self) {
+        for d in self.dispatcher_components.iter_mut() {
+            d.start();
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(d) = self.ebpf_dispatcher_component.as_mut() {
+            d.start();
+        }
+    }
+
+    fn remove_flow_acl_listeners(&self, synchronizer: &Synchronizer) {
+        synchronizer.remove_flow_acl_listener(FlowAclListenerId::Policy as usize);
+        synchronizer.remove_flow_acl_listener(FlowAclListenerId::NpbBandWatcher as usize);
+        for d in self.dispatcher_components.iter() {
+            synchronizer.remove_flow_acl_listener(d.dispatcher_listener.id());
+        }
+    }
+
+    // Takes the stopped eBPF collector and the queues it writes into, for the rebuilt components
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn retain_ebpf(&mut self) -> Option<RetainedEbpf> {
+        let component = self.ebpf_dispatcher_component.take()?;
+        Some(RetainedEbpf {
+            component,
+            lru_toa_info: self.socket_synchronizer.lru_toa_info(),
+            queues: EbpfSenderQueues {
+                metrics: Some((
+                    self.metrics_sender.clone(),
+                    self.metrics_uniform_sender.input(),
+                )),
+                proto_log: Some((
+                    self.proto_log_sender.clone(),
+                    self.l7_flow_uniform_sender.input(),
+                )),
+                proc_event: Some((
+                    self.proc_event_sender.clone(),
+                    self.proc_event_uniform_sender.input(),
+                )),
+                profile: Some((
+                    self.profile_sender.clone(),
+                    self.profile_uniform_sender.input(),
+                )),
+            },
+        })
+    }
+}
+
+// Reuses the queue kept from previous components, or creates a new one
+fn sender_queue<T: Send + 'static>(
+    kept: Option<SenderQueue<T>>,
+    size: usize,
+    name: &'static str,
+    yaml_config: &YamlConfig,
+    queue_debugger: &QueueDebugger,
+    stats_collector: &stats::Collector,
+) -> SenderQueue<T> {
+    if let Some(queue) = kept {
+        return queue;
+    }
+    let (sender, receiver, counter) = queue::bounded_with_debug_and_policy(
+        size,
+        name,
+        queue_debugger,
+        yaml_config.get_queue_overflow_policy(name),
+    );
+    stats_collector.register_countable(
+        &QueueStats {
+            module: name,
+            ..Default::default()
+        },
+        Countable::Owned(Box::new(counter)),
+    );
+    (sender, Arc::new(receiver))
 }
 
 impl Components {
+    fn is_running(&self) -> bool {
+        match self {
+            Self::Agent(a) => a.running.load(Ordering::Relaxed),
+            _ => false,
+        }
+    }
+
+    pub fn flush_senders(&self) {
+        if let Self::Agent(a) = self {
+            a.flush_senders();
+        }
+    }
+
     fn start(&mut self) {
         match self {
             Self::Agent(a) => a.start(),
@@ -2724,6 +4011,7 @@ impl Components {
         gateway_vmac_addrs: Vec<MacAddr>,
         agent_mode: RunningMode,
         runtime: Arc<Runtime>,
+        #[cfg(any(target_os = "linux", target_os = "android"))] retained_ebpf: Option<RetainedEbpf>,
     ) -> Result<Self> {
         #[cfg(target_os = "linux")]
         if crate::utils::environment::running_in_only_watch_k8s_mode() {
@@ -2748,6 +4036,8 @@ impl Components {
             gateway_vmac_addrs,
             agent_mode,
             runtime,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            retained_ebpf,
         )?;
         return Ok(Components::Agent(components));
     }
@@ -2760,6 +4050,76 @@ impl Components {
             _ => {}
         }
     }
+
+    fn stop_dispatchers(&mut self) {
+        if let Self::Agent(a) = self {
+            a.stop_dispatchers();
+        }
+    }
+
+    fn start_dispatchers(&mut self) {
+        if let Self::Agent(a) = self {
+            a.start_dispatchers();
+        }
+    }
+
+    // Stops the components and replaces them with new ones built from the current config,
+    // for config changes that can not be applied to running components.
+    // EbpfCollector can not be recreated in the same process, so the running one is kept
+    // and restarted with the new components.
+    fn rebuild(
+        &mut self,
+        version_info: &VersionInfo,
+        config_handler: &ConfigHandler,
+        stats_collector: Arc<stats::Collector>,
+        session: &Arc<Session>,
+        synchronizer: &Arc<Synchronizer>,
+        exception_handler: ExceptionHandler,
+        #[cfg(target_os = "linux")] libvirt_xml_extractor: Arc<LibvirtXmlExtractor>,
+        platform_synchronizer: Arc<PlatformSynchronizer>,
+        #[cfg(target_os = "linux")] sidecar_poller: Option<Arc<GenericPoller>>,
+        #[cfg(target_os = "linux")] api_watcher: Arc<ApiWatcher>,
+        vm_mac_addrs: Vec<MacAddr>,
+        gateway_vmac_addrs: Vec<MacAddr>,
+        agent_mode: RunningMode,
+        runtime: Arc<Runtime>,
+    ) -> Result<()> {
+        self.stop();
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let retained_ebpf = match self {
+            Self::Agent(a) => a.retain_ebpf(),
+            _ => None,
+        };
+        // Drops the stopped components and their flow acl listeners before building new ones,
+        // so that policies are not sent to them any more
+        if let Self::Agent(a) = mem::replace(self, Self::Other) {
+            a.remove_flow_acl_listeners(synchronizer);
+        }
+        let mut components = Self::new(
+            version_info,
+            config_handler,
+            stats_collector,
+            session,
+            synchronizer,
+            exception_handler,
+            #[cfg(target_os = "linux")]
+            libvirt_xml_extractor,
+            platform_synchronizer,
+            #[cfg(target_os = "linux")]
+            sidecar_poller,
+            #[cfg(target_os = "linux")]
+            api_watcher,
+            vm_mac_addrs,
+            gateway_vmac_addrs,
+            agent_mode,
+            runtime,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            retained_ebpf,
+        )?;
+        components.start();
+        *self = components;
+        Ok(())
+    }
 }
 
 fn build_pcap_assembler(
@@ -2801,12 +4161,60 @@ fn build_pcap_assembler(
     (pcap_assembler, mini_packet_sender)
 }
 
+// Analyzer mode dispatchers filter with the source ip of their own interface, which
+// differs from the route source ip on multi-homed hosts
+fn build_analyzer_bpf_options(
+    config_handler: &ConfigHandler,
+    analyzer_ip: &IpAddr,
+    link: &Link,
+) -> Arc<Mutex<BpfOptions>> {
+    let static_config = &config_handler.static_config;
+    let candidate_config = &config_handler.candidate_config;
+
+    let source_ip = match dispatcher::get_source_ip(&link.name, analyzer_ip) {
+        Ok(ip) => ip,
+        Err(e) => {
+            warn!(
+                "get source ip of {} to '{}' failed: {:?}",
+                link.name, analyzer_ip, e
+            );
+            if analyzer_ip.is_ipv6() {
+                Ipv6Addr::UNSPECIFIED.into()
+            } else {
+                Ipv4Addr::UNSPECIFIED.into()
+            }
+        }
+    };
+    info!("Dispatcher on {} uses source ip {}", link.name, source_ip);
+
+    let bpf_builder = bpf::Builder {
+        controller_is_ipv6: config_handler.ctrl_ip.is_ipv6(),
+        analyzer_is_ipv6: source_ip.is_ipv6(),
+        vxlan_flags: candidate_config.yaml_config.vxlan_flags,
+        npb_port: candidate_config.yaml_config.npb_port,
+        vxlan_ports: candidate_config.yaml_config.vxlan_ports.clone(),
+        controller_port: static_config.controller_port,
+        controller_tls_port: static_config.controller_tls_port,
+        proxy_controller_port: candidate_config.dispatcher.proxy_controller_port,
+        analyzer_source_ip: source_ip,
+        analyzer_port: candidate_config.dispatcher.analyzer_port,
+    };
+    let bpf_syntax_str = bpf_builder.build_pcap_syntax_to_str();
+    Arc::new(Mutex::new(BpfOptions {
+        capture_bpf: candidate_config.dispatcher.capture_bpf.clone(),
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        bpf_syntax: bpf_builder.build_pcap_syntax(),
+        bpf_syntax_str,
+    }))
+}
+
 fn build_dispatchers(
     id: usize,
     links: Vec<Link>,
     stats_collector: Arc<stats::Collector>,
     config_handler: &ConfigHandler,
     queue_debugger: Arc<QueueDebugger>,
+    dispatcher_debugger: &DispatcherDebugger,
     is_ce_version: bool,
     synchronizer: &Arc<Synchronizer>,
     npb_bps_limit: Arc<LeakyBucket>,
@@ -2816,7 +4224,7 @@ fn build_dispatchers(
     exception_handler: ExceptionHandler,
     local_dispatcher_count: usize,
     bpf_options: Arc<Mutex<BpfOptions>>,
-    packet_sequence_uniform_output: DebugSender<BoxedPacketSequenceBlock>,
+    packet_sequence_uniform_output: Option<DebugSender<BoxedPacketSequenceBlock>>,
     proto_log_sender: DebugSender<BoxAppProtoLogsData>,
     pcap_batch_sender: DebugSender<BoxedPcapBatch>,
     tap_typer: Arc<TapTyper>,
@@ -2825,6 +4233,7 @@ fn build_dispatchers(
     toa_info_sender: DebugSender<Box<(SocketAddr, SocketAddr)>>,
     l4_flow_aggr_sender: DebugSender<BoxedTaggedFlow>,
     metrics_sender: DebugSender<BoxedDocument>,
+    capture_paused: Arc<AtomicBool>,
     #[cfg(target_os = "linux")] netns: netns::NsFile,
     #[cfg(target_os = "linux")] kubernetes_poller: Arc<GenericPoller>,
     #[cfg(target_os = "linux")] libvirt_xml_extractor: Arc<LibvirtXmlExtractor>,
@@ -2838,10 +4247,11 @@ fn build_dispatchers(
     let ctrl_mac = config_handler.ctrl_mac;
     let src_link = links.get(0).map(|l| l.to_owned()).unwrap_or_default();
 
-    let (flow_sender, flow_receiver, counter) = queue::bounded_with_debug(
+    let (flow_sender, flow_receiver, counter) = queue::bounded_with_debug_and_policy(
         yaml_config.flow_queue_size,
         "1-tagged-flow-to-quadruple-generator",
         &queue_debugger,
+        yaml_config.get_queue_overflow_policy("1-tagged-flow-to-quadruple-generator"),
     );
     stats_collector.register_countable(
         &QueueStats {
@@ -2851,10 +4261,11 @@ fn build_dispatchers(
         Countable::Owned(Box::new(counter)),
     );
 
-    let (l7_stats_sender, l7_stats_receiver, counter) = queue::bounded_with_debug(
+    let (l7_stats_sender, l7_stats_receiver, counter) = queue::bounded_with_debug_and_policy(
         yaml_config.flow_queue_size,
         "1-l7-stats-to-quadruple-generator",
         &queue_debugger,
+        yaml_config.get_queue_overflow_policy("1-l7-stats-to-quadruple-generator"),
     );
     stats_collector.register_countable(
         &QueueStats {
@@ -2865,10 +4276,11 @@ fn build_dispatchers(
     );
 
     // create and start app proto logs
-    let (log_sender, log_receiver, counter) = queue::bounded_with_debug(
+    let (log_sender, log_receiver, counter) = queue::bounded_with_debug_and_policy(
         yaml_config.flow_queue_size,
         "1-tagged-flow-to-app-protocol-logs",
         &queue_debugger,
+        yaml_config.get_queue_overflow_policy("1-tagged-flow-to-app-protocol-logs"),
     );
     stats_collector.register_countable(
         &QueueStats {
@@ -2892,24 +4304,37 @@ fn build_dispatchers(
 
     // Enterprise Edition Feature: packet-sequence
     // create and start packet sequence
-    let (packet_sequence_sender, packet_sequence_receiver, counter) = queue::bounded_with_debug(
-        yaml_config.packet_sequence_queue_size,
-        "1-packet-sequence-block-to-parser",
-        &queue_debugger,
-    );
-    stats_collector.register_countable(
-        &QueueStats {
-            id,
-            module: "1-packet-sequence-block-to-parser",
-        },
-        Countable::Owned(Box::new(counter)),
-    );
+    let (packet_sequence_sender, packet_sequence_parser) = match packet_sequence_uniform_output {
+        Some(output) => {
+            let (packet_sequence_sender, packet_sequence_receiver, counter) =
+                queue::bounded_with_debug_and_policy(
+                    yaml_config.packet_sequence_queue_size,
+                    "1-packet-sequence-block-to-parser",
+                    &queue_debugger,
+                    yaml_config.get_queue_overflow_policy("1-packet-sequence-block-to-parser"),
+                );
+            stats_collector.register_countable(
+                &QueueStats {
+                    id,
+                    module: "1-packet-sequence-block-to-parser",
+                },
+                Countable::Owned(Box::new(counter)),
+            );
 
-    let packet_sequence_parser = PacketSequenceParser::new(
-        packet_sequence_receiver,
-        packet_sequence_uniform_output,
-        id as u32,
-    );
+            let (packet_sequence_parser, packet_sequence_counter) = PacketSequenceParser::new(
+                packet_sequence_receiver,
+                output,
+                id as u32,
+                yaml_config.packet_sequence_flush_interval,
+            );
+            stats_collector.register_countable(
+                &stats::SingleTagModule("packet_sequence", "index", id),
+                Countable::Ref(Arc::downgrade(&packet_sequence_counter) as Weak<dyn RefCountable>),
+            );
+            (Some(packet_sequence_sender), Some(packet_sequence_parser))
+        }
+        None => (None, None),
+    };
     let (pcap_assembler, mini_packet_sender) = build_pcap_assembler(
         is_ce_version,
         &yaml_config.pcap,
@@ -2939,27 +4364,72 @@ fn build_dispatchers(
             links.clone()
         };
 
+    let snap_len = match dispatcher::mtu_snap_len(&links) {
+        Some(l) if yaml_config.capture_packet_size_auto => {
+            info!("Dispatcher{} snap_len set to {} by interface mtu", id, l);
+            l
+        }
+        _ => dispatcher_config.capture_packet_size as usize,
+    };
+
     let dispatcher_builder = DispatcherBuilder::new()
         .id(id)
         .pause(agent_mode == RunningMode::Managed)
+        .capture_paused(capture_paused)
         .handler_builders(handler_builders.clone())
         .ctrl_mac(ctrl_mac)
         .leaky_bucket(rx_leaky_bucket.clone())
         .options(Arc::new(Mutex::new(dispatcher::Options {
             #[cfg(any(target_os = "linux", target_os = "android"))]
             af_packet_version: dispatcher_config.af_packet_version,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            af_packet_fanout: if candidate_config.tap_mode != TapMode::Local
+                && fanout_dispatchers(yaml_config) > 1
+                && links.len() == 1
+            {
+                let fanout = &yaml_config.af_packet_fanout;
+                Some(dispatcher::af_packet::Fanout {
+                    group_id: fanout.group_id.wrapping_add(src_link.if_index as u16),
+                    mode: fanout.mode,
+                })
+            } else {
+                None
+            },
             packet_blocks: dispatcher_config.af_packet_blocks,
             tap_mode: candidate_config.tap_mode,
             tap_mac_script: yaml_config.tap_mac_script.clone(),
             is_ipv6: ctrl_ip.is_ipv6(),
             npb_port: yaml_config.npb_port,
+            vxlan_ports: yaml_config.vxlan_ports.clone(),
             vxlan_flags: yaml_config.vxlan_flags,
             controller_port: static_config.controller_port,
             controller_tls_port: static_config.controller_tls_port,
             libpcap_enabled: yaml_config.libpcap_enabled,
-            snap_len: dispatcher_config.capture_packet_size as usize,
+            snap_len,
+            snap_len_auto: yaml_config.capture_packet_size_auto,
+            snap_len_overrides: Arc::new(
+                yaml_config
+                    .capture_packet_size_overrides
+                    .iter()
+                    .map(|o| dispatcher::SnapLenOverride {
+                        protocol: if o.protocol.eq_ignore_ascii_case("udp") {
+                            IpProtocol::UDP
+                        } else {
+                            IpProtocol::TCP
+                        },
+                        // validated in YamlConfig::load
+                        ports: parse_u16_range_list_to_bitmap(&o.ports, false).unwrap(),
+                        snap_len: o.capture_packet_size as usize,
+                    })
+                    .collect(),
+            ),
             dpdk_enabled: dispatcher_config.dpdk_enabled,
+            #[cfg(target_os = "linux")]
+            af_xdp_enabled: yaml_config.af_xdp_enabled,
             dispatcher_queue: dispatcher_config.dispatcher_queue,
+            analyzer_dedup_window: yaml_config.analyzer_dedup_window,
+            analyzer_dedup_table_size: yaml_config.analyzer_dedup_table_size,
+            capture_timestamp_source: yaml_config.capture_timestamp_source,
             ..Default::default()
         })))
         .bpf_options(bpf_options)
@@ -2969,12 +4439,21 @@ fn build_dispatchers(
                 .unwrap_or(TapType::Cloud),
         )
         .mirror_traffic_pcp(yaml_config.mirror_traffic_pcp)
+        .pcp_tap_types(
+            yaml_config
+                .mirror_traffic_pcp_tap_types
+                .iter()
+                .filter_map(|(pcp, t)| {
+                    // validated in YamlConfig::load
+                    TapType::try_from(*t as u16).ok().map(|t| (*pcp, t))
+                })
+                .collect(),
+        )
         .tap_typer(tap_typer.clone())
         .analyzer_dedup_disabled(yaml_config.analyzer_dedup_disabled)
         .flow_output_queue(flow_sender.clone())
         .l7_stats_output_queue(l7_stats_sender.clone())
         .log_output_queue(log_sender.clone())
-        .packet_sequence_output_queue(packet_sequence_sender) // Enterprise Edition Feature: packet-sequence
         .stats_collector(stats_collector.clone())
         .flow_map_config(config_handler.flow())
         .log_parse_config(config_handler.log_parser())
@@ -2982,19 +4461,40 @@ fn build_dispatchers(
         .policy_getter(policy_getter)
         .exception_handler(exception_handler.clone())
         .ntp_diff(synchronizer.ntp_diff())
-        .src_interface(if candidate_config.tap_mode != TapMode::Local {
-            src_link.name.clone()
+        .src_interfaces(if candidate_config.tap_mode != TapMode::Local {
+            links.iter().map(|l| l.name.clone()).collect()
         } else {
-            "".into()
+            vec![]
         })
         .trident_type(dispatcher_config.trident_type)
         .queue_debugger(queue_debugger.clone())
         .analyzer_queue_size(yaml_config.analyzer_queue_size as usize)
         .pcap_interfaces(pcap_interfaces.clone())
         .local_dispatcher_count(local_dispatcher_count)
+        .thread_stack_size(yaml_config.thread_stack_size)
         .tunnel_type_trim_bitmap(dispatcher_config.tunnel_type_trim_bitmap)
         .bond_group(dispatcher_config.bond_group.clone())
-        .analyzer_raw_packet_block_size(yaml_config.analyzer_raw_packet_block_size as usize);
+        .analyzer_raw_packet_block_size(yaml_config.analyzer_raw_packet_block_size as usize)
+        .batched_buffer_pool_size(yaml_config.batched_buffer_pool_size);
+    let dispatcher_builder = match yaml_config
+        .capture_bpf_overrides
+        .iter()
+        .find(|o| o.dispatcher_index == id)
+    {
+        Some(o) => {
+            info!(
+                "dispatcher {} uses capture bpf override: {}",
+                id, o.capture_bpf
+            );
+            dispatcher_builder.capture_bpf_override(o.capture_bpf.clone())
+        }
+        None => dispatcher_builder,
+    };
+    // Enterprise Edition Feature: packet-sequence
+    let dispatcher_builder = match packet_sequence_sender {
+        Some(s) => dispatcher_builder.packet_sequence_output_queue(s),
+        None => dispatcher_builder,
+    };
     #[cfg(target_os = "linux")]
     let dispatcher_builder = dispatcher_builder
         .netns(netns)
@@ -3011,6 +4511,7 @@ fn build_dispatchers(
             return Err(e.into());
         }
     };
+    dispatcher_debugger.set_timestamp_source(id, &src_link.name, dispatcher.timestamp_source());
     let mut dispatcher_listener = dispatcher.listener();
     dispatcher_listener.on_config_change(dispatcher_config);
     dispatcher_listener.on_tap_interface_change(
@@ -3020,7 +4521,7 @@ fn build_dispatchers(
         &vec![],
     );
     dispatcher_listener.on_vm_change(&vm_mac_addrs, &gateway_vmac_addrs);
-    synchronizer.add_flow_acl_listener(Box::new(dispatcher_listener.clone()));
+    synchronizer.replace_flow_acl_listener(Box::new(dispatcher_listener.clone()));
 
     // create and start collector
     let collector = AgentComponents::new_collector(
@@ -3030,7 +4531,7 @@ fn build_dispatchers(
         toa_info_sender.clone(),
         Some(l4_flow_aggr_sender.clone()),
         metrics_sender.clone(),
-        MetricsType::SECOND | MetricsType::MINUTE,
+        MetricsType::from(yaml_config.collector_metrics_type),
         config_handler,
         &queue_debugger,
         &synchronizer,
@@ -3042,7 +4543,7 @@ fn build_dispatchers(
         stats_collector.clone(),
         l7_stats_receiver,
         metrics_sender.clone(),
-        MetricsType::SECOND | MetricsType::MINUTE,
+        MetricsType::from(yaml_config.collector_metrics_type),
         config_handler,
         &queue_debugger,
         &synchronizer,
@@ -3059,5 +4560,127 @@ fn build_dispatchers(
         pcap_assembler,
         handler_builders,
         src_link,
+        src_links: links,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_links_by_interface_groups() {
+        let links = ["eth0", "span1", "span0", "span2", "eth1"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Link {
+                if_index: i as u32 + 1,
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let names = |entries: Vec<Vec<Link>>| {
+            entries
+                .into_iter()
+                .map(|e| e.into_iter().map(|l| l.name).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+
+        let groups = vec![
+            InterfaceGroup {
+                interfaces: vec!["span*".to_owned()],
+            },
+            InterfaceGroup {
+                interfaces: vec!["regex:eth1".to_owned()],
+            },
+        ];
+        assert_eq!(
+            names(group_links(links.clone(), &groups, 2)),
+            vec![
+                vec!["eth0"],
+                vec!["eth0"],
+                vec!["span1", "span0", "span2"],
+                vec!["eth1"],
+                vec!["eth1"],
+            ]
+        );
+
+        assert_eq!(
+            names(group_links(links, &[], 1)),
+            vec![
+                vec!["eth0"],
+                vec!["span1"],
+                vec!["span0"],
+                vec!["span2"],
+                vec!["eth1"],
+            ]
+        );
+    }
+
+    #[test]
+    fn state_transition_exception() {
+        let exception_handler = ExceptionHandler::default();
+        let mut state = State::Running;
+
+        state.transit(
+            State::ConfigChanged(ChangedConfig::default()),
+            StateTrigger::ServerPush,
+            &exception_handler,
+        );
+        state.transit(
+            State::Running,
+            StateTrigger::ConfigApplied,
+            &exception_handler,
+        );
+        assert!(!exception_handler.has(Exception::AgentStateChanged));
+
+        state.transit(
+            State::Disabled(None),
+            StateTrigger::EscapeTimeout,
+            &exception_handler,
+        );
+        assert!(exception_handler.has(Exception::AgentStateChanged));
+        exception_handler.take();
+        assert!(!exception_handler.has(Exception::AgentStateChanged));
+
+        state.transit(
+            State::Disabled(None),
+            StateTrigger::EscapeTimeout,
+            &exception_handler,
+        );
+        assert!(!exception_handler.has(Exception::AgentStateChanged));
+        let old = state.transit(
+            State::Terminated,
+            StateTrigger::Shutdown,
+            &exception_handler,
+        );
+        assert!(matches!(old, State::Disabled(None)));
+        assert!(exception_handler.has(Exception::AgentStateChanged));
+    }
+
+    #[test]
+    fn yaml_config_change_with_ebpf_running() {
+        let old = YamlConfig::default();
+        let mut new = old.clone();
+        assert_eq!(yaml_config_change(&old, &new, true), None);
+
+        new.vtap_group_id_request = "g-test".to_owned();
+        assert_eq!(yaml_config_change(&old, &new, true), None);
+
+        new.flow_queue_size += 1;
+        assert_eq!(
+            yaml_config_change(&old, &new, true),
+            Some(YamlConfigChange::Rebuild)
+        );
+
+        new.ebpf.ring_size += 1;
+        assert_eq!(
+            yaml_config_change(&old, &new, false),
+            Some(YamlConfigChange::Rebuild)
+        );
+        assert_eq!(
+            yaml_config_change(&old, &new, true),
+            Some(YamlConfigChange::Restart(vec!["ebpf.ring-size"]))
+        );
+    }
+}
@@ -0,0 +1,256 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{Datelike, Local, Timelike};
+use log::info;
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const ALL_DAYS: u8 = 0x7f;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+// A capture window in local time, formatted as "[DAYS ]HH:MM-HH:MM", where DAYS is a
+// weekday, a range like "Mon-Fri" or a list like "Sat,Sun", and all days if omitted.
+// A window ending before it starts crosses midnight and the part after midnight belongs
+// to the day it starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureWindow {
+    // bit 0 is Monday
+    days: u8,
+    // minutes of day
+    start: u16,
+    end: u16,
+}
+
+impl CaptureWindow {
+    fn has_day(&self, weekday: u32) -> bool {
+        self.days & (1 << weekday) != 0
+    }
+
+    // weekday starts from 0 for Monday
+    pub fn contains(&self, weekday: u32, minute: u16) -> bool {
+        if self.start < self.end {
+            self.has_day(weekday) && self.start <= minute && minute < self.end
+        } else {
+            (self.has_day(weekday) && minute >= self.start)
+                || (self.has_day((weekday + 6) % 7) && minute < self.end)
+        }
+    }
+
+    fn parse_weekday(s: &str) -> Result<u32, String> {
+        let lower = s.trim().to_ascii_lowercase();
+        WEEKDAYS
+            .iter()
+            .position(|d| *d == lower)
+            .map(|i| i as u32)
+            .ok_or_else(|| format!("invalid weekday {}", s))
+    }
+
+    fn parse_days(s: &str) -> Result<u8, String> {
+        let mut days = 0;
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((from, to)) => {
+                    let (from, to) = (Self::parse_weekday(from)?, Self::parse_weekday(to)?);
+                    let mut d = from;
+                    loop {
+                        days |= 1 << d;
+                        if d == to {
+                            break;
+                        }
+                        d = (d + 1) % 7;
+                    }
+                }
+                None => days |= 1 << Self::parse_weekday(part)?,
+            }
+        }
+        Ok(days)
+    }
+
+    fn parse_time(s: &str) -> Result<u16, String> {
+        let invalid = || format!("invalid time {}", s);
+        let (h, m) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let (h, m) = (
+            h.parse::<u16>().map_err(|_| invalid())?,
+            m.parse::<u16>().map_err(|_| invalid())?,
+        );
+        if m >= 60 || h * 60 + m > MINUTES_PER_DAY {
+            return Err(invalid());
+        }
+        Ok(h * 60 + m)
+    }
+}
+
+impl FromStr for CaptureWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (days, times) = match s.rsplit_once(char::is_whitespace) {
+            Some((days, times)) => (Self::parse_days(days)?, times),
+            None => (ALL_DAYS, s),
+        };
+        let (start, end) = times
+            .split_once('-')
+            .ok_or_else(|| format!("invalid time range {}", times))?;
+        let (start, end) = (Self::parse_time(start)?, Self::parse_time(end)?);
+        if start == end || start == MINUTES_PER_DAY {
+            return Err(format!("invalid time range {}", times));
+        }
+        Ok(Self {
+            days,
+            start,
+            end: end % MINUTES_PER_DAY,
+        })
+    }
+}
+
+fn in_windows(windows: &[CaptureWindow]) -> bool {
+    let now = Local::now();
+    let (weekday, minute) = (
+        now.weekday().num_days_from_monday(),
+        (now.hour() * 60 + now.minute()) as u16,
+    );
+    windows.iter().any(|w| w.contains(weekday, minute))
+}
+
+// Pauses packet capturing of dispatchers outside of the configured windows
+pub struct CaptureScheduler {
+    windows: Arc<Vec<CaptureWindow>>,
+    paused: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl CaptureScheduler {
+    const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    // empty windows means capturing all the time
+    pub fn new(windows: Vec<CaptureWindow>) -> Self {
+        let paused = !windows.is_empty() && !in_windows(&windows);
+        if paused {
+            info!("capture paused by schedule {:?}", windows);
+        }
+        Self {
+            windows: Arc::new(windows),
+            paused: Arc::new(AtomicBool::new(paused)),
+            running: Default::default(),
+            thread: Mutex::new(None),
+        }
+    }
+
+    // shared with dispatchers, which drop packets when it is set
+    pub fn paused(&self) -> Arc<AtomicBool> {
+        self.paused.clone()
+    }
+
+    pub fn start(&self) {
+        if self.windows.is_empty() || self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let windows = self.windows.clone();
+        let paused = self.paused.clone();
+        let running = self.running.clone();
+        self.thread.lock().unwrap().replace(
+            thread::Builder::new()
+                .name("capture-scheduler".to_owned())
+                .spawn(move || {
+                    while running.load(Ordering::Relaxed) {
+                        thread::sleep(Self::CHECK_INTERVAL);
+                        let pause = !in_windows(&windows);
+                        if paused.swap(pause, Ordering::Relaxed) != pause {
+                            info!(
+                                "capture {} by schedule",
+                                if pause { "paused" } else { "resumed" }
+                            );
+                        }
+                    }
+                })
+                .unwrap(),
+        );
+        info!("capture scheduler started");
+    }
+
+    pub fn notify_stop(&self) -> Option<JoinHandle<()>> {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        info!("notify capture scheduler stop");
+        self.thread.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_capture_window() {
+        let w: CaptureWindow = "08:00-18:00".parse().unwrap();
+        assert_eq!(
+            w,
+            CaptureWindow {
+                days: ALL_DAYS,
+                start: 480,
+                end: 1080
+            }
+        );
+        let w: CaptureWindow = "Mon-Fri 08:30-24:00".parse().unwrap();
+        assert_eq!(w.days, 0x1f);
+        assert_eq!((w.start, w.end), (510, 0));
+        let w: CaptureWindow = "sat,Sun 00:00-12:00".parse().unwrap();
+        assert_eq!(w.days, 0x60);
+        let w: CaptureWindow = "Sat-Mon 22:00-06:00".parse().unwrap();
+        assert_eq!(w.days, 0x61);
+
+        for s in [
+            "",
+            "08:00",
+            "08:00-08:00",
+            "25:00-26:00",
+            "Foo 08:00-09:00",
+            "08:60-09:00",
+        ] {
+            assert!(
+                s.parse::<CaptureWindow>().is_err(),
+                "{} should be invalid",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn capture_window_contains() {
+        let w: CaptureWindow = "Mon-Fri 08:00-18:00".parse().unwrap();
+        assert!(w.contains(0, 480));
+        assert!(!w.contains(0, 1080));
+        assert!(!w.contains(5, 600));
+
+        // crossing midnight, Friday night to Saturday morning is included
+        let w: CaptureWindow = "Fri 22:00-06:00".parse().unwrap();
+        assert!(w.contains(4, 1380));
+        assert!(w.contains(5, 300));
+        assert!(!w.contains(4, 300));
+        assert!(!w.contains(5, 1380));
+    }
+}
@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::Local;
+use flexi_logger::{LogSpecification, LoggerHandle};
+use log::{info, warn};
+use signal_hook::{
+    consts::{SIGUSR1, SIGUSR2},
+    iterator::{Handle, Signals},
+};
+
+use crate::trident::{TridentState, VersionInfo};
+use crate::utils::stats;
+
+const DUMP_FILE_PREFIX: &str = "deepflow-agent-diagnostic";
+const DEBUG_LOG_WINDOW: Duration = Duration::from_secs(300);
+
+// (enabled, generation), generation invalidates pending expiry of previous windows
+type DebugLogWindow = Arc<Mutex<(bool, u64)>>;
+
+struct Context {
+    dump_dir: PathBuf,
+    version_info: &'static VersionInfo,
+    state: TridentState,
+    effective_config: String,
+    stats_collector: Arc<stats::Collector>,
+    logger_handle: LoggerHandle,
+    debug_log_window: DebugLogWindow,
+}
+
+// SIGUSR1 writes a one-shot diagnostic dump under the log directory
+// SIGUSR2 toggles debug logging, which is reverted automatically after DEBUG_LOG_WINDOW
+pub struct DiagnosticHandler {
+    handle: Handle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DiagnosticHandler {
+    pub fn start(
+        dump_dir: PathBuf,
+        version_info: &'static VersionInfo,
+        state: TridentState,
+        effective_config: String,
+        stats_collector: Arc<stats::Collector>,
+        logger_handle: LoggerHandle,
+    ) -> io::Result<Self> {
+        let mut signals = Signals::new(&[SIGUSR1, SIGUSR2])?;
+        let handle = signals.handle();
+        let mut context = Context {
+            dump_dir,
+            version_info,
+            state,
+            effective_config,
+            stats_collector,
+            logger_handle,
+            debug_log_window: Default::default(),
+        };
+        let thread = thread::Builder::new()
+            .name("diagnostic-handler".to_owned())
+            .spawn(move || {
+                for signal in signals.forever() {
+                    match signal {
+                        SIGUSR1 => match context.dump() {
+                            Ok(path) => info!("diagnostic dump written to {}", path.display()),
+                            Err(e) => warn!("write diagnostic dump failed: {}", e),
+                        },
+                        SIGUSR2 => context.toggle_debug_log(),
+                        _ => (),
+                    }
+                }
+            })?;
+        info!(
+            "diagnostic handler started, SIGUSR1 to dump diagnostics, SIGUSR2 to toggle debug log"
+        );
+        Ok(Self {
+            handle,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.handle.close();
+        let _ = thread.join();
+        info!("diagnostic handler stopped");
+    }
+}
+
+impl Context {
+    fn dump(&self) -> io::Result<PathBuf> {
+        let path = self.dump_dir.join(format!(
+            "{}-{}.txt",
+            DUMP_FILE_PREFIX,
+            Local::now().format("%Y%m%d%H%M%S")
+        ));
+        let mut w = BufWriter::new(File::create(&path)?);
+        writeln!(w, "==================== Version ====================")?;
+        writeln!(w, "{}", self.version_info)?;
+        writeln!(w, "==================== State ====================")?;
+        let state = self.state.0.lock().unwrap().name();
+        writeln!(w, "{}", state)?;
+        writeln!(
+            w,
+            "debug log: {}",
+            if self.debug_log_window.lock().unwrap().0 {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        )?;
+        writeln!(w, "==================== Stats ====================")?;
+        self.stats_collector.dump(&mut w)?;
+        writeln!(w, "==================== Static Config ====================")?;
+        writeln!(w, "{}", self.effective_config)?;
+        w.flush()?;
+        Ok(path)
+    }
+
+    fn toggle_debug_log(&mut self) {
+        let mut window = self.debug_log_window.lock().unwrap();
+        window.1 += 1;
+        if window.0 {
+            window.0 = false;
+            self.logger_handle.pop_temp_spec();
+            info!("debug log disabled");
+            return;
+        }
+        window.0 = true;
+        self.logger_handle.push_temp_spec(LogSpecification::debug());
+        info!("debug log enabled for {:?}", DEBUG_LOG_WINDOW);
+
+        let generation = window.1;
+        let debug_log_window = self.debug_log_window.clone();
+        let mut logger_handle = self.logger_handle.clone();
+        let _ = thread::Builder::new()
+            .name("debug-log-window".to_owned())
+            .spawn(move || {
+                thread::sleep(DEBUG_LOG_WINDOW);
+                let mut window = debug_log_window.lock().unwrap();
+                if window.0 && window.1 == generation {
+                    window.0 = false;
+                    logger_handle.pop_temp_spec();
+                    info!("debug log disabled after {:?}", DEBUG_LOG_WINDOW);
+                }
+            });
+    }
+}
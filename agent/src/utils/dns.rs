@@ -0,0 +1,622 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use log::{debug, warn};
+use rand::Rng;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+#[cfg(not(target_os = "windows"))]
+const HOSTS_FILE: &str = "/etc/hosts";
+#[cfg(target_os = "windows")]
+const HOSTS_FILE: &str = "C:\\Windows\\System32\\drivers\\etc\\hosts";
+const DNS_PORT: u16 = 53;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_MESSAGE_SIZE: usize = 1232;
+// prevents loops of compression pointers in malformed messages
+const MAX_NAME_JUMPS: usize = 16;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+// resolv.conf defaults
+const DEFAULT_NDOTS: usize = 1;
+const MAX_NDOTS: usize = 15;
+
+// Service label prepended to controller domain names for SRV lookups
+pub const CONTROLLER_SRV_SERVICE: &str = "_deepflow._tcp";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+// Nameservers and search list in /etc/resolv.conf
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ResolvConf {
+    nameservers: Vec<IpAddr>,
+    search: Vec<String>,
+    ndots: usize,
+}
+
+impl Default for ResolvConf {
+    fn default() -> Self {
+        Self {
+            nameservers: vec![],
+            search: vec![],
+            ndots: DEFAULT_NDOTS,
+        }
+    }
+}
+
+impl ResolvConf {
+    // Nameservers default to localhost like libc if none is configured
+    fn load() -> Self {
+        let mut conf = fs::read_to_string(RESOLV_CONF)
+            .map(|c| parse_resolv_conf(&c))
+            .unwrap_or_default();
+        if conf.nameservers.is_empty() {
+            conf.nameservers = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+        }
+        conf
+    }
+
+    // Names to query for `name` in order like libc. Names ending with a dot are absolute,
+    // names with at least `ndots` dots are tried as is before appending search domains,
+    // others after.
+    fn search_names(&self, name: &str) -> Vec<String> {
+        if name.ends_with('.') {
+            return vec![name.to_owned()];
+        }
+        let mut names = self
+            .search
+            .iter()
+            .map(|domain| format!("{}.{}", name, domain.trim_end_matches('.')))
+            .collect::<Vec<_>>();
+        if name.matches('.').count() >= self.ndots {
+            names.insert(0, name.to_owned());
+        } else {
+            names.push(name.to_owned());
+        }
+        names
+    }
+}
+
+// Resolves domain names for controller connections. With no nameserver configured and
+// hosts file honored, names are resolved by the system resolver (getaddrinfo), otherwise
+// queries are sent to the nameservers (or those in /etc/resolv.conf) directly, with
+// `search` and `ndots` in /etc/resolv.conf applied to names not ending with a dot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolver {
+    nameservers: Vec<IpAddr>,
+    hosts_file: bool,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self {
+            nameservers: vec![],
+            hosts_file: true,
+        }
+    }
+}
+
+impl fmt::Display for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_system() {
+            return write!(f, "system resolver");
+        }
+        if self.nameservers.is_empty() {
+            write!(f, "nameservers in {}", RESOLV_CONF)?;
+        } else {
+            write!(f, "nameservers {:?}", self.nameservers)?;
+        }
+        if self.hosts_file {
+            write!(f, " after {}", HOSTS_FILE)
+        } else {
+            write!(f, " ignoring {}", HOSTS_FILE)
+        }
+    }
+}
+
+impl Resolver {
+    pub fn new(nameservers: Vec<IpAddr>, hosts_file: bool) -> Self {
+        Self {
+            nameservers,
+            hosts_file,
+        }
+    }
+
+    fn is_system(&self) -> bool {
+        self.nameservers.is_empty() && self.hosts_file
+    }
+
+    fn resolv_conf(&self) -> ResolvConf {
+        let mut conf = ResolvConf::load();
+        if !self.nameservers.is_empty() {
+            conf.nameservers = self.nameservers.clone();
+        }
+        conf
+    }
+
+    // Queries records of `name` with each name in the search list until one has records
+    fn search<T, F>(&self, name: &str, qtype: u16, parse_rdata: F) -> io::Result<Vec<T>>
+    where
+        F: Fn(&[u8], usize, usize) -> io::Result<T>,
+    {
+        let conf = self.resolv_conf();
+        let mut last_err = None;
+        for candidate in conf.search_names(name) {
+            match query(&candidate, qtype, &conf.nameservers, &parse_rdata) {
+                Ok(records) if !records.is_empty() => return Ok(records),
+                Ok(_) => (),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        last_err.map_or(Ok(vec![]), Err)
+    }
+
+    pub fn lookup_host(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        if let Ok(ip) = name.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        if self.is_system() {
+            return dns_lookup::lookup_host(name);
+        }
+        if self.hosts_file {
+            let ips = fs::read_to_string(HOSTS_FILE)
+                .map(|c| parse_hosts(&c, name))
+                .unwrap_or_default();
+            if !ips.is_empty() {
+                return Ok(ips);
+            }
+        }
+        let mut ips = self.search(name, TYPE_A, parse_addr_rdata)?;
+        if ips.is_empty() {
+            ips = self.search(name, TYPE_AAAA, parse_addr_rdata)?;
+        }
+        if ips.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no address of {}", name),
+            ));
+        }
+        Ok(ips)
+    }
+
+    // Queries SRV records of `name`, returns an empty list if the name or its SRV records do not exist
+    pub fn lookup_srv(&self, name: &str) -> io::Result<Vec<SrvRecord>> {
+        self.search(name, TYPE_SRV, parse_srv_rdata)
+    }
+
+    // Resolves SRV records of `name` to (ip, port) in the order they should be tried,
+    // targets failing to resolve are skipped
+    pub fn resolve_srv(&self, name: &str) -> io::Result<Vec<(IpAddr, u16)>> {
+        let records = order_srv_records(self.lookup_srv(name)?);
+        let mut targets = vec![];
+        for r in records {
+            match self.lookup_host(&r.target) {
+                Ok(ips) if !ips.is_empty() => targets.push((ips[0], r.port)),
+                _ => debug!("resolve srv target {} of {} failed", r.target, name),
+            }
+        }
+        Ok(targets)
+    }
+
+    // Controllers of a domain from its SRV records, empty if none exists so that the caller
+    // falls back to A records
+    pub fn resolve_controller_srv(&self, domain: &str) -> Vec<(IpAddr, u16)> {
+        let name = controller_srv_name(domain);
+        match self.resolve_srv(&name) {
+            Ok(targets) => targets,
+            Err(e) => {
+                warn!("lookup srv {} failed: {}", name, e);
+                vec![]
+            }
+        }
+    }
+}
+
+// The last of `domain` and `search` wins like libc
+fn parse_resolv_conf(contents: &str) -> ResolvConf {
+    let mut conf = ResolvConf::default();
+    for l in contents.lines() {
+        let mut fields = l.split_whitespace();
+        match fields.next() {
+            Some("nameserver") => {
+                if let Some(ip) = fields.next().and_then(|ip| ip.parse().ok()) {
+                    conf.nameservers.push(ip);
+                }
+            }
+            Some("domain") => conf.search = fields.next().into_iter().map(String::from).collect(),
+            Some("search") => conf.search = fields.map(String::from).collect(),
+            Some("options") => {
+                for option in fields {
+                    if let Some(ndots) = option
+                        .strip_prefix("ndots:")
+                        .and_then(|n| n.parse::<usize>().ok())
+                    {
+                        conf.ndots = ndots.min(MAX_NDOTS);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    conf
+}
+
+// Addresses of `name` in hosts file contents
+fn parse_hosts(contents: &str, name: &str) -> Vec<IpAddr> {
+    contents
+        .lines()
+        .filter_map(|l| {
+            let l = l.split('#').next().unwrap_or_default();
+            let mut fields = l.split_whitespace();
+            let ip = fields.next()?.parse::<IpAddr>().ok()?;
+            fields.any(|n| n.eq_ignore_ascii_case(name)).then_some(ip)
+        })
+        .collect()
+}
+
+// Queries records of `qtype` from nameservers in order, the first server answering wins.
+// Returns an empty list if the name or its records do not exist.
+fn query<T, F>(name: &str, qtype: u16, nameservers: &[IpAddr], parse_rdata: F) -> io::Result<Vec<T>>
+where
+    F: Fn(&[u8], usize, usize) -> io::Result<T>,
+{
+    let id = rand::thread_rng().gen::<u16>();
+    let query = build_query(id, name, qtype)?;
+    let mut last_err = Error::new(ErrorKind::NotFound, "no nameserver configured");
+    for server in nameservers {
+        match query_server(&query, SocketAddr::new(*server, DNS_PORT))
+            .and_then(|resp| parse_response(id, &resp, qtype, &parse_rdata))
+        {
+            Ok(records) => return Ok(records),
+            Err(e) => {
+                debug!(
+                    "query {} type {} from {} failed: {}",
+                    name, qtype, server, e
+                );
+                last_err = e;
+            }
+        }
+    }
+    Err(last_err)
+}
+
+// SRV name of a controller domain, names already starting with a service label are used as is
+pub fn controller_srv_name(domain: &str) -> String {
+    if domain.starts_with('_') {
+        domain.to_owned()
+    } else {
+        format!("{}.{}", CONTROLLER_SRV_SERVICE, domain)
+    }
+}
+
+// Orders records by priority, records of the same priority are ordered by weighted
+// random selection as described in RFC 2782
+pub fn order_srv_records(mut records: Vec<SrvRecord>) -> Vec<SrvRecord> {
+    // a single "." target means the service is decidedly not available
+    records.retain(|r| !r.target.is_empty());
+    records.sort_by_key(|r| (r.priority, r.weight));
+    let mut rng = rand::thread_rng();
+    let mut ordered = Vec::with_capacity(records.len());
+    while !records.is_empty() {
+        let priority = records[0].priority;
+        let mut group = records
+            .iter()
+            .position(|r| r.priority != priority)
+            .map(|end| records.drain(..end).collect::<Vec<_>>())
+            .unwrap_or_else(|| records.drain(..).collect());
+        while !group.is_empty() {
+            let total = group.iter().map(|r| r.weight as u32).sum::<u32>();
+            let mut pick = rng.gen_range(0..=total);
+            let index = group
+                .iter()
+                .position(|r| {
+                    if pick <= r.weight as u32 {
+                        return true;
+                    }
+                    pick -= r.weight as u32;
+                    false
+                })
+                .unwrap_or(group.len() - 1);
+            ordered.push(group.remove(index));
+        }
+    }
+    ordered
+}
+
+fn query_server(query: &[u8], server: SocketAddr) -> io::Result<Vec<u8>> {
+    let bind: SocketAddr = if server.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.connect(server)?;
+    socket.send(query)?;
+    let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+    let n = socket.recv(&mut buf)?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+fn build_query(id: u16, name: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(512);
+    buf.extend_from_slice(&id.to_be_bytes());
+    // recursion desired
+    buf.extend_from_slice(&0x0100u16.to_be_bytes());
+    // qdcount 1, ancount nscount arcount 0
+    buf.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid domain name {}", name),
+            ));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    buf.extend_from_slice(&qtype.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(buf)
+}
+
+fn too_short() -> Error {
+    Error::new(ErrorKind::InvalidData, "dns message too short")
+}
+
+fn read_u16(msg: &[u8], offset: usize) -> io::Result<u16> {
+    msg.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(too_short)
+}
+
+// Returns the name at offset and the offset right after it, compression pointers are followed
+fn read_name(msg: &[u8], mut offset: usize) -> io::Result<(String, usize)> {
+    let mut labels: Vec<String> = vec![];
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let len = *msg.get(offset).ok_or_else(too_short)? as usize;
+        if len == 0 {
+            offset += 1;
+            break;
+        }
+        if len & 0xC0 == 0xC0 {
+            if jumps >= MAX_NAME_JUMPS {
+                return Err(Error::new(ErrorKind::InvalidData, "dns name loop"));
+            }
+            jumps += 1;
+            let pointer = read_u16(msg, offset)? as usize & 0x3FFF;
+            end.get_or_insert(offset + 2);
+            offset = pointer;
+            continue;
+        }
+        let label = msg
+            .get(offset + 1..offset + 1 + len)
+            .ok_or_else(too_short)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + len;
+    }
+    Ok((labels.join("."), end.unwrap_or(offset)))
+}
+
+fn parse_response<T, F>(id: u16, msg: &[u8], qtype: u16, parse_rdata: &F) -> io::Result<Vec<T>>
+where
+    F: Fn(&[u8], usize, usize) -> io::Result<T>,
+{
+    if read_u16(msg, 0)? != id {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "dns response id mismatch",
+        ));
+    }
+    let flags = read_u16(msg, 2)?;
+    if flags & 0x0200 != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "dns response truncated"));
+    }
+    match (flags & 0x000F) as u8 {
+        0 => (),
+        RCODE_NXDOMAIN => return Ok(vec![]),
+        rcode => {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("dns response rcode {}", rcode),
+            ))
+        }
+    }
+    let qdcount = read_u16(msg, 4)?;
+    let ancount = read_u16(msg, 6)?;
+    let mut offset = 12;
+    for _ in 0..qdcount {
+        offset = read_name(msg, offset)?.1 + 4;
+    }
+    let mut records = vec![];
+    for _ in 0..ancount {
+        offset = read_name(msg, offset)?.1;
+        let rtype = read_u16(msg, offset)?;
+        let rdlen = read_u16(msg, offset + 8)? as usize;
+        let rdata = offset + 10;
+        // CNAME and other records in the answer are skipped
+        if rtype == qtype {
+            records.push(parse_rdata(msg, rdata, rdlen)?);
+        }
+        offset = rdata + rdlen;
+    }
+    Ok(records)
+}
+
+// A or AAAA record by rdata length
+fn parse_addr_rdata(msg: &[u8], rdata: usize, rdlen: usize) -> io::Result<IpAddr> {
+    let b = msg.get(rdata..rdata + rdlen).ok_or_else(too_short)?;
+    match rdlen {
+        4 => Ok(IpAddr::from(Ipv4Addr::new(b[0], b[1], b[2], b[3]))),
+        16 => Ok(IpAddr::from(Ipv6Addr::from(
+            <[u8; 16]>::try_from(b).unwrap(),
+        ))),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid address length {}", rdlen),
+        )),
+    }
+}
+
+fn parse_srv_rdata(msg: &[u8], rdata: usize, _: usize) -> io::Result<SrvRecord> {
+    Ok(SrvRecord {
+        priority: read_u16(msg, rdata)?,
+        weight: read_u16(msg, rdata + 2)?,
+        port: read_u16(msg, rdata + 4)?,
+        target: read_name(msg, rdata + 6)?.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srv(priority: u16, weight: u16, port: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port,
+            target: target.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_srv() {
+        let id = 0x1234;
+        let mut msg = build_query(id, "_deepflow._tcp.example.com", TYPE_SRV).unwrap();
+        // response flags, one answer
+        msg[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+        msg[6..8].copy_from_slice(&1u16.to_be_bytes());
+        // name pointer to question, type, class, ttl
+        msg.extend_from_slice(&[0xC0, 12, 0, 33, 0, 1, 0, 0, 0, 60]);
+        let mut rdata = vec![0, 10, 0, 5, 0x75, 0x53];
+        rdata.extend_from_slice(&[4]);
+        rdata.extend_from_slice(b"ctrl");
+        // ctrl.example.com, example.com follows _deepflow and _tcp labels in question
+        rdata.extend_from_slice(&[0xC0, 12 + 10 + 5]);
+        msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&rdata);
+
+        assert_eq!(
+            parse_response(id, &msg, TYPE_SRV, &parse_srv_rdata).unwrap(),
+            vec![srv(10, 5, 30035, "ctrl.example.com")]
+        );
+        assert!(parse_response(id + 1, &msg, TYPE_SRV, &parse_srv_rdata).is_err());
+    }
+
+    #[test]
+    fn parse_a() {
+        let id = 0x4321;
+        let mut msg = build_query(id, "ctrl.example.com", TYPE_A).unwrap();
+        msg[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+        msg[6..8].copy_from_slice(&2u16.to_be_bytes());
+        // a CNAME answer to be skipped followed by the A record
+        msg.extend_from_slice(&[0xC0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xC0, 12]);
+        msg.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 1, 2, 3]);
+
+        assert_eq!(
+            parse_response(id, &msg, TYPE_A, &parse_addr_rdata).unwrap(),
+            vec!["10.1.2.3".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn hosts() {
+        let contents = "127.0.0.1 localhost\n# 10.0.0.9 ctrl\n10.0.0.1 ctrl.local ctrl # controller\nfe80::1 CTRL\n";
+        assert_eq!(
+            parse_hosts(contents, "ctrl"),
+            vec![
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "fe80::1".parse().unwrap()
+            ]
+        );
+        assert!(parse_hosts(contents, "other").is_empty());
+    }
+
+    #[test]
+    fn order_srv() {
+        let records = vec![
+            srv(20, 0, 1, "c"),
+            srv(10, 0, 2, "b"),
+            srv(10, 100, 3, "a"),
+            srv(5, 0, 4, ""),
+        ];
+        let ordered = order_srv_records(records);
+        assert_eq!(ordered.len(), 3);
+        assert_eq!(ordered[2].target, "c");
+        assert!(ordered[..2].iter().any(|r| r.target == "a"));
+    }
+
+    #[test]
+    fn resolv_conf() {
+        let conf = parse_resolv_conf(
+            "# comment\nnameserver 10.0.0.1\ndomain corp\nsearch local svc.local\nnameserver ::1\noptions ndots:2 timeout:1\n",
+        );
+        assert_eq!(
+            conf.nameservers,
+            vec![
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+        assert_eq!(conf.search, vec!["local", "svc.local"]);
+        assert_eq!(conf.ndots, 2);
+
+        let conf = parse_resolv_conf("search local\ndomain corp\n");
+        assert_eq!(conf.search, vec!["corp"]);
+        assert_eq!(conf.ndots, DEFAULT_NDOTS);
+    }
+
+    #[test]
+    fn search_names() {
+        let conf = ResolvConf {
+            search: vec!["svc.local".to_owned(), "local.".to_owned()],
+            ndots: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            conf.search_names("ctrl"),
+            vec!["ctrl.svc.local", "ctrl.local", "ctrl"]
+        );
+        assert_eq!(
+            conf.search_names("ctrl.ns.svc"),
+            vec!["ctrl.ns.svc", "ctrl.ns.svc.svc.local", "ctrl.ns.svc.local"]
+        );
+        assert_eq!(
+            conf.search_names("ctrl.example.com."),
+            vec!["ctrl.example.com."]
+        );
+    }
+}
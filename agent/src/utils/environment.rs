@@ -16,16 +16,17 @@
 
 use std::{
     env::{self, VarError},
-    fs,
+    fmt, fs,
     iter::Iterator,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     path::Path,
+    sync::atomic::{AtomicBool, Ordering},
     thread,
     time::Duration,
 };
 
 use bytesize::ByteSize;
-use log::{error, warn};
+use log::{debug, error, warn};
 use sysinfo::{DiskExt, System, SystemExt};
 
 use crate::{
@@ -55,6 +56,8 @@ pub use self::windows::*;
 
 pub type Checker = Box<dyn Fn() -> Result<()>>;
 
+pub const KERNEL_CHECK_FAILED_EXIT_CODE: i32 = 3;
+
 const IN_CONTAINER: &str = "IN_CONTAINER";
 // K8S environment node ip environment variable
 const K8S_NODE_IP_FOR_DEEPFLOW: &str = "K8S_NODE_IP_FOR_DEEPFLOW";
@@ -64,6 +67,9 @@ const K8S_NODE_NAME_FOR_DEEPFLOW: &str = "K8S_NODE_NAME_FOR_DEEPFLOW";
 const ONLY_WATCH_K8S_RESOURCE: &str = "ONLY_WATCH_K8S_RESOURCE";
 const K8S_NAMESPACE_FOR_DEEPFLOW: &str = "K8S_NAMESPACE_FOR_DEEPFLOW";
 
+// set from static config `prefer-route-ctrl-ip` before ctrl ip is selected
+static PREFER_ROUTE_CTRL_IP: AtomicBool = AtomicBool::new(false);
+
 const DNS_HOST_IPV4: IpAddr = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
 const DNS_HOST_IPV6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x240c, 0, 0, 0, 0, 0, 0, 0x6666));
 
@@ -136,27 +142,33 @@ pub fn free_space_checker<P: AsRef<Path>>(
     Box::new(move || free_space_check(&path, required, &exception_handler))
 }
 
-pub fn controller_ip_check(ips: &[String]) {
+// Returns an error if controller ips are not all IPv4 or all IPv6
+pub fn controller_ips_check(ips: &[String]) -> Result<()> {
     if ips.iter().all(|ip| match ip.parse::<IpAddr>() {
         Ok(ip) if ip.is_ipv4() => true,
         _ => false,
     }) {
-        return;
+        return Ok(());
     }
 
     if ips.iter().all(|ip| match ip.parse::<IpAddr>() {
         Ok(ip) if ip.is_ipv6() => true,
         _ => false,
     }) {
-        return;
+        return Ok(());
     }
 
-    error!(
-        "controller ip({:?}) is not support both IPv4 and IPv6, deepflow-agent restart...",
+    Err(Error::Environment(format!(
+        "controller ip({:?}) is not support both IPv4 and IPv6",
         ips
-    );
+    )))
+}
 
-    crate::utils::notify_exit(-1);
+pub fn controller_ip_check(ips: &[String]) {
+    if let Err(e) = controller_ips_check(ips) {
+        error!("{}, deepflow-agent restart...", e);
+        crate::utils::notify_exit(-1);
+    }
 }
 
 pub fn trident_process_check(process_threshold: u32) {
@@ -258,6 +270,12 @@ pub fn get_k8s_namespace() -> String {
     env::var(K8S_NAMESPACE_FOR_DEEPFLOW).unwrap_or("deepflow".to_owned())
 }
 
+pub fn get_k8s_node_name() -> Option<String> {
+    env::var(K8S_NODE_NAME_FOR_DEEPFLOW)
+        .ok()
+        .filter(|n| !n.is_empty())
+}
+
 pub fn get_mac_by_name(src_interface: String) -> u32 {
     if src_interface.is_empty() {
         return 0;
@@ -271,12 +289,47 @@ pub fn get_mac_by_name(src_interface: String) -> u32 {
     }
 }
 
+pub fn set_prefer_route_ctrl_ip(enabled: bool) {
+    PREFER_ROUTE_CTRL_IP.store(enabled, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CtrlIpSource {
+    CtrlInterfaceEnv,
+    K8sNodeIpEnv,
+    Route,
+    // K8S_NODE_IP_FOR_DEEPFLOW is set but skipped because of `prefer-route-ctrl-ip`
+    RoutePreferred,
+}
+
+impl fmt::Display for CtrlIpSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CtrlInterfaceEnv => write!(f, "interface in env {}", ENV_INTERFACE_NAME),
+            Self::K8sNodeIpEnv => write!(f, "env {}", K8S_NODE_IP_FOR_DEEPFLOW),
+            Self::Route => write!(f, "route to controller"),
+            Self::RoutePreferred => write!(
+                f,
+                "route to controller, env {} ignored because prefer-route-ctrl-ip is enabled",
+                K8S_NODE_IP_FOR_DEEPFLOW
+            ),
+        }
+    }
+}
+
 pub fn get_ctrl_ip_and_mac(dest: &IpAddr) -> Result<(IpAddr, MacAddr)> {
+    let (ip, mac, source) = get_ctrl_ip_and_mac_with_source(dest)?;
+    debug!("ctrl ip {} mac {} selected by {}", ip, mac, source);
+    Ok((ip, mac))
+}
+
+pub fn get_ctrl_ip_and_mac_with_source(dest: &IpAddr) -> Result<(IpAddr, MacAddr, CtrlIpSource)> {
     // Steps to find ctrl ip and mac:
     // 1. If environment variable `ENV_INTERFACE_NAME` exists, use it as ctrl interface
     //    a) Use environment variable `K8S_POD_IP_FOR_DEEPFLOW` as ctrl ip if it exists
     //    b) If not, find addresses on the ctrl interface
-    // 2. Use env.K8S_NODE_IP_FOR_DEEPFLOW as the ctrl_ip reported by deepflow-agent if available
+    // 2. Use env.K8S_NODE_IP_FOR_DEEPFLOW as the ctrl_ip reported by deepflow-agent if available,
+    //    unless `prefer-route-ctrl-ip` is enabled
     // 3. Find ctrl ip and mac from controller address
     if let Ok(name) = env::var(ENV_INTERFACE_NAME) {
         let Ok(link) = link_by_name(&name) else {
@@ -312,7 +365,7 @@ pub fn get_ctrl_ip_and_mac(dest: &IpAddr) -> Result<(IpAddr, MacAddr)> {
         };
         for ip in ips {
             if is_global(&ip) {
-                return Ok((ip, link.mac_addr));
+                return Ok((ip, link.mac_addr, CtrlIpSource::CtrlInterfaceEnv));
             }
         }
         return Err(Error::Environment(format!(
@@ -320,10 +373,15 @@ pub fn get_ctrl_ip_and_mac(dest: &IpAddr) -> Result<(IpAddr, MacAddr)> {
             name, ENV_INTERFACE_NAME
         )));
     };
+    let mut route_source = CtrlIpSource::Route;
     if let Some(ip) = get_k8s_local_node_ip() {
-        let ctrl_mac = get_mac_by_ip(ip);
-        if let Ok(mac) = ctrl_mac {
-            return Ok((ip, mac));
+        if PREFER_ROUTE_CTRL_IP.load(Ordering::Relaxed) {
+            route_source = CtrlIpSource::RoutePreferred;
+        } else {
+            let ctrl_mac = get_mac_by_ip(ip);
+            if let Ok(mac) = ctrl_mac {
+                return Ok((ip, mac, CtrlIpSource::K8sNodeIpEnv));
+            }
         }
     }
 
@@ -364,13 +422,14 @@ pub fn get_ctrl_ip_and_mac(dest: &IpAddr) -> Result<(IpAddr, MacAddr)> {
                         warn!("failed getting control ip and mac from {}, because: {:?}, wait 1 second", dest, tuple);
                         continue 'outer;
                     }
-                    return Ok(tuple.unwrap());
+                    let (ip, mac) = tuple.unwrap();
+                    return Ok((ip, mac, route_source));
                 }
                 break;
             }
         }
 
-        return Ok((ip, mac));
+        return Ok((ip, mac, route_source));
     }
     Err(Error::Environment(
         "failed getting control ip and mac, deepflow-agent restart...".to_owned(),
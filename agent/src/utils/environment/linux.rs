@@ -28,10 +28,14 @@ use kube::{
     api::{Api, Patch, PatchParams},
     Client, Config,
 };
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
+use nix::{
+    sched::{sched_setaffinity, CpuSet},
+    unistd::Pid,
+};
 use nom::AsBytes;
 
-use public::utils::net::get_link_enabled_features;
+use public::{proto::trident::TapMode, utils::net::get_link_enabled_features};
 
 use super::{get_k8s_namespace, running_in_container, running_in_k8s};
 use crate::{
@@ -41,30 +45,168 @@ use crate::{
 };
 
 const CORE_FILE_CONFIG: &str = "/proc/sys/kernel/core_pattern";
+// Control plane threads besides the main thread, matched by comm which is truncated to 15 bytes
+const CONTROL_PLANE_THREADS: [&str; 5] = [
+    "trident-main",
+    "guard",
+    "monitor",
+    "stats-collector",
+    "tokio-runtime-w",
+];
 const CORE_FILE_LIMIT: usize = 3;
 
 pub fn free_memory_check(_required: u64, _exception_handler: &ExceptionHandler) -> Result<()> {
     return Ok(()); // fixme: The way to obtain free memory is different in earlier versions of Linux, which requires adaptation
 }
 
-pub fn kernel_check() {
+// Kernel versions are compared by (major, minor)
+const MIN_KERNEL_VERSION_AF_PACKET_V3: (u32, u32) = (3, 2); // TPACKET_V3 is supported from Linux 3.2
+const MIN_KERNEL_VERSION_EBPF: (u32, u32) = (4, 14);
+const RECOMMENDED_KERNEL_VERSION: (u32, u32, u32) = (4, 19, 17);
+
+// The release is in the format of 5.4.0-13-generic, returns (5, 4, 0)
+fn parse_kernel_version(release: &str) -> Option<(u32, u32, u32)> {
+    let version = release
+        .trim()
+        .split(|c: char| c != '.' && !c.is_ascii_digit())
+        .next()?;
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or_default();
+    let patch = parts.next().flatten().unwrap_or_default();
+    Some((major, minor, patch))
+}
+
+// Returns an error if the kernel does not meet the minimum version of `tap_mode`,
+// eBPF features are disabled by the collector itself on older kernels so only a warning is logged
+pub fn kernel_check(tap_mode: TapMode, ebpf_enabled: bool) -> Result<()> {
     use nix::sys::utsname::uname;
-    const RECOMMENDED_KERNEL_VERSION: &str = "4.19.17";
-    // The `kernel_version` is in the format of 5.4.0-13
     let sys_uname = uname();
-    if sys_uname
-        .release()
-        .trim()
-        .split_once('-') // The number after "-" represents the number of times the version has been modified, and it is separated by "-"
-        .unwrap_or_default()
-        .0
-        .ne(RECOMMENDED_KERNEL_VERSION)
-    {
+    let release = sys_uname.release().trim();
+    let Some(version) = parse_kernel_version(release) else {
         warn!(
-            "kernel version is not recommended({})",
-            RECOMMENDED_KERNEL_VERSION
+            "kernel version {} can not be parsed, skip kernel check",
+            release
+        );
+        return Ok(());
+    };
+    info!("kernel version {} detected", release);
+
+    match tap_mode {
+        TapMode::Mirror | TapMode::Analyzer => {
+            let (major, minor) = MIN_KERNEL_VERSION_AF_PACKET_V3;
+            if (version.0, version.1) < MIN_KERNEL_VERSION_AF_PACKET_V3 {
+                return Err(Error::Environment(format!(
+                    "kernel version {} is lower than {}.{} required by {:?} mode, upgrade the kernel to {}.{} or later, or deploy the agent in local mode",
+                    release, major, minor, tap_mode, major, minor
+                )));
+            }
+            if version < RECOMMENDED_KERNEL_VERSION {
+                let (major, minor, patch) = RECOMMENDED_KERNEL_VERSION;
+                warn!(
+                    "kernel version {} is lower than {}.{}.{} recommended for {:?} mode, capture performance may be degraded",
+                    release, major, minor, patch, tap_mode
+                );
+            }
+        }
+        _ if ebpf_enabled => {
+            let (major, minor) = MIN_KERNEL_VERSION_EBPF;
+            if (version.0, version.1) < MIN_KERNEL_VERSION_EBPF {
+                warn!(
+                    "kernel version {} is lower than {}.{} required by eBPF, eBPF features will not work, upgrade the kernel to {}.{} or later, or set `ebpf.disabled` to true in static config",
+                    release, major, minor, major, minor
+                );
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+const CAP_SYS_ADMIN: u32 = 21;
+const CAP_BPF: u32 = 39;
+
+// Returns the first missing capability which eBPF depends on, used to explain eBPF init failures
+pub fn ebpf_missing_capability() -> Option<String> {
+    use nix::sys::utsname::uname;
+
+    let cap_eff = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("CapEff:"))
+                .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        });
+    if let Some(caps) = cap_eff {
+        if caps & (1 << CAP_SYS_ADMIN) == 0 && caps & (1 << CAP_BPF) == 0 {
+            return Some(
+                "neither CAP_SYS_ADMIN nor CAP_BPF is in effective capabilities".to_owned(),
+            );
+        }
+    }
+
+    let sys_uname = uname();
+    let release = sys_uname.release().trim();
+    if let Some(version) = parse_kernel_version(release) {
+        if (version.0, version.1) < MIN_KERNEL_VERSION_EBPF {
+            let (major, minor) = MIN_KERNEL_VERSION_EBPF;
+            return Some(format!(
+                "kernel version {} is lower than {}.{}",
+                release, major, minor
+            ));
+        }
+    }
+
+    if !PathBuf::from("/sys/kernel/debug/tracing").exists()
+        && !PathBuf::from("/sys/kernel/tracing").exists()
+    {
+        return Some(
+            "tracefs is mounted at neither /sys/kernel/debug/tracing nor /sys/kernel/tracing"
+                .to_owned(),
         );
     }
+
+    if !PathBuf::from("/sys/kernel/btf/vmlinux").exists() {
+        return Some("kernel BTF /sys/kernel/btf/vmlinux is not found".to_owned());
+    }
+    None
+}
+
+// Binds control plane threads to `control_plane` and the other threads to `data_plane`,
+// returns the number of control plane threads
+pub fn bind_control_plane_threads(control_plane: &CpuSet, data_plane: &CpuSet) -> Result<usize> {
+    let pid = std::process::id();
+    let mut count = 0;
+    let tasks = fs::read_dir("/proc/self/task")
+        .map_err(|e| Error::Environment(format!("read /proc/self/task failed: {}", e)))?;
+    for task in tasks.filter_map(|t| t.ok()) {
+        let Some(tid) = task
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let comm = fs::read_to_string(task.path().join("comm")).unwrap_or_default();
+        let cpu_set = if tid == pid || CONTROL_PLANE_THREADS.contains(&comm.trim()) {
+            count += 1;
+            control_plane
+        } else {
+            data_plane
+        };
+        // threads may exit in the meantime
+        if let Err(e) = sched_setaffinity(Pid::from_raw(tid as i32), cpu_set) {
+            debug!(
+                "bind thread {} {} to {:?} failed: {}",
+                tid,
+                comm.trim(),
+                cpu_set,
+                e
+            );
+        }
+    }
+    Ok(count)
 }
 
 pub fn tap_interface_check(tap_interfaces: &[String]) {
@@ -14,13 +14,26 @@
  * limitations under the License.
  */
 
-use std::{ffi::OsString, io, os::windows::ffi::OsStringExt, path::PathBuf, ptr};
+use std::{
+    ffi::{OsStr, OsString},
+    io, mem,
+    os::windows::ffi::{OsStrExt, OsStringExt},
+    path::PathBuf,
+    ptr,
+};
 
 use bytesize::ByteSize;
+use log::info;
 use sysinfo::{System, SystemExt};
 use winapi::{
     shared::minwindef::{DWORD, MAX_PATH},
-    um::libloaderapi::GetModuleFileNameW,
+    um::{
+        libloaderapi::GetModuleFileNameW,
+        winsvc::{
+            CloseServiceHandle, OpenSCManagerW, OpenServiceW, QueryServiceStatus,
+            SC_MANAGER_CONNECT, SERVICE_QUERY_STATUS, SERVICE_RUNNING, SERVICE_STATUS,
+        },
+    },
 };
 
 use crate::{
@@ -28,7 +41,7 @@ use crate::{
     exception::ExceptionHandler,
     utils::process::get_memory_rss,
 };
-use public::proto::trident::Exception;
+use public::proto::trident::{Exception, TapMode};
 
 pub fn free_memory_check(required: u64, exception_handler: &ExceptionHandler) -> Result<()> {
     get_memory_rss()
@@ -55,7 +68,67 @@ pub fn free_memory_check(required: u64, exception_handler: &ExceptionHandler) ->
         })
 }
 
-pub fn kernel_check() {}
+// Npcap registers service "npcap", WinPcap or Npcap in WinPcap compatible mode registers "npf"
+const PACKET_DRIVER_SERVICES: [&str; 2] = ["npcap", "npf"];
+
+pub const NPF_CHECK_FAILED_EXIT_CODE: i32 = 2;
+
+// returns None if the service is not installed or can not be queried
+fn get_service_state(name: &str) -> Option<DWORD> {
+    let name = OsStr::new(name)
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<u16>>();
+    unsafe {
+        let manager = OpenSCManagerW(ptr::null(), ptr::null(), SC_MANAGER_CONNECT);
+        if manager.is_null() {
+            return None;
+        }
+        let service = OpenServiceW(manager, name.as_ptr(), SERVICE_QUERY_STATUS);
+        if service.is_null() {
+            CloseServiceHandle(manager);
+            return None;
+        }
+        let mut status: SERVICE_STATUS = mem::zeroed();
+        let ret = QueryServiceStatus(service, &mut status);
+        CloseServiceHandle(service);
+        CloseServiceHandle(manager);
+        if ret == 0 {
+            None
+        } else {
+            Some(status.dwCurrentState)
+        }
+    }
+}
+
+pub fn npf_check() -> Result<()> {
+    let mut not_running = vec![];
+    for name in PACKET_DRIVER_SERVICES {
+        match get_service_state(name) {
+            Some(SERVICE_RUNNING) => {
+                info!("packet driver service {} is running", name);
+                return Ok(());
+            }
+            Some(state) => not_running.push(format!("{}(state {})", name, state)),
+            None => (),
+        }
+    }
+    if not_running.is_empty() {
+        Err(Error::Environment(format!(
+            "packet driver service {} not installed, please install Npcap",
+            PACKET_DRIVER_SERVICES.join(" or ")
+        )))
+    } else {
+        Err(Error::Environment(format!(
+            "packet driver service {} not running, please start it with `sc start <service>`",
+            not_running.join(", ")
+        )))
+    }
+}
+
+pub fn kernel_check(_tap_mode: TapMode, _ebpf_enabled: bool) -> Result<()> {
+    Ok(())
+}
 
 pub fn tap_interface_check(_tap_interfaces: &[String]) {}
 
@@ -33,7 +33,7 @@ use public::{
 
 use super::stats::{self, QueueStats};
 use crate::{
-    config::handler::{LogAccess, LogConfig, SenderAccess},
+    config::handler::{LogAccess, LogConfig, ReconnectBackoffAccess, SenderAccess},
     exception::ExceptionHandler,
     sender::uniform_sender::UniformSenderThread,
 };
@@ -102,6 +102,7 @@ impl RemoteLogWriter {
         tag: String,
         log_config: LogAccess,
         sender_config: SenderAccess,
+        reconnect_backoff: ReconnectBackoffAccess,
         stats_collector: Arc<stats::Collector>,
         exception_handler: ExceptionHandler,
         ntp_diff: Arc<AtomicI64>,
@@ -123,6 +124,8 @@ impl RemoteLogWriter {
             exception_handler,
             true,
         );
+        // back off so that agents do not reconnect at the same pace while the ingester is recovering
+        uniform_sender.set_reconnect_backoff(reconnect_backoff);
         uniform_sender.start();
         Self {
             ntp_diff,
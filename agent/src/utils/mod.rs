@@ -14,8 +14,12 @@
  * limitations under the License.
  */
 
+pub(crate) mod capture_schedule;
 pub(crate) mod cgroups;
 pub(crate) mod command;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) mod diagnostic;
+pub(crate) mod dns;
 pub(crate) mod environment;
 pub(crate) mod guard;
 pub(crate) mod hasher;
@@ -24,6 +28,7 @@ pub(crate) mod lru;
 pub(crate) mod npb_bandwidth_watcher;
 pub(crate) mod possible_host;
 pub(crate) mod process;
+pub(crate) mod sensitive;
 pub mod stats;
 
 #[cfg(target_os = "linux")]
@@ -35,7 +40,18 @@ pub mod test;
 
 const WIN_ERROR_CODE_STR: &str = "please browse website(https://docs.microsoft.com/en-us/windows/win32/debug/system-error-codes) to get more detail";
 
+// Builds a named thread, stack_size of 0 uses the platform default
+pub fn thread_builder<S: Into<String>>(name: S, stack_size: usize) -> std::thread::Builder {
+    let builder = std::thread::Builder::new().name(name.into());
+    if stack_size > 0 {
+        builder.stack_size(stack_size)
+    } else {
+        builder
+    }
+}
+
 pub fn notify_exit(code: i32) {
+    process::record_exit_cause(code);
     #[cfg(any(target_os = "linux", target_os = "android"))]
     if let Err(_) =
         nix::sys::signal::kill(nix::unistd::Pid::this(), nix::sys::signal::Signal::SIGTERM)
@@ -24,7 +24,10 @@ use std::{
 };
 
 use log::debug;
-use nix::sys::utsname::uname;
+use nix::sys::{
+    resource::{getrlimit, Resource},
+    utsname::uname,
+};
 
 //返回当前进程占用内存RSS单位（字节）
 pub fn get_memory_rss() -> Result<u64> {
@@ -102,6 +105,21 @@ pub fn get_thread_num() -> Result<u32> {
     ))
 }
 
+// 返回当前进程打开的文件描述符数
+pub fn get_fd_num() -> Result<u32> {
+    Ok(fs::read_dir(format!("/proc/{}/fd", process::id()))?.count() as u32)
+}
+
+// 返回当前进程文件描述符数的(soft, hard)限制，无限制时为u64::MAX
+pub fn get_fd_limit() -> Result<(u64, u64)> {
+    let (soft, hard) = getrlimit(Resource::RLIMIT_NOFILE)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("getrlimit failed: {}", e)))?;
+    Ok((
+        soft.map(|l| l as u64).unwrap_or(u64::MAX),
+        hard.map(|l| l as u64).unwrap_or(u64::MAX),
+    ))
+}
+
 // Get the number of processes with the same name of the deepflow-agent
 pub fn get_process_num_by_name(name: &str) -> Result<u32> {
     get_num_from_status_file("Name:", name)
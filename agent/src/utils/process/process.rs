@@ -14,7 +14,103 @@
  * limitations under the License.
  */
 
-use std::{cmp::Ordering, fs, io, path::Path, time::SystemTime};
+use std::{
+    cmp::Ordering,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicBool},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+
+use crate::utils::stats;
+
+static EXIT_CAUSE_FILE: OnceLock<PathBuf> = OnceLock::new();
+static EXIT_CAUSE_RECORDED: AtomicBool = AtomicBool::new(false);
+
+// Exit codes are recorded to path so that the next run can tell why the agent exited
+pub fn set_exit_cause_file(path: PathBuf) {
+    let _ = EXIT_CAUSE_FILE.set(path);
+}
+
+// Only the first exit code is recorded, a graceful stop following notify_exit does not override it
+pub fn record_exit_cause(code: i32) {
+    let Some(path) = EXIT_CAUSE_FILE.get() else {
+        return;
+    };
+    if EXIT_CAUSE_RECORDED.swap(true, atomic::Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = fs::write(path, format!("{}\n", code)) {
+        warn!("record exit cause to {} failed: {}", path.display(), e);
+    }
+}
+
+// Exit code recorded by the previous run, None if it crashed or was killed without recording.
+// The file is removed so that it is not reported again.
+pub fn take_last_exit_cause() -> Option<i32> {
+    let path = EXIT_CAUSE_FILE.get()?;
+    let code = fs::read_to_string(path).ok()?.trim().parse().ok();
+    let _ = fs::remove_file(path);
+    code
+}
+
+pub struct ProcessCounter {
+    start_time: SystemTime,
+    last_exit_code: Option<i32>,
+}
+
+impl ProcessCounter {
+    pub fn new(start_time: SystemTime, last_exit_code: Option<i32>) -> Self {
+        Self {
+            start_time,
+            last_exit_code,
+        }
+    }
+}
+
+impl stats::OwnedCountable for ProcessCounter {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        vec![
+            (
+                "start_time",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(
+                    self.start_time
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                ),
+            ),
+            (
+                "uptime",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(
+                    self.start_time.elapsed().unwrap_or_default().as_secs(),
+                ),
+            ),
+            (
+                "last_exit_code",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Signed(self.last_exit_code.unwrap_or_default() as i64),
+            ),
+            // 0 if the previous run crashed or was killed
+            (
+                "last_exit_recorded",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(self.last_exit_code.is_some() as u64),
+            ),
+        ]
+    }
+
+    fn closed(&self) -> bool {
+        false
+    }
+}
 
 pub struct FileAndSizeSum {
     pub file_infos: Vec<FileInfo>, // 文件信息
@@ -72,6 +72,8 @@ impl fmt::Display for Source {
 pub struct Batch {
     module: &'static str,
     hostname: String,
+    // global and agent identity tags shared by all batches, module tags with the same key take precedence
+    identity: Arc<Vec<(&'static str, String)>>,
     tags: Vec<(&'static str, String)>,
     points: Vec<Counter>,
     timestamp: u32,
@@ -96,6 +98,12 @@ impl Batch {
             tag_names.push("host".to_string());
             tag_values.push(self.hostname.clone());
         }
+        for (k, v) in self.identity.iter() {
+            if self.tags.iter().all(|t| t.0 != *k) {
+                tag_names.push(k.to_string());
+                tag_values.push(v.clone());
+            }
+        }
 
         for p in self.points.iter() {
             metrics_float_names.push(p.0.to_string());
@@ -187,6 +195,7 @@ impl Module for QueueStats {
 
 pub struct Collector {
     hostname: Arc<Mutex<String>>,
+    identity: Arc<Mutex<Arc<Vec<(&'static str, String)>>>>,
 
     sources: Arc<Mutex<Vec<Source>>>,
     pre_hooks: Arc<Mutex<Vec<Box<dyn FnMut() + Send>>>>,
@@ -194,6 +203,8 @@ pub struct Collector {
     min_interval: Arc<AtomicU64>,
 
     ntp_diff: Arc<AtomicI64>,
+    // registrations and start are no-ops if disabled
+    disabled: bool,
     running: Arc<(Mutex<bool>, Condvar)>,
     thread: Mutex<Option<JoinHandle<()>>>,
 
@@ -202,12 +213,26 @@ pub struct Collector {
 }
 
 impl Collector {
-    pub fn new<S: AsRef<str>>(hostname: S, ntp_diff: Arc<AtomicI64>) -> Self {
-        Self::with_min_interval(hostname, TICK_CYCLE, ntp_diff)
+    // global_tags are added to stats of all countables, empty values are ignored
+    pub fn new<S: AsRef<str>>(
+        hostname: S,
+        global_tags: Vec<(&'static str, String)>,
+        ntp_diff: Arc<AtomicI64>,
+    ) -> Self {
+        Self::with_min_interval(hostname, global_tags, TICK_CYCLE, ntp_diff)
+    }
+
+    // A collector collecting nothing, for agents not reporting self-monitoring stats
+    pub fn disabled<S: AsRef<str>>(hostname: S, ntp_diff: Arc<AtomicI64>) -> Self {
+        let mut s = Self::new(hostname, vec![], ntp_diff);
+        s.disabled = true;
+        s.sources.lock().unwrap().clear();
+        s
     }
 
     pub fn with_min_interval<S: AsRef<str>>(
         hostname: S,
+        mut global_tags: Vec<(&'static str, String)>,
         interval: Duration,
         ntp_diff: Arc<AtomicI64>,
     ) -> Self {
@@ -220,8 +245,10 @@ impl Collector {
                     * TICK_CYCLE.as_secs(),
             )
         };
+        global_tags.retain(|(_, v)| !v.is_empty());
         let s = Self {
             hostname: Arc::new(Mutex::new(hostname.as_ref().to_owned())),
+            identity: Arc::new(Mutex::new(Arc::new(global_tags))),
             sources: Arc::new(Mutex::new(vec![])),
             pre_hooks: Arc::new(Mutex::new(vec![])),
             min_interval: Arc::new(AtomicU64::new(min_interval.as_secs())),
@@ -230,6 +257,7 @@ impl Collector {
             sender: Arc::new(stats_queue_sender),
             receiver: Arc::new(stats_queue_receiver),
             ntp_diff,
+            disabled: false,
         };
         s.register_countable(
             &QueueStats {
@@ -245,7 +273,14 @@ impl Collector {
         self.receiver.clone()
     }
 
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
     pub fn register_countable(&self, module: &dyn Module, countable: Countable) {
+        if self.disabled {
+            return;
+        }
         let mut source = Source {
             module: module.name(),
             interval: Duration::from_secs(self.min_interval.load(Ordering::Relaxed)),
@@ -324,6 +359,9 @@ impl Collector {
     }
 
     pub fn register_pre_hook(&self, hook: Box<dyn FnMut() + Send>) {
+        if self.disabled {
+            return;
+        }
         self.pre_hooks.lock().unwrap().push(hook);
     }
 
@@ -338,11 +376,71 @@ impl Collector {
         }
     }
 
+    // Tags identifying the agent on every batch, so that the server does not rely on source ip
+    // to attribute stats, an empty value removes the tag
+    pub fn set_identity_tag(&self, key: &'static str, value: String) {
+        let mut identity = self.identity.lock().unwrap();
+        if identity.iter().any(|(k, v)| *k == key && *v == value) {
+            return;
+        }
+        let mut new = identity
+            .iter()
+            .filter(|(k, _)| *k != key)
+            .cloned()
+            .collect::<Vec<_>>();
+        if !value.is_empty() {
+            info!("set stats identity {} to {:?}", key, value);
+            new.push((key, value));
+        } else if new.len() == identity.len() {
+            return;
+        }
+        *identity = Arc::new(new);
+    }
+
     pub fn set_min_interval(&self, interval: Duration) {
         self.min_interval
             .store(interval.as_secs(), Ordering::Relaxed);
     }
 
+    // Write current values of all countables to `w`
+    //
+    // Counted values are reset on collection, so the collected batches are also sent as usual
+    // to avoid losing them in the next report
+    pub fn dump<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let host = self.hostname.lock().unwrap().clone();
+        let identity = self.identity.lock().unwrap().clone();
+        let now = get_timestamp(self.ntp_diff.load(Ordering::Relaxed)).as_secs() as u32;
+        let mut sources = self.sources.lock().unwrap();
+        sources.retain(|s| !s.countable.closed());
+        for source in sources.iter() {
+            let points = source.countable.get_counters();
+            write!(w, "{}", source)?;
+            for (name, _, value) in points.iter() {
+                match value {
+                    CounterValue::Signed(v) => write!(w, " {}={}", name, v)?,
+                    CounterValue::Unsigned(v) => write!(w, " {}={}", name, v)?,
+                    CounterValue::Float(v) => write!(w, " {}={}", name, v)?,
+                }
+            }
+            writeln!(w)?;
+            if points.is_empty() {
+                continue;
+            }
+            let batch = Arc::new(Batch {
+                module: source.module,
+                hostname: host.clone(),
+                identity: identity.clone(),
+                tags: source.tags.clone(),
+                points,
+                timestamp: now,
+            });
+            if let Err(_) = self.sender.send(ArcBatch(batch)) {
+                debug!("stats to send queue failed because queue have terminated");
+            }
+        }
+        Ok(())
+    }
+
     fn new_statsd_client<A: ToSocketAddrs + std::fmt::Debug>(
         addr: A,
     ) -> MetricResult<StatsdClient> {
@@ -377,6 +475,9 @@ impl Collector {
     }
 
     pub fn start(&self) {
+        if self.disabled {
+            return;
+        }
         {
             let (started, _) = &*self.running;
             let mut started = started.lock().unwrap();
@@ -390,6 +491,7 @@ impl Collector {
         let sources = self.sources.clone();
         let pre_hooks = self.pre_hooks.clone();
         let hostname = self.hostname.clone();
+        let identity = self.identity.clone();
         let min_interval = self.min_interval.clone();
         let sender = self.sender.clone();
         let ntp_diff = self.ntp_diff.clone();
@@ -399,6 +501,7 @@ impl Collector {
                 .spawn(move || {
                     loop {
                         let host = hostname.lock().unwrap().clone();
+                        let identity = identity.lock().unwrap().clone();
                         {
                             pre_hooks.lock().unwrap().iter_mut().for_each(|hook| hook());
                         }
@@ -422,6 +525,7 @@ impl Collector {
                                     let batch = Arc::new(Batch {
                                         module: source.module,
                                         hostname: host.clone(),
+                                        identity: identity.clone(),
                                         tags: source.tags.clone(),
                                         points,
                                         timestamp: now,